@@ -0,0 +1,382 @@
+//! Command-line wallet, printing one result per line with tab-separated
+//! fields so it can be driven from scripts. Tokens and payment requests are
+//! read and printed as `mugraph:` URIs, see [`mugraph_core::uri`].
+
+use std::{io::Write, path::PathBuf};
+
+use clap::{Parser, Subcommand};
+use color_eyre::eyre::{eyre, Result};
+use mugraph_core::{
+    types::{Hash, PaymentRequest, PublicKey},
+    uri::{Assembler, Uri},
+};
+use mugraph_wallet::{Kind, Wallet};
+use rand::prelude::*;
+
+#[derive(Debug, Parser)]
+#[clap(name = "mugraph", about = "Wallet for Mugraph delegates")]
+pub struct Cli {
+    /// Database of the wallet.
+    #[clap(long, env = "MUGRAPH_WALLET", default_value = "wallet.db")]
+    pub wallet: PathBuf,
+
+    /// Splits the URIs printed into frames of at most this many characters,
+    /// one per line, to be shown as an animated QR code.
+    #[clap(long, global = true)]
+    pub frames: Option<usize>,
+
+    #[clap(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Creates a new wallet, printing its seed and the key owning its
+    /// deposits.
+    Init {
+        /// Seed to derive the secrets of the wallet from, in hex. A random
+        /// one by default.
+        #[clap(long)]
+        seed: Option<String>,
+    },
+    /// Prints the unspent amount of each asset, by delegate.
+    Balance,
+    /// Pays an amount of an asset, printing a token for the receiver.
+    Send {
+        amount: u64,
+        /// Asset id, or ticker of an asset of the delegates added.
+        asset: String,
+        /// Delegate to pay from, by default the first one holding enough.
+        #[clap(long)]
+        delegate: Option<String>,
+        /// Key to lock the note to, so the receiver can accept it offline.
+        #[clap(long)]
+        lock: Option<String>,
+    },
+    /// Claims the notes of a token.
+    Receive {
+        /// Token, or all of its frames.
+        #[clap(required = true)]
+        token: Vec<String>,
+        /// Accepts a token locked to the wallet without reaching the
+        /// delegate. It is claimed on the next recover.
+        #[clap(long)]
+        offline: bool,
+    },
+    /// Prints a request for someone else to pay an amount of an asset.
+    Request {
+        amount: u64,
+        asset: String,
+        /// Delegate to be paid at, by default the only one added.
+        #[clap(long)]
+        delegate: Option<String>,
+        #[clap(long)]
+        memo: Option<String>,
+        /// Asks for a payment locked to the wallet, so it can be accepted
+        /// offline.
+        #[clap(long)]
+        locked: bool,
+    },
+    /// Pays a payment request, printing a token for whoever made it.
+    Pay {
+        /// Payment request, or all of its frames.
+        #[clap(required = true)]
+        request: Vec<String>,
+    },
+    /// Mints notes for an L1 deposit owned by the wallet.
+    Mint {
+        deposit: String,
+        asset: String,
+        #[clap(required = true)]
+        amounts: Vec<u64>,
+        #[clap(long)]
+        delegate: Option<String>,
+    },
+    /// Sends again the requests left in flight, then asks the delegates
+    /// which notes were spent.
+    ///
+    /// Notes are only kept in the wallet's database, they can not be found
+    /// again from the seed alone.
+    #[clap(alias = "restore")]
+    Recover,
+    /// Manages the delegates of the wallet.
+    Delegates {
+        #[clap(subcommand)]
+        command: Delegates,
+    },
+    /// Prints the requests that went through, oldest first.
+    History,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Delegates {
+    /// Adds the delegate at a URL, or in a `mugraph:` URI, printing its key.
+    Add {
+        url: String,
+    },
+    List,
+}
+
+pub async fn run(cli: Cli, out: &mut impl Write) -> Result<()> {
+    if let Command::Init { seed } = &cli.command {
+        let seed = match seed {
+            Some(seed) => Hash(bytes(seed)?),
+            None => Hash::random(&mut thread_rng()),
+        };
+        let wallet = Wallet::create(&cli.wallet, seed)?;
+
+        writeln!(out, "seed\t{seed}")?;
+        writeln!(out, "key\t{}", wallet.keypair().public_key)?;
+
+        return Ok(());
+    }
+
+    let wallet = Wallet::open(&cli.wallet)?;
+
+    match cli.command {
+        Command::Init { .. } => unreachable!("Wallet is created above"),
+        Command::Balance => {
+            for ((delegate, asset_id), amount) in wallet.balances()? {
+                writeln!(out, "{delegate}\t{}\t{amount}", ticker(&wallet, asset_id)?)?;
+            }
+        }
+        Command::Send {
+            amount,
+            asset,
+            delegate,
+            lock,
+        } => {
+            let asset_id = asset_id(&wallet, &asset)?;
+            let delegate = match delegate {
+                Some(delegate) => public_key(&delegate)?,
+                None => wallet
+                    .balances()?
+                    .into_iter()
+                    .find(|((_, a), total)| *a == asset_id && *total >= amount as u128)
+                    .map(|((d, _), _)| d)
+                    .ok_or_else(|| eyre!("No delegate holds {amount} of {asset}"))?,
+            };
+
+            let token = match lock {
+                Some(lock) => {
+                    wallet
+                        .send_locked(delegate, asset_id, amount, public_key(&lock)?)
+                        .await?
+                }
+                None => wallet.send(delegate, asset_id, amount).await?,
+            };
+            print(out, Uri::Token(token), cli.frames)?;
+        }
+        Command::Receive { token, offline } => {
+            let token = match read(&token)? {
+                Uri::Token(token) => token,
+                _ => return Err(eyre!("Expected a token")),
+            };
+            let notes = match offline {
+                true => {
+                    wallet.receive_offline(&token)?;
+                    token.notes
+                }
+                false => wallet.receive(&token).await?,
+            };
+
+            for (asset_id, amount) in totals(notes.iter().map(|n| (n.asset_id, n.amount))) {
+                writeln!(out, "{}\t{amount}", ticker(&wallet, asset_id)?)?;
+            }
+        }
+        Command::Request {
+            amount,
+            asset,
+            delegate,
+            memo,
+            locked,
+        } => {
+            let delegates = wallet.delegates()?;
+            let (delegate, info) = match delegate {
+                Some(delegate) => {
+                    let delegate = public_key(&delegate)?;
+                    (delegate, wallet.store().delegate(delegate)?)
+                }
+                None if delegates.len() == 1 => delegates[0].clone(),
+                None => return Err(eyre!("Pick a delegate with --delegate")),
+            };
+            let request = PaymentRequest {
+                url: info.url,
+                delegate,
+                asset_id: asset_id(&wallet, &asset)?,
+                amount,
+                memo,
+                lock: locked.then(|| wallet.keypair().public_key),
+            };
+
+            print(out, Uri::Request(request), cli.frames)?;
+        }
+        Command::Pay { request } => {
+            let request = match read(&request)? {
+                Uri::Request(request) => request,
+                _ => return Err(eyre!("Expected a payment request")),
+            };
+            let token = wallet.pay(&request).await?;
+
+            print(out, Uri::Token(token), cli.frames)?;
+        }
+        Command::Mint {
+            deposit,
+            asset,
+            amounts,
+            delegate,
+        } => {
+            let delegates = wallet.delegates()?;
+            let delegate = match delegate {
+                Some(delegate) => public_key(&delegate)?,
+                None if delegates.len() == 1 => delegates[0].0,
+                None => return Err(eyre!("Pick a delegate with --delegate")),
+            };
+            let asset_id = asset_id(&wallet, &asset)?;
+            let notes = wallet
+                .mint(delegate, Hash(bytes(&deposit)?), asset_id, &amounts)
+                .await?;
+
+            for (asset_id, amount) in totals(notes.iter().map(|n| (n.asset_id, n.amount))) {
+                writeln!(out, "{}\t{amount}", ticker(&wallet, asset_id)?)?;
+            }
+        }
+        Command::Recover => {
+            let recovered = wallet.recover().await?;
+            let spent = wallet.sync().await?;
+
+            writeln!(out, "recovered\t{}", recovered.len())?;
+            writeln!(out, "spent\t{spent}")?;
+        }
+        Command::Delegates {
+            command: Delegates::Add { url },
+        } => {
+            let url = match url.parse::<Uri>() {
+                Ok(Uri::Delegate(url)) => url,
+                Ok(_) => return Err(eyre!("Expected the URI of a delegate")),
+                Err(_) => url,
+            };
+
+            writeln!(out, "{}", wallet.add_delegate(&url).await?)?;
+        }
+        Command::Delegates {
+            command: Delegates::List,
+        } => {
+            for (public_key, delegate) in wallet.delegates()? {
+                writeln!(out, "{public_key}\t{}", delegate.url)?;
+            }
+        }
+        Command::History => {
+            for event in wallet.history()? {
+                let (kind, memo) = match event.kind {
+                    Kind::Mint => ("mint", None),
+                    Kind::Send => ("send", None),
+                    Kind::Receive => ("receive", None),
+                    Kind::Pay { memo } => ("pay", memo),
+                };
+
+                for (asset_id, amount) in event.amounts {
+                    write!(
+                        out,
+                        "{}\t{kind}\t{}\t{amount}\t{}",
+                        event.timestamp,
+                        ticker(&wallet, asset_id)?,
+                        event.transaction
+                    )?;
+
+                    match &memo {
+                        Some(memo) => writeln!(out, "\t{memo}")?,
+                        None => writeln!(out)?,
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints a URI, split into frames of at most `frames` characters if given.
+fn print(out: &mut impl Write, uri: Uri, frames: Option<usize>) -> Result<()> {
+    let lines = match frames {
+        Some(size) => uri.frames(size)?,
+        None => vec![uri.encode()?],
+    };
+
+    for line in lines {
+        writeln!(out, "{line}")?;
+    }
+
+    Ok(())
+}
+
+/// Reads a URI given whole or as all of its frames, in any order.
+fn read(frames: &[String]) -> Result<Uri> {
+    let mut assembler = Assembler::new();
+
+    for frame in frames {
+        if let Some(uri) = assembler.push(frame)? {
+            return Ok(uri);
+        }
+    }
+
+    let (read, count) = assembler.progress();
+    Err(eyre!("Got {read} of the {count} frames of the URI"))
+}
+
+fn bytes(hex: &str) -> Result<[u8; 32]> {
+    hex::decode(hex)?
+        .try_into()
+        .map_err(|_| eyre!("Expected 32 bytes in hex, got {hex}"))
+}
+
+fn public_key(hex: &str) -> Result<PublicKey> {
+    Ok(PublicKey(bytes(hex)?))
+}
+
+/// Parses an asset given by id or by the ticker it has at the delegates of
+/// the wallet.
+fn asset_id(wallet: &Wallet, asset: &str) -> Result<Hash> {
+    if let Ok(id) = bytes(asset) {
+        return Ok(Hash(id));
+    }
+
+    let mut ids = wallet
+        .delegates()?
+        .into_iter()
+        .flat_map(|(_, delegate)| delegate.info.assets)
+        .filter(|a| a.ticker.eq_ignore_ascii_case(asset))
+        .map(|a| a.id)
+        .collect::<Vec<_>>();
+    ids.sort();
+    ids.dedup();
+
+    match ids[..] {
+        [id] => Ok(id),
+        [] => Err(eyre!("No delegate of the wallet has an asset {asset}")),
+        _ => Err(eyre!("Ticker {asset} is ambiguous, use the asset id")),
+    }
+}
+
+/// Ticker of an asset at the delegates of the wallet, or its id if unknown.
+fn ticker(wallet: &Wallet, asset_id: Hash) -> Result<String> {
+    Ok(wallet
+        .delegates()?
+        .into_iter()
+        .flat_map(|(_, delegate)| delegate.info.assets)
+        .find(|a| a.id == asset_id)
+        .map_or_else(|| asset_id.to_string(), |a| a.ticker))
+}
+
+fn totals(amounts: impl Iterator<Item = (Hash, u64)>) -> Vec<(Hash, u128)> {
+    let mut totals = Vec::<(Hash, u128)>::new();
+
+    for (asset_id, amount) in amounts {
+        match totals.iter_mut().find(|(a, _)| *a == asset_id) {
+            Some((_, total)) => *total += amount as u128,
+            None => totals.push((asset_id, amount as u128)),
+        }
+    }
+
+    totals
+}
@@ -0,0 +1,10 @@
+use clap::Parser;
+use color_eyre::eyre::Result;
+use mugraph_cli::{run, Cli};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    color_eyre::install()?;
+
+    run(Cli::parse(), &mut std::io::stdout()).await
+}
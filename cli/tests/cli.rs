@@ -0,0 +1,146 @@
+use std::{path::Path, process::Command};
+
+use clap::Parser;
+use mugraph_cli::{run, Cli};
+use mugraph_core::types::PublicKey;
+use mugraph_node::testing::Local;
+
+async fn mugraph(wallet: &Path, args: &[&str]) -> String {
+    let cli = Cli::parse_from(
+        ["mugraph", "--wallet", wallet.to_str().unwrap()]
+            .iter()
+            .chain(args),
+    );
+    let mut out = vec![];
+    run(cli, &mut out).await.unwrap();
+
+    String::from_utf8(out).unwrap()
+}
+
+fn field(output: &str, key: &str) -> String {
+    output
+        .lines()
+        .find_map(|line| line.strip_prefix(&format!("{key}\t")))
+        .unwrap()
+        .to_string()
+}
+
+#[tokio::test]
+async fn test_payment_flow() {
+    let node = Local::shared();
+    let dir = tempfile::tempdir().unwrap();
+    let (alice, bob) = (dir.path().join("alice"), dir.path().join("bob"));
+    let delegate = node.keypair.public_key.to_string();
+
+    let init = mugraph(&alice, &["init"]).await;
+    mugraph(&bob, &["init", "--seed", &"07".repeat(32)]).await;
+    assert_eq!(
+        mugraph(&alice, &["delegates", "add", &node.url]).await,
+        format!("{delegate}\n")
+    );
+    assert_eq!(
+        mugraph(&alice, &["delegates", "list"]).await,
+        format!("{delegate}\t{}\n", node.url)
+    );
+
+    let key = PublicKey(
+        hex::decode(field(&init, "key"))
+            .unwrap()
+            .try_into()
+            .unwrap(),
+    );
+    let deposit = node.deposit(100, key).to_string();
+    assert_eq!(
+        mugraph(&alice, &["mint", &deposit, "test", "60", "40"]).await,
+        "TEST\t100\n"
+    );
+    assert_eq!(
+        mugraph(&alice, &["balance"]).await,
+        format!("{delegate}\tTEST\t100\n")
+    );
+
+    let token = mugraph(&alice, &["send", "30", "TEST"]).await;
+    assert_eq!(
+        mugraph(&bob, &["receive", token.trim()]).await,
+        "TEST\t30\n"
+    );
+
+    let request = mugraph(&bob, &["request", "20", "TEST", "--memo", "invoice 42"]).await;
+    assert!(request.starts_with("MUGRAPH:R"));
+
+    // The token is read back from its frames, in any order.
+    let token = mugraph(&alice, &["pay", "--frames", "120", request.trim()]).await;
+    let mut frames = token.lines().collect::<Vec<_>>();
+    assert!(frames.len() > 1 && frames.iter().all(|f| f.starts_with("MUGRAPH:F")));
+    frames.reverse();
+    assert_eq!(
+        mugraph(&bob, &[&["receive"], &frames[..]].concat()).await,
+        "TEST\t20\n"
+    );
+
+    assert_eq!(
+        mugraph(&alice, &["balance"]).await,
+        format!("{delegate}\tTEST\t50\n")
+    );
+    assert_eq!(
+        mugraph(&bob, &["balance"]).await,
+        format!("{delegate}\tTEST\t50\n")
+    );
+
+    let history = mugraph(&alice, &["history"]).await;
+    let history = history
+        .lines()
+        .map(|line| {
+            let fields = line.split('\t').collect::<Vec<_>>();
+            [&fields[1..4], &fields[5..]].concat().join(" ")
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        history,
+        vec!["mint TEST 100", "send TEST 30", "pay TEST 20 invoice 42"]
+    );
+
+    assert_eq!(
+        mugraph(&alice, &["recover"]).await,
+        "recovered\t0\nspent\t2\n"
+    );
+
+    let request = mugraph(&bob, &["request", "5", "TEST", "--locked"]).await;
+    let token = mugraph(&alice, &["pay", request.trim()]).await;
+    assert_eq!(
+        mugraph(&bob, &["receive", "--offline", token.trim()]).await,
+        "TEST\t5\n"
+    );
+    assert_eq!(
+        mugraph(&bob, &["recover"]).await,
+        "recovered\t1\nspent\t0\n"
+    );
+    assert_eq!(
+        mugraph(&bob, &["balance"]).await,
+        format!("{delegate}\tTEST\t55\n")
+    );
+}
+
+#[test]
+fn test_binary() {
+    let dir = tempfile::tempdir().unwrap();
+    let mugraph = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_mugraph"))
+            .env("MUGRAPH_WALLET", dir.path().join("wallet"))
+            .args(args)
+            .output()
+            .unwrap();
+
+        (
+            output.status.success(),
+            String::from_utf8(output.stdout).unwrap(),
+        )
+    };
+
+    let (ok, init) = mugraph(&["init", "--seed", &"01".repeat(32)]);
+    assert!(ok);
+    assert_eq!(field(&init, "seed"), "01".repeat(32));
+
+    assert_eq!(mugraph(&["balance"]), (true, String::new()));
+    assert!(!mugraph(&["init"]).0);
+}
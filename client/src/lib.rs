@@ -0,0 +1,368 @@
+//! Client for the API of a delegate.
+
+use std::{
+    future::Future,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use mugraph_core::{error::Error, types::*};
+use reqwest::header::CONTENT_TYPE;
+use serde::de::DeserializeOwned;
+use tracing::debug;
+
+mod retry;
+
+pub use retry::*;
+
+/// How long to wait for the delegate before giving up on a request.
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Connection to a delegate through its HTTP API.
+///
+/// Requests failing with a retryable error are sent again following
+/// [`Retry`]. Transactions and mints that were already processed, when an
+/// earlier attempt got through but its response was lost, are answered with
+/// the signatures issued back then, so they are safe to send again.
+#[derive(Debug, Clone)]
+pub struct Client {
+    api: Api,
+    info: Info,
+}
+
+impl Client {
+    /// Connects to the delegate at `url`, fetching its metadata.
+    pub async fn connect(url: impl Into<String>) -> Result<Self, Error> {
+        Self::connect_with(url, Retry::default()).await
+    }
+
+    pub async fn connect_with(url: impl Into<String>, retry: Retry) -> Result<Self, Error> {
+        let http = reqwest::Client::builder()
+            .timeout(TIMEOUT)
+            .build()
+            .map_err(|e| Error::ServerError {
+                reason: e.to_string(),
+            })?;
+        let api = Api {
+            url: url.into().trim_end_matches('/').to_string(),
+            http,
+            retry,
+        };
+
+        // The delegate is not known yet, so its metadata can only be checked
+        // against the key it claims.
+        let info = api.get::<Info>("info", PublicKey::default()).await?;
+        let info = fresh(info.verify(&info.unverified()?.public_key)?, &api.url)?;
+
+        Ok(Self { api, info })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.api.url
+    }
+
+    /// Key the delegate signs notes with.
+    pub fn public_key(&self) -> PublicKey {
+        self.info.public_key
+    }
+
+    /// Metadata of the delegate, as of the connection or the last refresh.
+    pub fn info(&self) -> &Info {
+        &self.info
+    }
+
+    /// Fetches the metadata of the delegate again, failing if it is now
+    /// signed by another key.
+    pub async fn refresh(&mut self) -> Result<&Info, Error> {
+        let info = self.api.get::<Info>("info", self.public_key()).await?;
+        let info = fresh(info.verify(&self.public_key())?, self.url())?;
+
+        if info.public_key != self.public_key() {
+            return Err(Error::InvalidKey {
+                reason: format!(
+                    "Delegate at {} changed its key to {}",
+                    self.url(),
+                    info.public_key
+                ),
+            });
+        }
+
+        self.info = info;
+
+        Ok(&self.info)
+    }
+
+    pub async fn keys(&self) -> Result<Keys, Error> {
+        let keys = self.api.get::<Keys>("keys", self.public_key()).await?;
+
+        fresh(keys.verify(&self.public_key())?, self.url())
+    }
+
+    /// Sends a request to the delegate, retrying it as configured.
+    pub async fn rpc(&self, request: V0Request) -> Result<V0Response, Error> {
+        self.api.rpc(request, self.public_key()).await
+    }
+
+    /// Submits a transaction, returning the blinded signatures of its
+    /// outputs.
+    ///
+    /// A transaction whose inputs are already spent by itself is restored
+    /// instead with `secret`, the one its outputs are derived from, unless it
+    /// has atoms of other delegates, which only sign their own outputs.
+    pub async fn transaction(
+        &self,
+        transaction: &Transaction,
+        secret: &Hash,
+    ) -> Result<Vec<Blinded<OutputSignature>>, Error> {
+        match self.rpc(V0Request::Transaction(transaction.clone())).await {
+            Ok(V0Response::Transaction { outputs }) => Ok(outputs),
+            Ok(response) => Err(unexpected(response)),
+            Err(e @ Error::AlreadySpent { .. })
+                if transaction
+                    .atoms
+                    .iter()
+                    .all(|atom| atom.delegate == self.public_key()) =>
+            {
+                self.restore_or(transaction, secret, e).await
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Mints notes for a deposit, returning the blinded signatures of their
+    /// outputs. A mint that was already processed is restored instead with
+    /// `secret`, the one its outputs are derived from.
+    pub async fn mint(
+        &self,
+        mint: &Mint,
+        secret: &Hash,
+    ) -> Result<Vec<Blinded<OutputSignature>>, Error> {
+        match self.rpc(V0Request::Mint(mint.clone())).await {
+            Ok(V0Response::Mint { outputs }) => Ok(outputs),
+            Ok(response) => Err(unexpected(response)),
+            Err(e @ Error::InvalidTransaction { .. }) => {
+                self.restore_or(&mint.outputs, secret, e).await
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Checks the state of notes, in as many requests as needed.
+    pub async fn check(&self, notes: &[NoteRef]) -> Result<Vec<NoteState>, Error> {
+        let mut states = Vec::with_capacity(notes.len());
+
+        for chunk in notes.chunks(MAX_CHECK) {
+            match self
+                .rpc(V0Request::Check {
+                    notes: chunk.to_vec(),
+                })
+                .await?
+            {
+                V0Response::Check { states: s } => states.extend(s),
+                response => return Err(unexpected(response)),
+            }
+        }
+
+        Ok(states)
+    }
+
+    /// Fetches the blinded signatures of the outputs of this delegate in a
+    /// transaction or mint it already processed, proving they are owned with
+    /// `secret`, the one they are derived from.
+    pub async fn restore(
+        &self,
+        transaction: &Transaction,
+        secret: &Hash,
+    ) -> Result<Vec<Blinded<OutputSignature>>, Error> {
+        match self
+            .rpc(V0Request::Restore {
+                transaction: transaction.clone(),
+                secret: *secret,
+            })
+            .await?
+        {
+            V0Response::Restored { outputs } => Ok(outputs),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    /// Restores the transaction, failing with `error` if the delegate has not
+    /// processed it.
+    async fn restore_or(
+        &self,
+        transaction: &Transaction,
+        secret: &Hash,
+        error: Error,
+    ) -> Result<Vec<Blinded<OutputSignature>>, Error> {
+        match self.restore(transaction, secret).await {
+            Err(Error::NotFound { .. }) => Err(error),
+            result => {
+                debug!(id = %transaction.id(), "Restored already processed transaction");
+                result
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Api {
+    url: String,
+    http: reqwest::Client,
+    retry: Retry,
+}
+
+impl Api {
+    async fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        delegate: PublicKey,
+    ) -> Result<Signed<T>, Error> {
+        let url = &format!("{}/v0/{path}", self.url);
+
+        self.retry(|| async move {
+            let response = self
+                .http
+                .get(url)
+                .send()
+                .await
+                .map_err(|e| peer_error(delegate, e))?;
+
+            if response.status().is_success() {
+                return response
+                    .json::<Signed<T>>()
+                    .await
+                    .map_err(|e| Error::ServerError {
+                        reason: format!("Unexpected response from {url}: {e}"),
+                    });
+            }
+
+            Err(decode(response).await?.err().unwrap_or(Error::ServerError {
+                reason: format!("Unexpected response from {url}"),
+            }))
+        })
+        .await
+    }
+
+    async fn rpc(&self, request: V0Request, delegate: PublicKey) -> Result<V0Response, Error> {
+        let url = &format!("{}/v0/rpc", self.url);
+        let body = &serde_json::to_vec(&Request::from(request))?;
+
+        self.retry(|| async move {
+            let response = self
+                .http
+                .post(url)
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.clone())
+                .send()
+                .await
+                .map_err(|e| peer_error(delegate, e))?;
+
+            decode(response).await?
+        })
+        .await
+    }
+
+    async fn retry<T, F, Fut>(&self, send: F) -> Result<T, Error>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let mut attempt = 1;
+
+        loop {
+            match send().await {
+                Err(e) if e.is_retryable() && attempt < self.retry.attempts => {
+                    let delay = self.retry.delay(attempt);
+                    debug!(url = %self.url, reason = %e, ?delay, "Retrying request");
+
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Decodes the body of a response from the RPC endpoint, which carries an
+/// error whatever the status is.
+async fn decode(response: reqwest::Response) -> Result<Result<V0Response, Error>, Error> {
+    let status = response.status();
+    let url = response.url().to_string();
+
+    match response.json::<Response>().await {
+        Ok(Response::V0(response)) => Ok(Ok(response)),
+        Ok(Response::Error { error, .. }) => Ok(Err(error)),
+        Err(e) => Err(Error::ServerError {
+            reason: format!("Unexpected response from {url} ({status}): {e}"),
+        }),
+    }
+}
+
+/// Checks verified metadata from the delegate at `url`, failing if it is not
+/// valid now, like an old copy replayed by someone else.
+fn fresh<T: Expiring>(payload: T, url: &str) -> Result<T, Error> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+
+    if !payload.is_valid_at(now) {
+        return Err(Error::ServerError {
+            reason: format!(
+                "Metadata from {url} is valid from {} to {}, not at {now}",
+                payload.issued_at(),
+                payload.expires()
+            ),
+        });
+    }
+
+    Ok(payload)
+}
+
+fn peer_error(delegate: PublicKey, error: reqwest::Error) -> Error {
+    Error::PeerError {
+        delegate,
+        reason: error.to_string(),
+    }
+}
+
+fn unexpected(response: V0Response) -> Error {
+    Error::ServerError {
+        reason: format!("Unexpected response: {response:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::thread_rng;
+
+    use super::*;
+
+    #[test]
+    fn test_fresh() {
+        let keypair = Keypair::random(&mut thread_rng());
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let keys = |issued_at, expires| {
+            let keys = Keys {
+                keys: vec![],
+                issued_at,
+                expires,
+            };
+
+            Signed::new(&mut thread_rng(), &keypair.secret_key, &keys)
+                .unwrap()
+                .verify(&keypair.public_key)
+                .unwrap()
+        };
+
+        assert!(fresh(keys(now, now + 600), "url").is_ok());
+
+        // An old copy, still signed by the delegate.
+        assert!(matches!(
+            fresh(keys(now - 3600, now - 3000), "url"),
+            Err(Error::ServerError { .. })
+        ));
+    }
+}
@@ -0,0 +1,54 @@
+use std::time::Duration;
+
+/// How requests that failed with a retryable error are sent again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retry {
+    /// Times a request is sent before giving up, including the first one.
+    pub attempts: u32,
+    /// Delay before the first retry, doubled after each one.
+    pub delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Self {
+            attempts: 5,
+            delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+impl Retry {
+    /// Sends every request only once.
+    pub fn none() -> Self {
+        Self {
+            attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// Delay before sending a request again after `attempt` failures.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay() {
+        let retry = Retry::default();
+
+        assert_eq!(retry.delay(1), Duration::from_millis(100));
+        assert_eq!(retry.delay(2), Duration::from_millis(200));
+        assert_eq!(retry.delay(4), Duration::from_millis(800));
+        assert_eq!(retry.delay(10), Duration::from_secs(5));
+        assert_eq!(retry.delay(u32::MAX), Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,192 @@
+//! Runs the client against the delegate shared by the tests, see
+//! [`Local::shared`].
+
+use std::{net::TcpListener, time::Duration};
+
+use mugraph_client::{Client, Retry};
+use mugraph_core::{builder::TransactionBuilder, error::Error, types::*, utils::BitSet32};
+use mugraph_node::testing::Local;
+use rand::prelude::*;
+
+fn outputs(node: &Local, secret: &Hash, amounts: &[u64]) -> Transaction {
+    Transaction {
+        input_mask: BitSet32::new(),
+        atoms: amounts
+            .iter()
+            .enumerate()
+            .map(|(i, &amount)| Atom {
+                delegate: node.keypair.public_key,
+                asset_id: 0,
+                amount,
+                nonce: Transaction::output_nonce(secret, i),
+                signature: None,
+                lock: None,
+            })
+            .collect(),
+        asset_ids: vec![node.asset_id],
+        signatures: vec![],
+        data: vec![],
+        witnesses: vec![],
+    }
+}
+
+/// Deposits into the vault of the delegate and mints notes for it, along
+/// with the secret their outputs are derived from.
+fn mint(node: &Local, amounts: &[u64]) -> (Mint, Hash) {
+    let owner = Keypair::random(&mut thread_rng());
+    let deposit = node.deposit(amounts.iter().sum(), owner.public_key);
+    let secret = Hash::random(&mut thread_rng());
+    let mint = Mint::new(
+        &mut thread_rng(),
+        &owner.secret_key,
+        deposit,
+        outputs(node, &secret, amounts),
+    );
+
+    (mint, secret)
+}
+
+fn notes(transaction: &Transaction, signatures: &[Blinded<OutputSignature>]) -> Vec<Note> {
+    transaction.output_notes(signatures).unwrap()
+}
+
+/// Secret the outputs of [`spend`] are derived from, so spending the same
+/// notes gives the same transaction again.
+const SECRET: Hash = Hash([7; 32]);
+
+fn spend(notes: &[Note], amounts: &[u64]) -> Transaction {
+    let builder = notes
+        .iter()
+        .fold(TransactionBuilder::new(), |b, n| b.input(n.clone()));
+
+    amounts
+        .iter()
+        .fold(builder, |b, &amount| b.output(notes[0].asset_id, amount))
+        .build(&SECRET)
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_connect() {
+    let node = Local::shared();
+    let client = Client::connect(format!("{}/", node.url)).await.unwrap();
+
+    assert_eq!(client.url(), node.url);
+    assert_eq!(client.public_key(), node.keypair.public_key);
+    assert!(client
+        .info()
+        .assets
+        .iter()
+        .any(|asset| asset.id == node.asset_id));
+    assert_eq!(client.info().min_confirmations, 1);
+    assert_eq!(
+        client.keys().await.unwrap().active(),
+        Some(node.keypair.public_key)
+    );
+}
+
+#[tokio::test]
+async fn test_mint_and_spend() {
+    let node = Local::shared();
+    let client = Client::connect(&node.url).await.unwrap();
+
+    let (mint, secret) = mint(node, &[60, 40]);
+    let signatures = client.mint(&mint, &secret).await.unwrap();
+    let minted = notes(&mint.outputs, &signatures);
+
+    for note in minted.iter() {
+        assert_eq!(note.delegate, node.keypair.public_key);
+        assert!(note.verify().unwrap());
+    }
+
+    let transaction = spend(&minted, &[70, 30]);
+    let signatures = client.transaction(&transaction, &SECRET).await.unwrap();
+    let received = notes(&transaction, &signatures);
+    assert_eq!(received.len(), 2);
+
+    let refs = minted
+        .iter()
+        .chain(received.iter())
+        .map(|note| NoteRef::Commitment(note.commitment()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        client.check(&refs).await.unwrap(),
+        vec![
+            NoteState::Spent,
+            NoteState::Spent,
+            NoteState::Unspent,
+            NoteState::Unspent
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_idempotent_retries() {
+    let node = Local::shared();
+    let client = Client::connect(&node.url).await.unwrap();
+
+    let (mint, secret) = mint(node, &[10, 20]);
+    let signatures = client.mint(&mint, &secret).await.unwrap();
+    assert_eq!(client.mint(&mint, &secret).await.unwrap(), signatures);
+    assert_eq!(
+        client.restore(&mint.outputs, &secret).await.unwrap(),
+        signatures
+    );
+
+    let minted = notes(&mint.outputs, &signatures);
+    let transaction = spend(&minted, &[30]);
+    let signatures = client.transaction(&transaction, &SECRET).await.unwrap();
+    assert_eq!(
+        client.transaction(&transaction, &SECRET).await.unwrap(),
+        signatures
+    );
+
+    // Only the owner of the outputs can restore them.
+    assert!(matches!(
+        client.transaction(&transaction, &secret).await,
+        Err(Error::InvalidTransaction { .. })
+    ));
+
+    // Spending the same notes in another transaction is still a double spend.
+    assert!(matches!(
+        client
+            .transaction(&spend(&minted, &[15, 15]), &SECRET)
+            .await,
+        Err(Error::AlreadySpent { .. })
+    ));
+    assert!(matches!(
+        client.restore(&spend(&minted, &[15, 15]), &SECRET).await,
+        Err(Error::NotFound { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_errors() {
+    let node = Local::shared();
+    let client = Client::connect(&node.url).await.unwrap();
+
+    let (mut mint, secret) = mint(node, &[10]);
+    mint.outputs.atoms[0].amount = 20;
+
+    assert!(matches!(
+        client.mint(&mint, &secret).await,
+        Err(Error::InvalidTransaction { .. })
+    ));
+
+    let closed = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let retry = Retry {
+        attempts: 2,
+        delay: Duration::from_millis(1),
+        ..Retry::default()
+    };
+
+    let error = Client::connect_with(format!("http://{closed}"), retry)
+        .await
+        .unwrap_err();
+    assert!(matches!(error, Error::PeerError { .. }));
+    assert!(error.is_retryable());
+}
@@ -1,42 +1,205 @@
-use indexmap::IndexSet;
+use indexmap::{IndexMap, IndexSet};
 
 use crate::{
     error::{Error, Result},
-    types::{Atom, Hash, Note, Transaction},
+    types::{Atom, FeeSchedule, Hash, Note, PublicKey, Transaction, MAX_INPUTS},
     utils::BitSet32,
 };
 
+mod payment;
+mod selection;
+
+pub use self::{payment::*, selection::*};
+
+/// Selection is repeated with a higher target while the fee of the notes
+/// picked is not covered. This bounds it in case it never is.
+const MAX_SELECTION_ROUNDS: usize = 8;
+
 #[derive(Default)]
 pub struct TransactionBuilder {
     pub inputs: Vec<Note>,
     pre_balances: Vec<u128>,
     post_balances: Vec<u128>,
     assets: IndexSet<Hash>,
-    outputs: Vec<(u32, u64)>,
+    /// Delegate, asset index and amount of each output, at the delegate of
+    /// the first input when not given.
+    outputs: Vec<(Option<PublicKey>, u32, u64)>,
+    fees: FeeSchedule,
+    data: Vec<u8>,
 }
 
 impl TransactionBuilder {
     pub fn new() -> Self {
-        Self {
-            pre_balances: vec![0; 8],
-            post_balances: vec![0; 8],
-            ..Default::default()
+        Self::default()
+    }
+
+    /// Index of the asset in the transaction, adding it if it is new.
+    fn asset_index(&mut self, asset_id: Hash) -> usize {
+        let (i, added) = self.assets.insert_full(asset_id);
+
+        if added {
+            self.pre_balances.push(0);
+            self.post_balances.push(0);
         }
+
+        i
     }
 
     pub fn input(mut self, note: Note) -> Self {
-        match self.assets.get_index_of(&note.asset_id) {
-            Some(i) => {
-                self.pre_balances[i] += note.amount as u128;
+        let i = self.asset_index(note.asset_id);
+        self.pre_balances[i] += note.amount as u128;
+        self.inputs.push(note);
+
+        self
+    }
+
+    /// Builds the transaction so it pays the fees in `fees`, instead of
+    /// spending exactly its inputs.
+    pub fn fees(mut self, fees: FeeSchedule) -> Self {
+        self.fees = fees;
+        self
+    }
+
+    /// Fee owed for the inputs of `asset_id` added so far.
+    pub fn fee(&self, asset_id: Hash) -> u128 {
+        let (count, total) = self
+            .inputs
+            .iter()
+            .filter(|note| note.asset_id == asset_id)
+            .fold((0, 0), |(count, total), note| {
+                (count + 1, total + note.amount as u128)
+            });
+
+        self.fees.get(&asset_id).amount(count, total)
+    }
+
+    /// Amount of `asset_id` still to be assigned to outputs once the fee is
+    /// paid, which is what a change output should hold.
+    pub fn remaining(&self, asset_id: Hash) -> u128 {
+        match self.assets.get_index_of(&asset_id) {
+            Some(i) => self.pre_balances[i]
+                .saturating_sub(self.post_balances[i])
+                .saturating_sub(self.fee(asset_id)),
+            None => 0,
+        }
+    }
+
+    /// Starts paying `recipients` out of `pool`, over as many transactions as
+    /// needed. See [`Payment`].
+    pub fn pay(pool: Vec<Note>, recipients: &[(Hash, u64)]) -> Payment {
+        Payment::new(pool, recipients)
+    }
+
+    /// Picks inputs from `pool` with `strategy` to pay for `payment`, adding
+    /// an output for every payment and a change output for every asset with
+    /// some left over.
+    ///
+    /// Notes already added as inputs are never picked again, and the inputs
+    /// picked count towards [`MAX_INPUTS`] along with them.
+    pub fn select(
+        mut self,
+        pool: &[Note],
+        payment: &[(Hash, u64)],
+        strategy: &mut impl SelectionStrategy,
+    ) -> Result<Self> {
+        let mut targets = IndexMap::<Hash, u128>::new();
+
+        for (asset_id, amount) in payment {
+            *targets.entry(*asset_id).or_default() += *amount as u128;
+        }
+
+        for (&asset_id, &amount) in targets.iter() {
+            let candidates = pool
+                .iter()
+                .filter(|note| note.asset_id == asset_id && !self.inputs.contains(note))
+                .cloned()
+                .collect::<Vec<_>>();
+            let max = MAX_INPUTS.saturating_sub(self.inputs.len());
+
+            let selected = self.select_asset(&candidates, asset_id, amount, max, strategy)?;
+
+            for i in selected {
+                self = self.input(candidates[i].clone());
             }
-            None => {
-                self.pre_balances[self.assets.len()] += note.amount as u128;
-                self.assets.insert(note.asset_id);
+        }
+
+        for (asset_id, amount) in payment {
+            self = self.output(*asset_id, *amount);
+        }
+
+        for &asset_id in targets.keys() {
+            let change = self.remaining(asset_id);
+
+            if change > 0 {
+                let change = u64::try_from(change).map_err(|_| Error::InvalidTransaction {
+                    reason: format!("Change for {asset_id} does not fit in a single output"),
+                })?;
+
+                self = self.output(asset_id, change);
             }
         }
 
-        self.inputs.push(note);
+        Ok(self)
+    }
+
+    /// Picks notes of one asset from `candidates` covering `amount` on top of
+    /// the outputs already added, plus the fee for all of its inputs.
+    fn select_asset(
+        &self,
+        candidates: &[Note],
+        asset_id: Hash,
+        amount: u128,
+        max: usize,
+        strategy: &mut impl SelectionStrategy,
+    ) -> Result<Vec<usize>> {
+        let (pre, post) = match self.assets.get_index_of(&asset_id) {
+            Some(i) => (self.pre_balances[i], self.post_balances[i]),
+            None => (0, 0),
+        };
+        let inputs = self
+            .inputs
+            .iter()
+            .filter(|note| note.asset_id == asset_id)
+            .count() as u32;
+        let fee = self.fees.get(&asset_id);
+
+        let insufficient = || Error::InsufficientFunds {
+            asset_id,
+            expected: amount.min(u64::MAX as u128) as u64,
+            got: candidates
+                .iter()
+                .map(|note| note.amount as u128)
+                .sum::<u128>()
+                .saturating_add(pre.saturating_sub(post))
+                .min(u64::MAX as u128) as u64,
+        };
+
+        let mut target = (amount + post).saturating_sub(pre);
+
+        for _ in 0..MAX_SELECTION_ROUNDS {
+            let selected = strategy
+                .select(candidates, target, max)
+                .ok_or_else(insufficient)?;
+            let total = pre
+                + selected
+                    .iter()
+                    .map(|&i| candidates[i].amount as u128)
+                    .sum::<u128>();
+            let required = amount + post + fee.amount(inputs + selected.len() as u32, total);
+
+            if total >= required {
+                return Ok(selected);
+            }
+
+            target = required - pre;
+        }
 
+        Err(insufficient())
+    }
+
+    /// Attaches opaque data to the transaction, see [`Transaction::data`].
+    pub fn data(mut self, data: Vec<u8>) -> Self {
+        self.data = data;
         self
     }
 
@@ -44,17 +207,21 @@ impl TransactionBuilder {
         self.inputs.len()
     }
 
-    pub fn output(mut self, asset_id: Hash, amount: u64) -> Self {
-        match self.assets.get_index_of(&asset_id) {
-            Some(i) => {
-                self.post_balances[i] += amount as u128;
-                self.outputs.push((i as u32, amount));
-            }
-            None => {
-                self.post_balances[self.assets.len()] += amount as u128;
-                self.assets.insert(asset_id);
-            }
-        }
+    /// Adds an output at the delegate of the first input.
+    pub fn output(self, asset_id: Hash, amount: u64) -> Self {
+        self.push_output(None, asset_id, amount)
+    }
+
+    /// Adds an output at `delegate`, which makes the transaction
+    /// cross-delegate when it is not the delegate of the inputs.
+    pub fn output_to(self, delegate: PublicKey, asset_id: Hash, amount: u64) -> Self {
+        self.push_output(Some(delegate), asset_id, amount)
+    }
+
+    fn push_output(mut self, delegate: Option<PublicKey>, asset_id: Hash, amount: u64) -> Self {
+        let i = self.asset_index(asset_id);
+        self.post_balances[i] += amount as u128;
+        self.outputs.push((delegate, i as u32, amount));
 
         self
     }
@@ -63,11 +230,20 @@ impl TransactionBuilder {
         self.outputs.len()
     }
 
-    pub fn build(self) -> Result<Transaction> {
+    /// Builds the transaction, with the nonces of its outputs derived from
+    /// `secret`, see [`Transaction::output_nonce`].
+    pub fn build(self, secret: &Hash) -> Result<Transaction> {
         let mut atoms = Vec::new();
         let mut signatures = Vec::new();
         let mut input_mask = BitSet32::new();
-        let delegate = self.inputs[0].delegate;
+        let delegate = match self.inputs.first() {
+            Some(note) => note.delegate,
+            None => {
+                return Err(Error::InvalidTransaction {
+                    reason: "Transaction has no inputs".to_string(),
+                })
+            }
+        };
 
         for (index, note) in self.inputs.into_iter().enumerate() {
             input_mask.insert(index as u32);
@@ -87,18 +263,20 @@ impl TransactionBuilder {
                 amount: note.amount,
                 nonce: note.nonce,
                 signature: Some(signatures.len() as u32),
+                lock: note.lock,
             });
 
             signatures.push(note.signature);
         }
 
-        for (asset_id, amount) in self.outputs.into_iter() {
+        for (index, (to, asset_id, amount)) in self.outputs.into_iter().enumerate() {
             atoms.push(Atom {
-                delegate,
+                delegate: to.unwrap_or(delegate),
                 asset_id,
                 amount,
-                nonce: Hash::zero(), // TODO: Generate a nonce for outputs
+                nonce: Transaction::output_nonce(secret, index),
                 signature: None,
+                lock: None,
             });
         }
 
@@ -107,10 +285,243 @@ impl TransactionBuilder {
             atoms,
             asset_ids: self.assets.into_iter().collect(),
             signatures,
+            data: self.data,
+            witnesses: vec![],
         };
 
-        transaction.verify()?;
+        transaction.verify_with_fees(&self.fees)?;
 
         Ok(transaction)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Fee, Keypair, MAX_ATOMS};
+
+    #[test]
+    fn test_fees() {
+        let asset_id = Hash::digest(b"asset");
+        let fees = FeeSchedule {
+            default: Fee {
+                flat: 1,
+                per_input: 2,
+                basis_points: 0,
+            },
+            ..Default::default()
+        };
+        let note = |nonce: &[u8]| Note {
+            amount: 100,
+            asset_id,
+            nonce: Hash::digest(nonce),
+            ..Default::default()
+        };
+
+        let builder = TransactionBuilder::new()
+            .fees(fees.clone())
+            .input(note(b"a"))
+            .input(note(b"b"))
+            .output(asset_id, 50);
+
+        assert_eq!(builder.fee(asset_id), 5);
+        assert_eq!(builder.remaining(asset_id), 145);
+
+        let change = builder.remaining(asset_id) as u64;
+        let transaction = builder.output(asset_id, change).build(&SECRET).unwrap();
+
+        assert_eq!(
+            transaction.verify_with_fees(&fees).unwrap(),
+            vec![(asset_id, 5)]
+        );
+        assert!(TransactionBuilder::new()
+            .fees(fees)
+            .input(note(b"a"))
+            .output(asset_id, 100)
+            .build(&SECRET)
+            .is_err());
+    }
+
+    pub(super) const SECRET: Hash = Hash([7; 32]);
+
+    #[test]
+    fn test_output_nonces() {
+        let asset_id = Hash::digest(b"asset");
+        let builder = || {
+            TransactionBuilder::new()
+                .input(pool(asset_id, &[100])[0].clone())
+                .output(asset_id, 60)
+                .output(asset_id, 40)
+        };
+        let nonces = |transaction: Transaction| {
+            transaction.atoms[1..]
+                .iter()
+                .map(|atom| atom.nonce)
+                .collect::<Vec<_>>()
+        };
+
+        // Knowing the inputs is not enough to predict the outputs.
+        let other = Hash::digest(b"other");
+        assert_ne!(
+            nonces(builder().build(&SECRET).unwrap()),
+            nonces(builder().build(&other).unwrap())
+        );
+        assert_eq!(
+            nonces(builder().build(&SECRET).unwrap()),
+            [0, 1].map(|i| Transaction::output_nonce(&SECRET, i))
+        );
+    }
+
+    #[test]
+    fn test_output_to() {
+        let asset_id = Hash::digest(b"asset");
+        let inputs = pool(asset_id, &[100]);
+        let other = Keypair::random(&mut rand::thread_rng()).public_key;
+        let transaction = TransactionBuilder::new()
+            .input(inputs[0].clone())
+            .output_to(other, asset_id, 60)
+            .output(asset_id, 40)
+            .build(&SECRET)
+            .unwrap();
+
+        assert_eq!(
+            transaction
+                .atoms
+                .iter()
+                .map(|atom| atom.delegate)
+                .collect::<Vec<_>>(),
+            [inputs[0].delegate, other, inputs[0].delegate]
+        );
+    }
+
+    pub(super) fn pool(asset_id: Hash, amounts: &[u64]) -> Vec<Note> {
+        amounts
+            .iter()
+            .enumerate()
+            .map(|(i, &amount)| Note {
+                amount,
+                asset_id,
+                nonce: Hash::digest(&[asset_id.as_ref(), &i.to_le_bytes()[..]].concat()),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn outputs(transaction: &Transaction) -> Vec<(Hash, u64)> {
+        transaction
+            .atoms
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| transaction.is_output(*i))
+            .map(|(_, atom)| (transaction.asset_ids[atom.asset_id as usize], atom.amount))
+            .collect()
+    }
+
+    #[test]
+    fn test_output_before_input() {
+        let asset_id = Hash::digest(b"asset");
+        let transaction = TransactionBuilder::new()
+            .output(asset_id, 60)
+            .input(pool(asset_id, &[100])[0].clone())
+            .output(asset_id, 40)
+            .build(&SECRET)
+            .unwrap();
+
+        assert_eq!(outputs(&transaction), [(asset_id, 60), (asset_id, 40)]);
+    }
+
+    #[test]
+    fn test_many_assets() {
+        let assets = (0..MAX_ATOMS as u32)
+            .map(|i| Hash::digest(&i.to_le_bytes()))
+            .collect::<Vec<_>>();
+        let builder = assets
+            .iter()
+            .fold(TransactionBuilder::new(), |builder, &asset_id| {
+                builder.output(asset_id, 1)
+            });
+
+        assert_eq!(builder.output_count(), assets.len());
+        assert!(matches!(
+            builder
+                .input(pool(assets[0], &[1])[0].clone())
+                .build(&SECRET),
+            Err(Error::UnbalancedTransaction { .. })
+        ));
+    }
+
+    #[test]
+    fn test_select() {
+        let (a, b) = (Hash::digest(b"a"), Hash::digest(b"b"));
+        let pool = [pool(a, &[100, 50, 20, 5]), pool(b, &[30])].concat();
+
+        let transaction = TransactionBuilder::new()
+            .select(&pool, &[(a, 60), (b, 10)], &mut LargestFirst)
+            .unwrap()
+            .build(&SECRET)
+            .unwrap();
+
+        assert_eq!(transaction.input_mask.count_ones(), 2);
+        assert_eq!(outputs(&transaction), [(a, 60), (b, 10), (a, 40), (b, 20)]);
+    }
+
+    #[test]
+    fn test_select_with_fees() {
+        let asset_id = Hash::digest(b"asset");
+        let pool = pool(asset_id, &[50, 50]);
+        let fees = FeeSchedule {
+            default: Fee {
+                per_input: 10,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let select = |amount| {
+            TransactionBuilder::new()
+                .fees(fees.clone())
+                .select(&pool, &[(asset_id, amount)], &mut LargestFirst)
+                .and_then(|builder| builder.build(&SECRET))
+        };
+
+        // One note covers the payment and its fee exactly.
+        assert_eq!(outputs(&select(40).unwrap()), [(asset_id, 40)]);
+        // The fee of the first note is not covered, so a second one is
+        // needed, which has a fee of its own.
+        assert_eq!(
+            outputs(&select(45).unwrap()),
+            [(asset_id, 45), (asset_id, 35)]
+        );
+        assert!(matches!(select(81), Err(Error::InsufficientFunds { .. })));
+    }
+
+    #[test]
+    fn test_select_nothing() {
+        let asset_id = Hash::digest(b"asset");
+
+        assert!(matches!(
+            TransactionBuilder::new()
+                .select(&pool(asset_id, &[10]), &[], &mut LargestFirst)
+                .unwrap()
+                .build(&SECRET),
+            Err(Error::InvalidTransaction { .. })
+        ));
+    }
+
+    #[test]
+    fn test_select_max_inputs() {
+        let asset_id = Hash::digest(b"asset");
+        let pool = pool(asset_id, &[1; 10]);
+
+        assert!(TransactionBuilder::new()
+            .select(&pool, &[(asset_id, MAX_INPUTS as u64)], &mut FewestNotes)
+            .is_ok());
+        assert!(matches!(
+            TransactionBuilder::new().select(
+                &pool,
+                &[(asset_id, MAX_INPUTS as u64 + 1)],
+                &mut FewestNotes
+            ),
+            Err(Error::InsufficientFunds { .. })
+        ));
+    }
+}
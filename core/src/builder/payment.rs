@@ -0,0 +1,381 @@
+use std::collections::VecDeque;
+
+use super::{SelectionStrategy, TransactionBuilder};
+use crate::{
+    error::{Error, Result},
+    types::{
+        Blinded, FeeSchedule, Hash, Note, OutputSignature, Transaction, MAX_ATOMS, MAX_INPUTS,
+        MAX_OUTPUTS,
+    },
+};
+
+/// Pays several recipients out of a pool of notes, splitting the work into
+/// as many transactions as [`MAX_INPUTS`] and [`MAX_OUTPUTS`] require.
+///
+/// Transactions are chained: each one may spend the change of the previous
+/// one, which only exists once the delegate signs it. So every transaction
+/// from [`Payment::next`] must be submitted and its signatures passed to
+/// [`Payment::settle`] before the next one is built.
+///
+/// When no single transaction can cover a recipient, because the notes of
+/// its asset are too small to fit in [`MAX_INPUTS`], the next transaction
+/// pays nobody and merges the largest notes of that asset into one instead.
+pub struct Payment {
+    pool: Vec<Note>,
+    pending: VecDeque<(Hash, u64)>,
+    fees: FeeSchedule,
+    /// Transaction waiting to be settled, along with how many of its outputs
+    /// pay recipients. The rest is change.
+    in_flight: Option<(Transaction, usize)>,
+}
+
+impl Payment {
+    pub fn new(pool: Vec<Note>, recipients: &[(Hash, u64)]) -> Self {
+        Self {
+            pool,
+            pending: recipients.iter().copied().collect(),
+            fees: FeeSchedule::default(),
+            in_flight: None,
+        }
+    }
+
+    /// Builds every transaction so it pays the fees in `fees`.
+    pub fn fees(mut self, fees: FeeSchedule) -> Self {
+        self.fees = fees;
+        self
+    }
+
+    /// Whether every recipient was paid and settled.
+    pub fn is_done(&self) -> bool {
+        self.pending.is_empty() && self.in_flight.is_none()
+    }
+
+    /// Notes still available, which is what is left to the sender once the
+    /// payment is done.
+    pub fn pool(&self) -> &[Note] {
+        &self.pool
+    }
+
+    /// Builds the next transaction, paying as many of the remaining
+    /// recipients as fit in it, in order. Returns `None` once they are all
+    /// paid.
+    ///
+    /// Its outputs are derived from `secret`, which must be new for every
+    /// transaction, see [`Transaction::output_nonce`].
+    pub fn next(
+        &mut self,
+        strategy: &mut impl SelectionStrategy,
+        secret: &Hash,
+    ) -> Result<Option<Transaction>> {
+        if self.in_flight.is_some() {
+            return Err(Error::InvalidTransaction {
+                reason: "Previous transaction of the payment was not settled".to_string(),
+            });
+        }
+
+        if self.pending.is_empty() {
+            return Ok(None);
+        }
+
+        let mut error = None;
+
+        for count in (1..=self.pending.len().min(MAX_OUTPUTS)).rev() {
+            let recipients = self.pending.range(..count).copied().collect::<Vec<_>>();
+
+            let builder = match TransactionBuilder::new().fees(self.fees.clone()).select(
+                &self.pool,
+                &recipients,
+                strategy,
+            ) {
+                Ok(builder) => builder,
+                Err(e @ Error::InsufficientFunds { .. }) => {
+                    error.get_or_insert(e);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+
+            if builder.output_count() > MAX_OUTPUTS
+                || builder.input_count() + builder.output_count() > MAX_ATOMS
+            {
+                continue;
+            }
+
+            let transaction = self.send(builder, count, secret)?;
+            self.pending.drain(..count);
+
+            return Ok(Some(transaction));
+        }
+
+        let (asset_id, amount) = self.pending[0];
+        let available = self
+            .pool
+            .iter()
+            .filter(|note| note.asset_id == asset_id)
+            .map(|note| note.amount as u128)
+            .sum::<u128>();
+
+        match self.merge(asset_id) {
+            Some(builder) if available >= amount as u128 => self.send(builder, 0, secret).map(Some),
+            _ => Err(error.unwrap_or(Error::InsufficientFunds {
+                asset_id,
+                expected: amount,
+                got: available.min(u64::MAX as u128) as u64,
+            })),
+        }
+    }
+
+    /// Records the signatures the delegate returned for the last transaction,
+    /// returning the notes paying its recipients. Its change goes back to the
+    /// pool for the next transactions.
+    pub fn settle(&mut self, signatures: &[Blinded<OutputSignature>]) -> Result<Vec<Note>> {
+        let (transaction, paid) =
+            self.in_flight
+                .as_ref()
+                .ok_or_else(|| Error::InvalidTransaction {
+                    reason: "No transaction of the payment is waiting to be settled".to_string(),
+                })?;
+
+        let mut notes = transaction.output_notes(signatures)?;
+        self.pool.extend(notes.split_off(*paid));
+        self.in_flight = None;
+
+        Ok(notes)
+    }
+
+    /// Merges the largest notes of `asset_id` into a single one, if there is
+    /// more than one to merge and anything is left once the fee is paid.
+    fn merge(&self, asset_id: Hash) -> Option<TransactionBuilder> {
+        let mut notes = self
+            .pool
+            .iter()
+            .filter(|note| note.asset_id == asset_id)
+            .collect::<Vec<_>>();
+        notes.sort_by_key(|note| std::cmp::Reverse(note.amount));
+
+        let mut builder = TransactionBuilder::new().fees(self.fees.clone());
+        let mut total = 0u64;
+
+        for note in notes.into_iter().take(MAX_INPUTS) {
+            match total.checked_add(note.amount) {
+                Some(sum) => total = sum,
+                None => break,
+            }
+
+            builder = builder.input(note.clone());
+        }
+
+        let change = builder.remaining(asset_id) as u64;
+
+        match builder.input_count() > 1 && change > 0 {
+            true => Some(builder.output(asset_id, change)),
+            false => None,
+        }
+    }
+
+    fn send(
+        &mut self,
+        builder: TransactionBuilder,
+        paid: usize,
+        secret: &Hash,
+    ) -> Result<Transaction> {
+        let spent = builder.inputs.clone();
+        let transaction = builder.build(secret)?;
+
+        self.pool.retain(|note| !spent.contains(note));
+        self.in_flight = Some((transaction.clone(), paid));
+
+        Ok(transaction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        builder::{tests::pool, LargestFirst},
+        types::{Fee, Keypair},
+    };
+
+    fn delegate(seed: u64) -> Keypair {
+        Keypair::random(&mut StdRng::seed_from_u64(seed))
+    }
+
+    /// A new secret for the outputs of each transaction.
+    fn secret() -> Hash {
+        Hash::random(&mut rand::thread_rng())
+    }
+
+    /// Signs the outputs of `transaction` the way a delegate would.
+    fn sign(keypair: &Keypair, transaction: &Transaction) -> Vec<Blinded<OutputSignature>> {
+        transaction
+            .atoms
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| transaction.is_output(*i))
+            .map(|(_, atom)| {
+                let commitment = atom.commitment(&transaction.asset_ids);
+                Blinded(OutputSignature::new(
+                    &keypair.secret_key,
+                    commitment.as_ref(),
+                ))
+            })
+            .collect()
+    }
+
+    /// Runs `payment` to completion, returning how many transactions it took
+    /// and the notes paying the recipients.
+    fn run(keypair: &Keypair, mut payment: Payment) -> Result<(usize, Vec<Note>)> {
+        let mut count = 0;
+        let mut paid = Vec::new();
+
+        while let Some(transaction) = payment.next(&mut LargestFirst, &secret())? {
+            assert!(transaction.input_mask.count_ones() as usize <= MAX_INPUTS);
+            assert!(transaction.atoms.len() - transaction.signatures.len() <= MAX_OUTPUTS);
+
+            paid.extend(payment.settle(&sign(keypair, &transaction))?);
+            count += 1;
+        }
+
+        assert!(payment.is_done());
+
+        Ok((count, paid))
+    }
+
+    fn signed_pool(keypair: &Keypair, asset_id: Hash, amounts: &[u64]) -> Vec<Note> {
+        pool(asset_id, amounts)
+            .into_iter()
+            .map(|mut note| {
+                note.delegate = keypair.public_key;
+                note
+            })
+            .collect()
+    }
+
+    fn amounts(notes: &[Note]) -> Vec<(Hash, u64)> {
+        notes
+            .iter()
+            .map(|note| (note.asset_id, note.amount))
+            .collect()
+    }
+
+    #[test]
+    fn test_pay_single_transaction() {
+        let keypair = delegate(1);
+        let (a, b) = (Hash::digest(b"a"), Hash::digest(b"b"));
+        let pool = [
+            signed_pool(&keypair, a, &[100]),
+            signed_pool(&keypair, b, &[100]),
+        ]
+        .concat();
+        let recipients = [(a, 10), (b, 20), (a, 30)];
+
+        let payment = TransactionBuilder::pay(pool, &recipients);
+        let (count, paid) = run(&keypair, payment).unwrap();
+
+        assert_eq!(count, 1);
+        assert_eq!(amounts(&paid), recipients);
+    }
+
+    #[test]
+    fn test_pay_chained() {
+        let keypair = delegate(1);
+        let asset_id = Hash::digest(b"asset");
+        let pool = signed_pool(&keypair, asset_id, &[1_000]);
+        let recipients = (1..=20).map(|i| (asset_id, i)).collect::<Vec<_>>();
+
+        let mut payment = Payment::new(pool, &recipients);
+        let transaction = payment.next(&mut LargestFirst, &secret()).unwrap().unwrap();
+
+        // The change of the first transaction funds the next one, so it must
+        // be settled first.
+        assert!(payment.next(&mut LargestFirst, &secret()).is_err());
+        payment.settle(&sign(&keypair, &transaction)).unwrap();
+
+        let (count, paid) = run(&keypair, payment).unwrap();
+
+        // Seven recipients and the change fit in each transaction.
+        assert_eq!(count, 2);
+        assert_eq!(
+            [transaction.atoms.len() - 1, paid.len()],
+            [MAX_OUTPUTS, recipients.len() - 7]
+        );
+        assert_eq!(amounts(&paid), recipients[7..]);
+    }
+
+    #[test]
+    fn test_pay_merges_small_notes() {
+        let keypair = delegate(1);
+        let asset_id = Hash::digest(b"asset");
+        let pool = signed_pool(&keypair, asset_id, &[10; 10]);
+
+        let payment = Payment::new(pool, &[(asset_id, 80)]);
+        let (count, paid) = run(&keypair, payment).unwrap();
+
+        // Two merges of four notes each, then the payment out of them.
+        assert_eq!(count, 3);
+        assert_eq!(amounts(&paid), [(asset_id, 80)]);
+    }
+
+    #[test]
+    fn test_pay_with_fees() {
+        let keypair = delegate(1);
+        let asset_id = Hash::digest(b"asset");
+        let pool = signed_pool(&keypair, asset_id, &[100; 3]);
+        let fees = FeeSchedule {
+            default: Fee {
+                per_input: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let recipients = (0..10).map(|_| (asset_id, 25)).collect::<Vec<_>>();
+
+        let mut payment = Payment::new(pool, &recipients).fees(fees.clone());
+        let mut paid = Vec::new();
+
+        while let Some(transaction) = payment.next(&mut LargestFirst, &secret()).unwrap() {
+            transaction.verify_with_fees(&fees).unwrap();
+            paid.extend(payment.settle(&sign(&keypair, &transaction)).unwrap());
+        }
+
+        assert_eq!(amounts(&paid), recipients);
+        assert!(payment.pool().iter().map(|note| note.amount).sum::<u64>() < 50);
+    }
+
+    #[test]
+    fn test_pay_insufficient_funds() {
+        let keypair = delegate(1);
+        let asset_id = Hash::digest(b"asset");
+        let pool = signed_pool(&keypair, asset_id, &[10; 10]);
+
+        assert!(matches!(
+            run(&keypair, Payment::new(pool, &[(asset_id, 101)])),
+            Err(Error::InsufficientFunds { .. })
+        ));
+    }
+
+    #[test]
+    fn test_settle_checks_signatures() {
+        let keypair = delegate(1);
+        let asset_id = Hash::digest(b"asset");
+        let pool = signed_pool(&keypair, asset_id, &[100]);
+
+        let mut payment = Payment::new(pool, &[(asset_id, 10)]);
+        let transaction = payment.next(&mut LargestFirst, &secret()).unwrap().unwrap();
+        let other = delegate(2);
+
+        assert!(matches!(
+            payment.settle(&sign(&other, &transaction)),
+            Err(Error::InvalidSignature { .. })
+        ));
+        assert!(payment.settle(&sign(&keypair, &transaction)[1..]).is_err());
+        assert_eq!(
+            payment.settle(&sign(&keypair, &transaction)).unwrap().len(),
+            1
+        );
+        assert!(payment.is_done());
+    }
+}
@@ -0,0 +1,206 @@
+//! Strategies for picking which notes pay for a transaction.
+
+use rand::prelude::*;
+
+use crate::types::Note;
+
+/// Picks notes of a single asset adding up to at least a target amount.
+pub trait SelectionStrategy {
+    /// Returns the indexes in `notes` of at most `max` notes adding up to at
+    /// least `target`, or `None` if there is no such selection.
+    fn select(&mut self, notes: &[Note], target: u128, max: usize) -> Option<Vec<usize>>;
+}
+
+/// Spends the largest notes first, keeping the number of notes held low.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LargestFirst;
+
+impl SelectionStrategy for LargestFirst {
+    fn select(&mut self, notes: &[Note], target: u128, max: usize) -> Option<Vec<usize>> {
+        let mut indexes = (0..notes.len()).collect::<Vec<_>>();
+        indexes.sort_by_key(|&i| std::cmp::Reverse(notes[i].amount));
+
+        let mut total = 0u128;
+        let mut selected = vec![];
+
+        for i in indexes.into_iter().take(max) {
+            if total >= target {
+                break;
+            }
+
+            total += notes[i].amount as u128;
+            selected.push(i);
+        }
+
+        (total >= target).then_some(selected)
+    }
+}
+
+/// Spends as few notes as possible, and among those the selection leaving
+/// the least change.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FewestNotes;
+
+impl SelectionStrategy for FewestNotes {
+    fn select(&mut self, notes: &[Note], target: u128, max: usize) -> Option<Vec<usize>> {
+        let mut selected = LargestFirst.select(notes, target, max)?;
+        let mut total = selected
+            .iter()
+            .map(|&i| notes[i].amount as u128)
+            .sum::<u128>();
+
+        // Swap each note, smallest first, for the smallest one that still
+        // covers the target.
+        selected.sort_by_key(|&i| notes[i].amount);
+
+        for slot in 0..selected.len() {
+            let current = notes[selected[slot]].amount as u128;
+            let replacement = (0..notes.len())
+                .filter(|i| !selected.contains(i))
+                .filter(|&i| total - current + notes[i].amount as u128 >= target)
+                .min_by_key(|&i| notes[i].amount);
+
+            if let Some(i) = replacement.filter(|&i| (notes[i].amount as u128) < current) {
+                total = total - current + notes[i].amount as u128;
+                selected[slot] = i;
+            }
+        }
+
+        Some(selected)
+    }
+}
+
+/// Random-Improve, as described in CIP-2.
+///
+/// Notes are picked at random, so the selection says nothing about the other
+/// notes held, and more are added while that brings the change closer to the
+/// target, so payments and change have similar amounts and can not be told
+/// apart.
+#[derive(Debug, Clone)]
+pub struct RandomImprove<R> {
+    rng: R,
+}
+
+impl<R: Rng> RandomImprove<R> {
+    pub fn new(rng: R) -> Self {
+        Self { rng }
+    }
+}
+
+impl<R: Rng> SelectionStrategy for RandomImprove<R> {
+    fn select(&mut self, notes: &[Note], target: u128, max: usize) -> Option<Vec<usize>> {
+        let mut available = (0..notes.len()).collect::<Vec<_>>();
+        available.shuffle(&mut self.rng);
+
+        let mut selected = vec![];
+        let mut total = 0u128;
+
+        while total < target {
+            if selected.len() == max {
+                // Random picks ran out of room, which does not mean there is
+                // no selection covering the target.
+                return LargestFirst.select(notes, target, max);
+            }
+
+            let i = available.pop()?;
+            total += notes[i].amount as u128;
+            selected.push(i);
+        }
+
+        let ideal = target * 2;
+        let limit = target * 3;
+
+        while selected.len() < max {
+            let Some(i) = available.pop() else {
+                break;
+            };
+            let next = total + notes[i].amount as u128;
+
+            if next > limit || next.abs_diff(ideal) >= total.abs_diff(ideal) {
+                break;
+            }
+
+            total = next;
+            selected.push(i);
+        }
+
+        Some(selected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use rand::rngs::StdRng;
+    use test_strategy::proptest;
+
+    use super::*;
+    use crate::testing::rng;
+
+    fn notes(amounts: &[u64]) -> Vec<Note> {
+        amounts
+            .iter()
+            .map(|&amount| Note {
+                amount,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn total(notes: &[Note], selected: &[usize]) -> u128 {
+        selected.iter().map(|&i| notes[i].amount as u128).sum()
+    }
+
+    #[test]
+    fn test_largest_first() {
+        let notes = notes(&[5, 50, 20, 10]);
+
+        assert_eq!(LargestFirst.select(&notes, 60, 4), Some(vec![1, 2]));
+        assert_eq!(LargestFirst.select(&notes, 80, 2), None);
+        assert_eq!(LargestFirst.select(&notes, 86, 4), None);
+    }
+
+    #[test]
+    fn test_fewest_notes() {
+        let notes = notes(&[100, 30, 45, 5, 40]);
+
+        // A single note covers it, and 45 is the smallest one that does.
+        assert_eq!(FewestNotes.select(&notes, 42, 4), Some(vec![2]));
+        // Two notes are needed, and 100 + 5 covers it exactly.
+        let selected = FewestNotes.select(&notes, 105, 4).unwrap();
+        assert_eq!(selected.len(), 2);
+        assert_eq!(total(&notes, &selected), 105);
+    }
+
+    #[proptest]
+    fn test_strategies_cover_target(
+        #[strategy(rng())] rng: StdRng,
+        #[strategy(proptest::collection::vec(1..1_000u64, 1..32))] amounts: Vec<u64>,
+        #[strategy(1..4_000u128)] target: u128,
+        #[strategy(1..8usize)] max: usize,
+    ) {
+        let notes = notes(&amounts);
+        let possible = LargestFirst.select(&notes, target, max);
+
+        let strategies: [&mut dyn SelectionStrategy; 3] = [
+            &mut LargestFirst,
+            &mut FewestNotes,
+            &mut RandomImprove::new(rng),
+        ];
+
+        for strategy in strategies {
+            let selected = strategy.select(&notes, target, max);
+            prop_assert_eq!(selected.is_some(), possible.is_some());
+
+            if let Some(selected) = selected {
+                let mut unique = selected.clone();
+                unique.sort();
+                unique.dedup();
+
+                prop_assert_eq!(unique.len(), selected.len());
+                prop_assert!(selected.len() <= max);
+                prop_assert!(total(&notes, &selected) >= target);
+            }
+        }
+    }
+}
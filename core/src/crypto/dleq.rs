@@ -0,0 +1,124 @@
+//! Proofs that a signature `S = k * H(m)` was made with the key `K = k * G`
+//! of a delegate, so anyone can check it without the secret key.
+//!
+//! The proof shows that `log_G(K) = log_H(m)(S)` as a Schnorr signature over
+//! both bases at once, made with the same nonce.
+
+use serde::{Deserialize, Serialize};
+use test_strategy::Arbitrary;
+
+use crate::{
+    crypto::*,
+    error::{Error, Result},
+};
+
+pub const DLEQ_SEP: &[u8] = b"mugraph_v0_dleq";
+
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    Arbitrary,
+)]
+pub struct Proof {
+    pub e: Hash,
+    pub s: Hash,
+}
+
+/// Proves `signature` was made with `secret_key`. The nonce is derived from
+/// the key and the message, so the same signature always gets the same
+/// proof, as answers to retried requests must not change.
+pub fn prove(secret_key: &SecretKey, message: &[u8], signature: Signature) -> Proof {
+    let y = hash_to_curve(message);
+    let r = hash_to_scalar(&[DLEQ_SEP, &secret_key.0, message]);
+    let e = challenge(&secret_key.public(), &signature, &(G * r), &(y * r));
+
+    Proof {
+        e: Hash(e.to_bytes()),
+        s: Hash((r + e * secret_key.to_scalar()).to_bytes()),
+    }
+}
+
+pub fn verify(
+    public_key: &PublicKey,
+    message: &[u8],
+    signature: Signature,
+    proof: &Proof,
+) -> Result<bool> {
+    let scalar = |bytes: &Hash| {
+        Option::from(Scalar::from_canonical_bytes(bytes.0)).ok_or(Error::InvalidSignature {
+            reason: "proof is not made of canonical scalars".to_string(),
+            signature,
+        })
+    };
+    let (e, s) = (scalar(&proof.e)?, scalar(&proof.s)?);
+
+    let y = hash_to_curve(message);
+    let r1 = G * s - public_key.to_point()? * e;
+    let r2 = y * s - signature.to_point()? * e;
+
+    Ok(challenge(public_key, &signature, &r1, &r2) == e)
+}
+
+fn challenge(public_key: &PublicKey, signature: &Signature, r1: &Point, r2: &Point) -> Scalar {
+    hash_to_scalar(&[
+        DLEQ_SEP,
+        &public_key.0,
+        &signature.0,
+        r1.compress().as_bytes(),
+        r2.compress().as_bytes(),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use test_strategy::proptest;
+
+    use super::*;
+
+    #[proptest]
+    fn test_prove_verify(pair: Keypair, msg: Vec<u8>) {
+        let signature = sign(&pair.secret_key, &msg);
+        let proof = prove(&pair.secret_key, &msg, signature);
+
+        prop_assert!(verify(&pair.public_key, &msg, signature, &proof)?);
+        prop_assert_eq!(prove(&pair.secret_key, &msg, signature), proof);
+    }
+
+    #[proptest]
+    fn test_wrong_statement(a: Keypair, b: Keypair, msg: Vec<u8>, other: Vec<u8>) {
+        let signature = sign(&a.secret_key, &msg);
+        let proof = prove(&a.secret_key, &msg, signature);
+
+        prop_assert_eq!(verify(&b.public_key, &msg, signature, &proof)?, a == b);
+        prop_assert_eq!(
+            verify(&a.public_key, &other, signature, &proof)?,
+            msg == other
+        );
+
+        // A signature of another key can not be passed off with any proof.
+        let forged = sign(&b.secret_key, &msg);
+        prop_assert_eq!(verify(&a.public_key, &msg, forged, &proof)?, a == b);
+    }
+
+    #[proptest]
+    fn test_forged_signature(pair: Keypair, msg: Vec<u8>, proof: Proof) {
+        // The signature anyone could compute if `H(m)` were `h(m) * G`.
+        let forged: Signature =
+            (pair.public_key.to_point()? * hash_to_scalar(&[HTC_SEP, &msg])).into();
+
+        prop_assert!(!matches!(
+            verify(&pair.public_key, &msg, forged, &proof),
+            Ok(true)
+        ));
+    }
+}
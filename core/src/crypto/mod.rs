@@ -1,8 +1,10 @@
 use blake3::Hasher;
+use curve25519_dalek::traits::{IsIdentity, VartimeMultiscalarMul};
 use rand::prelude::{CryptoRng, RngCore};
 
 use crate::{error::Result, types::*};
 
+pub mod dleq;
 pub mod schnorr;
 
 pub const HTC_SEP: &[u8] = b"mugraph_v0_htc";
@@ -48,9 +50,62 @@ pub fn unblind_signature(
     Ok(Signature(res.compress().0))
 }
 
-pub fn verify(public_key: &PublicKey, message: &[u8], signature: Signature) -> Result<bool> {
-    let y = hash_to_scalar(&[HTC_SEP, message]);
-    Ok(y * public_key.to_point()? == signature.to_point()?)
+/// Signs `message` as `k * H(m)`, where `H` hashes onto the group.
+pub fn sign(secret_key: &SecretKey, message: &[u8]) -> Signature {
+    (hash_to_curve(message) * secret_key.to_scalar()).into()
+}
+
+/// Checks a signature made with [`sign`]. Only the signer can do it, as it
+/// needs the secret key; anyone else checks a [`dleq::Proof`] instead.
+pub fn verify(secret_key: &SecretKey, message: &[u8], signature: Signature) -> Result<bool> {
+    Ok(hash_to_curve(message) * secret_key.to_scalar() == signature.to_point()?)
+}
+
+/// Verifies many signatures made with `secret_key` at once, returning the
+/// indexes of the ones that are invalid.
+///
+/// Every equation `k * H(m) = S` is weighted by a random scalar and all of
+/// them are checked with a single multiscalar multiplication, so a forged
+/// signature can only cancel out another one with negligible probability.
+/// When the combined check fails, each item is verified on its own to find
+/// which ones are wrong.
+pub fn verify_batch<R: RngCore + CryptoRng>(
+    rng: &mut R,
+    secret_key: &SecretKey,
+    items: &[(&[u8], Signature)],
+) -> Vec<usize> {
+    let k = secret_key.to_scalar();
+    let mut scalars = Vec::with_capacity(items.len() * 2);
+    let mut points = Vec::with_capacity(items.len() * 2);
+
+    for (message, signature) in items {
+        let Ok(signature) = signature.to_point() else {
+            return invalid(secret_key, items);
+        };
+
+        let z = Scalar::random(rng);
+
+        scalars.push(z * k);
+        points.push(hash_to_curve(message));
+        scalars.push(-z);
+        points.push(signature);
+    }
+
+    match Point::vartime_multiscalar_mul(scalars, points).is_identity() {
+        true => vec![],
+        false => invalid(secret_key, items),
+    }
+}
+
+fn invalid(secret_key: &SecretKey, items: &[(&[u8], Signature)]) -> Vec<usize> {
+    items
+        .iter()
+        .enumerate()
+        .filter(|(_, (message, signature))| {
+            !matches!(verify(secret_key, message, *signature), Ok(true))
+        })
+        .map(|(i, _)| i)
+        .collect()
 }
 
 fn hash_to_scalar(data: &[&[u8]]) -> Scalar {
@@ -63,9 +118,23 @@ fn hash_to_scalar(data: &[&[u8]]) -> Scalar {
     Hash(*hasher.finalize().as_bytes()).into()
 }
 
+/// Hashes `message` onto the group, with no known discrete logarithm to
+/// `G`, so a signature `k * H(m)` can not be computed from `k * G`.
 pub fn hash_to_curve(message: &[u8]) -> Point {
-    let scalar = hash_to_scalar(&[HTC_SEP, message]);
-    G * scalar
+    let mut hasher = Hasher::new();
+    hasher.update(HTC_SEP);
+    hasher.update(message);
+
+    let mut bytes = [0u8; 64];
+    hasher.finalize_xof().fill(&mut bytes);
+
+    Point::from_uniform_bytes(&bytes)
+}
+
+/// Compressed encoding of the point a note with `commitment` is signed on,
+/// which identifies the note without revealing its commitment or signature.
+pub fn y_point(commitment: &Hash) -> Hash {
+    Hash(hash_to_curve(commitment.as_ref()).compress().0)
 }
 
 #[cfg(test)]
@@ -100,7 +169,21 @@ mod tests {
         let sig = sign_blinded(&pair.secret_key, &blinded.point);
         let unblinded = unblind_signature(&sig, &blinded.factor, &pair.public_key)?;
 
-        prop_assert!(verify(&pair.public_key, &msg, unblinded)?);
+        prop_assert!(verify(&pair.secret_key, &msg, unblinded)?);
+    }
+
+    #[proptest]
+    fn test_forged_signature(#[strategy(rng())] mut rng: StdRng, pair: Keypair, msg: Vec<u8>) {
+        // Hashing onto the group as `h(m) * G` would make `h(m) * K` a valid
+        // signature that anyone can compute from the public key.
+        let forged: Signature =
+            (pair.public_key.to_point()? * hash_to_scalar(&[HTC_SEP, &msg])).into();
+
+        prop_assert!(!verify(&pair.secret_key, &msg, forged)?);
+        prop_assert_eq!(
+            verify_batch(&mut rng, &pair.secret_key, &[(&msg, forged)]),
+            vec![0]
+        );
     }
 
     #[proptest]
@@ -115,7 +198,38 @@ mod tests {
         let sig = sign_blinded(&pair.secret_key, &blinded.point);
         let unblinded = unblind_signature(&sig, &blinded.factor, &pair.public_key)?;
 
-        prop_assert_eq!(verify(&pair.public_key, &b, unblinded)?, a == b);
+        prop_assert_eq!(verify(&pair.secret_key, &b, unblinded)?, a == b);
+    }
+
+    #[proptest]
+    fn test_verify_batch(
+        #[strategy(rng())] mut rng: StdRng,
+        pair: Keypair,
+        #[strategy(proptest::collection::vec(any::<Vec<u8>>(), 1..16))] messages: Vec<Vec<u8>>,
+        forged: prop::sample::Index,
+    ) {
+        let mut signatures = messages
+            .iter()
+            .map(|m| sign(&pair.secret_key, m))
+            .collect::<Vec<_>>();
+        let items = |signatures: &[Signature]| {
+            messages
+                .iter()
+                .zip(signatures)
+                .map(|(m, s)| (m.as_slice(), *s))
+                .collect::<Vec<_>>()
+        };
+
+        prop_assert!(verify_batch(&mut rng, &pair.secret_key, &items(&signatures)).is_empty());
+
+        let forged = forged.index(signatures.len());
+        let other = [messages[forged].as_slice(), b"forged"].concat();
+        signatures[forged] = sign(&pair.secret_key, &other);
+
+        prop_assert_eq!(
+            verify_batch(&mut rng, &pair.secret_key, &items(&signatures)),
+            vec![forged]
+        );
     }
 
     #[proptest]
@@ -130,6 +244,6 @@ mod tests {
         let sig = sign_blinded(&a.secret_key, &blinded.point);
         let unblinded = unblind_signature(&sig, &blinded.factor, &a.public_key)?;
 
-        prop_assert_eq!(verify(&b.public_key, &msg, unblinded)?, a == b);
+        prop_assert_eq!(verify(&b.secret_key, &msg, unblinded)?, a == b);
     }
 }
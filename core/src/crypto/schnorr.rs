@@ -1,11 +1,16 @@
 use curve25519_dalek::ristretto::CompressedRistretto;
 use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+use test_strategy::Arbitrary;
 
 use crate::{
     crypto::*,
     error::{Error, Result},
 };
 
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, Arbitrary,
+)]
 pub struct Signature {
     r: Hash,
     s: Hash,
@@ -4,7 +4,7 @@ use onlyerror::Error;
 use serde::{Deserialize, Serialize};
 use test_strategy::Arbitrary;
 
-use crate::types::{Hash, Signature};
+use crate::types::{Hash, PublicKey, Signature};
 
 pub type Result<T> = core::result::Result<T, Error>;
 
@@ -60,6 +60,38 @@ pub enum Error {
     #[error("Invalid Transaction: {reason}")]
     InvalidTransaction { reason: String },
 
+    #[error("Deposit {deposit} has {confirmations} confirmations, but {required} are required")]
+    DepositNotConfirmed {
+        deposit: Hash,
+        confirmations: u64,
+        required: u64,
+    },
+
+    #[error("Asset {asset_id} is not supported: {reason}")]
+    UnsupportedAsset { asset_id: Hash, reason: String },
+
+    #[error("Not found: {reason}")]
+    NotFound { reason: String },
+
+    #[error("Invalid fee for {asset_id}, expected {expected} but got {got}")]
+    InvalidFee {
+        asset_id: Hash,
+        expected: u128,
+        got: u128,
+    },
+
+    #[error("Invalid netting statement: {reason}")]
+    InvalidStatement { reason: String },
+
+    #[error("Invalid URI: {reason}")]
+    InvalidUri { reason: String },
+
+    #[error("Error reaching delegate {delegate}: {reason}")]
+    PeerError { delegate: PublicKey, reason: String },
+
+    #[error("Delegate is unavailable: {reason}")]
+    Unavailable { reason: String },
+
     #[error("Multiple errors happened at once: {errors:?}")]
     Multiple { errors: Vec<Error> },
 
@@ -67,6 +99,62 @@ pub enum Error {
     Other,
 }
 
+impl Error {
+    /// Stable numeric code for the kind of error, so clients don't need to
+    /// parse messages. Codes below 2000 are caused by the request, the rest
+    /// by the delegate itself.
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::InsufficientFunds { .. } => 1001,
+            Self::AlreadySpent { .. } => 1002,
+            Self::InvalidSignature { .. } => 1003,
+            Self::InvalidKey { .. } => 1004,
+            Self::InvalidHash { .. } => 1005,
+            Self::InvalidAtom { .. } => 1006,
+            Self::UnbalancedTransaction { .. } => 1007,
+            Self::InvalidTransaction { .. } => 1008,
+            Self::DepositNotConfirmed { .. } => 1009,
+            Self::UnsupportedAsset { .. } => 1010,
+            Self::NotFound { .. } => 1011,
+            Self::JsonError { .. } => 1012,
+            Self::InvalidFee { .. } => 1013,
+            Self::InvalidStatement { .. } => 1014,
+            Self::InvalidUri { .. } => 1015,
+            Self::Other => 2000,
+            Self::ServerError { .. } => 2001,
+            Self::StorageError { .. } => 2002,
+            Self::RngError { .. } => 2003,
+            Self::SimulatedError { .. } => 2004,
+            Self::SimulationError { .. } => 2005,
+            Self::Multiple { .. } => 2006,
+            Self::PeerError { .. } => 2007,
+            Self::Unavailable { .. } => 2008,
+        }
+    }
+
+    #[inline]
+    pub fn is_client_error(&self) -> bool {
+        match self {
+            Self::Multiple { errors } => errors.iter().all(Self::is_client_error),
+            e => e.code() < 2000,
+        }
+    }
+
+    /// Whether sending the same request again later can succeed.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::DepositNotConfirmed { .. }
+            | Self::StorageError { .. }
+            | Self::RngError { .. }
+            | Self::SimulatedError { .. }
+            | Self::PeerError { .. }
+            | Self::Unavailable { .. } => true,
+            Self::Multiple { errors } => errors.iter().all(Self::is_retryable),
+            _ => false,
+        }
+    }
+}
+
 impl From<std::io::Error> for Error {
     fn from(e: std::io::Error) -> Self {
         let reason = e.to_string();
@@ -3,7 +3,10 @@ use std::sync::{atomic::AtomicBool, Arc};
 pub mod builder;
 pub mod crypto;
 pub mod error;
+pub mod merkle;
+pub mod record;
 pub mod types;
+pub mod uri;
 pub mod utils;
 
 #[cfg(test)]
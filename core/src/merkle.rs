@@ -0,0 +1,577 @@
+//! Merkle trees following the structure of RFC 6962, using blake3 with domain
+//! separated leaves and nodes.
+
+use blake3::Hasher;
+
+use crate::types::Hash;
+
+pub const LEAF_PREFIX: u8 = 0x00;
+pub const NODE_PREFIX: u8 = 0x01;
+
+#[inline]
+pub fn leaf_hash(data: &[u8]) -> Hash {
+    let mut hasher = Hasher::new();
+    hasher.update(&[LEAF_PREFIX]);
+    hasher.update(data);
+    hasher.finalize().into()
+}
+
+#[inline]
+pub fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Hasher::new();
+    hasher.update(&[NODE_PREFIX]);
+    hasher.update(left.as_ref());
+    hasher.update(right.as_ref());
+    hasher.finalize().into()
+}
+
+/// Largest power of two strictly smaller than `n`.
+#[inline]
+fn split(n: usize) -> usize {
+    debug_assert!(n > 1);
+    1 << (usize::BITS - (n - 1).leading_zeros() - 1)
+}
+
+/// Computes the root for a list of leaf hashes.
+pub fn root(leaves: &[Hash]) -> Hash {
+    match leaves.is_empty() {
+        true => Hash::digest(&[]),
+        false => fold_root(leaves, &node_hash),
+    }
+}
+
+/// Audit path for the leaf at `index`, from the bottom of the tree up.
+pub fn inclusion_proof(leaves: &[Hash], index: usize) -> Vec<Hash> {
+    fold_inclusion(leaves, index, &node_hash)
+}
+
+/// Proves that the tree made of the first `first` leaves is a prefix of the
+/// tree made of all `leaves`.
+pub fn consistency_proof(leaves: &[Hash], first: usize) -> Vec<Hash> {
+    if first == 0 || first >= leaves.len() {
+        return vec![];
+    }
+
+    subproof(first, leaves, true)
+}
+
+fn subproof(m: usize, leaves: &[Hash], complete: bool) -> Vec<Hash> {
+    let n = leaves.len();
+
+    if m == n {
+        return match complete {
+            true => vec![],
+            false => vec![root(leaves)],
+        };
+    }
+
+    let k = split(n);
+
+    if m <= k {
+        let mut proof = subproof(m, &leaves[..k], complete);
+        proof.push(root(&leaves[k..]));
+        proof
+    } else {
+        let mut proof = subproof(m - k, &leaves[k..], false);
+        proof.push(root(&leaves[..k]));
+        proof
+    }
+}
+
+pub fn verify_inclusion(leaf: Hash, index: u64, size: u64, path: &[Hash], root: Hash) -> bool {
+    fold_path(leaf, index, size, path, &node_hash) == Some(root)
+}
+
+pub fn verify_consistency(
+    first: u64,
+    second: u64,
+    first_root: Hash,
+    second_root: Hash,
+    proof: &[Hash],
+) -> bool {
+    if first > second {
+        return false;
+    }
+
+    if first == second {
+        return proof.is_empty() && first_root == second_root;
+    }
+
+    if first == 0 {
+        return proof.is_empty();
+    }
+
+    let mut path = Vec::with_capacity(proof.len() + 1);
+
+    if first.is_power_of_two() {
+        path.push(first_root);
+    }
+
+    path.extend_from_slice(proof);
+
+    let (mut f, mut s) = (first - 1, second - 1);
+
+    while f & 1 == 1 {
+        f >>= 1;
+        s >>= 1;
+    }
+
+    let Some((head, rest)) = path.split_first() else {
+        return false;
+    };
+
+    let (mut fr, mut sr) = (*head, *head);
+
+    for c in rest {
+        if s == 0 {
+            return false;
+        }
+
+        if f & 1 == 1 || f == s {
+            fr = node_hash(c, &fr);
+            sr = node_hash(c, &sr);
+
+            while f & 1 == 0 && f != 0 {
+                f >>= 1;
+                s >>= 1;
+            }
+        } else {
+            sr = node_hash(&sr, c);
+        }
+
+        f >>= 1;
+        s >>= 1;
+    }
+
+    s == 0 && fr == first_root && sr == second_root
+}
+
+/// A node in a Merkle-sum tree, where every node commits to the sum of the
+/// amounts of all leaves below it.
+pub type SumNode = (Hash, u128);
+
+#[inline]
+pub fn sum_leaf(data: &[u8], amount: u64) -> SumNode {
+    let mut hasher = Hasher::new();
+    hasher.update(&[LEAF_PREFIX]);
+    hasher.update(data);
+    hasher.update(&amount.to_le_bytes());
+
+    (hasher.finalize().into(), amount as u128)
+}
+
+#[inline]
+pub fn sum_node(left: &SumNode, right: &SumNode) -> SumNode {
+    let mut hasher = Hasher::new();
+    hasher.update(&[NODE_PREFIX]);
+    hasher.update(left.0.as_ref());
+    hasher.update(&left.1.to_le_bytes());
+    hasher.update(right.0.as_ref());
+    hasher.update(&right.1.to_le_bytes());
+
+    (hasher.finalize().into(), left.1.saturating_add(right.1))
+}
+
+pub fn sum_root(leaves: &[SumNode]) -> SumNode {
+    match leaves.is_empty() {
+        true => (Hash::digest(&[]), 0),
+        false => fold_root(leaves, &sum_node),
+    }
+}
+
+pub fn sum_inclusion_proof(leaves: &[SumNode], index: usize) -> Vec<SumNode> {
+    fold_inclusion(leaves, index, &sum_node)
+}
+
+pub fn verify_sum_inclusion(
+    leaf: SumNode,
+    index: u64,
+    size: u64,
+    path: &[SumNode],
+    root: SumNode,
+) -> bool {
+    fold_path(leaf, index, size, path, &sum_node) == Some(root)
+}
+
+/// Complete subtrees finished by appending `leaf` at `index`, as `(level,
+/// index, node)` from the leaf up, with `subtree(level, index)` returning the
+/// ones stored before. Storing them all is enough to build any root and
+/// proof of the tree with [`stored_root`] and friends.
+pub fn append_nodes<T: Copy, E>(
+    index: u64,
+    leaf: T,
+    subtree: &mut impl FnMut(u32, u64) -> Result<T, E>,
+    combine: &impl Fn(&T, &T) -> T,
+) -> Result<Vec<(u32, u64, T)>, E> {
+    let (mut level, mut index, mut node) = (0, index, leaf);
+    let mut nodes = vec![(level, index, node)];
+
+    while index & 1 == 1 {
+        node = combine(&subtree(level, index - 1)?, &node);
+        level += 1;
+        index >>= 1;
+        nodes.push((level, index, node));
+    }
+
+    Ok(nodes)
+}
+
+/// Root of the leaves `lo..hi`, built from the complete subtrees stored by
+/// [`append_nodes`] instead of every leaf.
+pub fn stored_root<T: Copy, E>(
+    lo: u64,
+    hi: u64,
+    subtree: &mut impl FnMut(u32, u64) -> Result<T, E>,
+    combine: &impl Fn(&T, &T) -> T,
+) -> Result<T, E> {
+    let n = hi - lo;
+
+    if n.is_power_of_two() {
+        let level = n.trailing_zeros();
+        return subtree(level, lo >> level);
+    }
+
+    let k = split(n as usize) as u64;
+
+    Ok(combine(
+        &stored_root(lo, lo + k, subtree, combine)?,
+        &stored_root(lo + k, hi, subtree, combine)?,
+    ))
+}
+
+/// Audit path for the leaf at `index` in the tree of the first `size`
+/// leaves, like [`inclusion_proof`], from stored subtrees.
+pub fn stored_inclusion_proof<T: Copy, E>(
+    index: u64,
+    size: u64,
+    subtree: &mut impl FnMut(u32, u64) -> Result<T, E>,
+    combine: &impl Fn(&T, &T) -> T,
+) -> Result<Vec<T>, E> {
+    let (mut lo, mut hi) = (0, size);
+    let mut path = vec![];
+
+    while hi - lo > 1 {
+        let k = lo + split((hi - lo) as usize) as u64;
+
+        if index < k {
+            path.push(stored_root(k, hi, subtree, combine)?);
+            hi = k;
+        } else {
+            path.push(stored_root(lo, k, subtree, combine)?);
+            lo = k;
+        }
+    }
+
+    path.reverse();
+
+    Ok(path)
+}
+
+/// Proves that the tree of the first `first` leaves is a prefix of the one
+/// of the first `size`, like [`consistency_proof`], from stored subtrees.
+pub fn stored_consistency_proof<E>(
+    first: u64,
+    size: u64,
+    subtree: &mut impl FnMut(u32, u64) -> Result<Hash, E>,
+) -> Result<Vec<Hash>, E> {
+    if first == 0 || first >= size {
+        return Ok(vec![]);
+    }
+
+    stored_subproof(first, 0, size, true, subtree)
+}
+
+fn stored_subproof<E>(
+    m: u64,
+    lo: u64,
+    hi: u64,
+    complete: bool,
+    subtree: &mut impl FnMut(u32, u64) -> Result<Hash, E>,
+) -> Result<Vec<Hash>, E> {
+    let n = hi - lo;
+
+    if m == n {
+        return Ok(match complete {
+            true => vec![],
+            false => vec![stored_root(lo, hi, subtree, &node_hash)?],
+        });
+    }
+
+    let k = split(n as usize) as u64;
+
+    let mut proof = match m <= k {
+        true => stored_subproof(m, lo, lo + k, complete, subtree)?,
+        false => stored_subproof(m - k, lo + k, hi, false, subtree)?,
+    };
+
+    proof.push(match m <= k {
+        true => stored_root(lo + k, hi, subtree, &node_hash)?,
+        false => stored_root(lo, lo + k, subtree, &node_hash)?,
+    });
+
+    Ok(proof)
+}
+
+fn fold_root<T: Copy>(leaves: &[T], combine: &impl Fn(&T, &T) -> T) -> T {
+    match leaves.len() {
+        1 => leaves[0],
+        n => {
+            let k = split(n);
+            combine(
+                &fold_root(&leaves[..k], combine),
+                &fold_root(&leaves[k..], combine),
+            )
+        }
+    }
+}
+
+fn fold_inclusion<T: Copy>(leaves: &[T], index: usize, combine: &impl Fn(&T, &T) -> T) -> Vec<T> {
+    let n = leaves.len();
+
+    if n <= 1 {
+        return vec![];
+    }
+
+    let k = split(n);
+
+    if index < k {
+        let mut path = fold_inclusion(&leaves[..k], index, combine);
+        path.push(fold_root(&leaves[k..], combine));
+        path
+    } else {
+        let mut path = fold_inclusion(&leaves[k..], index - k, combine);
+        path.push(fold_root(&leaves[..k], combine));
+        path
+    }
+}
+
+/// Recomputes the root from a leaf and its audit path, following the
+/// verification algorithm from RFC 9162.
+fn fold_path<T: Copy>(
+    leaf: T,
+    index: u64,
+    size: u64,
+    path: &[T],
+    combine: &impl Fn(&T, &T) -> T,
+) -> Option<T> {
+    if index >= size {
+        return None;
+    }
+
+    let (mut f, mut s) = (index, size - 1);
+    let mut r = leaf;
+
+    for p in path {
+        if s == 0 {
+            return None;
+        }
+
+        if f & 1 == 1 || f == s {
+            r = combine(p, &r);
+
+            while f & 1 == 0 && f != 0 {
+                f >>= 1;
+                s >>= 1;
+            }
+        } else {
+            r = combine(&r, p);
+        }
+
+        f >>= 1;
+        s >>= 1;
+    }
+
+    match s {
+        0 => Some(r),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use test_strategy::proptest;
+
+    use super::*;
+
+    fn leaves(data: &[Vec<u8>]) -> Vec<Hash> {
+        data.iter().map(|d| leaf_hash(d)).collect()
+    }
+
+    #[test]
+    fn test_stored_subtrees() {
+        let leaves = (0..40u64)
+            .map(|i| leaf_hash(&i.to_le_bytes()))
+            .collect::<Vec<_>>();
+        let mut nodes = std::collections::HashMap::new();
+
+        for (i, leaf) in leaves.iter().enumerate() {
+            let mut subtree = |level, index| nodes.get(&(level, index)).copied().ok_or(());
+            let added = append_nodes(i as u64, *leaf, &mut subtree, &node_hash).unwrap();
+
+            for (level, index, node) in added {
+                nodes.insert((level, index), node);
+            }
+
+            let size = i + 1;
+            let mut subtree = |level, index| nodes.get(&(level, index)).copied().ok_or(());
+
+            assert_eq!(
+                stored_root(0, size as u64, &mut subtree, &node_hash),
+                Ok(root(&leaves[..size]))
+            );
+
+            for j in 0..size {
+                assert_eq!(
+                    stored_inclusion_proof(j as u64, size as u64, &mut subtree, &node_hash),
+                    Ok(inclusion_proof(&leaves[..size], j))
+                );
+                assert_eq!(
+                    stored_consistency_proof(j as u64, size as u64, &mut subtree),
+                    Ok(consistency_proof(&leaves[..size], j))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_leaf_and_node_are_domain_separated() {
+        let (a, b) = (Hash::digest(b"a"), Hash::digest(b"b"));
+        let concatenated = [a.as_ref().as_slice(), b.as_ref().as_slice()].concat();
+
+        assert_ne!(leaf_hash(&concatenated), node_hash(&a, &b));
+    }
+
+    #[proptest]
+    fn test_inclusion(
+        #[strategy(prop::collection::vec(any::<Vec<u8>>(), 1..64))] data: Vec<Vec<u8>>,
+    ) {
+        let leaves = leaves(&data);
+        let root = root(&leaves);
+
+        for (i, leaf) in leaves.iter().enumerate() {
+            let path = inclusion_proof(&leaves, i);
+
+            prop_assert!(verify_inclusion(
+                *leaf,
+                i as u64,
+                leaves.len() as u64,
+                &path,
+                root
+            ));
+        }
+    }
+
+    #[proptest]
+    fn test_inclusion_wrong_leaf(
+        #[strategy(prop::collection::vec(any::<Vec<u8>>(), 2..64))] data: Vec<Vec<u8>>,
+        extra: Vec<u8>,
+    ) {
+        prop_assume!(!data.contains(&extra));
+
+        let leaves = leaves(&data);
+        let root = root(&leaves);
+        let path = inclusion_proof(&leaves, 0);
+
+        prop_assert!(!verify_inclusion(
+            leaf_hash(&extra),
+            0,
+            leaves.len() as u64,
+            &path,
+            root
+        ));
+    }
+
+    #[proptest]
+    fn test_sum_inclusion(
+        #[strategy(prop::collection::vec(any::<(Vec<u8>, u64)>(), 1..64))] data: Vec<(
+            Vec<u8>,
+            u64,
+        )>,
+    ) {
+        let leaves = data
+            .iter()
+            .map(|(d, a)| sum_leaf(d, *a))
+            .collect::<Vec<_>>();
+        let root = sum_root(&leaves);
+
+        prop_assert_eq!(root.1, data.iter().map(|(_, a)| *a as u128).sum::<u128>());
+
+        for (i, leaf) in leaves.iter().enumerate() {
+            let path = sum_inclusion_proof(&leaves, i);
+
+            prop_assert!(verify_sum_inclusion(
+                *leaf,
+                i as u64,
+                leaves.len() as u64,
+                &path,
+                root
+            ));
+        }
+    }
+
+    #[proptest]
+    fn test_sum_inclusion_wrong_amount(
+        #[strategy(prop::collection::vec(any::<(Vec<u8>, u64)>(), 1..64))] data: Vec<(
+            Vec<u8>,
+            u64,
+        )>,
+        amount: u64,
+    ) {
+        prop_assume!(data[0].1 != amount);
+
+        let leaves = data
+            .iter()
+            .map(|(d, a)| sum_leaf(d, *a))
+            .collect::<Vec<_>>();
+        let path = sum_inclusion_proof(&leaves, 0);
+
+        prop_assert!(!verify_sum_inclusion(
+            sum_leaf(&data[0].0, amount),
+            0,
+            leaves.len() as u64,
+            &path,
+            sum_root(&leaves)
+        ));
+    }
+
+    #[proptest]
+    fn test_consistency(
+        #[strategy(prop::collection::vec(any::<Vec<u8>>(), 1..64))] data: Vec<Vec<u8>>,
+        #[strategy(1..=#data.len())] first: usize,
+    ) {
+        let leaves = leaves(&data);
+        let proof = consistency_proof(&leaves, first);
+
+        prop_assert!(verify_consistency(
+            first as u64,
+            leaves.len() as u64,
+            root(&leaves[..first]),
+            root(&leaves),
+            &proof
+        ));
+    }
+
+    #[proptest]
+    fn test_consistency_rewritten_history(
+        #[strategy(prop::collection::vec(any::<Vec<u8>>(), 2..64))] data: Vec<Vec<u8>>,
+        #[strategy(1..#data.len())] first: usize,
+        extra: Vec<u8>,
+    ) {
+        prop_assume!(data[0] != extra);
+
+        let leaves = leaves(&data);
+        let mut rewritten = leaves.clone();
+        rewritten[0] = leaf_hash(&extra);
+
+        let proof = consistency_proof(&rewritten, first);
+
+        prop_assert!(!verify_consistency(
+            first as u64,
+            leaves.len() as u64,
+            root(&leaves[..first]),
+            root(&rewritten),
+            &proof
+        ));
+    }
+}
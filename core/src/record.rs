@@ -0,0 +1,67 @@
+use std::{fmt::Debug, marker::PhantomData};
+
+use redb::{TypeName, Value};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::error::Error;
+
+/// Stores any serializable type as a JSON-encoded value.
+///
+/// Values are read as a `Result`, so a row that does not decode is an error
+/// for whoever reads it instead of a panic, and written as `Ok`.
+#[derive(Debug)]
+pub struct Record<T>(PhantomData<T>);
+
+impl<T> Value for Record<T>
+where
+    T: Debug + Serialize + DeserializeOwned + 'static,
+{
+    type SelfType<'a> = Result<T, Error> where Self: 'a;
+    type AsBytes<'a> = Vec<u8> where Self: 'a;
+
+    fn fixed_width() -> Option<usize> {
+        None
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Self::SelfType<'a>
+    where
+        Self: 'a,
+    {
+        serde_json::from_slice(data).map_err(|e| Error::ServerError {
+            reason: format!("Invalid {}: {e}", std::any::type_name::<T>()),
+        })
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a>
+    where
+        Self: 'a,
+        Self: 'b,
+    {
+        match value {
+            Ok(value) => serde_json::to_vec(value).expect("Record should be serializable"),
+            Err(e) => panic!("Record can not store an error: {e}"),
+        }
+    }
+
+    fn type_name() -> TypeName {
+        TypeName::new(&format!("record<{}>", std::any::type_name::<T>()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Hash;
+
+    #[test]
+    fn test_invalid_record() {
+        let hash = Hash::digest(b"record");
+        let bytes = Record::<Hash>::as_bytes(&Ok(hash));
+
+        assert_eq!(Record::<Hash>::from_bytes(&bytes).unwrap(), hash);
+        assert!(matches!(
+            Record::<Hash>::from_bytes(b"{\"truncated"),
+            Err(Error::ServerError { .. })
+        ));
+    }
+}
@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+use test_strategy::Arbitrary;
+
+use crate::{error::Error, types::Hash};
+
+/// An asset accepted by a delegate, with the Cardano asset backing it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+pub struct Asset {
+    pub id: Hash,
+    /// Minting policy of the asset on Cardano, empty for ada.
+    #[serde(with = "hex::serde")]
+    pub policy_id: Vec<u8>,
+    #[serde(with = "hex::serde")]
+    pub asset_name: Vec<u8>,
+    /// Number of decimal places used when displaying amounts.
+    pub decimals: u8,
+    pub ticker: String,
+    /// Smallest amount a single note can hold.
+    pub min_amount: u64,
+    /// Largest amount a single note can hold.
+    pub max_amount: u64,
+    pub enabled: bool,
+}
+
+impl Asset {
+    #[inline]
+    pub fn is_ada(&self) -> bool {
+        self.policy_id.is_empty()
+    }
+
+    /// Cardano unit backing the asset, the hex of its policy id followed by
+    /// its asset name, or `lovelace` for ada.
+    pub fn unit(&self) -> String {
+        match self.is_ada() {
+            true => "lovelace".to_string(),
+            false => hex::encode([&self.policy_id[..], &self.asset_name].concat()),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        let invalid = |reason: &str| {
+            Err(Error::UnsupportedAsset {
+                asset_id: self.id,
+                reason: reason.to_string(),
+            })
+        };
+
+        if !matches!(self.policy_id.len(), 0 | 28) {
+            return invalid("policy id must have 28 bytes");
+        }
+
+        if self.asset_name.len() > 32 {
+            return invalid("asset name can not be over 32 bytes");
+        }
+
+        if self.is_ada() && !self.asset_name.is_empty() {
+            return invalid("ada can not have an asset name");
+        }
+
+        if self.ticker.is_empty() {
+            return invalid("ticker can not be empty");
+        }
+
+        if self.min_amount == 0 || self.min_amount > self.max_amount {
+            return invalid("amount limits must satisfy 0 < min <= max");
+        }
+
+        Ok(())
+    }
+
+    pub fn check_amount(&self, amount: u64) -> Result<(), Error> {
+        if amount < self.min_amount || amount > self.max_amount {
+            return Err(Error::InvalidAtom {
+                reason: format!(
+                    "Amount {amount} of {} is outside of [{}, {}]",
+                    self.ticker, self.min_amount, self.max_amount
+                ),
+            });
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+use test_strategy::Arbitrary;
+
+use crate::{
+    error::Error,
+    types::{Blinded, OutputSignature},
+};
+
+/// Most transactions that can be submitted in a single batch or bundle.
+pub const MAX_BATCH: usize = 64;
+
+/// Outcome of one transaction in a batch.
+///
+/// Transactions in a batch are independent: each one is accepted or rejected
+/// on its own, in the order they were submitted, so a transaction spending an
+/// input already spent by an earlier one in the same batch is rejected.
+#[derive(Debug, Clone, Serialize, Deserialize, Arbitrary)]
+#[serde(tag = "n")]
+pub enum BatchResult {
+    #[serde(rename = "ok")]
+    Ok {
+        #[serde(rename = "s")]
+        outputs: Vec<Blinded<OutputSignature>>,
+    },
+    #[serde(rename = "error")]
+    Error {
+        code: u16,
+        retryable: bool,
+        message: String,
+        error: Error,
+    },
+}
+
+impl BatchResult {
+    #[inline]
+    pub fn is_ok(&self) -> bool {
+        matches!(self, Self::Ok { .. })
+    }
+}
+
+impl From<Result<Vec<Blinded<OutputSignature>>, Error>> for BatchResult {
+    fn from(result: Result<Vec<Blinded<OutputSignature>>, Error>) -> Self {
+        match result {
+            Ok(outputs) => Self::Ok { outputs },
+            Err(error) => Self::Error {
+                code: error.code(),
+                retryable: error.is_retryable(),
+                message: error.to_string(),
+                error,
+            },
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use serde::{Deserialize, Serialize};
+use test_strategy::Arbitrary;
+
+use crate::{crypto, types::Hash};
+
+/// Most notes that can be checked in a single request.
+pub const MAX_CHECK: usize = 256;
+
+/// Reference to a note that does not reveal its signature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteRef {
+    Commitment(Hash),
+    /// The compressed point the note is signed on, see [`crypto::y_point`].
+    Y(Hash),
+}
+
+impl NoteRef {
+    pub fn y(&self) -> Hash {
+        match self {
+            Self::Commitment(commitment) => crypto::y_point(commitment),
+            Self::Y(y) => *y,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteState {
+    Unspent,
+    /// Reserved by an operation that has not completed yet.
+    Pending,
+    Spent,
+    /// Not a reference to any note, like a Y that is not a valid point.
+    Invalid,
+}
@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+use test_strategy::Arbitrary;
+
+use crate::types::{Hash, PublicKey, Transaction};
+
+/// Decision taken by the coordinator of a cross-delegate transaction, once
+/// every participant prepared it or one of them refused to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    Commit,
+    Abort,
+}
+
+/// Outcome of a cross-delegate transaction. The coordinator signs it, see
+/// [`crate::types::Signed`], so participants only apply decisions it took.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+pub struct Outcome {
+    pub id: Hash,
+    pub decision: Decision,
+}
+
+/// Request of the coordinator of a cross-delegate transaction for another
+/// delegate in it to prepare it. The coordinator signs it, see
+/// [`crate::types::Signed`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+pub struct Proposal {
+    #[serde(rename = "t")]
+    pub transaction: Transaction,
+    #[serde(rename = "c")]
+    pub coordinator: PublicKey,
+    /// Seconds since the Unix epoch after which the coordinator no longer
+    /// commits the transaction, and aborts it if asked for its outcome.
+    #[serde(rename = "e")]
+    pub expires: u64,
+}
+
+/// A cross-delegate transaction as recorded by one of its delegates.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrossTransaction {
+    pub transaction: Transaction,
+    pub coordinator: PublicKey,
+    /// Fees charged for the transaction. Only the coordinator charges them,
+    /// so this is empty for every other delegate.
+    pub fees: Vec<(Hash, u128)>,
+    /// `None` while the transaction is prepared but not decided yet.
+    pub decision: Option<Decision>,
+    /// See [`Proposal::expires`].
+    #[serde(default)]
+    pub expires: u64,
+}
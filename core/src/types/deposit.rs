@@ -0,0 +1,64 @@
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+use test_strategy::Arbitrary;
+
+use crate::{
+    crypto::schnorr,
+    error::Result,
+    types::{Hash, PublicKey, SecretKey, Transaction},
+};
+
+pub const MINT_SEP: &[u8] = b"mugraph_v0_mint";
+
+/// Funds locked in a delegate's vault on the L1, as observed by the node.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+pub struct Deposit {
+    /// Reference to the deposit on the L1, like a transaction output.
+    pub id: Hash,
+    pub asset_id: Hash,
+    pub amount: u64,
+    /// Key allowed to claim notes for this deposit.
+    pub owner: PublicKey,
+    pub confirmations: u64,
+}
+
+/// Request for the delegate to sign outputs backed by an L1 deposit.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+pub struct Mint {
+    #[serde(rename = "d")]
+    pub deposit: Hash,
+    #[serde(rename = "o")]
+    pub outputs: Transaction,
+    #[serde(rename = "s")]
+    pub signature: schnorr::Signature,
+}
+
+impl Mint {
+    fn message(deposit: &Hash, outputs: &Transaction) -> Vec<u8> {
+        [MINT_SEP, deposit.as_ref(), outputs.id().as_ref()].concat()
+    }
+
+    pub fn new<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        owner: &SecretKey,
+        deposit: Hash,
+        outputs: Transaction,
+    ) -> Self {
+        let signature = schnorr::sign(rng, owner, &Self::message(&deposit, &outputs));
+
+        Self {
+            deposit,
+            outputs,
+            signature,
+        }
+    }
+
+    /// Checks that this mint was authorized by the owner of the deposit.
+    pub fn verify(&self, owner: &PublicKey) -> Result<()> {
+        schnorr::verify(
+            owner,
+            &self.signature,
+            &Self::message(&self.deposit, &self.outputs),
+        )
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use test_strategy::Arbitrary;
+
+use crate::{
+    error::Error,
+    types::{Hash, Transaction},
+};
+
+/// Basis points in a whole, used for proportional fees.
+pub const BASIS_POINTS: u128 = 10_000;
+
+/// Fee charged on the inputs of a single asset, paid in that asset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+pub struct Fee {
+    /// Charged once for every transaction spending the asset.
+    #[serde(default)]
+    pub flat: u64,
+    /// Charged for every input of the asset.
+    #[serde(default)]
+    pub per_input: u64,
+    /// Charged on the total amount of the inputs, in basis points and
+    /// rounded up.
+    #[serde(default)]
+    pub basis_points: u16,
+}
+
+impl Fee {
+    /// Fee for spending `inputs` notes adding up to `total`.
+    pub fn amount(&self, inputs: u32, total: u128) -> u128 {
+        if inputs == 0 {
+            return 0;
+        }
+
+        self.flat as u128
+            + self.per_input as u128 * inputs as u128
+            + (total * self.basis_points as u128).div_ceil(BASIS_POINTS)
+    }
+}
+
+/// Fees charged by a delegate for processing transactions.
+///
+/// Fees only depend on the inputs of a transaction, so the amount left for
+/// its outputs is known as soon as the inputs are picked.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+pub struct FeeSchedule {
+    /// Fee for assets without an entry in `assets`.
+    #[serde(default)]
+    pub default: Fee,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub assets: BTreeMap<Hash, Fee>,
+}
+
+impl FeeSchedule {
+    pub fn get(&self, asset_id: &Hash) -> Fee {
+        self.assets.get(asset_id).copied().unwrap_or(self.default)
+    }
+
+    /// Fee owed for each asset of the transaction, in the order of its asset
+    /// ids.
+    pub fn fees(&self, transaction: &Transaction) -> Result<Vec<u128>, Error> {
+        let mut inputs = vec![0u32; transaction.asset_ids.len()];
+        let (pre, _) = transaction.balances()?;
+
+        for (i, atom) in transaction.atoms.iter().enumerate() {
+            if transaction.is_input(i) {
+                inputs[atom.asset_id as usize] += 1;
+            }
+        }
+
+        Ok(transaction
+            .asset_ids
+            .iter()
+            .enumerate()
+            .map(|(i, asset_id)| self.get(asset_id).amount(inputs[i], pre[i]))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{types::Atom, utils::BitSet32};
+
+    fn transaction(input: u64, output: u64) -> Transaction {
+        let mut input_mask = BitSet32::new();
+        input_mask.insert(0);
+
+        Transaction {
+            input_mask,
+            atoms: [(input, Some(0)), (output, None)]
+                .into_iter()
+                .map(|(amount, signature)| Atom {
+                    amount,
+                    signature,
+                    ..Default::default()
+                })
+                .collect(),
+            asset_ids: vec![Hash::digest(b"asset")],
+            signatures: vec![Default::default()],
+            data: vec![],
+            witnesses: vec![],
+        }
+    }
+
+    #[test]
+    fn test_fee_amount() {
+        let fee = Fee {
+            flat: 10,
+            per_input: 2,
+            basis_points: 30,
+        };
+
+        assert_eq!(fee.amount(0, 1_000), 0);
+        assert_eq!(fee.amount(1, 0), 12);
+        assert_eq!(fee.amount(3, 10_000), 10 + 6 + 30);
+        // Proportional fees are rounded up.
+        assert_eq!(fee.amount(1, 1), 13);
+    }
+
+    #[test]
+    fn test_schedule_serialization() {
+        let asset_id = Hash::digest(b"asset");
+        let schedule = FeeSchedule {
+            default: Fee {
+                flat: 1,
+                ..Default::default()
+            },
+            assets: BTreeMap::from([(
+                asset_id,
+                Fee {
+                    basis_points: 5,
+                    ..Default::default()
+                },
+            )]),
+        };
+
+        let json = serde_json::to_value(&schedule).unwrap();
+
+        assert_eq!(json["assets"][asset_id.to_string()]["basis_points"], 5);
+        assert_eq!(
+            serde_json::from_value::<FeeSchedule>(json).unwrap(),
+            schedule
+        );
+        assert_eq!(schedule.get(&Hash::zero()).flat, 1);
+    }
+
+    #[test]
+    fn test_verify_with_fees() {
+        let schedule = FeeSchedule {
+            default: Fee {
+                flat: 10,
+                per_input: 2,
+                basis_points: 0,
+            },
+            ..Default::default()
+        };
+
+        assert_eq!(
+            transaction(100, 88).verify_with_fees(&schedule).unwrap(),
+            vec![(Hash::digest(b"asset"), 12)]
+        );
+        assert!(matches!(
+            transaction(100, 90).verify_with_fees(&schedule),
+            Err(Error::InvalidFee {
+                expected: 12,
+                got: 10,
+                ..
+            })
+        ));
+        assert!(matches!(
+            transaction(100, 101).verify_with_fees(&schedule),
+            Err(Error::UnbalancedTransaction { .. })
+        ));
+        assert_eq!(
+            transaction(100, 100)
+                .verify_with_fees(&FeeSchedule::default())
+                .unwrap(),
+            vec![]
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+use test_strategy::Arbitrary;
+
+use crate::types::{Asset, FeeSchedule, PublicKey, DATA_SIZE, MAX_ATOMS, MAX_INPUTS, MAX_OUTPUTS};
+
+/// Versions of the protocol spoken by this implementation, matching the
+/// prefixes of the routes serving them.
+pub const PROTOCOL_VERSIONS: &[&str] = &["v0"];
+
+/// Seconds the clock of a client may be off from the one of the delegate.
+pub const CLOCK_SKEW: u64 = 60;
+
+/// Signed metadata only valid for a while, so a copy with outdated fees or
+/// assets can't be passed off as current once it expires.
+pub trait Expiring {
+    /// Unix time, in seconds, the metadata was issued at.
+    fn issued_at(&self) -> u64;
+
+    /// Unix time, in seconds, the metadata stops being valid at.
+    fn expires(&self) -> u64;
+
+    /// Whether the metadata is valid at `now`, within [`CLOCK_SKEW`].
+    fn is_valid_at(&self, now: u64) -> bool {
+        self.issued_at() <= now.saturating_add(CLOCK_SKEW)
+            && now < self.expires().saturating_add(CLOCK_SKEW)
+    }
+}
+
+/// Limits a transaction must respect to be accepted by a delegate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+pub struct Limits {
+    pub max_inputs: u64,
+    pub max_outputs: u64,
+    pub max_atoms: u64,
+    pub data_size: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_inputs: MAX_INPUTS as u64,
+            max_outputs: MAX_OUTPUTS as u64,
+            max_atoms: MAX_ATOMS as u64,
+            data_size: DATA_SIZE as u64,
+        }
+    }
+}
+
+/// Everything a wallet needs to know to start using a delegate.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+pub struct Info {
+    pub public_key: PublicKey,
+    pub versions: Vec<String>,
+    pub limits: Limits,
+    pub assets: Vec<Asset>,
+    /// Confirmations a deposit needs before notes can be minted for it.
+    pub min_confirmations: u64,
+    /// Fees charged on transactions.
+    pub fees: FeeSchedule,
+    pub issued_at: u64,
+    pub expires: u64,
+}
+
+impl Expiring for Info {
+    fn issued_at(&self) -> u64 {
+        self.issued_at
+    }
+
+    fn expires(&self) -> u64 {
+        self.expires
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+pub struct DelegateKey {
+    pub public_key: PublicKey,
+    /// Whether the delegate signs new outputs with this key. Notes signed by
+    /// inactive keys can still be spent.
+    pub active: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+pub struct Keys {
+    pub keys: Vec<DelegateKey>,
+    pub issued_at: u64,
+    pub expires: u64,
+}
+
+impl Expiring for Keys {
+    fn issued_at(&self) -> u64 {
+        self.issued_at
+    }
+
+    fn expires(&self) -> u64 {
+        self.expires
+    }
+}
+
+impl Keys {
+    pub fn active(&self) -> Option<PublicKey> {
+        self.keys.iter().find(|k| k.active).map(|k| k.public_key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_valid_at() {
+        let keys = Keys {
+            keys: vec![],
+            issued_at: 1000,
+            expires: 1600,
+        };
+
+        assert!(keys.is_valid_at(1000));
+        assert!(keys.is_valid_at(1000 - CLOCK_SKEW));
+        assert!(keys.is_valid_at(1600 + CLOCK_SKEW - 1));
+        assert!(!keys.is_valid_at(1000 - CLOCK_SKEW - 1));
+        assert!(!keys.is_valid_at(1600 + CLOCK_SKEW));
+    }
+}
@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+use test_strategy::Arbitrary;
+
+use crate::{
+    merkle::{self, SumNode},
+    types::{Hash, PublicKey},
+};
+
+pub const SHARE_SEP: &[u8] = b"mugraph_v0_share";
+
+/// Id of one of the two shares an output is split in, which is what the
+/// leaves of the Merkle-sum tree commit to.
+pub fn share_id(commitment: &Hash, share: u8) -> Hash {
+    Hash::digest(&[SHARE_SEP, commitment.as_ref(), &[share]].concat())
+}
+
+/// Totals for a single asset, along with the root of a Merkle-sum tree over
+/// every output the delegate has issued for it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+pub struct AssetLiabilities {
+    pub asset_id: Hash,
+    pub issued: u128,
+    pub burned: u128,
+    /// Leaves of the tree, two for every output after a leading empty one.
+    pub leaves: u64,
+    pub root: Hash,
+}
+
+impl AssetLiabilities {
+    /// Value the delegate owes to note holders, which should never exceed
+    /// what is locked in its vault.
+    pub fn outstanding(&self) -> u128 {
+        self.issued.saturating_sub(self.burned)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+pub struct LiabilitiesReport {
+    pub delegate: PublicKey,
+    pub assets: Vec<AssetLiabilities>,
+}
+
+impl LiabilitiesReport {
+    pub fn asset(&self, asset_id: &Hash) -> Option<&AssetLiabilities> {
+        self.assets.iter().find(|a| a.asset_id == *asset_id)
+    }
+}
+
+/// Share of an output in the Merkle-sum tree, with its path to the root.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+pub struct LiabilityShare {
+    pub index: u64,
+    pub amount: u64,
+    pub path: Vec<SumNode>,
+}
+
+/// Proves that an issued output is counted in an asset's total.
+///
+/// Every output is split in two shares of random amounts, which are leaves
+/// of the tree next to the shares of the outputs before and after it. Every
+/// subtree but the root then holds part of an output, so the sums along the
+/// path only bound the amounts of other notes instead of giving them away.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+pub struct LiabilityProof {
+    pub asset_id: Hash,
+    pub commitment: Hash,
+    pub amount: u64,
+    pub size: u64,
+    pub shares: [LiabilityShare; 2],
+}
+
+impl LiabilityProof {
+    pub fn verify(&self, liabilities: &AssetLiabilities) -> bool {
+        liabilities.asset_id == self.asset_id
+            && liabilities.leaves == self.size
+            && self.shares.iter().map(|s| s.amount as u128).sum::<u128>() == self.amount as u128
+            && self.shares.iter().enumerate().all(|(i, share)| {
+                merkle::verify_sum_inclusion(
+                    merkle::sum_leaf(share_id(&self.commitment, i as u8).as_ref(), share.amount),
+                    share.index,
+                    self.size,
+                    &share.path,
+                    (liabilities.root, liabilities.issued),
+                )
+            })
+    }
+}
@@ -0,0 +1,89 @@
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+use test_strategy::Arbitrary;
+
+use crate::{
+    crypto::schnorr,
+    error::Result,
+    merkle,
+    types::{Hash, PublicKey, SecretKey},
+};
+
+pub const TREE_HEAD_SEP: &[u8] = b"mugraph_v0_tree_head";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+pub struct TreeHead {
+    pub size: u64,
+    pub root: Hash,
+}
+
+impl TreeHead {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [
+            TREE_HEAD_SEP,
+            self.size.to_le_bytes().as_ref(),
+            self.root.as_ref(),
+        ]
+        .concat()
+    }
+
+    pub fn sign<R: RngCore + CryptoRng>(
+        self,
+        rng: &mut R,
+        secret_key: &SecretKey,
+    ) -> SignedTreeHead {
+        SignedTreeHead {
+            signature: schnorr::sign(rng, secret_key, &self.to_bytes()),
+            head: self,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+pub struct SignedTreeHead {
+    #[serde(flatten)]
+    pub head: TreeHead,
+    pub signature: schnorr::Signature,
+}
+
+impl SignedTreeHead {
+    pub fn verify(&self, public_key: &PublicKey) -> Result<()> {
+        schnorr::verify(public_key, &self.signature, &self.head.to_bytes())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+pub struct InclusionProof {
+    pub id: Hash,
+    pub index: u64,
+    pub size: u64,
+    pub path: Vec<Hash>,
+}
+
+impl InclusionProof {
+    /// Checks that the transaction `id` is included in a tree with `root`.
+    pub fn verify(&self, root: Hash) -> bool {
+        merkle::verify_inclusion(
+            merkle::leaf_hash(self.id.as_ref()),
+            self.index,
+            self.size,
+            &self.path,
+            root,
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+pub struct ConsistencyProof {
+    pub first: u64,
+    pub second: u64,
+    pub path: Vec<Hash>,
+}
+
+impl ConsistencyProof {
+    /// Checks that the tree with `first_root` is a prefix of the tree with
+    /// `second_root`, meaning no entries have been rewritten or removed.
+    pub fn verify(&self, first_root: Hash, second_root: Hash) -> bool {
+        merkle::verify_consistency(self.first, self.second, first_root, second_root, &self.path)
+    }
+}
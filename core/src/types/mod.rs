@@ -1,21 +1,49 @@
+mod asset;
+mod batch;
+mod check;
+mod cross;
+mod deposit;
+mod fee;
 mod hash;
+mod info;
 mod keypair;
+mod liabilities;
+mod log;
 mod note;
+mod payment_request;
 mod public_key;
 mod request;
 mod response;
 mod secret_key;
+mod settlement;
 mod signature;
+mod signed;
+mod token;
 mod transaction;
+mod withdrawal;
 
 pub use self::{
+    asset::*,
+    batch::*,
+    check::*,
+    cross::*,
+    deposit::*,
+    fee::*,
     hash::*,
+    info::*,
     keypair::*,
+    liabilities::*,
+    log::*,
     note::*,
+    payment_request::*,
     public_key::*,
     request::{v0::Request as V0Request, Request},
     response::{v0::Response as V0Response, Response},
     secret_key::*,
+    settlement::*,
     signature::*,
+    signed::*,
+    token::*,
     transaction::*,
+    withdrawal::*,
 };
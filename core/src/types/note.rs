@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-use crate::types::*;
+use crate::{crypto::dleq, error::Error, types::*};
 
 pub const COMMITMENT_INPUT_SIZE: usize = 104;
 
@@ -13,18 +13,65 @@ pub struct Note {
     pub asset_id: Hash,
     pub nonce: Hash,
     pub signature: Signature,
+    /// Key whose owner must sign any transaction spending the note, see
+    /// [`Transaction::witnesses`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lock: Option<PublicKey>,
+    /// Proof that `signature` was made by the delegate, so the note can be
+    /// checked without it, see [`crate::crypto::dleq`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proof: Option<dleq::Proof>,
 }
 
 impl Note {
     pub fn commitment(&self) -> Hash {
-        let mut output = [0u8; COMMITMENT_INPUT_SIZE];
+        commitment(
+            &self.delegate,
+            &self.asset_id,
+            self.amount,
+            &self.nonce,
+            self.lock.as_ref(),
+        )
+    }
+
+    /// Checks with its proof that the note is signed by its delegate. Notes
+    /// without a proof can only be checked by the delegate.
+    pub fn verify(&self) -> Result<bool, Error> {
+        match &self.proof {
+            Some(proof) => dleq::verify(
+                &self.delegate,
+                self.commitment().as_ref(),
+                self.signature,
+                proof,
+            ),
+            None => Ok(false),
+        }
+    }
+}
+
+/// Commitment signed by the delegate for a note. The lock is appended only
+/// when there is one, so unlocked notes commit to the same bytes as before
+/// locks existed.
+pub fn commitment(
+    delegate: &PublicKey,
+    asset_id: &Hash,
+    amount: u64,
+    nonce: &Hash,
+    lock: Option<&PublicKey>,
+) -> Hash {
+    let mut output = [0u8; COMMITMENT_INPUT_SIZE + 32];
 
-        output[0..32].copy_from_slice(self.delegate.as_ref());
-        output[32..64].copy_from_slice(self.asset_id.as_ref());
-        output[64..72].copy_from_slice(&self.amount.to_le_bytes());
-        output[72..104].copy_from_slice(self.nonce.as_ref());
+    output[0..32].copy_from_slice(delegate.as_ref());
+    output[32..64].copy_from_slice(asset_id.as_ref());
+    output[64..72].copy_from_slice(&amount.to_le_bytes());
+    output[72..104].copy_from_slice(nonce.as_ref());
 
-        Hash::digest(&output)
+    match lock {
+        Some(lock) => {
+            output[COMMITMENT_INPUT_SIZE..].copy_from_slice(lock.as_ref());
+            Hash::digest(&output)
+        }
+        None => Hash::digest(&output[..COMMITMENT_INPUT_SIZE]),
     }
 }
 
@@ -37,7 +84,7 @@ mod tests {
 
     #[test]
     fn test_byte_sizes() {
-        assert_eq!(size_of::<Note>(), 136);
+        assert_eq!(size_of::<Note>(), 240);
         assert_eq!(align_of::<Note>(), 8);
     }
 
@@ -49,7 +96,7 @@ mod tests {
 
     #[proptest]
     fn test_commitment(note: Note) {
-        let expected = [
+        let mut expected = [
             note.delegate.as_ref(),
             note.asset_id.as_ref(),
             note.amount.to_le_bytes().as_ref(),
@@ -57,6 +104,10 @@ mod tests {
         ]
         .concat();
 
+        if let Some(lock) = note.lock {
+            expected.extend_from_slice(lock.as_ref());
+        }
+
         prop_assert_eq!(Hash::digest(&expected), note.commitment());
     }
 }
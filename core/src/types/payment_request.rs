@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+use crate::types::*;
+
+/// Asks for an amount of an asset held with a given delegate, to be paid
+/// with a [`Token`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaymentRequest {
+    #[serde(rename = "u")]
+    pub url: String,
+    #[serde(rename = "d")]
+    pub delegate: PublicKey,
+    #[serde(rename = "a")]
+    pub asset_id: Hash,
+    #[serde(rename = "v")]
+    pub amount: u64,
+    /// Attached to the data of the paying transaction, so the receiver can
+    /// tell what it pays for.
+    #[serde(rename = "m", default, skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+    /// Key to lock the payment to, so whoever made the request can accept
+    /// it without reaching the delegate, see [`Token::verify`].
+    #[serde(rename = "l", default, skip_serializing_if = "Option::is_none")]
+    pub lock: Option<PublicKey>,
+}
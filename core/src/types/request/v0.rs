@@ -1,9 +1,118 @@
 use serde::{Deserialize, Serialize};
 use test_strategy::Arbitrary;
 
+use crate::types::Hash;
+
 #[derive(Debug, Clone, Serialize, Deserialize, Arbitrary)]
 #[serde(tag = "m", content = "p")]
 pub enum Request {
     #[serde(rename = "transaction")]
     Transaction(crate::types::Transaction),
+    #[serde(rename = "mint")]
+    Mint(crate::types::Mint),
+    #[serde(rename = "withdraw")]
+    Withdraw(crate::types::Withdraw),
+    #[serde(rename = "withdrawal")]
+    Withdrawal { id: Hash },
+    #[serde(rename = "refund")]
+    Refund(crate::types::Refund),
+    #[serde(rename = "check")]
+    Check {
+        #[serde(rename = "n")]
+        notes: Vec<crate::types::NoteRef>,
+    },
+    #[serde(rename = "batch")]
+    Batch {
+        #[serde(rename = "t")]
+        transactions: Vec<crate::types::Transaction>,
+    },
+    #[serde(rename = "bundle")]
+    Bundle {
+        #[serde(rename = "t")]
+        transactions: Vec<crate::types::Transaction>,
+    },
+    /// First phase of a cross-delegate transaction, sent by its coordinator
+    /// to every other delegate with atoms in it.
+    #[serde(rename = "prepare")]
+    Prepare {
+        #[serde(rename = "p")]
+        proposal: crate::types::Signed<crate::types::Proposal>,
+    },
+    /// Second phase of a cross-delegate transaction.
+    #[serde(rename = "decide")]
+    Decide {
+        #[serde(rename = "o")]
+        outcome: crate::types::Signed<crate::types::Outcome>,
+    },
+    /// Decision taken on a cross-delegate transaction coordinated by the
+    /// delegate answering, for delegates that prepared it and never heard
+    /// back. Aborts it if it expired undecided.
+    #[serde(rename = "outcome")]
+    Outcome {
+        #[serde(rename = "i")]
+        id: crate::types::Hash,
+    },
+    /// State of the netting with the peer signing the query, as recorded by
+    /// the delegate answering.
+    #[serde(rename = "netting")]
+    Netting {
+        #[serde(rename = "q")]
+        query: crate::types::Signed<crate::types::NettingQuery>,
+    },
+    /// Proposes the next netting statement, for the delegate answering to
+    /// countersign.
+    #[serde(rename = "net")]
+    Net {
+        #[serde(rename = "s")]
+        statement: crate::types::Signed<crate::types::Statement>,
+    },
+    /// Signs again the outputs of a transaction the delegate answering has
+    /// already processed, for clients that lost its response.
+    #[serde(rename = "restore")]
+    Restore {
+        #[serde(rename = "t")]
+        transaction: crate::types::Transaction,
+        /// Secret the outputs of the transaction are derived from, proving
+        /// the caller owns them.
+        #[serde(rename = "s")]
+        secret: crate::types::Hash,
+    },
+}
+
+impl Request {
+    /// Name of the method, as in the `m` field of its encoding.
+    pub fn method(&self) -> &'static str {
+        match self {
+            Self::Transaction(_) => "transaction",
+            Self::Mint(_) => "mint",
+            Self::Withdraw(_) => "withdraw",
+            Self::Withdrawal { .. } => "withdrawal",
+            Self::Refund(_) => "refund",
+            Self::Check { .. } => "check",
+            Self::Batch { .. } => "batch",
+            Self::Bundle { .. } => "bundle",
+            Self::Prepare { .. } => "prepare",
+            Self::Decide { .. } => "decide",
+            Self::Outcome { .. } => "outcome",
+            Self::Netting { .. } => "netting",
+            Self::Net { .. } => "net",
+            Self::Restore { .. } => "restore",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use test_strategy::proptest;
+
+    use super::*;
+
+    #[proptest]
+    fn test_method(request: Request) {
+        prop_assert_eq!(
+            &serde_json::to_value(&request).unwrap()["m"],
+            request.method()
+        );
+    }
 }
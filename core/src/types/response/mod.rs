@@ -1,6 +1,8 @@
 use serde::{Deserialize, Serialize};
 use test_strategy::Arbitrary;
 
+use crate::error::Error;
+
 pub mod v0;
 
 #[derive(Debug, Serialize, Deserialize, Arbitrary)]
@@ -8,6 +10,24 @@ pub mod v0;
 pub enum Response {
     #[serde(rename = "v0")]
     V0(v0::Response),
+    #[serde(rename = "error")]
+    Error {
+        code: u16,
+        retryable: bool,
+        message: String,
+        error: Error,
+    },
+}
+
+impl From<Error> for Response {
+    fn from(error: Error) -> Self {
+        Self::Error {
+            code: error.code(),
+            retryable: error.is_retryable(),
+            message: error.to_string(),
+            error,
+        }
+    }
 }
 
 impl From<v0::Response> for Response {
@@ -15,3 +35,55 @@ impl From<v0::Response> for Response {
         Self::V0(response)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::types::Signature;
+
+    #[test]
+    fn test_error_serialization() {
+        let response: Response = Error::AlreadySpent {
+            signature: Signature::zero(),
+        }
+        .into();
+
+        let expected = json!({
+            "n": "error",
+            "code": 1002,
+            "retryable": false,
+            "message": format!("Atom has already been spent: {}", Signature::zero()),
+            "error": {
+                "already_spent": {
+                    "signature": Signature::zero(),
+                }
+            }
+        });
+
+        assert_eq!(expected, serde_json::to_value(&response).unwrap());
+    }
+
+    #[test]
+    fn test_retryable() {
+        assert!(Error::StorageError {
+            kind: "io".to_string(),
+            reason: "disk full".to_string()
+        }
+        .is_retryable());
+        assert!(!Error::InvalidTransaction {
+            reason: "bad".to_string()
+        }
+        .is_retryable());
+        assert!(!Error::Multiple {
+            errors: vec![
+                Error::RngError {
+                    reason: "entropy".to_string()
+                },
+                Error::Other
+            ]
+        }
+        .is_retryable());
+    }
+}
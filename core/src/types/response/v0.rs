@@ -9,6 +9,78 @@ pub enum Response {
     #[serde(rename = "transaction")]
     Transaction {
         #[serde(rename = "s")]
-        outputs: Vec<Blinded<Signature>>,
+        outputs: Vec<Blinded<OutputSignature>>,
+    },
+    #[serde(rename = "mint")]
+    Mint {
+        #[serde(rename = "s")]
+        outputs: Vec<Blinded<OutputSignature>>,
+    },
+    #[serde(rename = "withdraw")]
+    Withdraw {
+        id: Hash,
+        #[serde(rename = "s")]
+        outputs: Vec<Blinded<OutputSignature>>,
+    },
+    #[serde(rename = "withdrawal")]
+    Withdrawal(Withdrawal),
+    #[serde(rename = "refund")]
+    Refund {
+        #[serde(rename = "s")]
+        outputs: Vec<Blinded<OutputSignature>>,
+    },
+    #[serde(rename = "check")]
+    Check {
+        #[serde(rename = "s")]
+        states: Vec<NoteState>,
+    },
+    #[serde(rename = "batch")]
+    Batch {
+        #[serde(rename = "r")]
+        results: Vec<BatchResult>,
+    },
+    /// Outputs of every transaction in the bundle, in the order they were
+    /// submitted.
+    #[serde(rename = "bundle")]
+    Bundle {
+        #[serde(rename = "s")]
+        outputs: Vec<Vec<Blinded<OutputSignature>>>,
+    },
+    #[serde(rename = "prepared")]
+    Prepared { id: Hash },
+    /// Signatures for the outputs of the delegate answering, in order. Empty
+    /// when the transaction was aborted.
+    #[serde(rename = "decided")]
+    Decided {
+        #[serde(rename = "s")]
+        outputs: Vec<Blinded<OutputSignature>>,
+    },
+    #[serde(rename = "outcome")]
+    Outcome {
+        #[serde(rename = "o")]
+        outcome: Signed<Outcome>,
+    },
+    #[serde(rename = "netting")]
+    Netting {
+        #[serde(rename = "v")]
+        vault: String,
+        /// Last statement signed by both delegates.
+        #[serde(rename = "s")]
+        statement: Option<Box<NettingStatement>>,
+        /// Settlements confirmed since that statement.
+        #[serde(rename = "c")]
+        settlements: Vec<Settlement>,
+    },
+    /// Signature of the delegate answering over the proposed statement.
+    #[serde(rename = "netted")]
+    Netted {
+        #[serde(rename = "s")]
+        signature: crate::crypto::schnorr::Signature,
+    },
+    /// Signatures for the outputs of the delegate answering, in order.
+    #[serde(rename = "restored")]
+    Restored {
+        #[serde(rename = "s")]
+        outputs: Vec<Blinded<OutputSignature>>,
     },
 }
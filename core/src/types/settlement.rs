@@ -0,0 +1,474 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use test_strategy::Arbitrary;
+
+use crate::{
+    crypto::schnorr,
+    error::{Error, Result},
+    types::{Hash, PublicKey, Signed, Transaction},
+};
+
+/// Value a cross-delegate transaction moved between the vaults of two
+/// delegates. The debtor burned inputs backing more than it issued, so it
+/// holds value on the L1 that now backs notes issued by the creditor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Obligation {
+    pub debtor: PublicKey,
+    pub creditor: PublicKey,
+    pub asset_id: Hash,
+    pub amount: u128,
+}
+
+impl Obligation {
+    /// Obligations left by `transaction` between its delegates.
+    ///
+    /// Delegates left with value pay the ones short of it, in the order they
+    /// appear in the transaction, so every delegate in it computes the same
+    /// obligations. Fees stay with the delegates that burned them.
+    pub fn of(transaction: &Transaction) -> Vec<Self> {
+        let delegates = transaction.delegates();
+        let mut obligations = vec![];
+
+        for (index, asset_id) in transaction.asset_ids.iter().enumerate() {
+            // Value burned minus value issued by each delegate.
+            let mut net = vec![0i128; delegates.len()];
+
+            for (i, atom) in transaction.atoms.iter().enumerate() {
+                if atom.asset_id as usize != index {
+                    continue;
+                }
+
+                let Some(d) = delegates.iter().position(|d| *d == atom.delegate) else {
+                    continue;
+                };
+
+                match transaction.is_input(i) {
+                    true => net[d] += atom.amount as i128,
+                    false => net[d] -= atom.amount as i128,
+                }
+            }
+
+            let side = |debtors: bool| {
+                delegates
+                    .iter()
+                    .zip(&net)
+                    .filter(|(_, net)| (**net > 0) == debtors && **net != 0)
+                    .map(|(delegate, net)| (*delegate, net.unsigned_abs()))
+                    .collect::<Vec<_>>()
+            };
+            let (mut debtors, mut creditors) = (side(true), side(false));
+            let (mut i, mut j) = (0, 0);
+
+            while i < debtors.len() && j < creditors.len() {
+                let amount = debtors[i].1.min(creditors[j].1);
+
+                obligations.push(Self {
+                    debtor: debtors[i].0,
+                    creditor: creditors[j].0,
+                    asset_id: *asset_id,
+                    amount,
+                });
+
+                debtors[i].1 -= amount;
+                creditors[j].1 -= amount;
+
+                if debtors[i].1 == 0 {
+                    i += 1;
+                }
+
+                if creditors[j].1 == 0 {
+                    j += 1;
+                }
+            }
+        }
+
+        obligations
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+#[serde(tag = "s", rename_all = "snake_case")]
+pub enum SettlementStatus {
+    /// Waiting to be submitted to the L1.
+    Pending,
+    Submitted {
+        tx: Hash,
+    },
+    Confirmed {
+        tx: Hash,
+    },
+    Failed {
+        reason: String,
+    },
+}
+
+/// Payout from the vault of a delegate to the vault of a peer, paying off
+/// what it owes as of their last netting statement.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+pub struct Settlement {
+    /// Id of the statement being paid off, also used as the id of the payout.
+    pub id: Hash,
+    pub debtor: PublicKey,
+    pub creditor: PublicKey,
+    pub address: String,
+    pub assets: Vec<(Hash, u64)>,
+    pub status: SettlementStatus,
+}
+
+/// Net obligations between two delegates, accumulated over every
+/// cross-delegate transaction and settlement between them.
+///
+/// Statements form a chain, each one covering what happened since the
+/// previous one, so both delegates can check it against their own records.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+pub struct Statement {
+    /// The two delegates, in ascending order.
+    pub delegates: [PublicKey; 2],
+    /// Addresses of their vaults, in the same order, where settlements are
+    /// paid to.
+    pub vaults: [String; 2],
+    pub sequence: u64,
+    /// Id of the previous statement, zero for the first one.
+    pub previous: Hash,
+    /// Cross-delegate transactions netted since the previous statement.
+    pub transactions: Vec<Hash>,
+    /// Settlements confirmed on the L1 since the previous statement.
+    pub settlements: Vec<Settlement>,
+    /// What the first delegate owes the second for each asset, negative when
+    /// it is owed instead. Assets with nothing owed are left out.
+    #[serde(with = "balances")]
+    pub balances: Vec<(Hash, i128)>,
+}
+
+impl Statement {
+    pub fn id(&self) -> Hash {
+        Hash::digest(&serde_json::to_vec(self).expect("Statement should be serializable"))
+    }
+
+    /// Position of `delegate` in the statement, if it is part of it.
+    pub fn position(&self, delegate: &PublicKey) -> Option<usize> {
+        self.delegates.iter().position(|d| d == delegate)
+    }
+
+    /// What `delegate` owes the other delegate for each asset, negative when
+    /// it is owed instead.
+    pub fn owed(&self, delegate: &PublicKey) -> Vec<(Hash, i128)> {
+        let sign = match self.position(delegate) {
+            Some(0) => 1,
+            Some(_) => -1,
+            None => return vec![],
+        };
+
+        self.balances
+            .iter()
+            .map(|(asset_id, balance)| (*asset_id, balance * sign))
+            .collect()
+    }
+
+    /// Balances once `obligations` and `settlements` are added to
+    /// `balances`, ignoring those between other delegates.
+    pub fn apply(
+        delegates: &[PublicKey; 2],
+        balances: &[(Hash, i128)],
+        obligations: &[Obligation],
+        settlements: &[Settlement],
+    ) -> Vec<(Hash, i128)> {
+        let mut totals = balances.iter().copied().collect::<BTreeMap<_, _>>();
+        let sign = |debtor: &PublicKey, creditor: &PublicKey| match (debtor, creditor) {
+            (d, c) if [*d, *c] == *delegates => Some(1),
+            (d, c) if [*c, *d] == *delegates => Some(-1),
+            _ => None,
+        };
+
+        for o in obligations {
+            if let Some(sign) = sign(&o.debtor, &o.creditor) {
+                *totals.entry(o.asset_id).or_default() += o.amount as i128 * sign;
+            }
+        }
+
+        for s in settlements {
+            if let Some(sign) = sign(&s.debtor, &s.creditor) {
+                for (asset_id, amount) in &s.assets {
+                    *totals.entry(*asset_id).or_default() -= *amount as i128 * sign;
+                }
+            }
+        }
+
+        totals.into_iter().filter(|(_, b)| *b != 0).collect()
+    }
+}
+
+/// Statement signed by both of its delegates, binding them to its balances.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+pub struct NettingStatement {
+    pub statement: Statement,
+    /// Encoding of the statement both delegates signed, see [`Signed`].
+    #[serde(with = "hex::serde")]
+    pub payload: Vec<u8>,
+    /// Signatures of the delegates over the payload, in the same order as
+    /// them.
+    pub signatures: [schnorr::Signature; 2],
+}
+
+impl NettingStatement {
+    /// Statement of `proposal`, signed by the first delegate, along with the
+    /// signature of the second one over the same payload.
+    pub fn new(proposal: &Signed<Statement>, signature: schnorr::Signature) -> Result<Self> {
+        Ok(Self {
+            statement: proposal.unverified()?,
+            payload: proposal.payload.clone(),
+            signatures: [proposal.signature, signature],
+        })
+    }
+
+    /// Checks that both delegates signed the payload, and that it encodes
+    /// the statement.
+    pub fn verify(&self) -> Result<()> {
+        for (delegate, signature) in self.statement.delegates.iter().zip(self.signatures) {
+            let signed = Signed::<Statement>::from_parts(self.payload.clone(), signature);
+
+            if signed.verify(delegate)? != self.statement {
+                return Err(Error::InvalidStatement {
+                    reason: format!(
+                        "Statement {} is not the one its delegates signed",
+                        self.statement.sequence
+                    ),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether `other` is a different statement for the same point of the
+    /// chain, which two honest delegates never sign.
+    pub fn conflicts(&self, other: &Self) -> bool {
+        self.statement.delegates == other.statement.delegates
+            && self.statement.sequence == other.statement.sequence
+            && self.statement != other.statement
+            && self.verify().is_ok()
+            && other.verify().is_ok()
+    }
+}
+
+/// Request of a delegate for the netting a peer recorded with it, signed by
+/// the delegate asking, see [`Signed`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+pub struct NettingQuery {
+    /// Delegate asking, which signs the query.
+    #[serde(rename = "p")]
+    pub peer: PublicKey,
+    /// Delegate asked, so the query can not be replayed against others.
+    #[serde(rename = "d")]
+    pub delegate: PublicKey,
+    /// Seconds since the Unix epoch when the query was made.
+    #[serde(rename = "t")]
+    pub timestamp: u64,
+}
+
+/// Evidence that two delegates disagree on what they owe each other, which
+/// anyone can check with their public keys.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+#[serde(tag = "k", rename_all = "snake_case")]
+pub enum Dispute {
+    /// Both delegates signed different statements for the same sequence.
+    Conflict {
+        ours: Box<NettingStatement>,
+        theirs: Box<NettingStatement>,
+    },
+    /// The delegates came up with different balances over the same
+    /// transactions and settlements.
+    Mismatch {
+        ours: Signed<Statement>,
+        theirs: Signed<Statement>,
+    },
+}
+
+impl Dispute {
+    pub fn id(&self) -> Hash {
+        Hash::digest(&serde_json::to_vec(self).expect("Dispute should be serializable"))
+    }
+}
+
+/// Encodes balances as decimal strings, since serde can not buffer 128-bit
+/// integers, which it does for tagged enums like [`crate::types::Response`].
+mod balances {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use crate::types::Hash;
+
+    pub fn serialize<S: Serializer>(
+        balances: &[(Hash, i128)],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(
+            balances
+                .iter()
+                .map(|(asset_id, balance)| (asset_id, balance.to_string())),
+        )
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<(Hash, i128)>, D::Error> {
+        Vec::<(Hash, String)>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(asset_id, balance)| Ok((asset_id, balance.parse().map_err(D::Error::custom)?)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        types::{Atom, Keypair, Response, Signature, V0Response},
+        utils::BitSet32,
+    };
+
+    fn atom(delegate: PublicKey, amount: u64, input: bool) -> Atom {
+        Atom {
+            delegate,
+            asset_id: 0,
+            amount,
+            nonce: Hash::default(),
+            signature: input.then_some(0),
+            lock: None,
+        }
+    }
+
+    fn keypairs() -> [Keypair; 2] {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut keypairs = [Keypair::random(&mut rng), Keypair::random(&mut rng)];
+        keypairs.sort_by_key(|k| k.public_key);
+
+        keypairs
+    }
+
+    fn netting(keypairs: &[Keypair; 2], balances: Vec<(Hash, i128)>) -> NettingStatement {
+        let statement = Statement {
+            delegates: keypairs.each_ref().map(|k| k.public_key),
+            vaults: ["a".to_string(), "b".to_string()],
+            sequence: 0,
+            previous: Hash::default(),
+            transactions: vec![],
+            settlements: vec![],
+            balances,
+        };
+        let mut rng = StdRng::seed_from_u64(2);
+        let proposal = Signed::new(&mut rng, &keypairs[0].secret_key, &statement).unwrap();
+        let signature = proposal
+            .countersign(&mut rng, &keypairs[1].secret_key)
+            .signature;
+
+        NettingStatement::new(&proposal, signature).unwrap()
+    }
+
+    #[test]
+    fn test_obligations() {
+        let [a, b, c] = [1, 2, 3].map(|i| PublicKey([i; 32]));
+        let asset_id = Hash::digest(b"asset");
+        let mut input_mask = BitSet32::new();
+        input_mask.insert(0);
+        input_mask.insert(1);
+
+        // A and C spend 150, of which B gets 100 and A 30, leaving a fee of
+        // 20 at C.
+        let transaction = Transaction {
+            input_mask,
+            atoms: vec![
+                atom(a, 100, true),
+                atom(c, 50, true),
+                atom(b, 100, false),
+                atom(a, 30, false),
+            ],
+            asset_ids: vec![asset_id],
+            signatures: vec![Signature::zero()],
+            data: vec![],
+            witnesses: vec![],
+        };
+
+        assert_eq!(
+            Obligation::of(&transaction),
+            [(a, 70), (c, 30)].map(|(debtor, amount)| Obligation {
+                debtor,
+                creditor: b,
+                asset_id,
+                amount,
+            })
+        );
+    }
+
+    #[test]
+    fn test_apply() {
+        let keypairs = keypairs();
+        let [a, b] = keypairs.each_ref().map(|k| k.public_key);
+        let asset_id = Hash::digest(b"asset");
+        let obligations = [(a, b, 70), (b, a, 20), (a, PublicKey([9; 32]), 5)].map(
+            |(debtor, creditor, amount)| Obligation {
+                debtor,
+                creditor,
+                asset_id,
+                amount,
+            },
+        );
+        let settlement = Settlement {
+            id: Hash::default(),
+            debtor: a,
+            creditor: b,
+            address: "b".to_string(),
+            assets: vec![(asset_id, 60)],
+            status: SettlementStatus::Confirmed {
+                tx: Hash::default(),
+            },
+        };
+
+        let balances = Statement::apply(&[a, b], &[(asset_id, 5)], &obligations, &[]);
+        assert_eq!(balances, [(asset_id, 55)]);
+
+        let mut statement = netting(&keypairs, balances.clone()).statement;
+        assert_eq!(statement.owed(&b), [(asset_id, -55)]);
+
+        statement.balances = Statement::apply(&[a, b], &balances, &[], &[settlement]);
+        assert_eq!(statement.owed(&a), [(asset_id, -5)]);
+    }
+
+    #[test]
+    fn test_netting_statement() {
+        let keypairs = keypairs();
+        let asset_id = Hash::digest(b"asset");
+        let netting = netting(&keypairs, vec![(asset_id, 10)]);
+        let other = self::netting(&keypairs, vec![(asset_id, 20)]);
+
+        assert!(netting.verify().is_ok());
+        assert!(netting.conflicts(&other));
+
+        let response = Response::V0(V0Response::Netting {
+            vault: "a".to_string(),
+            statement: Some(Box::new(netting.clone())),
+            settlements: vec![],
+        });
+        let json = serde_json::to_vec(&response).unwrap();
+
+        assert!(matches!(
+            serde_json::from_slice(&json).unwrap(),
+            Response::V0(V0Response::Netting { statement: Some(s), .. }) if *s == netting
+        ));
+        assert!(!netting.conflicts(&netting));
+
+        let mut forged = other.clone();
+        forged.signatures[0] = netting.signatures[0];
+
+        assert!(forged.verify().is_err());
+        assert!(!netting.conflicts(&forged));
+
+        // The statement must be the one in the signed payload.
+        let mut altered = netting.clone();
+        altered.statement.balances = vec![];
+
+        assert!(altered.verify().is_err());
+    }
+}
@@ -8,11 +8,12 @@ use serde::{Deserialize, Serialize};
 use test_strategy::Arbitrary;
 
 use crate::{
-    crypto::Point,
+    crypto::{self, dleq, Point},
     error::{Error, Result},
+    types::{PublicKey, SecretKey},
 };
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, Arbitrary)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
 #[repr(transparent)]
 #[serde(transparent)]
 pub struct Blinded<T>(pub T);
@@ -24,6 +25,31 @@ pub struct Blinded<T>(pub T);
 #[serde(transparent)]
 pub struct Signature(#[serde(with = "hex::serde")] pub [u8; 32]);
 
+/// Signature of a delegate over an output, with a proof that anyone can
+/// check it against the key of the delegate.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+pub struct OutputSignature {
+    #[serde(rename = "s")]
+    pub signature: Signature,
+    #[serde(rename = "p")]
+    pub proof: dleq::Proof,
+}
+
+impl OutputSignature {
+    pub fn new(secret_key: &SecretKey, message: &[u8]) -> Self {
+        let signature = crypto::sign(secret_key, message);
+
+        Self {
+            signature,
+            proof: dleq::prove(secret_key, message, signature),
+        }
+    }
+
+    pub fn verify(&self, public_key: &PublicKey, message: &[u8]) -> Result<bool> {
+        dleq::verify(public_key, message, self.signature, &self.proof)
+    }
+}
+
 impl Signature {
     #[inline]
     pub const fn zero() -> Self {
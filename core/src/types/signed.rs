@@ -0,0 +1,111 @@
+use std::marker::PhantomData;
+
+use rand::{CryptoRng, RngCore};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use test_strategy::Arbitrary;
+
+use crate::{
+    crypto::schnorr,
+    error::Result,
+    types::{PublicKey, SecretKey},
+};
+
+pub const SIGNED_SEP: &[u8] = b"mugraph_v0_signed";
+
+/// A payload signed by a delegate over its JSON encoding.
+///
+/// The encoding is carried as it was signed, so the signature is checked on
+/// the exact bytes before they are decoded, instead of on an encoding of the
+/// decoded payload, which could differ from it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+#[serde(bound = "")]
+#[arbitrary(bound(T: std::fmt::Debug + 'static, ..))]
+pub struct Signed<T> {
+    /// JSON encoding of the payload, as signed.
+    #[serde(with = "hex::serde")]
+    pub payload: Vec<u8>,
+    pub signature: schnorr::Signature,
+    #[serde(skip)]
+    _payload: PhantomData<T>,
+}
+
+impl<T> Signed<T> {
+    fn message(payload: &[u8]) -> Vec<u8> {
+        [SIGNED_SEP, payload].concat()
+    }
+
+    /// Pairs an encoded payload with a signature over it, which is only
+    /// checked by [`Signed::verify`].
+    pub fn from_parts(payload: Vec<u8>, signature: schnorr::Signature) -> Self {
+        Self {
+            payload,
+            signature,
+            _payload: PhantomData,
+        }
+    }
+
+    /// Signs the same payload with `secret_key`, leaving its bytes as they
+    /// are.
+    pub fn countersign<R: RngCore + CryptoRng>(&self, rng: &mut R, secret_key: &SecretKey) -> Self {
+        let signature = schnorr::sign(rng, secret_key, &Self::message(&self.payload));
+
+        Self::from_parts(self.payload.clone(), signature)
+    }
+}
+
+impl<T: Serialize> Signed<T> {
+    pub fn new<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        secret_key: &SecretKey,
+        payload: &T,
+    ) -> Result<Self> {
+        let payload = serde_json::to_vec(payload)?;
+        let signature = schnorr::sign(rng, secret_key, &Self::message(&payload));
+
+        Ok(Self::from_parts(payload, signature))
+    }
+}
+
+impl<T: DeserializeOwned> Signed<T> {
+    /// Checks that the payload is signed by `public_key`, and only then
+    /// decodes it.
+    pub fn verify(&self, public_key: &PublicKey) -> Result<T> {
+        schnorr::verify(public_key, &self.signature, &Self::message(&self.payload))?;
+
+        self.unverified()
+    }
+
+    /// Decodes the payload without checking its signature, only to find out
+    /// who should have signed it. It must not be trusted before
+    /// [`Signed::verify`].
+    pub fn unverified(&self) -> Result<T> {
+        Ok(serde_json::from_slice(&self.payload)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::thread_rng;
+
+    use super::*;
+    use crate::types::Keypair;
+
+    #[test]
+    fn test_signed_bytes() {
+        let keypair = Keypair::random(&mut thread_rng());
+        let signed = Signed::new(&mut thread_rng(), &keypair.secret_key, &(1u64, 2u64)).unwrap();
+
+        assert_eq!(signed.verify(&keypair.public_key).unwrap(), (1, 2));
+
+        // The same payload encoded differently is not what was signed.
+        let respaced = Signed::<(u64, u64)>::from_parts(b"[1, 2]".to_vec(), signed.signature);
+        assert_eq!(respaced.unverified().unwrap(), (1, 2));
+        assert!(respaced.verify(&keypair.public_key).is_err());
+
+        let other = Keypair::random(&mut thread_rng());
+        let countersigned = signed.countersign(&mut thread_rng(), &other.secret_key);
+        assert_eq!(countersigned.payload, signed.payload);
+        assert!(countersigned.verify(&other.public_key).is_ok());
+        assert!(countersigned.verify(&keypair.public_key).is_err());
+    }
+}
@@ -0,0 +1,118 @@
+use crate::{error::Error, types::*};
+use serde::{Deserialize, Serialize};
+
+/// Notes handed over from one wallet to another, along with where to reach
+/// their delegate.
+///
+/// Whoever holds a token can spend its notes, so the receiver swaps them for
+/// new ones right away. Notes locked to the receiver can only be spent by it,
+/// so it can accept them without reaching the delegate and swap them later,
+/// see [`Token::verify`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Token {
+    #[serde(rename = "u")]
+    pub url: String,
+    #[serde(rename = "n")]
+    pub notes: Vec<Note>,
+}
+
+impl Token {
+    /// Delegate of the notes, which must all belong to the same one.
+    pub fn delegate(&self) -> Result<PublicKey, Error> {
+        let delegate = match self.notes.first() {
+            Some(note) => note.delegate,
+            None => {
+                return Err(Error::InvalidTransaction {
+                    reason: "Token has no notes".to_string(),
+                })
+            }
+        };
+
+        if self.notes.iter().any(|note| note.delegate != delegate) {
+            return Err(Error::InvalidTransaction {
+                reason: "Token has notes of more than one delegate".to_string(),
+            });
+        }
+
+        Ok(delegate)
+    }
+
+    /// Checks offline that every note is signed by its delegate, with the
+    /// proof of its signature, and locked to `owner`, so nobody else can
+    /// spend it.
+    pub fn verify(&self, owner: PublicKey) -> Result<(), Error> {
+        self.delegate()?;
+
+        for note in self.notes.iter() {
+            if !note.verify()? {
+                return Err(Error::InvalidSignature {
+                    reason: format!("Note {} is not signed by its delegate", note.nonce),
+                    signature: note.signature,
+                });
+            }
+
+            if note.lock != Some(owner) {
+                return Err(Error::InvalidAtom {
+                    reason: format!("Note {} is not locked to {owner}", note.nonce),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::crypto::{dleq, Scalar, HTC_SEP};
+
+    #[test]
+    fn test_forged_token() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let delegate = Keypair::random(&mut rng);
+        let owner = Keypair::random(&mut rng);
+        let mut note = Note {
+            amount: 1_000_000,
+            delegate: delegate.public_key,
+            asset_id: Hash::random(&mut rng),
+            nonce: Hash::random(&mut rng),
+            lock: Some(owner.public_key),
+            ..Default::default()
+        };
+        let commitment = note.commitment();
+
+        // The signature anyone could compute from the key of the delegate if
+        // `H(m)` were `h(m) * G`, with or without a made up proof.
+        let h: Scalar = Hash::digest(&[HTC_SEP, commitment.as_ref()].concat()).into();
+        note.signature = (delegate.public_key.to_point().unwrap() * h).into();
+
+        for proof in [None, Some(dleq::Proof::default())] {
+            let token = Token {
+                url: "https://delegate.example".to_string(),
+                notes: vec![Note {
+                    proof,
+                    ..note.clone()
+                }],
+            };
+
+            assert!(matches!(
+                token.verify(owner.public_key),
+                Err(Error::InvalidSignature { .. })
+            ));
+        }
+
+        let signed = OutputSignature::new(&delegate.secret_key, commitment.as_ref());
+        let token = Token {
+            url: "https://delegate.example".to_string(),
+            notes: vec![Note {
+                signature: signed.signature,
+                proof: Some(signed.proof),
+                ..note
+            }],
+        };
+        token.verify(owner.public_key).unwrap();
+    }
+}
@@ -1,12 +1,18 @@
+use blake3::Hasher;
+use rand::{CryptoRng, RngCore};
 use serde::{Deserialize, Serialize};
 
-use super::{PublicKey, Signature, COMMITMENT_INPUT_SIZE};
-use crate::{error::Error, types::Hash, utils::BitSet32};
+use super::{
+    commitment, Blinded, FeeSchedule, Note, OutputSignature, PublicKey, SecretKey, Signature,
+};
+use crate::{crypto::schnorr, error::Error, types::Hash, utils::BitSet32};
 
 pub const MAX_ATOMS: usize = 12;
 pub const MAX_INPUTS: usize = 4;
 pub const MAX_OUTPUTS: usize = 8;
 pub const DATA_SIZE: usize = 256 * MAX_ATOMS;
+pub const WITNESS_SEP: &[u8] = b"mugraph_v0_witness";
+pub const OUTPUT_NONCE_SEP: &[u8] = b"mugraph_v0_output_nonce";
 
 #[derive(
     Debug,
@@ -27,18 +33,20 @@ pub struct Atom {
     pub amount: u64,
     pub nonce: Hash,
     pub signature: Option<u32>,
+    /// Lock of the note, see [`Note::lock`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lock: Option<PublicKey>,
 }
 
 impl Atom {
     pub fn commitment(&self, assets: &[Hash]) -> Hash {
-        let mut output = [0u8; COMMITMENT_INPUT_SIZE];
-
-        output[0..32].copy_from_slice(self.delegate.as_ref());
-        output[32..64].copy_from_slice(assets[self.asset_id as usize].as_ref());
-        output[64..72].copy_from_slice(&self.amount.to_le_bytes());
-        output[72..104].copy_from_slice(self.nonce.as_ref());
-
-        Hash::digest(&output)
+        commitment(
+            &self.delegate,
+            &assets[self.asset_id as usize],
+            self.amount,
+            &self.nonce,
+            self.lock.as_ref(),
+        )
     }
 }
 
@@ -64,9 +72,36 @@ pub struct Transaction {
     pub asset_ids: Vec<Hash>,
     #[serde(rename = "s")]
     pub signatures: Vec<Signature>,
+    /// Opaque data attached by the sender, such as an encrypted payment
+    /// reference. Delegates never interpret it.
+    #[serde(
+        rename = "d",
+        with = "hex::serde",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub data: Vec<u8>,
+    /// Signatures over [`Transaction::witness_message`] by the lock of each
+    /// locked input, in order.
+    #[serde(rename = "w", default, skip_serializing_if = "Vec::is_empty")]
+    pub witnesses: Vec<schnorr::Signature>,
 }
 
 impl Transaction {
+    /// Nonce of the output at `index`, counting outputs only, of a
+    /// transaction whose outputs are derived from `secret`.
+    ///
+    /// The secret must be random and used for a single transaction, so
+    /// nobody else can predict the outputs, or two of them share a nonce.
+    pub fn output_nonce(secret: &Hash, index: usize) -> Hash {
+        Hasher::new()
+            .update(OUTPUT_NONCE_SEP)
+            .update(secret.as_ref())
+            .update(&(index as u64).to_le_bytes())
+            .finalize()
+            .into()
+    }
+
     pub fn is_input(&self, id: usize) -> bool {
         self.input_mask.contains(id as u32)
     }
@@ -75,7 +110,63 @@ impl Transaction {
         !self.input_mask.contains(id as u32)
     }
 
-    pub fn verify(&self) -> Result<(), Error> {
+    /// Delegates with atoms in the transaction, in the order they first
+    /// appear.
+    pub fn delegates(&self) -> Vec<PublicKey> {
+        let mut delegates = Vec::new();
+
+        for atom in self.atoms.iter() {
+            if !delegates.contains(&atom.delegate) {
+                delegates.push(atom.delegate);
+            }
+        }
+
+        delegates
+    }
+
+    /// Unique identifier for this transaction, committing to every field but
+    /// the witnesses, which sign it.
+    pub fn id(&self) -> Hash {
+        let mut hasher = Hasher::new();
+
+        hasher.update(&self.input_mask.to_bytes());
+
+        hasher.update(&(self.atoms.len() as u32).to_le_bytes());
+        for atom in self.atoms.iter() {
+            hasher.update(atom.delegate.as_ref());
+            hasher.update(&atom.asset_id.to_le_bytes());
+            hasher.update(&atom.amount.to_le_bytes());
+            hasher.update(atom.nonce.as_ref());
+
+            match atom.signature {
+                Some(s) => hasher.update(&[1]).update(&s.to_le_bytes()),
+                None => hasher.update(&[0]),
+            };
+
+            match atom.lock {
+                Some(lock) => hasher.update(&[1]).update(lock.as_ref()),
+                None => hasher.update(&[0]),
+            };
+        }
+
+        hasher.update(&(self.asset_ids.len() as u32).to_le_bytes());
+        for asset_id in self.asset_ids.iter() {
+            hasher.update(asset_id.as_ref());
+        }
+
+        hasher.update(&(self.signatures.len() as u32).to_le_bytes());
+        for signature in self.signatures.iter() {
+            hasher.update(signature.as_ref());
+        }
+
+        hasher.update(&(self.data.len() as u32).to_le_bytes());
+        hasher.update(&self.data);
+
+        hasher.finalize().into()
+    }
+
+    /// Sums the amounts of inputs and outputs for each asset.
+    pub fn balances(&self) -> Result<(Vec<u128>, Vec<u128>), Error> {
         let mut pre = vec![0; self.asset_ids.len()];
         let mut post = vec![0; self.asset_ids.len()];
 
@@ -97,10 +188,305 @@ impl Transaction {
             target[atom.asset_id as usize] += atom.amount as u128;
         }
 
-        if pre == post {
+        Ok((pre, post))
+    }
+
+    pub fn verify(&self) -> Result<(), Error> {
+        self.check_data()?;
+        let (pre, post) = self.balances()?;
+
+        if pre != post {
             return Err(Error::UnbalancedTransaction { pre, post });
         }
 
         Ok(())
     }
+
+    /// Checks that the inputs of every asset add up to its outputs plus the
+    /// fee in `fees`, returning the fees paid.
+    pub fn verify_with_fees(&self, fees: &FeeSchedule) -> Result<Vec<(Hash, u128)>, Error> {
+        self.check_data()?;
+        let (pre, post) = self.balances()?;
+        let expected = fees.fees(self)?;
+
+        if pre.iter().zip(post.iter()).any(|(pre, post)| pre < post) {
+            return Err(Error::UnbalancedTransaction { pre, post });
+        }
+
+        for (i, asset_id) in self.asset_ids.iter().enumerate() {
+            if pre[i] - post[i] != expected[i] {
+                return Err(Error::InvalidFee {
+                    asset_id: *asset_id,
+                    expected: expected[i],
+                    got: pre[i] - post[i],
+                });
+            }
+        }
+
+        Ok(self
+            .asset_ids
+            .iter()
+            .zip(expected)
+            .filter(|(_, fee)| *fee > 0)
+            .map(|(asset_id, fee)| (*asset_id, fee))
+            .collect())
+    }
+
+    /// Turns the signatures returned by the delegate into the notes for the
+    /// outputs, in order, checking each signature against its commitment.
+    pub fn output_notes(
+        &self,
+        signatures: &[Blinded<OutputSignature>],
+    ) -> Result<Vec<Note>, Error> {
+        let outputs = (0..self.atoms.len())
+            .filter(|i| self.is_output(*i))
+            .map(|i| &self.atoms[i])
+            .collect::<Vec<_>>();
+
+        if outputs.len() != signatures.len() {
+            return Err(Error::InvalidTransaction {
+                reason: format!(
+                    "Transaction has {} outputs, but got {} signatures",
+                    outputs.len(),
+                    signatures.len()
+                ),
+            });
+        }
+
+        outputs
+            .into_iter()
+            .zip(signatures)
+            .map(|(atom, signature)| {
+                let commitment = atom.commitment(&self.asset_ids);
+
+                if !signature.0.verify(&atom.delegate, commitment.as_ref())? {
+                    return Err(Error::InvalidSignature {
+                        reason: "signature does not match the output".to_string(),
+                        signature: signature.0.signature,
+                    });
+                }
+
+                Ok(Note {
+                    amount: atom.amount,
+                    delegate: atom.delegate,
+                    asset_id: self.asset_ids[atom.asset_id as usize],
+                    nonce: atom.nonce,
+                    signature: signature.0.signature,
+                    lock: atom.lock,
+                    proof: Some(signature.0.proof),
+                })
+            })
+            .collect()
+    }
+
+    /// Checks that the attached data fits in [`DATA_SIZE`].
+    pub fn check_data(&self) -> Result<(), Error> {
+        if self.data.len() > DATA_SIZE {
+            return Err(Error::InvalidTransaction {
+                reason: format!(
+                    "Data has {} bytes, but at most {DATA_SIZE} are allowed",
+                    self.data.len()
+                ),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Indices of the locked inputs, in the order of their witnesses.
+    pub fn locked_inputs(&self) -> Vec<usize> {
+        (0..self.atoms.len())
+            .filter(|&i| self.is_input(i) && self.atoms[i].lock.is_some())
+            .collect()
+    }
+
+    /// Message the lock of every locked input signs.
+    pub fn witness_message(&self) -> Vec<u8> {
+        [WITNESS_SEP, self.id().as_ref()].concat()
+    }
+
+    /// Signs the transaction for every locked input, replacing any witnesses
+    /// it had. Fails if an input is locked to another key.
+    pub fn sign_witnesses<R: RngCore + CryptoRng>(
+        &mut self,
+        rng: &mut R,
+        secret_key: &SecretKey,
+    ) -> Result<(), Error> {
+        let public_key = secret_key.public();
+        let message = self.witness_message();
+        let mut witnesses = vec![];
+
+        for i in self.locked_inputs() {
+            if self.atoms[i].lock != Some(public_key) {
+                return Err(Error::InvalidAtom {
+                    reason: format!("Atom {i} is not locked to {public_key}"),
+                });
+            }
+
+            witnesses.push(schnorr::sign(rng, secret_key, &message));
+        }
+
+        self.witnesses = witnesses;
+
+        Ok(())
+    }
+
+    /// Checks that every locked input selected by `filter` is signed by its
+    /// lock, and that there is exactly one witness per locked input.
+    pub fn verify_witnesses(&self, filter: impl Fn(&Atom) -> bool) -> Result<(), Error> {
+        let locked = self.locked_inputs();
+
+        if locked.len() != self.witnesses.len() {
+            return Err(Error::InvalidTransaction {
+                reason: format!(
+                    "Transaction has {} locked inputs, but {} witnesses",
+                    locked.len(),
+                    self.witnesses.len()
+                ),
+            });
+        }
+
+        let message = self.witness_message();
+
+        for (i, witness) in locked.into_iter().zip(self.witnesses.iter()) {
+            let atom = &self.atoms[i];
+
+            match atom.lock {
+                Some(lock) if filter(atom) => {
+                    schnorr::verify(&lock, witness, &message).map_err(|_| Error::InvalidAtom {
+                        reason: format!("Atom {i} is not signed by its lock {lock}"),
+                    })?
+                }
+                _ => continue,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use test_strategy::proptest;
+
+    use super::*;
+    use crate::types::Keypair;
+
+    #[proptest]
+    fn test_id_commits_to_data(transaction: Transaction, data: Vec<u8>) {
+        let mut other = transaction.clone();
+        other.data = data;
+
+        prop_assert_eq!(
+            transaction.id() == other.id(),
+            transaction.data == other.data
+        );
+    }
+
+    #[test]
+    fn test_data_size() {
+        let mut transaction = Transaction {
+            data: vec![0; DATA_SIZE],
+            ..Default::default()
+        };
+        assert!(transaction.verify().is_ok());
+
+        transaction.data.push(0);
+        assert!(matches!(
+            transaction.verify(),
+            Err(Error::InvalidTransaction { .. })
+        ));
+    }
+
+    #[test]
+    fn test_verify_balance() {
+        let mut input_mask = BitSet32::new();
+        input_mask.insert(0);
+
+        let mut transaction = Transaction {
+            input_mask,
+            atoms: [(100, Some(0)), (60, None), (40, None)]
+                .into_iter()
+                .map(|(amount, signature)| Atom {
+                    amount,
+                    signature,
+                    ..Default::default()
+                })
+                .collect(),
+            asset_ids: vec![Hash::default()],
+            signatures: vec![Default::default()],
+            ..Default::default()
+        };
+        assert!(transaction.verify().is_ok());
+
+        transaction.atoms[2].amount = 41;
+        assert!(matches!(
+            transaction.verify(),
+            Err(Error::UnbalancedTransaction { .. })
+        ));
+    }
+
+    #[test]
+    fn test_data_serialization() {
+        let mut transaction = Transaction::default();
+        assert!(serde_json::to_value(&transaction)
+            .unwrap()
+            .get("d")
+            .is_none());
+
+        transaction.data = b"invoice 42".to_vec();
+        let json = serde_json::to_value(&transaction).unwrap();
+
+        assert_eq!(json["d"], hex::encode(b"invoice 42"));
+        assert_eq!(
+            serde_json::from_value::<Transaction>(json).unwrap(),
+            transaction
+        );
+    }
+
+    #[test]
+    fn test_witnesses() {
+        let mut rng = rand::thread_rng();
+        let (owner, other) = (Keypair::random(&mut rng), Keypair::random(&mut rng));
+        let mut input_mask = BitSet32::new();
+        input_mask.insert(0);
+        input_mask.insert(1);
+
+        let mut transaction = Transaction {
+            input_mask,
+            atoms: vec![
+                Atom {
+                    lock: Some(owner.public_key),
+                    ..Default::default()
+                },
+                Atom::default(),
+                Atom::default(),
+            ],
+            asset_ids: vec![Hash::default()],
+            ..Default::default()
+        };
+        assert_eq!(transaction.locked_inputs(), vec![0]);
+        assert!(matches!(
+            transaction.verify_witnesses(|_| true),
+            Err(Error::InvalidTransaction { .. })
+        ));
+
+        assert!(matches!(
+            transaction.sign_witnesses(&mut rng, &other.secret_key),
+            Err(Error::InvalidAtom { .. })
+        ));
+        transaction
+            .sign_witnesses(&mut rng, &owner.secret_key)
+            .unwrap();
+        assert!(transaction.verify_witnesses(|_| true).is_ok());
+
+        // Witnesses sign the outputs too.
+        transaction.atoms[2].amount = 1;
+        assert!(matches!(
+            transaction.verify_witnesses(|_| true),
+            Err(Error::InvalidAtom { .. })
+        ));
+        assert!(transaction.verify_witnesses(|_| false).is_ok());
+    }
 }
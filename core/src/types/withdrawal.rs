@@ -0,0 +1,151 @@
+use blake3::Hasher;
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+use test_strategy::Arbitrary;
+
+use crate::{
+    crypto::schnorr,
+    error::{Error, Result},
+    types::{FeeSchedule, Hash, PublicKey, SecretKey, Transaction},
+};
+
+pub const REFUND_SEP: &[u8] = b"mugraph_v0_refund";
+
+/// Request to burn notes and pay their value out on the L1.
+///
+/// Whatever the inputs of `transaction` do not send to its outputs, less the
+/// fee on those inputs, is paid out to `address`. If the payout fails, the
+/// delegate signs the outputs in `refund` instead, which must add up to the
+/// same amounts, once `owner` asks for it with a [`Refund`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+pub struct Withdraw {
+    #[serde(rename = "t")]
+    pub transaction: Transaction,
+    #[serde(rename = "a")]
+    pub address: String,
+    #[serde(rename = "r")]
+    pub refund: Transaction,
+    /// Key allowed to claim the refund.
+    #[serde(rename = "o")]
+    pub owner: PublicKey,
+}
+
+impl Withdraw {
+    pub fn id(&self) -> Hash {
+        let mut hasher = Hasher::new();
+
+        hasher.update(self.transaction.id().as_ref());
+        hasher.update(&(self.address.len() as u32).to_le_bytes());
+        hasher.update(self.address.as_bytes());
+        hasher.update(self.refund.id().as_ref());
+        hasher.update(self.owner.as_ref());
+
+        hasher.finalize().into()
+    }
+
+    /// Amounts for each asset that should be paid out on the L1, once the
+    /// fees in `fees` are taken.
+    pub fn payouts(&self, fees: &FeeSchedule) -> Result<Vec<(Hash, u64)>> {
+        let (pre, post) = self.transaction.balances()?;
+        let expected = fees.fees(&self.transaction)?;
+        let mut payouts = Vec::new();
+
+        for (i, asset_id) in self.transaction.asset_ids.iter().enumerate() {
+            if post[i] > pre[i] {
+                return Err(Error::UnbalancedTransaction { pre, post });
+            }
+
+            if pre[i] - post[i] < expected[i] {
+                return Err(Error::InvalidFee {
+                    asset_id: *asset_id,
+                    expected: expected[i],
+                    got: pre[i] - post[i],
+                });
+            }
+
+            let amount = u64::try_from(pre[i] - post[i] - expected[i]).map_err(|_| {
+                Error::InvalidTransaction {
+                    reason: format!("Payout for {asset_id} does not fit in a single output"),
+                }
+            })?;
+
+            if amount > 0 {
+                payouts.push((*asset_id, amount));
+            }
+        }
+
+        if payouts.is_empty() {
+            return Err(Error::InvalidTransaction {
+                reason: "Withdrawal does not pay anything out".to_string(),
+            });
+        }
+
+        Ok(payouts)
+    }
+
+    /// Fees paid by the withdrawal, for the assets that pay any.
+    pub fn fees(&self, fees: &FeeSchedule) -> Result<Vec<(Hash, u128)>> {
+        Ok(self
+            .transaction
+            .asset_ids
+            .iter()
+            .copied()
+            .zip(fees.fees(&self.transaction)?)
+            .filter(|(_, fee)| *fee > 0)
+            .collect())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+#[serde(tag = "s", rename_all = "snake_case")]
+pub enum WithdrawalStatus {
+    /// Accepted by the delegate, waiting to be submitted to the L1.
+    Pending,
+    Submitted {
+        tx: Hash,
+    },
+    Confirmed {
+        tx: Hash,
+    },
+    /// The payout did not go through, and the notes can be refunded.
+    Failed {
+        reason: String,
+    },
+    Refunded,
+}
+
+/// State of a withdrawal, as anyone knowing its id can see it. The refund is
+/// left out, as knowing its outputs is enough to spend them once signed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+pub struct Withdrawal {
+    pub id: Hash,
+    pub address: String,
+    pub payouts: Vec<(Hash, u64)>,
+    pub status: WithdrawalStatus,
+}
+
+/// Request for the refund of a failed withdrawal, signed by its owner.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Arbitrary)]
+pub struct Refund {
+    pub id: Hash,
+    #[serde(rename = "s")]
+    pub signature: schnorr::Signature,
+}
+
+impl Refund {
+    fn message(id: &Hash) -> Vec<u8> {
+        [REFUND_SEP, id.as_ref()].concat()
+    }
+
+    pub fn new<R: RngCore + CryptoRng>(rng: &mut R, owner: &SecretKey, id: Hash) -> Self {
+        Self {
+            id,
+            signature: schnorr::sign(rng, owner, &Self::message(&id)),
+        }
+    }
+
+    /// Checks that the refund was asked for by the owner of the withdrawal.
+    pub fn verify(&self, owner: &PublicKey) -> Result<()> {
+        schnorr::verify(owner, &self.signature, &Self::message(&self.id))
+    }
+}
@@ -0,0 +1,91 @@
+//! Base45 encoding from RFC 9285, whose alphabet is the one of the
+//! alphanumeric mode of QR codes.
+
+use crate::error::Error;
+
+pub const ALPHABET: &[u8; 45] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+pub fn encode(data: &[u8]) -> String {
+    let mut output = String::with_capacity(data.len().div_ceil(2) * 3);
+
+    for chunk in data.chunks(2) {
+        let (mut n, digits) = match chunk {
+            [a, b] => ((*a as usize) << 8 | *b as usize, 3),
+            [a] => (*a as usize, 2),
+            _ => unreachable!("Chunks have one or two bytes"),
+        };
+
+        for _ in 0..digits {
+            output.push(ALPHABET[n % 45] as char);
+            n /= 45;
+        }
+    }
+
+    output
+}
+
+pub fn decode(data: &str) -> Result<Vec<u8>, Error> {
+    let digits = data
+        .bytes()
+        .map(|c| match ALPHABET.iter().position(|a| *a == c) {
+            Some(i) => Ok(i),
+            None => Err(Error::InvalidUri {
+                reason: format!("{:?} is not a base45 character", c as char),
+            }),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut output = Vec::with_capacity(digits.len() / 3 * 2 + 1);
+
+    for chunk in digits.chunks(3) {
+        let n = chunk.iter().rev().fold(0, |n, digit| n * 45 + digit);
+
+        match chunk.len() {
+            3 if n <= 0xffff => output.extend_from_slice(&(n as u16).to_be_bytes()),
+            2 if n <= 0xff => output.push(n as u8),
+            _ => {
+                return Err(Error::InvalidUri {
+                    reason: "Base45 data is not valid".to_string(),
+                })
+            }
+        }
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use test_strategy::proptest;
+
+    use super::*;
+
+    #[test]
+    fn test_vectors() {
+        for (data, encoded) in [
+            (&b"AB"[..], "BB8"),
+            (b"Hello!!", "%69 VD92EX0"),
+            (b"base-45", "UJCLQE7W581"),
+            (b"ietf!", "QED8WEX0"),
+            (b"", ""),
+        ] {
+            assert_eq!(encode(data), encoded);
+            assert_eq!(decode(encoded).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_invalid() {
+        for data in ["GGW", "A", "ab", "ZZ"] {
+            assert!(matches!(decode(data), Err(Error::InvalidUri { .. })));
+        }
+    }
+
+    #[proptest]
+    fn test_roundtrip(data: Vec<u8>) {
+        let encoded = encode(&data);
+
+        prop_assert!(encoded.bytes().all(|c| ALPHABET.contains(&c)));
+        prop_assert_eq!(decode(&encoded)?, data);
+    }
+}
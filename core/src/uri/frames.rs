@@ -0,0 +1,205 @@
+use super::{Uri, SCHEME};
+use crate::{error::Error, types::Hash};
+
+/// Bounds how many frames a reader keeps track of for a single URI.
+pub const MAX_FRAMES: usize = 1024;
+
+impl Uri {
+    /// Formats the URI, split into frames carrying at most `size` characters
+    /// of it each, to be shown one after the other as an animated QR code. A
+    /// URI that fits in one frame is returned as is.
+    ///
+    /// Frames are `MUGRAPH:F<index>/<count>/<checksum>/<part>`, with the index
+    /// starting at 1 and the first 4 bytes of the hash of the whole URI as
+    /// checksum, in hex.
+    pub fn frames(&self, size: usize) -> Result<Vec<String>, Error> {
+        let uri = self.encode()?;
+
+        if uri.len() <= size {
+            return Ok(vec![uri]);
+        }
+
+        let parts = uri.as_bytes()[SCHEME.len()..]
+            .chunks(size.max(1))
+            .map(|part| String::from_utf8_lossy(part).into_owned())
+            .collect::<Vec<_>>();
+
+        if parts.len() > MAX_FRAMES {
+            return Err(Error::InvalidUri {
+                reason: format!(
+                    "URI needs {} frames, at most {MAX_FRAMES} are allowed",
+                    parts.len()
+                ),
+            });
+        }
+
+        let checksum = checksum(&uri);
+
+        Ok(parts
+            .iter()
+            .enumerate()
+            .map(|(i, part)| format!("{SCHEME}F{}/{}/{checksum}/{part}", i + 1, parts.len()))
+            .collect())
+    }
+}
+
+fn checksum(uri: &str) -> String {
+    hex::encode_upper(&Hash::digest(uri.as_bytes()).as_ref()[..4])
+}
+
+/// Puts a URI back together out of its frames, read in any order and any
+/// number of times.
+#[derive(Debug, Default)]
+pub struct Assembler {
+    checksum: String,
+    parts: Vec<Option<String>>,
+}
+
+impl Assembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Frames read so far and how many there are, once one was read.
+    pub fn progress(&self) -> (usize, usize) {
+        (
+            self.parts.iter().filter(|p| p.is_some()).count(),
+            self.parts.len(),
+        )
+    }
+
+    /// Reads a frame, returning the URI once all of its frames were read.
+    /// URIs that fit in a single frame are returned right away.
+    ///
+    /// A frame of another URI starts over with that one, as the reader was
+    /// pointed at another QR code.
+    pub fn push(&mut self, frame: &str) -> Result<Option<Uri>, Error> {
+        let frame = frame.trim().to_ascii_uppercase();
+        let invalid = || Error::InvalidUri {
+            reason: format!("Frame {frame} is not valid"),
+        };
+
+        let header = match frame.strip_prefix(SCHEME).and_then(|f| f.strip_prefix('F')) {
+            Some(header) => header,
+            None => return Uri::decode(&frame).map(Some),
+        };
+
+        let mut fields = header.splitn(4, '/');
+        let (index, count, check, part) =
+            match (fields.next(), fields.next(), fields.next(), fields.next()) {
+                (Some(index), Some(count), Some(check), Some(part)) => (
+                    index.parse::<usize>().map_err(|_| invalid())?,
+                    count.parse::<usize>().map_err(|_| invalid())?,
+                    check,
+                    part,
+                ),
+                _ => return Err(invalid()),
+            };
+
+        if index == 0 || index > count || count > MAX_FRAMES || check.len() != 8 {
+            return Err(invalid());
+        }
+
+        if check != self.checksum || count != self.parts.len() {
+            self.checksum = check.to_string();
+            self.parts = vec![None; count];
+        }
+
+        self.parts[index - 1] = Some(part.to_string());
+
+        if self.parts.iter().any(Option::is_none) {
+            return Ok(None);
+        }
+
+        let uri = self
+            .parts
+            .drain(..)
+            .flatten()
+            .fold(SCHEME.to_string(), |uri, part| uri + &part);
+        self.checksum.clear();
+
+        if checksum(&uri) != check {
+            return Err(Error::InvalidUri {
+                reason: "Frames do not match their checksum".to_string(),
+            });
+        }
+
+        Uri::decode(&uri).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uri::tests::token;
+
+    #[test]
+    fn test_frames() {
+        let uri = Uri::Token(token(6));
+        let frames = uri.frames(100).unwrap();
+        assert!(frames.len() > 2);
+        assert!(frames.iter().all(|f| f.len() <= 100 + 24));
+
+        // Frames are read out of order and repeated, as an animation loops.
+        let mut assembler = Assembler::new();
+        assert_eq!(assembler.push(&frames[1]).unwrap(), None);
+        assert_eq!(assembler.push(&frames[1]).unwrap(), None);
+        assert_eq!(assembler.progress(), (1, frames.len()));
+
+        for frame in frames.iter().skip(2) {
+            assert_eq!(assembler.push(frame).unwrap(), None);
+        }
+        assert_eq!(assembler.push(&frames[0]).unwrap(), Some(uri.clone()));
+        assert_eq!(assembler.progress(), (0, 0));
+
+        // A short URI is a single frame.
+        let short = Uri::Delegate("https://delegate.example".to_string());
+        let frames = short.frames(100).unwrap();
+        assert_eq!(frames, vec![short.encode().unwrap()]);
+        assert_eq!(assembler.push(&frames[0]).unwrap(), Some(short));
+    }
+
+    #[test]
+    fn test_other_uri() {
+        let a = Uri::Token(token(4)).frames(80).unwrap();
+        let b = Uri::Token(token(5)).frames(80).unwrap();
+        let mut assembler = Assembler::new();
+
+        assembler.push(&a[0]).unwrap();
+        for frame in b.iter().skip(1) {
+            assembler.push(frame).unwrap();
+        }
+        assert_eq!(assembler.push(&b[0]).unwrap(), Some(Uri::Token(token(5))));
+    }
+
+    #[test]
+    fn test_invalid_frames() {
+        let frames = Uri::Token(token(4)).frames(80).unwrap();
+        let mut assembler = Assembler::new();
+
+        for frame in [
+            format!("{SCHEME}F0/2/00000000/ABC"),
+            format!("{SCHEME}F3/2/00000000/ABC"),
+            format!("{SCHEME}F1/2/0000/ABC"),
+            format!("{SCHEME}F1/{}/00000000/ABC", MAX_FRAMES + 1),
+            format!("{SCHEME}F1/2"),
+        ] {
+            assert!(matches!(
+                assembler.push(&frame),
+                Err(Error::InvalidUri { .. })
+            ));
+        }
+
+        // A part that was altered is caught by the checksum.
+        let mut tampered = frames.clone();
+        tampered[0] = tampered[0].replacen("/T", "/U", 1);
+        let results = tampered
+            .iter()
+            .map(|frame| assembler.push(frame))
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            results.last(),
+            Some(Err(Error::InvalidUri { .. }))
+        ));
+    }
+}
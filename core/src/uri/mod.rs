@@ -0,0 +1,348 @@
+//! `mugraph:` URIs for payment requests, tokens and delegates.
+//!
+//! A URI is the scheme, a letter for its kind and the base45 encoding of its
+//! payload, packed as CBOR. Everything is uppercase and within the alphabet
+//! of the alphanumeric mode of QR codes, which keeps QR codes small:
+//!
+//! ```text
+//! MUGRAPH:R<payment request>
+//! MUGRAPH:T<token>
+//! MUGRAPH:D<delegate URL>
+//! ```
+//!
+//! URIs too long for a single QR code are split into frames shown one after
+//! the other, see [`Uri::frames`] and [`Assembler`].
+
+use std::{convert::Infallible, str::FromStr};
+
+use minicbor::{data::Type, decode, encode, Decoder, Encoder};
+
+use crate::{crypto::dleq, error::Error, types::*};
+
+pub mod base45;
+mod frames;
+
+pub use self::frames::*;
+
+pub const SCHEME: &str = "MUGRAPH:";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Uri {
+    Request(PaymentRequest),
+    Token(Token),
+    /// URL of a delegate to add.
+    Delegate(String),
+}
+
+impl Uri {
+    /// Formats the URI. Fails for tokens with notes of more than one
+    /// delegate, or none.
+    pub fn encode(&self) -> Result<String, Error> {
+        let (kind, payload) = match self {
+            Self::Request(request) => ('R', cbor(|e| encode_request(e, request))),
+            Self::Token(token) => {
+                let delegate = token.delegate()?;
+                ('T', cbor(|e| encode_token(e, token, delegate)))
+            }
+            Self::Delegate(url) => ('D', url.as_bytes().to_vec()),
+        };
+
+        Ok(format!("{SCHEME}{kind}{}", base45::encode(&payload)))
+    }
+
+    /// Parses a URI, ignoring the case of its letters as some QR code
+    /// readers change it.
+    pub fn decode(uri: &str) -> Result<Self, Error> {
+        let uri = uri.trim().to_ascii_uppercase();
+        let body = uri.strip_prefix(SCHEME).ok_or_else(|| Error::InvalidUri {
+            reason: format!("URI does not start with {SCHEME}"),
+        })?;
+
+        let mut chars = body.chars();
+        let kind = chars.next();
+        let payload = base45::decode(chars.as_str())?;
+
+        match kind {
+            Some('R') => decode_all(&payload, decode_request).map(Self::Request),
+            Some('T') => decode_all(&payload, decode_token).map(Self::Token),
+            Some('D') => {
+                String::from_utf8(payload)
+                    .map(Self::Delegate)
+                    .map_err(|e| Error::InvalidUri {
+                        reason: e.to_string(),
+                    })
+            }
+            Some('F') => Err(Error::InvalidUri {
+                reason: "URI is a frame of a longer one, see Assembler".to_string(),
+            }),
+            _ => Err(Error::InvalidUri {
+                reason: format!("Unknown kind of URI in {uri}"),
+            }),
+        }
+    }
+}
+
+impl FromStr for Uri {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::decode(s)
+    }
+}
+
+type Encoded = Result<(), encode::Error<Infallible>>;
+
+fn cbor(f: impl FnOnce(&mut Encoder<Vec<u8>>) -> Encoded) -> Vec<u8> {
+    let mut e = Encoder::new(Vec::new());
+    f(&mut e).expect("Writing to a Vec can not fail");
+
+    e.into_writer()
+}
+
+fn encode_request(e: &mut Encoder<Vec<u8>>, request: &PaymentRequest) -> Encoded {
+    e.array(6)?
+        .str(&request.url)?
+        .bytes(request.delegate.as_ref())?
+        .bytes(request.asset_id.as_ref())?
+        .u64(request.amount)?;
+
+    match &request.memo {
+        Some(memo) => e.str(memo)?,
+        None => e.null()?,
+    };
+
+    encode_lock(e, request.lock)
+}
+
+/// Encodes a token with its delegate and assets once, and every note
+/// pointing to its asset.
+fn encode_token(e: &mut Encoder<Vec<u8>>, token: &Token, delegate: PublicKey) -> Encoded {
+    let mut assets = token.notes.iter().map(|n| n.asset_id).collect::<Vec<_>>();
+    assets.sort();
+    assets.dedup();
+
+    e.array(4)?.str(&token.url)?.bytes(delegate.as_ref())?;
+    e.array(assets.len() as u64)?;
+    for asset_id in assets.iter() {
+        e.bytes(asset_id.as_ref())?;
+    }
+
+    e.array(token.notes.len() as u64)?;
+    for note in token.notes.iter() {
+        let asset = assets
+            .binary_search(&note.asset_id)
+            .expect("Asset of every note");
+
+        e.array(6)?
+            .u32(asset as u32)?
+            .u64(note.amount)?
+            .bytes(note.nonce.as_ref())?
+            .bytes(note.signature.as_ref())?;
+        encode_lock(e, note.lock)?;
+
+        match &note.proof {
+            Some(proof) => e.bytes(&[proof.e.0, proof.s.0].concat())?,
+            None => e.null()?,
+        };
+    }
+
+    Ok(())
+}
+
+fn encode_lock(e: &mut Encoder<Vec<u8>>, lock: Option<PublicKey>) -> Encoded {
+    match lock {
+        Some(lock) => e.bytes(lock.as_ref())?,
+        None => e.null()?,
+    };
+
+    Ok(())
+}
+
+/// Decodes a payload with `f`, failing if anything is left after it.
+fn decode_all<T>(
+    payload: &[u8],
+    f: impl FnOnce(&mut Decoder) -> Result<T, decode::Error>,
+) -> Result<T, Error> {
+    let mut d = Decoder::new(payload);
+    let value = f(&mut d).map_err(|e| Error::InvalidUri {
+        reason: e.to_string(),
+    })?;
+
+    if d.position() != payload.len() {
+        return Err(Error::InvalidUri {
+            reason: "URI has data after its payload".to_string(),
+        });
+    }
+
+    Ok(value)
+}
+
+fn decode_request(d: &mut Decoder) -> Result<PaymentRequest, decode::Error> {
+    array(d, 6)?;
+
+    Ok(PaymentRequest {
+        url: d.str()?.to_string(),
+        delegate: PublicKey(bytes(d)?),
+        asset_id: Hash(bytes(d)?),
+        amount: d.u64()?,
+        memo: match d.datatype()? {
+            Type::Null => d.null().map(|_| None)?,
+            _ => Some(d.str()?.to_string()),
+        },
+        lock: decode_lock(d)?,
+    })
+}
+
+fn decode_token(d: &mut Decoder) -> Result<Token, decode::Error> {
+    array(d, 4)?;
+
+    let url = d.str()?.to_string();
+    let delegate = PublicKey(bytes(d)?);
+    let assets = (0..array(d, None)?)
+        .map(|_| bytes(d).map(Hash))
+        .collect::<Result<Vec<_>, _>>()?;
+    let notes = (0..array(d, None)?)
+        .map(|_| {
+            array(d, 6)?;
+
+            let asset = d.u32()? as usize;
+            let asset_id = *assets
+                .get(asset)
+                .ok_or_else(|| decode::Error::message(format!("Unknown asset {asset}")))?;
+
+            Ok(Note {
+                asset_id,
+                delegate,
+                amount: d.u64()?,
+                nonce: Hash(bytes(d)?),
+                signature: Signature(bytes(d)?),
+                lock: decode_lock(d)?,
+                proof: decode_proof(d)?,
+            })
+        })
+        .collect::<Result<Vec<_>, decode::Error>>()?;
+
+    Ok(Token { url, notes })
+}
+
+fn decode_lock(d: &mut Decoder) -> Result<Option<PublicKey>, decode::Error> {
+    match d.datatype()? {
+        Type::Null => d.null().map(|_| None),
+        _ => Ok(Some(PublicKey(bytes(d)?))),
+    }
+}
+
+fn decode_proof(d: &mut Decoder) -> Result<Option<dleq::Proof>, decode::Error> {
+    if d.datatype()? == Type::Null {
+        return d.null().map(|_| None);
+    }
+
+    match d.bytes()? {
+        proof if proof.len() == 64 => Ok(Some(dleq::Proof {
+            e: Hash(proof[..32].try_into().expect("Slice of 32 bytes")),
+            s: Hash(proof[32..].try_into().expect("Slice of 32 bytes")),
+        })),
+        _ => Err(decode::Error::message("Expected a proof of 64 bytes")),
+    }
+}
+
+/// Reads the header of a definite array, checking its length if `expected`
+/// is given.
+fn array(d: &mut Decoder, expected: impl Into<Option<u64>>) -> Result<u64, decode::Error> {
+    let len = d
+        .array()?
+        .ok_or_else(|| decode::Error::message("Expected an array of known length"))?;
+
+    match expected.into() {
+        Some(expected) if expected != len => Err(decode::Error::message(format!(
+            "Expected an array of {expected} items, got {len}"
+        ))),
+        _ => Ok(len),
+    }
+}
+
+fn bytes(d: &mut Decoder) -> Result<[u8; 32], decode::Error> {
+    d.bytes()?
+        .try_into()
+        .map_err(|_| decode::Error::message("Expected 32 bytes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub(super) fn token(notes: usize) -> Token {
+        let delegate = PublicKey([1; 32]);
+
+        Token {
+            url: "https://delegate.example/v0".to_string(),
+            notes: (0..notes)
+                .map(|i| Note {
+                    amount: 10 + i as u64,
+                    delegate,
+                    asset_id: Hash([i as u8 % 2; 32]),
+                    nonce: Hash([i as u8; 32]),
+                    signature: Signature([3; 32]),
+                    lock: (i % 2 == 0).then_some(PublicKey([4; 32])),
+                    proof: (i % 3 == 0).then_some(dleq::Proof {
+                        e: Hash([5; 32]),
+                        s: Hash([6; 32]),
+                    }),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let request = PaymentRequest {
+            url: "https://delegate.example/v0".to_string(),
+            delegate: PublicKey([1; 32]),
+            asset_id: Hash([2; 32]),
+            amount: 42,
+            memo: Some("invoice 42".to_string()),
+            lock: None,
+        };
+
+        for uri in [
+            Uri::Request(request.clone()),
+            Uri::Request(PaymentRequest {
+                memo: None,
+                lock: Some(PublicKey([3; 32])),
+                ..request
+            }),
+            Uri::Token(token(3)),
+            Uri::Delegate("https://delegate.example/v0".to_string()),
+        ] {
+            let encoded = uri.encode().unwrap();
+
+            assert!(encoded.starts_with(SCHEME));
+            assert!(encoded
+                .bytes()
+                .skip(SCHEME.len())
+                .all(|c| base45::ALPHABET.contains(&c)));
+            assert_eq!(encoded.parse::<Uri>().unwrap(), uri);
+            assert_eq!(Uri::decode(&encoded.to_lowercase()).unwrap(), uri);
+        }
+    }
+
+    #[test]
+    fn test_invalid() {
+        let mut mixed = token(2);
+        mixed.notes[1].delegate = PublicKey([9; 32]);
+        assert!(Uri::Token(mixed).encode().is_err());
+
+        let valid = Uri::Token(token(1)).encode().unwrap();
+
+        for uri in [
+            "https://delegate.example".to_string(),
+            format!("{SCHEME}X"),
+            format!("{SCHEME}R"),
+            valid.replace(":T", ":R"),
+            format!("{valid}00"),
+            valid[..valid.len() - 3].to_string(),
+        ] {
+            assert!(matches!(Uri::decode(&uri), Err(Error::InvalidUri { .. })));
+        }
+    }
+}
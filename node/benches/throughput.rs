@@ -0,0 +1,107 @@
+//! Transaction throughput as the number of threads submitting them grows.
+//!
+//! Every transaction spends a single fresh note, so the only contention
+//! between threads is on the spent set.
+
+use std::thread;
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use mugraph_core::{crypto, types::*, utils::BitSet32};
+use mugraph_node::{assets, database::Database, v0::transaction_v0};
+use rand::prelude::*;
+use rand_chacha::ChaCha20Rng;
+
+const TRANSACTIONS: usize = 256;
+
+fn transaction<R: CryptoRng + Rng>(rng: &mut R, keypair: &Keypair, asset_id: Hash) -> Transaction {
+    let mut input_mask = BitSet32::new();
+    input_mask.insert(0);
+
+    let mut transaction = Transaction {
+        input_mask,
+        atoms: [Some(0), None]
+            .into_iter()
+            .map(|signature| Atom {
+                delegate: keypair.public_key,
+                asset_id: 0,
+                amount: 100,
+                nonce: Hash::random(rng),
+                signature,
+                lock: None,
+            })
+            .collect(),
+        asset_ids: vec![asset_id],
+        signatures: vec![],
+        data: vec![],
+        witnesses: vec![],
+    };
+
+    let commitment = transaction.atoms[0].commitment(&transaction.asset_ids);
+    let signature = crypto::sign(&keypair.secret_key, commitment.as_ref());
+    transaction.signatures.push(signature);
+
+    transaction
+}
+
+fn throughput(c: &mut Criterion) {
+    let mut rng = ChaCha20Rng::seed_from_u64(0);
+    let keypair = Keypair::random(&mut rng);
+    let dir = tempfile::tempdir().unwrap();
+    let database = Database::setup(dir.path().join("db")).unwrap();
+    let fees = FeeSchedule::default();
+
+    let asset = Asset {
+        id: Hash::random(&mut rng),
+        policy_id: vec![],
+        asset_name: vec![],
+        decimals: 6,
+        ticker: "ADA".to_string(),
+        min_amount: 1,
+        max_amount: u64::MAX,
+        enabled: true,
+    };
+
+    let w = database.write().unwrap();
+    assets::register(&w, &asset).unwrap();
+    w.commit().unwrap();
+
+    let mut group = c.benchmark_group("transaction_v0");
+    group.throughput(Throughput::Elements(TRANSACTIONS as u64));
+    group.sample_size(10);
+
+    for threads in [1, 2, 4, 8] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(threads),
+            &threads,
+            |b, &threads| {
+                b.iter_batched(
+                    || {
+                        (0..TRANSACTIONS)
+                            .map(|_| transaction(&mut rng, &keypair, asset.id))
+                            .collect::<Vec<_>>()
+                    },
+                    |transactions| {
+                        thread::scope(|scope| {
+                            for chunk in transactions.chunks(TRANSACTIONS / threads) {
+                                let (fees, database) = (&fees, &database);
+
+                                scope.spawn(move || {
+                                    for transaction in chunk {
+                                        transaction_v0(transaction, keypair, fees, database)
+                                            .unwrap();
+                                    }
+                                });
+                            }
+                        });
+                    },
+                    BatchSize::PerIteration,
+                );
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, throughput);
+criterion_main!(benches);
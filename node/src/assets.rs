@@ -0,0 +1,196 @@
+//! Registry of the assets accepted by the delegate.
+
+use mugraph_core::{error::Error, types::*};
+use redb::ReadableTable;
+
+use crate::database::{Database, Record, Write, ASSETS};
+
+/// Adds an asset to the registry, replacing it if it was already there.
+///
+/// Fails if another asset is backed by the same Cardano unit, as their
+/// liabilities would be split between them.
+pub fn register(w: &Write, asset: &Asset) -> Result<(), Error> {
+    asset.validate()?;
+
+    let mut table = w.open_table(ASSETS)?;
+
+    for entry in table.iter()? {
+        let (id, other) = entry?;
+
+        if id.value() != asset.id && other.value()?.unit() == asset.unit() {
+            return Err(Error::UnsupportedAsset {
+                asset_id: asset.id,
+                reason: format!("Cardano unit {} already backs {}", asset.unit(), id.value()),
+            });
+        }
+    }
+
+    table.insert(asset.id, Ok(asset.clone()))?;
+
+    Ok(())
+}
+
+pub fn list(database: &Database) -> Result<Vec<Asset>, Error> {
+    database
+        .read()?
+        .open_table(ASSETS)?
+        .iter()?
+        .map(|entry| entry?.1.value())
+        .collect()
+}
+
+pub fn get(
+    assets: &impl ReadableTable<Hash, Record<Asset>>,
+    asset_id: Hash,
+) -> Result<Asset, Error> {
+    match assets.get(asset_id)? {
+        Some(asset) => asset.value(),
+        None => Err(Error::UnsupportedAsset {
+            asset_id,
+            reason: "asset is not registered".to_string(),
+        }),
+    }
+}
+
+/// Checks that every asset in the transaction is registered and that its
+/// outputs are within the limits of their asset.
+///
+/// Outputs for disabled assets are rejected unless `allow_disabled` is set,
+/// which is used for withdrawals so funds can always leave the delegate.
+pub fn check(
+    transaction: &Transaction,
+    assets: &impl ReadableTable<Hash, Record<Asset>>,
+    allow_disabled: bool,
+) -> Result<(), Error> {
+    for asset_id in transaction.asset_ids.iter() {
+        get(assets, *asset_id)?;
+    }
+
+    check_where(transaction, assets, allow_disabled, |_| true)
+}
+
+/// Like [`check`], but only for the atoms selected by `filter`, so a
+/// cross-delegate transaction can use assets only its other delegates know.
+pub fn check_where(
+    transaction: &Transaction,
+    assets: &impl ReadableTable<Hash, Record<Asset>>,
+    allow_disabled: bool,
+    filter: impl Fn(&Atom) -> bool,
+) -> Result<(), Error> {
+    for (i, atom) in transaction.atoms.iter().enumerate() {
+        if transaction.is_input(i) || !filter(atom) {
+            continue;
+        }
+
+        let asset_id = transaction
+            .asset_ids
+            .get(atom.asset_id as usize)
+            .ok_or_else(|| Error::InvalidAtom {
+                reason: format!("Atom {i} points to a missing asset"),
+            })?;
+        let asset = get(assets, *asset_id)?;
+
+        if !asset.enabled && !allow_disabled {
+            return Err(Error::UnsupportedAsset {
+                asset_id: asset.id,
+                reason: "asset is disabled".to_string(),
+            });
+        }
+
+        asset.check_amount(atom.amount)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testing::{spend, Setup},
+        v0::transaction_v0,
+    };
+
+    #[test]
+    fn test_unknown_asset() {
+        let mut s = Setup::new();
+        let asset_id = s.asset();
+        let notes = s.notes(asset_id, &[10]);
+
+        let mut transaction = spend(&notes, &[(asset_id, 10)]);
+        transaction.asset_ids[0] = Hash::random(&mut s.rng);
+
+        assert!(matches!(
+            transaction_v0(&transaction, s.keypair, &s.fees, &s.database),
+            Err(Error::UnsupportedAsset { .. })
+        ));
+    }
+
+    #[test]
+    fn test_disabled_asset() {
+        let mut s = Setup::new();
+        let asset_id = s.asset();
+        let notes = s.notes(asset_id, &[10]);
+
+        let mut asset = get(
+            &s.database.read().unwrap().open_table(ASSETS).unwrap(),
+            asset_id,
+        )
+        .unwrap();
+        asset.enabled = false;
+
+        let w = s.database.write().unwrap();
+        register(&w, &asset).unwrap();
+        w.commit().unwrap();
+
+        assert!(matches!(
+            transaction_v0(
+                &spend(&notes, &[(asset_id, 10)]),
+                s.keypair,
+                &s.fees,
+                &s.database
+            ),
+            Err(Error::UnsupportedAsset { .. })
+        ));
+        assert_eq!(list(&s.database).unwrap(), vec![asset]);
+    }
+
+    #[test]
+    fn test_same_unit() {
+        let mut s = Setup::new();
+        let asset_id = s.asset();
+
+        let w = s.database.write().unwrap();
+        let mut asset = get(&w.open_table(ASSETS).unwrap(), asset_id).unwrap();
+        asset.id = Hash::random(&mut s.rng);
+
+        assert!(matches!(
+            register(&w, &asset),
+            Err(Error::UnsupportedAsset { .. })
+        ));
+    }
+
+    #[test]
+    fn test_amount_limits() {
+        let mut s = Setup::new();
+        let asset_id = s.asset();
+        let notes = s.notes(asset_id, &[1_000]);
+
+        let too_large = spend(&notes, &[(asset_id, 1_000)]);
+        let too_small = spend(&notes, &[(asset_id, 999), (asset_id, 1)]);
+
+        let w = s.database.write().unwrap();
+        let mut asset = get(&w.open_table(ASSETS).unwrap(), asset_id).unwrap();
+        asset.min_amount = 2;
+        asset.max_amount = 999;
+        register(&w, &asset).unwrap();
+        w.commit().unwrap();
+
+        for transaction in [too_large, too_small] {
+            assert!(matches!(
+                transaction_v0(&transaction, s.keypair, &s.fees, &s.database),
+                Err(Error::InvalidAtom { .. })
+            ));
+        }
+    }
+}
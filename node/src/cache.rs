@@ -0,0 +1,44 @@
+//! Signed heads kept between requests, so they are only built and signed
+//! again once what they cover changes.
+
+use std::sync::Mutex;
+
+use mugraph_core::{error::Error, types::*};
+
+/// Last signed tree head, current for as long as the log does not grow, and
+/// liabilities report, with the version of the liabilities it was made at,
+/// see [`crate::database::LIABILITIES_VERSION`].
+#[derive(Debug, Default)]
+pub struct Cache {
+    head: Mutex<Option<SignedTreeHead>>,
+    liabilities: Mutex<Option<(u64, Signed<LiabilitiesReport>)>>,
+}
+
+impl Cache {
+    pub fn head(&self, size: u64) -> Result<Option<SignedTreeHead>, Error> {
+        Ok(self.head.lock()?.filter(|head| head.head.size == size))
+    }
+
+    pub fn set_head(&self, head: SignedTreeHead) -> Result<(), Error> {
+        *self.head.lock()? = Some(head);
+
+        Ok(())
+    }
+
+    pub fn liabilities(&self, version: u64) -> Result<Option<Signed<LiabilitiesReport>>, Error> {
+        Ok(match &*self.liabilities.lock()? {
+            Some((at, report)) if *at == version => Some(report.clone()),
+            _ => None,
+        })
+    }
+
+    pub fn set_liabilities(
+        &self,
+        version: u64,
+        report: Signed<LiabilitiesReport>,
+    ) -> Result<(), Error> {
+        *self.liabilities.lock()? = Some((version, report));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,68 @@
+//! Shelley addresses, as described in CIP-19.
+
+use blake2::{digest::consts::U28, Blake2b, Digest};
+use ed25519_dalek::VerifyingKey;
+use mugraph_core::error::Error;
+use serde::{Deserialize, Serialize};
+
+/// Header type of an address paying to a key hash, without a stake part.
+const ENTERPRISE_KEY: u8 = 0b0110;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Network {
+    Mainnet,
+    Testnet,
+}
+
+impl Network {
+    #[inline]
+    pub fn id(&self) -> u8 {
+        match self {
+            Self::Mainnet => 1,
+            Self::Testnet => 0,
+        }
+    }
+
+    #[inline]
+    pub fn hrp(&self) -> &'static str {
+        match self {
+            Self::Mainnet => "addr",
+            Self::Testnet => "addr_test",
+        }
+    }
+}
+
+#[inline]
+pub fn key_hash(key: &VerifyingKey) -> [u8; 28] {
+    Blake2b::<U28>::digest(key.as_bytes()).into()
+}
+
+/// Address paying to `key`, with no delegation rights attached.
+pub fn enterprise_address(network: Network, key: &VerifyingKey) -> Vec<u8> {
+    [
+        [ENTERPRISE_KEY << 4 | network.id()].as_slice(),
+        key_hash(key).as_slice(),
+    ]
+    .concat()
+}
+
+/// Decodes a bech32 address, checking it is a Shelley address for `network`.
+pub fn parse_address(address: &str, network: Network) -> Result<Vec<u8>, Error> {
+    let invalid = |reason: String| Error::InvalidTransaction {
+        reason: format!("Invalid address {address}: {reason}"),
+    };
+
+    let (hrp, bytes) = bech32::decode(address).map_err(|e| invalid(e.to_string()))?;
+
+    if hrp.as_str() != network.hrp() {
+        return Err(invalid(format!("expected prefix {}", network.hrp())));
+    }
+
+    match bytes.first() {
+        Some(header) if header >> 4 > 0b0111 => Err(invalid("not a Shelley address".into())),
+        Some(header) if header & 0x0f != network.id() => Err(invalid("wrong network".into())),
+        Some(_) => Ok(bytes),
+        None => Err(invalid("empty address".into())),
+    }
+}
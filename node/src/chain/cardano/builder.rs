@@ -0,0 +1,341 @@
+use std::{cmp::Reverse, convert::Infallible};
+
+use blake2::{digest::consts::U32, Blake2b, Digest};
+use ed25519_dalek::{Signer, SigningKey};
+use minicbor::{encode, Encoder};
+use mugraph_core::{error::Error, types::Hash};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    enterprise_address, parse_address, AssetResolver, AssetUnit, Network, PayoutBuilder, Value,
+};
+use crate::chain::Payout;
+
+/// Bytes added to the size of an output when computing the minimum amount of
+/// lovelace it must hold, as defined by the Babbage ledger rules.
+const UTXO_ENTRY_OVERHEAD: u64 = 160;
+
+/// The fee only depends on the size of the transaction, so it converges in a
+/// couple of rounds. This bounds it in case it never does.
+const MAX_FEE_ROUNDS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProtocolParams {
+    /// Fee per byte of the serialized transaction, in lovelace.
+    pub min_fee_a: u64,
+    /// Fixed fee per transaction, in lovelace.
+    pub min_fee_b: u64,
+    pub coins_per_utxo_byte: u64,
+    pub max_tx_size: u64,
+}
+
+impl ProtocolParams {
+    #[inline]
+    pub fn min_fee(&self, size: u64) -> u64 {
+        self.min_fee_a * size + self.min_fee_b
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct TxIn {
+    pub tx_hash: Hash,
+    pub index: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Utxo {
+    pub input: TxIn,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxOut {
+    pub address: Vec<u8>,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedTransaction {
+    pub id: Hash,
+    pub fee: u64,
+    pub inputs: Vec<TxIn>,
+    /// Value sent back to the vault, as the second output.
+    pub change: Option<Value>,
+    /// The transaction, ready to be submitted.
+    pub bytes: Vec<u8>,
+}
+
+/// Key controlling the vault, which holds every asset backing the notes.
+pub struct Vault {
+    key: SigningKey,
+    network: Network,
+}
+
+impl Vault {
+    pub fn new(key: SigningKey, network: Network) -> Self {
+        Self { key, network }
+    }
+
+    #[inline]
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    #[inline]
+    pub fn address(&self) -> Vec<u8> {
+        enterprise_address(self.network, &self.key.verifying_key())
+    }
+}
+
+/// Builds payouts spending from a vault locked by a single key.
+///
+/// Inputs are selected largest-first, starting with the ones needed for the
+/// native assets of the payout, and anything left over goes back to the vault
+/// as change.
+pub struct CardanoBuilder<A> {
+    params: ProtocolParams,
+    vault: Vault,
+    assets: A,
+}
+
+impl<A: AssetResolver> CardanoBuilder<A> {
+    pub fn new(params: ProtocolParams, vault: Vault, assets: A) -> Self {
+        Self {
+            params,
+            vault,
+            assets,
+        }
+    }
+
+    fn min_ada(&self, output: &TxOut) -> u64 {
+        let size = encode_with(|e| encode_output(e, output)).len() as u64;
+
+        (UTXO_ENTRY_OVERHEAD + size) * self.params.coins_per_utxo_byte
+    }
+
+    /// Selects inputs covering `target` plus `fee`, returning them along with
+    /// the change and the fee actually paid.
+    ///
+    /// Change that is too small to be an output on its own is added to the
+    /// fee when it only holds lovelace, otherwise more inputs are selected.
+    fn balance(
+        &self,
+        target: &Value,
+        fee: u64,
+        utxos: &[Utxo],
+    ) -> Result<(Vec<TxIn>, Option<Value>, u64), Error> {
+        let mut extra = 0;
+
+        loop {
+            let mut required = target.clone();
+            required.coin = [fee, extra]
+                .iter()
+                .try_fold(target.coin, |acc, x| acc.checked_add(*x))
+                .ok_or_else(|| Error::ServerError {
+                    reason: "Payout amount overflows".to_string(),
+                })?;
+
+            let (inputs, total) = select(utxos, &required)?;
+
+            let mut change = total
+                .checked_sub(target)
+                .expect("Selected inputs must cover the target");
+            change.coin -= fee;
+
+            if change.is_zero() {
+                return Ok((inputs, None, fee));
+            }
+
+            let min = self.min_ada(&TxOut {
+                address: self.vault.address(),
+                value: change.clone(),
+            });
+
+            match change.coin >= min {
+                true => return Ok((inputs, Some(change), fee)),
+                false if change.assets.is_empty() => return Ok((inputs, None, fee + change.coin)),
+                false => extra = min,
+            }
+        }
+    }
+}
+
+impl<A: AssetResolver> PayoutBuilder for CardanoBuilder<A> {
+    fn check_address(&self, address: &str) -> Result<(), Error> {
+        parse_address(address, self.vault.network()).map(|_| ())
+    }
+
+    fn build(
+        &self,
+        payout: &Payout,
+        utxos: &[Utxo],
+        valid_until: u64,
+    ) -> Result<SignedTransaction, Error> {
+        let mut output = TxOut {
+            address: parse_address(&payout.address, self.vault.network())?,
+            value: Value::default(),
+        };
+
+        for (asset_id, amount) in payout.assets.iter() {
+            output
+                .value
+                .add(&self.assets.resolve(*asset_id)?, *amount)?;
+        }
+
+        // Raising the amount can make the output larger, so repeat until it
+        // holds enough lovelace for its final size.
+        loop {
+            let min = self.min_ada(&output);
+
+            if output.value.coin >= min {
+                break;
+            }
+
+            output.value.coin = min;
+        }
+
+        let mut fee = 0;
+
+        for _ in 0..MAX_FEE_ROUNDS {
+            let (inputs, change, paid) = self.balance(&output.value, fee, utxos)?;
+
+            let mut outputs = vec![output.clone()];
+
+            if let Some(value) = change.clone() {
+                outputs.push(TxOut {
+                    address: self.vault.address(),
+                    value,
+                });
+            }
+
+            let body = encode_with(|e| encode_body(e, &inputs, &outputs, paid, valid_until));
+            let id = Hash(Blake2b::<U32>::digest(&body).into());
+            let signature = self.vault.key.sign(id.as_ref());
+            let bytes = encode_with(|e| {
+                e.array(4)?;
+                e.writer_mut().extend_from_slice(&body);
+                e.map(1)?
+                    .u64(0)?
+                    .array(1)?
+                    .array(2)?
+                    .bytes(self.vault.key.verifying_key().as_bytes())?
+                    .bytes(&signature.to_bytes())?;
+                e.bool(true)?.null()?;
+
+                Ok(())
+            });
+
+            if bytes.len() as u64 > self.params.max_tx_size {
+                return Err(Error::ServerError {
+                    reason: format!(
+                        "Payout {} needs a transaction of {} bytes, above the limit of {}",
+                        payout.id,
+                        bytes.len(),
+                        self.params.max_tx_size
+                    ),
+                });
+            }
+
+            let required = self.params.min_fee(bytes.len() as u64);
+
+            if paid >= required {
+                return Ok(SignedTransaction {
+                    id,
+                    fee: paid,
+                    inputs,
+                    change,
+                    bytes,
+                });
+            }
+
+            fee = required;
+        }
+
+        Err(Error::ServerError {
+            reason: format!("Fee for payout {} did not converge", payout.id),
+        })
+    }
+}
+
+/// Picks the UTxOs holding the most of each required asset, then the ones
+/// holding the most lovelace, until `required` is covered.
+fn select(utxos: &[Utxo], required: &Value) -> Result<(Vec<TxIn>, Value), Error> {
+    let mut selected = vec![false; utxos.len()];
+    let mut total = Value::default();
+
+    for (unit, amount) in required
+        .native()
+        .chain([(AssetUnit::Lovelace, required.coin)])
+    {
+        while total.get(&unit) < amount {
+            let next = utxos
+                .iter()
+                .enumerate()
+                .filter(|(i, u)| !selected[*i] && u.value.get(&unit) > 0)
+                .max_by_key(|(_, u)| (u.value.get(&unit), Reverse(u.input)))
+                .map(|(i, _)| i)
+                .ok_or_else(|| Error::ServerError {
+                    reason: format!(
+                        "Vault holds {} of {unit:?}, but {amount} are needed",
+                        total.get(&unit)
+                    ),
+                })?;
+
+            selected[next] = true;
+            total.add_value(&utxos[next].value)?;
+        }
+    }
+
+    let mut inputs = utxos
+        .iter()
+        .zip(selected)
+        .filter_map(|(u, s)| s.then_some(u.input))
+        .collect::<Vec<_>>();
+    inputs.sort();
+
+    Ok((inputs, total))
+}
+
+fn encode_with(
+    f: impl FnOnce(&mut Encoder<Vec<u8>>) -> Result<(), encode::Error<Infallible>>,
+) -> Vec<u8> {
+    let mut e = Encoder::new(Vec::new());
+    f(&mut e).expect("Encoding into a Vec never fails");
+    e.into_writer()
+}
+
+/// `[address, value]`
+fn encode_output(
+    e: &mut Encoder<Vec<u8>>,
+    output: &TxOut,
+) -> Result<(), encode::Error<Infallible>> {
+    e.array(2)?.bytes(&output.address)?;
+    output.value.encode(e)
+}
+
+/// `{0: inputs, 1: outputs, 2: fee, 3: ttl}`
+fn encode_body(
+    e: &mut Encoder<Vec<u8>>,
+    inputs: &[TxIn],
+    outputs: &[TxOut],
+    fee: u64,
+    ttl: u64,
+) -> Result<(), encode::Error<Infallible>> {
+    e.map(4)?.u64(0)?.array(inputs.len() as u64)?;
+
+    for input in inputs {
+        e.array(2)?
+            .bytes(input.tx_hash.as_ref())?
+            .u64(input.index)?;
+    }
+
+    e.u64(1)?.array(outputs.len() as u64)?;
+
+    for output in outputs {
+        encode_output(e, output)?;
+    }
+
+    e.u64(2)?.u64(fee)?.u64(3)?.u64(ttl)?;
+
+    Ok(())
+}
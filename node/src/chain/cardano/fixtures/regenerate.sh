@@ -0,0 +1,102 @@
+#!/usr/bin/env bash
+# Rebuilds the expected transaction of every fixture with cardano-cli, so the
+# fixtures check `CardanoBuilder` against the reference implementation instead
+# of against itself. See README.md.
+#
+# Usage: PARAMS=protocol.json ./regenerate.sh [fixture.json...]
+
+set -euo pipefail
+
+cd "$(dirname "$0")"
+
+: "${PARAMS:?set PARAMS to a protocol parameters file from cardano-cli}"
+CLI=(cardano-cli babbage)
+TMP=$(mktemp -d)
+trap 'rm -rf "$TMP"' EXIT
+
+if [ $# -eq 0 ]; then
+  set -- *.json
+fi
+
+# `<lovelace> + <amount> <policy id>.<asset name> + ...`, as cardano-cli
+# takes values.
+value_arg() {
+  jq -r '[.lovelace | tostring] + (del(.lovelace) | to_entries | map("\(.value) \(.key)")) | join(" + ")'
+}
+
+for fixture in "$@"; do
+  echo "$fixture"
+
+  case $(jq -r .network "$fixture") in
+    mainnet) network=(--mainnet) ;;
+    *) network=(--testnet-magic 1) ;;
+  esac
+
+  jq '{type: "PaymentSigningKeyShelley_ed25519", description: "", cborHex: ("5820" + .vault)}' \
+    "$fixture" >"$TMP/vault.skey"
+  "${CLI[@]}" key verification-key --signing-key-file "$TMP/vault.skey" \
+    --verification-key-file "$TMP/vault.vkey"
+  vault=$("${CLI[@]}" address build --payment-verification-key-file "$TMP/vault.vkey" "${network[@]}")
+
+  jq --slurpfile pp "$PARAMS" '$pp[0] + {
+    txFeePerByte: .params.min_fee_a,
+    txFeeFixed: .params.min_fee_b,
+    utxoCostPerByte: .params.coins_per_utxo_byte,
+    maxTxSize: .params.max_tx_size
+  }' "$fixture" >"$TMP/params.json"
+
+  # Values as `{"lovelace": n, "<policy id>.<asset name>": n}`.
+  jq '.assets as $assets | [.payout.assets[] | . as [$id, $amount] | $assets[$id] |
+    {key: (if .type == "lovelace" then "lovelace" else "\(.policy_id).\(.asset_name)" end), value: $amount}]
+    | group_by(.key) | map({key: .[0].key, value: (map(.value) | add)}) | from_entries
+    | {lovelace: 0} + .' "$fixture" >"$TMP/payout.json"
+
+  # Raised to the minimum the ledger requires for the output, like the builder.
+  address=$(jq -r .payout.address "$fixture")
+  min=$("${CLI[@]}" transaction calculate-min-required-utxo --protocol-params-file "$TMP/params.json" \
+    --tx-out "$address+$(value_arg <"$TMP/payout.json")" | awk '{print $2}')
+  jq --argjson min "$min" '.lovelace |= ([., $min] | max)' "$TMP/payout.json" >"$TMP/output.json"
+
+  # The inputs and the fee are chosen by the builder, the change is whatever
+  # they leave, with dust already folded into the fee.
+  jq --slurpfile output "$TMP/output.json" '.expected as $expected |
+    [.utxos[] | select(.input as $i | $expected.inputs | index([$i])) | .value |
+      [{key: "lovelace", value: .coin}] +
+      [(.assets // {}) | to_entries[] | .key as $p | .value | to_entries[] | {key: "\($p).\(.key)", value: .value}]
+    | .[]]
+    + [$output[0] | to_entries[] | .value |= -.]
+    + [{key: "lovelace", value: -$expected.fee}]
+    | group_by(.key) | map({key: .[0].key, value: (map(.value) | add)})
+    | map(select(.value != 0)) | from_entries' "$fixture" >"$TMP/change.json"
+
+  args=()
+  for input in $(jq -r '.expected.inputs[] | "\(.tx_hash)#\(.index)"' "$fixture"); do
+    args+=(--tx-in "$input")
+  done
+  args+=(--tx-out "$address+$(value_arg <"$TMP/output.json")")
+  if [ "$(jq length "$TMP/change.json")" -gt 0 ]; then
+    args+=(--tx-out "$vault+$(jq '{lovelace: 0} + .' "$TMP/change.json" | value_arg)")
+  fi
+
+  "${CLI[@]}" transaction build-raw "${args[@]}" \
+    --fee "$(jq .expected.fee "$fixture")" \
+    --invalid-hereafter "$(jq .valid_until "$fixture")" \
+    --out-file "$TMP/tx.raw"
+  "${CLI[@]}" transaction sign --tx-body-file "$TMP/tx.raw" \
+    --signing-key-file "$TMP/vault.skey" "${network[@]}" --out-file "$TMP/tx.signed"
+
+  fee=$("${CLI[@]}" transaction calculate-min-fee --tx-body-file "$TMP/tx.raw" \
+    --protocol-params-file "$TMP/params.json" --witness-count 1 | awk '{print $1}')
+  if [ "$fee" -gt "$(jq .expected.fee "$fixture")" ]; then
+    echo "$fixture: fee below the minimum of $fee" >&2
+    exit 1
+  fi
+
+  id=$("${CLI[@]}" transaction txid --tx-file "$TMP/tx.signed" --output-text 2>/dev/null ||
+    "${CLI[@]}" transaction txid --tx-file "$TMP/tx.signed")
+  cbor=$(jq -r .cborHex "$TMP/tx.signed")
+
+  jq --arg id "$id" --arg cbor "$cbor" '.expected.id = $id | .expected.cbor = $cbor' \
+    "$fixture" >"$TMP/fixture.json"
+  mv "$TMP/fixture.json" "$fixture"
+done
@@ -0,0 +1,249 @@
+//! Construction of the Cardano transactions that pay out of the delegate's
+//! vault.
+//!
+//! Transactions are encoded following the Babbage CDDL, with inputs sorted and
+//! multi-asset maps in canonical order so the same payout over the same UTxOs
+//! always produces the same bytes.
+
+use std::collections::HashMap;
+
+use mugraph_core::{
+    error::Error,
+    types::{Asset, Hash},
+};
+
+use super::Payout;
+
+mod address;
+mod builder;
+mod value;
+
+pub use self::{address::*, builder::*, value::*};
+
+/// Maps the asset ids used by notes to the assets they represent on Cardano.
+pub trait AssetResolver: Send + Sync {
+    fn resolve(&self, asset_id: Hash) -> Result<AssetUnit, Error>;
+}
+
+impl AssetResolver for HashMap<Hash, AssetUnit> {
+    fn resolve(&self, asset_id: Hash) -> Result<AssetUnit, Error> {
+        self.get(&asset_id)
+            .cloned()
+            .ok_or_else(|| Error::UnsupportedAsset {
+                asset_id,
+                reason: "asset has no Cardano equivalent".to_string(),
+            })
+    }
+}
+
+impl AssetResolver for HashMap<Hash, Asset> {
+    fn resolve(&self, asset_id: Hash) -> Result<AssetUnit, Error> {
+        match self.get(&asset_id) {
+            Some(asset) => asset.try_into(),
+            None => Err(Error::UnsupportedAsset {
+                asset_id,
+                reason: "asset is not registered".to_string(),
+            }),
+        }
+    }
+}
+
+/// Builds and signs the L1 transaction for a payout.
+pub trait PayoutBuilder: Send + Sync {
+    /// Builds the transaction paying `payout` out of `utxos`, the unspent
+    /// outputs currently held by the vault. The transaction is only valid up
+    /// to the slot `valid_until`.
+    fn build(
+        &self,
+        payout: &Payout,
+        utxos: &[Utxo],
+        valid_until: u64,
+    ) -> Result<SignedTransaction, Error>;
+
+    /// Checks that `address` can be paid to by the transactions built.
+    fn check_address(&self, address: &str) -> Result<(), Error>;
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::{Signature, SigningKey, Verifier};
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::chain::{ChainBackend, MockChain};
+
+    #[derive(Deserialize)]
+    struct Fixture {
+        network: Network,
+        #[serde(with = "hex::serde")]
+        vault: [u8; 32],
+        params: ProtocolParams,
+        assets: HashMap<Hash, AssetUnit>,
+        utxos: Vec<Utxo>,
+        payout: Payout,
+        valid_until: u64,
+        expected: Expected,
+    }
+
+    #[derive(Deserialize)]
+    struct Expected {
+        id: Hash,
+        fee: u64,
+        inputs: Vec<TxIn>,
+        cbor: String,
+    }
+
+    const FIXTURES: &[(&str, &str)] = &[
+        ("ada_only", include_str!("fixtures/ada_only.json")),
+        ("multi_asset", include_str!("fixtures/multi_asset.json")),
+        ("token_only", include_str!("fixtures/token_only.json")),
+        ("dust_change", include_str!("fixtures/dust_change.json")),
+        ("mainnet", include_str!("fixtures/mainnet.json")),
+    ];
+
+    fn load(fixture: &str) -> (Fixture, CardanoBuilder<HashMap<Hash, AssetUnit>>) {
+        let fixture: Fixture = serde_json::from_str(fixture).unwrap();
+        let vault = Vault::new(SigningKey::from_bytes(&fixture.vault), fixture.network);
+        let builder = CardanoBuilder::new(fixture.params, vault, fixture.assets.clone());
+
+        (fixture, builder)
+    }
+
+    /// Checks the builder still produces the transactions recorded in the
+    /// fixtures. They were recorded from the builder itself, so this catches
+    /// changes to the encoding, not encodings the ledger would reject, see
+    /// `fixtures/README.md`.
+    #[test]
+    fn test_fixtures_unchanged() {
+        for (name, fixture) in FIXTURES {
+            let (fixture, builder) = load(fixture);
+            let tx = builder
+                .build(&fixture.payout, &fixture.utxos, fixture.valid_until)
+                .unwrap();
+
+            assert_eq!(hex::encode(&tx.bytes), fixture.expected.cbor, "{name}");
+            assert_eq!(tx.id, fixture.expected.id, "{name}");
+            assert_eq!(tx.fee, fixture.expected.fee, "{name}");
+            assert_eq!(tx.inputs, fixture.expected.inputs, "{name}");
+            assert!(
+                tx.fee >= fixture.params.min_fee(tx.bytes.len() as u64),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_mock_chain() {
+        let (fixture, builder) = load(FIXTURES[0].1);
+        let chain = MockChain::default().with_builder(builder, fixture.utxos.clone());
+
+        let tx = chain.submit_payout(&fixture.payout).unwrap();
+        assert_eq!(chain.submit_payout(&fixture.payout).unwrap(), tx);
+
+        let first = chain.transaction(tx).unwrap().unwrap();
+        assert_eq!(first.id, tx);
+        assert_eq!(first.inputs, fixture.expected.inputs);
+
+        // Later payouts spend the change of the earlier ones.
+        let second = Payout {
+            id: Hash::digest(b"second"),
+            ..fixture.payout.clone()
+        };
+        let tx = chain.submit_payout(&second).unwrap();
+        let second = chain.transaction(tx).unwrap().unwrap();
+        assert!(second.inputs.contains(&TxIn {
+            tx_hash: first.id,
+            index: 1
+        }));
+
+        let third = Payout {
+            id: Hash::digest(b"third"),
+            ..fixture.payout.clone()
+        };
+        assert!(chain.submit_payout(&third).is_err());
+    }
+
+    #[test]
+    fn test_witness_signs_id() {
+        let (fixture, builder) = load(FIXTURES[1].1);
+        let key = SigningKey::from_bytes(&fixture.vault).verifying_key();
+        let tx = builder
+            .build(&fixture.payout, &fixture.utxos, fixture.valid_until)
+            .unwrap();
+
+        // [body, {0: [[vkey, signature]]}, true, null], with the signature at
+        // the end of the witness set.
+        let signature = &tx.bytes[tx.bytes.len() - 2 - 64..tx.bytes.len() - 2];
+        let signature = Signature::from_slice(signature).unwrap();
+
+        assert!(key.verify(tx.id.as_ref(), &signature).is_ok());
+    }
+
+    #[test]
+    fn test_cip19_enterprise_address() {
+        let key = ed25519_dalek::VerifyingKey::from_bytes(
+            &hex::decode("73fea80d424276ad0978d4fe5310e8bc2d485f5f6bb3bf87612989f112ad5a7d")
+                .unwrap()
+                .try_into()
+                .unwrap(),
+        )
+        .unwrap();
+
+        for (network, address) in [
+            (
+                Network::Mainnet,
+                "addr1vx2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzers66hrl8",
+            ),
+            (
+                Network::Testnet,
+                "addr_test1vz2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzerspjrlsz",
+            ),
+        ] {
+            assert_eq!(
+                enterprise_address(network, &key),
+                parse_address(address, network).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_wrong_network() {
+        let (mut fixture, builder) = load(FIXTURES[0].1);
+        assert!(builder.check_address(&fixture.payout.address).is_ok());
+
+        fixture.payout.address =
+            "addr1vx2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzers66hrl8".to_string();
+
+        assert!(builder.check_address(&fixture.payout.address).is_err());
+        assert!(builder
+            .build(&fixture.payout, &fixture.utxos, fixture.valid_until)
+            .is_err());
+    }
+
+    #[test]
+    fn test_insufficient_funds() {
+        let (mut fixture, builder) = load(FIXTURES[1].1);
+        fixture.payout.assets[0].1 = u32::MAX as u64;
+
+        assert!(builder
+            .build(&fixture.payout, &fixture.utxos, fixture.valid_until)
+            .is_err());
+
+        let (fixture, builder) = load(FIXTURES[0].1);
+
+        assert!(builder
+            .build(&fixture.payout, &fixture.utxos[..0], fixture.valid_until)
+            .is_err());
+    }
+
+    #[test]
+    fn test_unknown_asset() {
+        let (mut fixture, builder) = load(FIXTURES[0].1);
+        fixture.payout.assets.push((Hash::digest(b"unknown"), 1));
+
+        assert!(matches!(
+            builder.build(&fixture.payout, &fixture.utxos, fixture.valid_until),
+            Err(Error::UnsupportedAsset { .. })
+        ));
+    }
+}
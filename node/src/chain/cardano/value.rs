@@ -0,0 +1,195 @@
+use std::{cmp::Ordering, collections::BTreeMap, convert::Infallible};
+
+use minicbor::{encode, Encoder};
+use mugraph_core::{error::Error, types::Asset};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PolicyId(#[serde(with = "hex::serde")] pub [u8; 28]);
+
+/// Name of a native asset, ordered like canonical CBOR map keys (shorter names
+/// first) so multi-asset maps are always encoded the same way.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct AssetName(#[serde(with = "hex::serde")] pub Vec<u8>);
+
+impl Ord for AssetName {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.0.len(), &self.0).cmp(&(other.0.len(), &other.0))
+    }
+}
+
+impl PartialOrd for AssetName {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AssetUnit {
+    Lovelace,
+    Native {
+        policy_id: PolicyId,
+        asset_name: AssetName,
+    },
+}
+
+impl TryFrom<&Asset> for AssetUnit {
+    type Error = Error;
+
+    fn try_from(asset: &Asset) -> Result<Self, Error> {
+        if asset.is_ada() {
+            return Ok(Self::Lovelace);
+        }
+
+        Ok(Self::Native {
+            policy_id: PolicyId(asset.policy_id.as_slice().try_into().map_err(|_| {
+                Error::UnsupportedAsset {
+                    asset_id: asset.id,
+                    reason: "policy id must have 28 bytes".to_string(),
+                }
+            })?),
+            asset_name: AssetName(asset.asset_name.clone()),
+        })
+    }
+}
+
+/// An amount of lovelace and native assets. Assets are never stored with a
+/// zero amount.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Value {
+    pub coin: u64,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub assets: BTreeMap<PolicyId, BTreeMap<AssetName, u64>>,
+}
+
+impl Value {
+    pub fn get(&self, unit: &AssetUnit) -> u64 {
+        match unit {
+            AssetUnit::Lovelace => self.coin,
+            AssetUnit::Native {
+                policy_id,
+                asset_name,
+            } => self
+                .assets
+                .get(policy_id)
+                .and_then(|names| names.get(asset_name))
+                .copied()
+                .unwrap_or(0),
+        }
+    }
+
+    pub fn add(&mut self, unit: &AssetUnit, amount: u64) -> Result<(), Error> {
+        let overflow = || Error::ServerError {
+            reason: format!("Amount of {unit:?} overflows"),
+        };
+
+        match unit {
+            AssetUnit::Lovelace => {
+                self.coin = self.coin.checked_add(amount).ok_or_else(overflow)?;
+            }
+            AssetUnit::Native { .. } if amount == 0 => {}
+            AssetUnit::Native {
+                policy_id,
+                asset_name,
+            } => {
+                let current = self
+                    .assets
+                    .entry(*policy_id)
+                    .or_default()
+                    .entry(asset_name.clone())
+                    .or_default();
+
+                *current = current.checked_add(amount).ok_or_else(overflow)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn add_value(&mut self, other: &Value) -> Result<(), Error> {
+        for (unit, amount) in other.units() {
+            self.add(&unit, amount)?;
+        }
+
+        Ok(())
+    }
+
+    /// Subtracts `other`, returning `None` if any amount would be negative.
+    pub fn checked_sub(&self, other: &Value) -> Option<Value> {
+        let mut result = self.clone();
+
+        for (unit, amount) in other.units() {
+            match unit {
+                AssetUnit::Lovelace => result.coin = result.coin.checked_sub(amount)?,
+                AssetUnit::Native {
+                    policy_id,
+                    asset_name,
+                } => {
+                    let names = result.assets.get_mut(&policy_id)?;
+                    let current = names.get_mut(&asset_name)?;
+                    *current = current.checked_sub(amount)?;
+
+                    if *current == 0 {
+                        names.remove(&asset_name);
+                    }
+
+                    if names.is_empty() {
+                        result.assets.remove(&policy_id);
+                    }
+                }
+            }
+        }
+
+        Some(result)
+    }
+
+    #[inline]
+    pub fn is_zero(&self) -> bool {
+        self.coin == 0 && self.assets.is_empty()
+    }
+
+    /// Native assets held, in canonical order.
+    pub fn native(&self) -> impl Iterator<Item = (AssetUnit, u64)> + '_ {
+        self.assets.iter().flat_map(|(policy_id, names)| {
+            names.iter().map(|(asset_name, amount)| {
+                (
+                    AssetUnit::Native {
+                        policy_id: *policy_id,
+                        asset_name: asset_name.clone(),
+                    },
+                    *amount,
+                )
+            })
+        })
+    }
+
+    /// Every unit held, starting with lovelace.
+    pub fn units(&self) -> impl Iterator<Item = (AssetUnit, u64)> + '_ {
+        [(AssetUnit::Lovelace, self.coin)]
+            .into_iter()
+            .chain(self.native())
+    }
+
+    /// `value = coin / [coin, multiasset<uint>]`
+    pub(super) fn encode(&self, e: &mut Encoder<Vec<u8>>) -> Result<(), encode::Error<Infallible>> {
+        if self.assets.is_empty() {
+            e.u64(self.coin)?;
+            return Ok(());
+        }
+
+        e.array(2)?.u64(self.coin)?.map(self.assets.len() as u64)?;
+
+        for (policy_id, names) in self.assets.iter() {
+            e.bytes(&policy_id.0)?.map(names.len() as u64)?;
+
+            for (asset_name, amount) in names.iter() {
+                e.bytes(&asset_name.0)?.u64(*amount)?;
+            }
+        }
+
+        Ok(())
+    }
+}
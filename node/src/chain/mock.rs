@@ -0,0 +1,282 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use mugraph_core::{error::Error, types::*};
+use rand::prelude::*;
+use rand_chacha::ChaCha20Rng;
+
+use super::{
+    cardano::{PayoutBuilder, SignedTransaction, TxIn, Utxo},
+    ChainBackend, Payout, PayoutStatus,
+};
+
+/// Blocks a payout built from the vault's UTxOs stays valid for.
+const PAYOUT_TTL: u64 = 7200;
+
+/// In-process chain, used for tests and for running a node without an L1.
+#[derive(Debug, Clone)]
+pub struct MockChain {
+    state: Arc<Mutex<MockState>>,
+    vault: String,
+}
+
+#[derive(Debug)]
+struct MockState {
+    rng: ChaCha20Rng,
+    height: u64,
+    min_confirmations: u64,
+    deposits: HashMap<Hash, (u64, Deposit)>,
+    payouts: HashMap<Hash, MockPayout>,
+    vault: Option<MockVault>,
+}
+
+#[derive(Debug, Clone)]
+struct MockPayout {
+    tx: Hash,
+    height: u64,
+    payout: Payout,
+    transaction: Option<SignedTransaction>,
+    failure: Option<String>,
+}
+
+/// UTxOs of the vault, spent by the transactions built for payouts.
+struct MockVault {
+    builder: Box<dyn PayoutBuilder>,
+    utxos: Vec<Utxo>,
+}
+
+impl fmt::Debug for MockVault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockVault")
+            .field("utxos", &self.utxos)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for MockChain {
+    fn default() -> Self {
+        Self::new(&mut thread_rng(), 1)
+    }
+}
+
+impl MockChain {
+    pub fn new<R: Rng + CryptoRng>(rng: &mut R, min_confirmations: u64) -> Self {
+        let seed = rng.gen();
+
+        Self {
+            vault: format!("mock_vault_{seed:016x}"),
+            state: Arc::new(Mutex::new(MockState {
+                rng: ChaCha20Rng::seed_from_u64(seed),
+                height: 0,
+                min_confirmations,
+                deposits: HashMap::new(),
+                payouts: HashMap::new(),
+                vault: None,
+            })),
+        }
+    }
+
+    /// Handle to the same chain for a delegate with its vault at `vault`.
+    pub fn with_vault(&self, vault: impl Into<String>) -> Self {
+        Self {
+            state: self.state.clone(),
+            vault: vault.into(),
+        }
+    }
+
+    /// Builds the L1 transaction of every payout with `builder`, spending
+    /// `utxos` and the change of earlier payouts, instead of only recording
+    /// it.
+    pub fn with_builder(self, builder: impl PayoutBuilder + 'static, utxos: Vec<Utxo>) -> Self {
+        if let Ok(mut state) = self.state.lock() {
+            state.vault = Some(MockVault {
+                builder: Box::new(builder),
+                utxos,
+            });
+        }
+
+        self
+    }
+
+    /// Locks funds in the vault, returning the reference for the deposit.
+    pub fn lock(&self, asset_id: Hash, amount: u64, owner: PublicKey) -> Result<Hash, Error> {
+        let mut state = self.state.lock()?;
+        let id = Hash::random(&mut state.rng);
+        let height = state.height;
+
+        state.deposits.insert(
+            id,
+            (
+                height,
+                Deposit {
+                    id,
+                    asset_id,
+                    amount,
+                    owner,
+                    confirmations: 0,
+                },
+            ),
+        );
+
+        Ok(id)
+    }
+
+    /// Payouts submitted to the chain so far.
+    pub fn payouts(&self) -> Result<Vec<Payout>, Error> {
+        let state = self.state.lock()?;
+
+        Ok(state.payouts.values().map(|p| p.payout.clone()).collect())
+    }
+
+    /// L1 transaction built for the payout submitted as `tx`, if it was built
+    /// with [`MockChain::with_builder`].
+    pub fn transaction(&self, tx: Hash) -> Result<Option<SignedTransaction>, Error> {
+        let state = self.state.lock()?;
+
+        Ok(state
+            .payouts
+            .values()
+            .find(|p| p.tx == tx)
+            .and_then(|p| p.transaction.clone()))
+    }
+
+    /// Makes a submitted payout fail, like if it was rejected by the L1.
+    pub fn fail_payout(&self, tx: Hash, reason: &str) -> Result<(), Error> {
+        let mut state = self.state.lock()?;
+
+        match state.payouts.values_mut().find(|p| p.tx == tx) {
+            Some(payout) => {
+                payout.failure = Some(reason.to_string());
+                Ok(())
+            }
+            None => Err(Error::NotFound {
+                reason: format!("Payout {tx} was not submitted"),
+            }),
+        }
+    }
+
+    /// Produces `blocks` new blocks, confirming pending deposits and payouts.
+    pub fn advance(&self, blocks: u64) -> Result<(), Error> {
+        self.state.lock()?.height += blocks;
+
+        Ok(())
+    }
+}
+
+impl ChainBackend for MockChain {
+    fn deposit(&self, id: Hash) -> Result<Option<Deposit>, Error> {
+        let state = self.state.lock()?;
+
+        Ok(state.deposits.get(&id).map(|(height, deposit)| Deposit {
+            confirmations: state.height - height,
+            ..deposit.clone()
+        }))
+    }
+
+    fn min_confirmations(&self) -> u64 {
+        self.state
+            .lock()
+            .map(|s| s.min_confirmations)
+            .unwrap_or(u64::MAX)
+    }
+    fn submit_payout(&self, payout: &Payout) -> Result<Hash, Error> {
+        let mut guard = self.state.lock()?;
+        let state = &mut *guard;
+
+        if let Some(existing) = state.payouts.get(&payout.id) {
+            return Ok(existing.tx);
+        }
+
+        let height = state.height;
+        let (tx, transaction) = match &mut state.vault {
+            Some(vault) => {
+                let transaction = vault
+                    .builder
+                    .build(payout, &vault.utxos, height + PAYOUT_TTL)?;
+
+                vault
+                    .utxos
+                    .retain(|u| !transaction.inputs.contains(&u.input));
+
+                if let Some(value) = transaction.change.clone() {
+                    vault.utxos.push(Utxo {
+                        input: TxIn {
+                            tx_hash: transaction.id,
+                            index: 1,
+                        },
+                        value,
+                    });
+                }
+
+                (transaction.id, Some(transaction))
+            }
+            None => (Hash::random(&mut state.rng), None),
+        };
+
+        state.payouts.insert(
+            payout.id,
+            MockPayout {
+                tx,
+                height,
+                payout: payout.clone(),
+                transaction,
+                failure: None,
+            },
+        );
+
+        Ok(tx)
+    }
+
+    fn payout_status(&self, tx: Hash) -> Result<PayoutStatus, Error> {
+        let state = self.state.lock()?;
+
+        let payout =
+            state
+                .payouts
+                .values()
+                .find(|p| p.tx == tx)
+                .ok_or_else(|| Error::NotFound {
+                    reason: format!("Payout {tx} was not submitted"),
+                })?;
+
+        Ok(match &payout.failure {
+            Some(reason) => PayoutStatus::Failed {
+                reason: reason.clone(),
+            },
+            None if state.height - payout.height >= state.min_confirmations => {
+                PayoutStatus::Confirmed
+            }
+            None => PayoutStatus::Pending,
+        })
+    }
+
+    fn outputs(&self, tx: Hash, address: &str) -> Result<Vec<(Hash, u64)>, Error> {
+        let state = self.state.lock()?;
+
+        Ok(state
+            .payouts
+            .values()
+            .find(|p| p.tx == tx && p.payout.address == address)
+            .map(|p| p.payout.assets.clone())
+            .unwrap_or_default())
+    }
+
+    fn vault_address(&self) -> String {
+        self.vault.clone()
+    }
+
+    /// Any address but an empty one is accepted, unless payouts are built
+    /// with [`MockChain::with_builder`], which checks it.
+    fn check_address(&self, address: &str) -> Result<(), Error> {
+        match &self.state.lock()?.vault {
+            Some(vault) => vault.builder.check_address(address),
+            None if address.is_empty() => Err(Error::InvalidTransaction {
+                reason: "Invalid address: empty address".to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+}
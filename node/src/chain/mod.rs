@@ -0,0 +1,53 @@
+//! Abstraction over the L1 chain where the delegate's vault lives.
+
+use mugraph_core::{error::Error, types::*};
+use serde::{Deserialize, Serialize};
+
+pub mod cardano;
+mod mock;
+
+pub use self::mock::*;
+
+/// Assets to be sent out of the delegate's vault on the L1.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Payout {
+    /// Identifier of the withdrawal this payout is for.
+    pub id: Hash,
+    pub address: String,
+    pub assets: Vec<(Hash, u64)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PayoutStatus {
+    Pending,
+    Confirmed,
+    Failed { reason: String },
+}
+
+pub trait ChainBackend: Send + Sync + 'static {
+    /// Looks up a deposit into the delegate's vault by its L1 reference.
+    fn deposit(&self, id: Hash) -> Result<Option<Deposit>, Error>;
+
+    /// Confirmations required before a deposit can be minted against, or a
+    /// payout is considered final.
+    fn min_confirmations(&self) -> u64;
+
+    /// Submits a payout to the L1, returning the id of the L1 transaction.
+    ///
+    /// Implementations must be idempotent on `payout.id`, as a payout can be
+    /// submitted again if the node fails before recording the result.
+    fn submit_payout(&self, payout: &Payout) -> Result<Hash, Error>;
+
+    fn payout_status(&self, tx: Hash) -> Result<PayoutStatus, Error>;
+
+    /// Assets the L1 transaction `tx` sent to `address`, empty when it sent
+    /// nothing there or is unknown.
+    fn outputs(&self, tx: Hash, address: &str) -> Result<Vec<(Hash, u64)>, Error>;
+
+    /// Address of the delegate's vault, where peers pay their settlements.
+    fn vault_address(&self) -> String;
+
+    /// Checks that payouts can be sent to `address`, before accepting a
+    /// withdrawal to it.
+    fn check_address(&self, address: &str) -> Result<(), Error>;
+}
@@ -1,66 +1,676 @@
-use std::net::SocketAddr;
+//! Configuration of the delegate, read from a TOML file with every field
+//! optional. Environment variables and command-line flags override the
+//! file, see [`Args`], and the result is checked with [`Config::validate`]
+//! before the delegate starts.
+//!
+//! `mugraph.toml.example` documents every field.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write as _,
+    fs,
+    io::Write as _,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
 use clap::Parser;
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{eyre, Result, WrapErr};
 use mugraph_core::{
     error::Error,
-    types::{Keypair, SecretKey},
+    types::{Asset, FeeSchedule, Keypair, SecretKey, BASIS_POINTS},
 };
 use rand::thread_rng;
-use tracing::warn;
+use serde::Deserialize;
+use tracing::{info, level_filters::LevelFilter, warn};
+
+use crate::{
+    chain::{ChainBackend, MockChain},
+    peer::{self, HttpPeer, Peer, Peers},
+};
+
+/// Command-line flags of the delegate, each of which can also be set
+/// through the environment variable named after it.
+#[derive(Debug, Clone, Default, Parser)]
+pub struct Args {
+    /// TOML file to read the configuration from.
+    #[clap(short, long, env = "MUGRAPH_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Address to listen on, replacing the ones in the file. Can be repeated.
+    #[clap(short, long, env = "MUGRAPH_LISTEN", value_delimiter = ',')]
+    pub listen: Vec<SocketAddr>,
+
+    /// File of the database.
+    #[clap(long, env = "MUGRAPH_DATABASE")]
+    pub database: Option<PathBuf>,
+
+    /// File with the secret key of the delegate, created if missing.
+    #[clap(long, env = "MUGRAPH_KEYSTORE")]
+    pub keystore: Option<PathBuf>,
+
+    /// One of `off`, `error`, `warn`, `info`, `debug` or `trace`.
+    #[clap(long, env = "MUGRAPH_LOG_LEVEL")]
+    pub log_level: Option<String>,
+
+    /// Address to serve Prometheus metrics on.
+    #[clap(long, env = "MUGRAPH_METRICS")]
+    pub metrics: Option<SocketAddr>,
+
+    /// Delegate to settle cross-delegate transactions with, as
+    /// `<public key>@<url>`, added to the ones in the file. Can be repeated.
+    #[clap(long = "peer", env = "MUGRAPH_PEERS", value_delimiter = ',')]
+    pub peers: Vec<String>,
 
-#[derive(Debug, Clone, Parser)]
+    /// Allows development settings, like running on the mock chain.
+    #[clap(long, env = "MUGRAPH_DEV")]
+    pub dev: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
-    #[clap(short, long, default_value = "0.0.0.0:9999")]
-    pub addr: SocketAddr,
+    pub server: Server,
+    pub storage: Storage,
+    pub limits: Limits,
+    /// Fees charged on transactions, none by default.
+    pub fees: FeeSchedule,
+    /// Assets registered on startup, updating the ones already registered.
+    pub assets: Vec<Asset>,
+    /// Delegates to settle cross-delegate transactions with, as
+    /// `<public key>@<url>`.
+    pub peers: Vec<String>,
+    pub chain: Chain,
+    pub logging: Logging,
+    pub metrics: Metrics,
+    /// Allows development settings, like running on the mock chain. Never
+    /// set it for a delegate holding funds of value.
+    pub dev: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Server {
+    /// Addresses to serve the API on, all with the same TLS settings.
+    pub listen: Vec<SocketAddr>,
+    /// Serves HTTPS instead of plain HTTP when set.
+    pub tls: Option<Tls>,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Self {
+            listen: vec![SocketAddr::from(([0, 0, 0, 0], 9999))],
+            tls: None,
+        }
+    }
+}
 
-    #[clap(long)]
-    pub seed: Option<u64>,
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Tls {
+    /// PEM file with the certificate chain.
+    pub cert: PathBuf,
+    /// PEM file with the private key of the certificate.
+    pub key: PathBuf,
+}
 
-    #[clap(short, long)]
-    pub public_key: Option<String>,
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Storage {
+    /// File of the database.
+    pub database: PathBuf,
+    /// File with the secret key of the delegate, in hex. A new key is saved
+    /// there if it does not exist, and a random one is used on every start
+    /// without it.
+    pub keystore: Option<PathBuf>,
+}
 
-    #[clap(short, long)]
-    pub secret_key: Option<String>,
+impl Default for Storage {
+    fn default() -> Self {
+        Self {
+            database: PathBuf::from("db"),
+            keystore: None,
+        }
+    }
 }
 
-impl Default for Config {
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    /// Largest request body accepted, in bytes.
+    pub max_body_size: usize,
+    /// Requests handled at once, others wait for their turn.
+    pub max_concurrent_requests: usize,
+    /// Seconds a request can take, including its wait for a turn.
+    pub request_timeout: u64,
+}
+
+impl Default for Limits {
     fn default() -> Self {
-        Self::new()
+        Self {
+            max_body_size: 2 * 1024 * 1024,
+            max_concurrent_requests: 1024,
+            request_timeout: 30,
+        }
+    }
+}
+
+impl Limits {
+    pub fn request_timeout(&self) -> Duration {
+        Duration::from_secs(self.request_timeout)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Chain {
+    /// L1 backend watched for deposits and paying out withdrawals. The
+    /// delegate does not start without one, unless `dev` is set.
+    pub backend: Option<Backend>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Backend {
+    /// In-memory chain, lost on restart, for development only.
+    Mock,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Logging {
+    /// One of `off`, `error`, `warn`, `info`, `debug` or `trace`.
+    pub level: String,
+    /// Colors the output, for terminals.
+    pub ansi: bool,
+}
+
+impl Default for Logging {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            ansi: true,
+        }
+    }
+}
+
+impl Logging {
+    pub fn level(&self) -> Result<LevelFilter> {
+        self.level
+            .parse()
+            .map_err(|_| eyre!("unknown log level {:?}", self.level))
+    }
+
+    /// Sets up the global subscriber printing logs.
+    pub fn init(&self) -> Result<()> {
+        tracing_subscriber::fmt()
+            .with_max_level(self.level()?)
+            .with_ansi(self.ansi)
+            .try_init()
+            .map_err(|e| eyre!(e))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Metrics {
+    /// Address to serve Prometheus metrics on, none are exported without it.
+    pub listen: Option<SocketAddr>,
+}
+
+impl Metrics {
+    /// Installs the global recorder and starts serving metrics, if enabled.
+    /// Must be called from within the runtime.
+    pub fn init(&self) -> Result<()> {
+        if let Some(addr) = self.listen {
+            metrics_exporter_prometheus::PrometheusBuilder::new()
+                .with_http_listener(addr)
+                .install()?;
+            info!(%addr, "Serving metrics");
+        }
+
+        Ok(())
     }
 }
 
 impl Config {
-    pub fn new() -> Self {
-        Self::parse()
+    /// Reads the file given in `args`, if any, applies the overrides in
+    /// `args` and validates the result.
+    pub fn load(args: &Args) -> Result<Self> {
+        let mut config = match &args.config {
+            Some(path) => Self::read(path)?,
+            None => Self::default(),
+        };
+
+        config.apply(args);
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read config file {}", path.display()))?;
+
+        toml::from_str(&contents)
+            .wrap_err_with(|| format!("Failed to parse config file {}", path.display()))
     }
 
+    /// Overrides the settings given in `args`.
+    pub fn apply(&mut self, args: &Args) {
+        if !args.listen.is_empty() {
+            self.server.listen = args.listen.clone();
+        }
+
+        if let Some(database) = &args.database {
+            self.storage.database = database.clone();
+        }
+
+        if let Some(keystore) = &args.keystore {
+            self.storage.keystore = Some(keystore.clone());
+        }
+
+        if let Some(level) = &args.log_level {
+            self.logging.level = level.clone();
+        }
+
+        if let Some(addr) = args.metrics {
+            self.metrics.listen = Some(addr);
+        }
+
+        self.peers.extend(args.peers.iter().cloned());
+        self.dev |= args.dev;
+    }
+
+    /// Checks the settings that can be checked before starting, failing
+    /// with every problem found at once.
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = vec![];
+        let mut problem = |p: String| problems.push(p);
+
+        if self.server.listen.is_empty() {
+            problem("server.listen: at least one address is needed".to_string());
+        }
+
+        let mut addrs = BTreeSet::new();
+        for addr in self.server.listen.iter() {
+            if !addrs.insert(addr) {
+                problem(format!("server.listen: {addr} is given more than once"));
+            }
+        }
+
+        if let Some(tls) = &self.server.tls {
+            for (name, path) in [("cert", &tls.cert), ("key", &tls.key)] {
+                if !path.is_file() {
+                    problem(format!(
+                        "server.tls.{name}: {} is not a file",
+                        path.display()
+                    ));
+                }
+            }
+        }
+
+        if self.storage.database.as_os_str().is_empty() {
+            problem("storage.database: path can not be empty".to_string());
+        }
+
+        if let Some(keystore) = &self.storage.keystore {
+            if keystore.exists() {
+                if let Err(e) = read_key(keystore) {
+                    problem(format!("storage.keystore: {e}"));
+                }
+            }
+        }
+
+        let limits = &self.limits;
+        for (name, value) in [
+            ("max_body_size", limits.max_body_size as u64),
+            (
+                "max_concurrent_requests",
+                limits.max_concurrent_requests as u64,
+            ),
+            ("request_timeout", limits.request_timeout),
+        ] {
+            if value == 0 {
+                problem(format!("limits.{name}: must be over 0"));
+            }
+        }
+
+        let fees = std::iter::once(("default".to_string(), &self.fees.default)).chain(
+            self.fees
+                .assets
+                .iter()
+                .map(|(asset_id, fee)| (format!("assets.{asset_id}"), fee)),
+        );
+        for (name, fee) in fees {
+            if fee.basis_points as u128 > BASIS_POINTS {
+                problem(format!(
+                    "fees.{name}.basis_points: can not be over {BASIS_POINTS}"
+                ));
+            }
+        }
+
+        let mut assets = BTreeSet::new();
+        let mut units = BTreeMap::new();
+        for asset in self.assets.iter() {
+            if let Err(e) = asset.validate() {
+                problem(format!("assets: {e}"));
+            }
+
+            if !assets.insert(asset.id) {
+                problem(format!("assets: {} is given more than once", asset.id));
+            }
+
+            match units.insert(asset.unit(), asset.id) {
+                Some(other) if other != asset.id => problem(format!(
+                    "assets: {} and {other} are both backed by {}",
+                    asset.id,
+                    asset.unit()
+                )),
+                _ => {}
+            }
+        }
+
+        for p in self.peers.iter() {
+            if let Err(e) = peer::parse(p) {
+                problem(format!("peers: {e}"));
+            }
+        }
+
+        if let Err(e) = self.backend() {
+            problem(format!("chain.backend: {e}"));
+        }
+
+        if let Err(e) = self.logging.level() {
+            problem(format!("logging.level: {e}"));
+        }
+
+        if let Some(addr) = self.metrics.listen {
+            if self.server.listen.contains(&addr) {
+                problem(format!("metrics.listen: {addr} is used by the server"));
+            }
+        }
+
+        if problems.is_empty() {
+            return Ok(());
+        }
+
+        let mut message = "Invalid configuration:".to_string();
+        for p in problems {
+            let _ = write!(message, "\n  - {p}");
+        }
+
+        Err(eyre!(message))
+    }
+
+    /// Reads the key of the delegate from the keystore, creating it with a
+    /// new key if missing.
     pub fn keypair(&self) -> Result<Keypair, Error> {
-        match (&self.public_key, &self.secret_key) {
-            (None, None) => {
-                warn!("No keypair provided, using a random one.");
-                Ok(Keypair::random(&mut thread_rng()))
+        let secret_key = match &self.storage.keystore {
+            None => {
+                warn!("No keystore configured, using a random keypair.");
+                SecretKey::random(&mut thread_rng())
             }
-            (None, Some(secret)) => {
-                let secret_key: SecretKey = serde_json::from_str(secret)?;
+            Some(path) if path.exists() => read_key(path)?,
+            Some(path) => {
+                let secret_key = SecretKey::random(&mut thread_rng());
+                write_key(path, &secret_key)?;
+                info!(path = %path.display(), "Saved a new keypair to the keystore");
 
-                Ok(Keypair {
-                    public_key: secret_key.public(),
-                    secret_key,
-                })
+                secret_key
             }
-            (Some(_), None) => Err(Error::InvalidKey {
-                reason: "Keypair contains public key but no private key".to_string(),
+        };
+
+        Ok(Keypair {
+            public_key: secret_key.public(),
+            secret_key,
+        })
+    }
+
+    pub fn peers(&self) -> Result<Peers, Error> {
+        self.peers
+            .iter()
+            .map(|peer| {
+                let (public_key, url) = peer::parse(peer)?;
+                let peer: Arc<dyn Peer> = Arc::new(HttpPeer::new(public_key, url));
+
+                Ok((public_key, peer))
+            })
+            .collect()
+    }
+
+    /// Picks the chain backend from the settings, without building it.
+    fn backend(&self) -> Result<Backend, Error> {
+        match (self.chain.backend, self.dev) {
+            (Some(Backend::Mock) | None, true) => Ok(Backend::Mock),
+            (Some(Backend::Mock), false) => Err(Error::ServerError {
+                reason: "the mock chain is for development only, set `dev` to use it".to_string(),
+            }),
+            (None, false) => Err(Error::ServerError {
+                reason: "no chain backend configured, set `dev` to use a mock chain".to_string(),
             }),
-            (Some(public), Some(secret)) => {
-                let public_key = serde_json::from_str(public)?;
-                let secret_key = serde_json::from_str(secret)?;
-
-                Ok(Keypair {
-                    public_key,
-                    secret_key,
-                })
+        }
+    }
+
+    pub fn chain(&self) -> Result<Arc<dyn ChainBackend>, Error> {
+        match self.backend()? {
+            Backend::Mock => {
+                warn!("Using an in-memory mock chain, for development only.");
+                Ok(Arc::new(MockChain::default()))
             }
         }
     }
 }
+
+fn read_key(path: &Path) -> Result<SecretKey, Error> {
+    let invalid = |reason: String| Error::InvalidKey {
+        reason: format!("Invalid keystore {}: {reason}", path.display()),
+    };
+
+    let key: [u8; 32] = hex::decode(fs::read_to_string(path)?.trim())
+        .map_err(|e| invalid(e.to_string()))?
+        .try_into()
+        .map_err(|_| invalid("secret key must have 32 bytes".to_string()))?;
+
+    Ok(SecretKey(key))
+}
+
+/// Saves the key readable by its owner only.
+fn write_key(path: &Path, secret_key: &SecretKey) -> Result<(), Error> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options.open(path)?;
+    writeln!(file, "{}", hex::encode(secret_key.0))?;
+    file.sync_all()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use mugraph_core::types::{Fee, Hash, PublicKey};
+
+    use super::*;
+
+    const EXAMPLE: &str = include_str!("../mugraph.toml.example");
+
+    #[test]
+    fn test_example() {
+        let mut config: Config = toml::from_str(EXAMPLE).unwrap();
+        assert_eq!(config.chain.backend, Some(Backend::Mock));
+        assert!(config.validate().is_err());
+
+        config.dev = true;
+        config.validate().unwrap();
+
+        assert_eq!(
+            config.server.listen,
+            vec!["0.0.0.0:9999".parse::<SocketAddr>().unwrap()]
+        );
+        assert_eq!(config.storage.database, PathBuf::from("db"));
+        assert_eq!(config.fees.default.basis_points, 10);
+        assert_eq!(config.assets.len(), 1);
+        assert!(config.assets[0].is_ada());
+        assert_eq!(config.logging.level().unwrap(), LevelFilter::INFO);
+    }
+
+    #[test]
+    fn test_defaults() {
+        assert_eq!(toml::from_str::<Config>("").unwrap(), Config::default());
+        Config {
+            dev: true,
+            ..Default::default()
+        }
+        .validate()
+        .unwrap();
+
+        let config: Config = toml::from_str("[limits]\nrequest_timeout = 5").unwrap();
+        assert_eq!(config.limits.request_timeout(), Duration::from_secs(5));
+        assert_eq!(config.limits.max_body_size, Limits::default().max_body_size);
+
+        // Typos are caught instead of silently falling back to defaults.
+        assert!(toml::from_str::<Config>("[storage]\ndatbase = \"db\"").is_err());
+    }
+
+    #[test]
+    fn test_overrides() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mugraph.toml");
+        let peer = |n: u8| format!("{}@http://{n}.example", PublicKey([n; 32]));
+        fs::write(
+            &path,
+            format!(
+                "peers = [\"{}\"]\n[server]\nlisten = [\"127.0.0.1:1\"]\n[storage]\ndatabase = \"a\"",
+                peer(1)
+            ),
+        )
+        .unwrap();
+
+        let args = Args::try_parse_from([
+            "mugraph-node",
+            "--config",
+            path.to_str().unwrap(),
+            "--listen",
+            "127.0.0.1:2,127.0.0.1:3",
+            "--database",
+            "b",
+            "--log-level",
+            "debug",
+            "--peer",
+            &peer(2),
+            "--dev",
+        ])
+        .unwrap();
+        let config = Config::load(&args).unwrap();
+
+        assert_eq!(
+            config.server.listen,
+            vec![
+                "127.0.0.1:2".parse::<SocketAddr>().unwrap(),
+                "127.0.0.1:3".parse().unwrap()
+            ]
+        );
+        assert_eq!(config.storage.database, PathBuf::from("b"));
+        assert_eq!(config.logging.level().unwrap(), LevelFilter::DEBUG);
+        assert_eq!(config.peers, vec![peer(1), peer(2)]);
+        assert_eq!(config.peers().unwrap().len(), 2);
+        assert!(config.dev);
+
+        let missing = Args {
+            config: Some(dir.path().join("missing.toml")),
+            ..Default::default()
+        };
+        assert!(Config::load(&missing).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut config = Config::default();
+        config.server.listen.push(config.server.listen[0]);
+        config.server.tls = Some(Tls {
+            cert: "missing.pem".into(),
+            key: "missing.key".into(),
+        });
+        config.limits.max_concurrent_requests = 0;
+        config.fees.assets.insert(
+            Hash([1; 32]),
+            Fee {
+                basis_points: 10_001,
+                ..Default::default()
+            },
+        );
+        config.peers.push("localhost:9999".to_string());
+        config.logging.level = "loud".to_string();
+        config.chain.backend = Some(Backend::Mock);
+
+        let ada = Asset {
+            id: Hash([1; 32]),
+            policy_id: vec![],
+            asset_name: vec![],
+            decimals: 6,
+            ticker: "ADA".to_string(),
+            min_amount: 1,
+            max_amount: u64::MAX,
+            enabled: true,
+        };
+        config.assets = vec![
+            ada.clone(),
+            Asset {
+                id: Hash([2; 32]),
+                ..ada
+            },
+        ];
+
+        let message = config.validate().unwrap_err().to_string();
+
+        for expected in [
+            "server.listen: 0.0.0.0:9999 is given more than once",
+            "server.tls.cert",
+            "server.tls.key",
+            "limits.max_concurrent_requests",
+            "basis_points: can not be over 10000",
+            "peers: ",
+            "logging.level: unknown log level \"loud\"",
+            "the mock chain is for development only",
+            "are both backed by lovelace",
+        ] {
+            assert!(message.contains(expected), "{expected} in {message}");
+        }
+    }
+
+    #[test]
+    fn test_keystore() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            storage: Storage {
+                database: dir.path().join("db"),
+                keystore: Some(dir.path().join("key")),
+            },
+            dev: true,
+            ..Default::default()
+        };
+
+        let keypair = config.keypair().unwrap();
+        assert_eq!(config.keypair().unwrap(), keypair);
+        config.validate().unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let metadata = fs::metadata(dir.path().join("key")).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        }
+
+        fs::write(dir.path().join("key"), "not hex").unwrap();
+        assert!(matches!(config.keypair(), Err(Error::InvalidKey { .. })));
+        assert!(config.validate().is_err());
+    }
+}
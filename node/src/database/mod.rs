@@ -1,7 +1,10 @@
-use std::{fs::OpenOptions, path::PathBuf};
+use std::{fs::OpenOptions, path::PathBuf, sync::RwLock};
 
 use metrics::counter;
-use mugraph_core::{error::Error, types::Signature};
+use mugraph_core::{
+    error::Error,
+    types::{Asset, CrossTransaction, Dispute, Hash, PublicKey, Settlement, Signature},
+};
 use rand::prelude::*;
 use rand_chacha::ChaCha20Rng;
 use redb::{
@@ -9,17 +12,58 @@ use redb::{
     StorageBackend, Table, TableDefinition, Value, WriteTransaction,
 };
 
+use crate::{settlement::Netting, withdrawal::Entry};
+
 mod test_backend;
 
+pub use mugraph_core::record::Record;
+
 pub use self::test_backend::*;
 
 pub const NOTES: TableDefinition<Signature, bool> = TableDefinition::new("notes");
-
+pub const LOG: TableDefinition<u64, Hash> = TableDefinition::new("log");
+pub const LOG_INDEX: TableDefinition<Hash, u64> = TableDefinition::new("log_index");
+/// Complete subtrees of the log, by level and index, see [`crate::log`].
+pub const LOG_NODES: TableDefinition<(u32, u64), Hash> = TableDefinition::new("log_nodes");
+pub const ISSUED: TableDefinition<Hash, u128> = TableDefinition::new("issued");
+pub const BURNED: TableDefinition<Hash, u128> = TableDefinition::new("burned");
+/// Number of writes to [`ISSUED`] and [`BURNED`] so far, bumped along with
+/// them, see [`crate::liabilities`].
+pub const LIABILITIES_VERSION: TableDefinition<(), u64> =
+    TableDefinition::new("liabilities_version");
+pub const OUTPUTS: TableDefinition<(Hash, u64), (Hash, u64)> = TableDefinition::new("outputs");
+pub const OUTPUT_INDEX: TableDefinition<Hash, (Hash, u64)> = TableDefinition::new("output_index");
+/// Complete subtrees of the outputs of every asset, by asset, level and
+/// index, see [`crate::liabilities`].
+pub const OUTPUT_NODES: TableDefinition<(Hash, u32, u64), (Hash, u128)> =
+    TableDefinition::new("output_nodes");
+pub const DEPOSITS: TableDefinition<Hash, u64> = TableDefinition::new("deposits");
+pub const WITHDRAWALS: TableDefinition<Hash, Record<Entry>> = TableDefinition::new("withdrawals");
+/// Y points of spent notes, see [`mugraph_core::crypto::y_point`].
+pub const SPENT: TableDefinition<Hash, bool> = TableDefinition::new("spent");
+/// Y points of notes reserved by an unfinished operation, with its id.
+pub const PENDING: TableDefinition<Hash, Hash> = TableDefinition::new("pending");
+pub const ASSETS: TableDefinition<Hash, Record<Asset>> = TableDefinition::new("assets");
+/// Total fees collected for each asset.
+pub const FEES: TableDefinition<Hash, u128> = TableDefinition::new("fees");
+/// Cross-delegate transactions this delegate took part in, by id.
+pub const CROSS: TableDefinition<Hash, Record<CrossTransaction>> = TableDefinition::new("cross");
+/// Netting with each peer, see [`crate::settlement`].
+pub const NETTING: TableDefinition<PublicKey, Record<Netting>> = TableDefinition::new("netting");
+/// Settlements paid by this delegate, by id.
+pub const SETTLEMENTS: TableDefinition<Hash, Record<Settlement>> =
+    TableDefinition::new("settlements");
+pub const DISPUTES: TableDefinition<Hash, Record<Dispute>> = TableDefinition::new("disputes");
+
+/// Handle to the node's storage, safe to share between threads.
+///
+/// Any number of readers can run at once, while redb makes sure there is only
+/// one write transaction at a time.
 #[derive(Debug)]
 pub struct Database {
     mode: Mode,
     rng: ChaCha20Rng,
-    db: Redb,
+    db: RwLock<Redb>,
 }
 
 #[derive(Debug, Clone)]
@@ -50,13 +94,13 @@ impl Write {
         &self,
         table: TableDefinition<K, V>,
     ) -> Result<Table<K, V>, Error> {
-        counter!("mugraph.simulator.database.write.open_table").increment(1);
+        counter!("mugraph.node.database.write.open_table").increment(1);
         Ok(self.0.open_table(table)?)
     }
 
     #[tracing::instrument(skip_all)]
     pub fn commit(self) -> Result<(), Error> {
-        counter!("mugraph.simulator.database.write.commit").increment(1);
+        counter!("mugraph.node.database.write.commit").increment(1);
         Ok(self.0.commit()?)
     }
 }
@@ -64,6 +108,7 @@ impl Write {
 impl Database {
     pub fn setup(path: impl Into<PathBuf>) -> Result<Self, Error> {
         let path = path.into();
+        let exists = path.exists();
         let file = OpenOptions::new()
             .read(true)
             .write(true)
@@ -73,7 +118,7 @@ impl Database {
         let backend = FileBackend::new(file)?;
 
         Ok(Self {
-            db: Self::setup_with_backend(backend, !path.exists())?,
+            db: RwLock::new(Self::setup_with_backend(backend, !exists)?),
             mode: Mode::File { path },
             rng: ChaCha20Rng::seed_from_u64(thread_rng().gen()),
         })
@@ -91,14 +136,14 @@ impl Database {
 
         Ok(Self {
             mode: Mode::Test { path },
-            db,
+            db: RwLock::new(db),
             rng: ChaCha20Rng::seed_from_u64(rng.gen()),
         })
     }
 
     #[tracing::instrument(skip_all)]
-    pub fn reopen(&mut self) -> Result<(), Error> {
-        match self.mode {
+    pub fn reopen(&self) -> Result<(), Error> {
+        let db = match self.mode {
             Mode::File { ref path } => {
                 let file = OpenOptions::new()
                     .read(true)
@@ -108,15 +153,17 @@ impl Database {
                     .open(path)?;
                 let backend = FileBackend::new(file)?;
 
-                self.db = Self::setup_with_backend(backend, false)?;
+                Self::setup_with_backend(backend, false)?
             }
             Mode::Test { ref path } => {
                 let backend = TestBackend::new(&mut self.rng.clone(), Some(path.clone()))?;
-                self.db = Self::setup_with_backend(backend, false)?;
+                Self::setup_with_backend(backend, false)?
             }
-        }
+        };
 
-        counter!("mugraph.simulator.database.reopen").increment(1);
+        *self.db.write()? = db;
+
+        counter!("mugraph.node.database.reopen").increment(1);
 
         Ok(())
     }
@@ -127,23 +174,49 @@ impl Database {
     ) -> Result<Redb, Error> {
         let db = Builder::new().create_with_backend(backend)?;
 
-        if should_setup {
-            let w = db.begin_write()?;
+        let w = db.begin_write()?;
 
-            {
-                let mut t = w.open_table(NOTES)?;
+        {
+            let mut t = w.open_table(NOTES)?;
+
+            if should_setup {
                 t.insert(Signature::zero(), true)?;
             }
 
-            w.commit()?;
+            w.open_table(LOG)?;
+            w.open_table(LOG_INDEX)?;
+            w.open_table(LOG_NODES)?;
+            w.open_table(ISSUED)?;
+            w.open_table(BURNED)?;
+            w.open_table(LIABILITIES_VERSION)?;
+            w.open_table(OUTPUTS)?;
+            w.open_table(OUTPUT_INDEX)?;
+            w.open_table(OUTPUT_NODES)?;
+            w.open_table(DEPOSITS)?;
+            w.open_table(WITHDRAWALS)?;
+            w.open_table(ASSETS)?;
+            w.open_table(SPENT)?;
+            w.open_table(PENDING)?;
+            w.open_table(FEES)?;
+            w.open_table(CROSS)?;
+            w.open_table(NETTING)?;
+            w.open_table(SETTLEMENTS)?;
+            w.open_table(DISPUTES)?;
         }
 
+        w.commit()?;
+
+        let w = Write(db.begin_write()?);
+        crate::log::backfill(&w)?;
+        crate::liabilities::backfill(&w)?;
+        w.commit()?;
+
         Ok(db)
     }
 
     #[tracing::instrument(skip_all)]
-    pub fn read(&mut self) -> Result<Read, Error> {
-        let result = { self.db.begin_read().map(Read).map_err(Error::from) };
+    pub fn read(&self) -> Result<Read, Error> {
+        let result = { self.db.read()?.begin_read().map(Read).map_err(Error::from) };
 
         match result {
             Err(Error::StorageError { reason, .. })
@@ -153,15 +226,21 @@ impl Database {
                 self.read()
             }
             v => {
-                counter!("mugraph.simulator.database.read").increment(1);
+                counter!("mugraph.node.database.read").increment(1);
                 v
             }
         }
     }
 
     #[tracing::instrument(skip_all)]
-    pub fn write(&mut self) -> Result<Write, Error> {
-        let result = { self.db.begin_write().map(Write).map_err(Error::from) };
+    pub fn write(&self) -> Result<Write, Error> {
+        let result = {
+            self.db
+                .read()?
+                .begin_write()
+                .map(Write)
+                .map_err(Error::from)
+        };
 
         match result {
             Err(Error::StorageError { reason, .. })
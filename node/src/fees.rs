@@ -0,0 +1,69 @@
+//! Ledger of the fees collected by the delegate.
+
+use mugraph_core::{error::Error, types::*};
+use redb::ReadableTable;
+
+use crate::database::{Database, Write, FEES};
+
+/// Adds the fees paid by a transaction to the totals of their assets.
+#[tracing::instrument(skip_all)]
+pub fn record(w: &Write, fees: &[(Hash, u128)]) -> Result<(), Error> {
+    let mut table = w.open_table(FEES)?;
+
+    for (asset_id, amount) in fees {
+        let total = table.get(asset_id)?.map(|v| v.value()).unwrap_or(0);
+        table.insert(asset_id, total + amount)?;
+    }
+
+    Ok(())
+}
+
+/// Total fees collected for every asset.
+pub fn collected(database: &Database) -> Result<Vec<(Hash, u128)>, Error> {
+    database
+        .read()?
+        .open_table(FEES)?
+        .iter()?
+        .map(|entry| {
+            let (asset_id, total) = entry?;
+            Ok((asset_id.value(), total.value()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testing::{spend, Setup},
+        v0::transaction_v0,
+    };
+
+    #[test]
+    fn test_fees_collected() {
+        let mut s = Setup::new();
+        let asset_id = s.asset();
+        let notes = s.notes(asset_id, &[100, 200]);
+
+        s.fees.default = Fee {
+            flat: 1,
+            per_input: 2,
+            basis_points: 100,
+        };
+
+        // 1 + 2 + 1% of 100.
+        let paid = spend(&notes[..1], &[(asset_id, 96)]);
+        let unpaid = spend(&notes[1..], &[(asset_id, 200)]);
+
+        assert!(transaction_v0(&paid, s.keypair, &s.fees, &s.database).is_ok());
+        assert!(matches!(
+            transaction_v0(&unpaid, s.keypair, &s.fees, &s.database),
+            Err(Error::InvalidFee {
+                expected: 5,
+                got: 0,
+                ..
+            })
+        ));
+        assert_eq!(collected(&s.database).unwrap(), vec![(asset_id, 4)]);
+    }
+}
@@ -0,0 +1,320 @@
+//! Per-asset issuance and burn counters, used to produce proofs of
+//! liabilities that can be compared against the delegate's vault.
+//!
+//! Like the log, the Merkle-sum tree of every asset stores its complete
+//! subtrees as they are finished, so reports and proofs do not read every
+//! output.
+
+use mugraph_core::{error::Error, merkle, types::*};
+use rand::{thread_rng, Rng};
+use redb::{ReadableTable, ReadableTableMetadata};
+
+use crate::{
+    cache::Cache,
+    database::{
+        Database, Read, Write, BURNED, ISSUED, LIABILITIES_VERSION, OUTPUTS, OUTPUT_INDEX,
+        OUTPUT_NODES,
+    },
+};
+
+/// Records an output signed by the delegate, as two leaves of the asset's
+/// Merkle-sum tree splitting its amount at random, see [`LiabilityProof`].
+#[tracing::instrument(skip_all)]
+pub fn record_issued(
+    w: &Write,
+    asset_id: Hash,
+    commitment: Hash,
+    amount: u64,
+) -> Result<(), Error> {
+    let mut issued = w.open_table(ISSUED)?;
+    let mut index = w.open_table(OUTPUT_INDEX)?;
+
+    let total = issued.get(asset_id)?.map(|v| v.value()).unwrap_or(0);
+    issued.insert(asset_id, total + amount as u128)?;
+
+    let size = size(&w.open_table(OUTPUTS)?, asset_id)?;
+    let position = match size {
+        0 => {
+            // Shifts the shares of every output into different subtrees.
+            append(w, asset_id, 0, Hash::default(), 0)?;
+            1
+        }
+        size => size,
+    };
+
+    let first = thread_rng().gen_range(0..=amount);
+
+    for (i, share) in [first, amount - first].into_iter().enumerate() {
+        let id = share_id(&commitment, i as u8);
+        append(w, asset_id, position + i as u64, id, share)?;
+    }
+
+    index.insert(commitment, (asset_id, position))?;
+    bump(w)?;
+
+    Ok(())
+}
+
+/// Adds a leaf to the tree of an asset, along with the subtrees it finishes.
+fn append(w: &Write, asset_id: Hash, position: u64, id: Hash, amount: u64) -> Result<(), Error> {
+    let mut nodes = w.open_table(OUTPUT_NODES)?;
+
+    w.open_table(OUTPUTS)?
+        .insert((asset_id, position), (id, amount))?;
+
+    let added = merkle::append_nodes(
+        position,
+        merkle::sum_leaf(id.as_ref(), amount),
+        &mut |level, index| node(&nodes, asset_id, level, index),
+        &merkle::sum_node,
+    )?;
+
+    for (level, index, node) in added {
+        nodes.insert((asset_id, level, index), node)?;
+    }
+
+    Ok(())
+}
+
+/// Stores the subtrees of outputs recorded before they were, once.
+pub fn backfill(w: &Write) -> Result<(), Error> {
+    if !w.open_table(OUTPUT_NODES)?.is_empty()? {
+        return Ok(());
+    }
+
+    let leaves = w
+        .open_table(OUTPUTS)?
+        .iter()?
+        .map(|entry| {
+            let (k, v) = entry?;
+            Ok((k.value(), v.value()))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    for ((asset_id, position), (id, amount)) in leaves {
+        append(w, asset_id, position, id, amount)?;
+    }
+
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+pub fn record_burned(w: &Write, asset_id: Hash, amount: u64) -> Result<(), Error> {
+    let mut burned = w.open_table(BURNED)?;
+
+    let total = burned.get(asset_id)?.map(|v| v.value()).unwrap_or(0);
+    burned.insert(asset_id, total + amount as u128)?;
+    bump(w)?;
+
+    Ok(())
+}
+
+/// Marks the liabilities as changed, so reports made before are not served
+/// from the cache anymore.
+fn bump(w: &Write) -> Result<(), Error> {
+    let mut table = w.open_table(LIABILITIES_VERSION)?;
+    let version = table.get(())?.map_or(0, |v| v.value());
+    table.insert((), version + 1)?;
+
+    Ok(())
+}
+
+/// Version of the liabilities in `r`, see [`LIABILITIES_VERSION`].
+fn version(r: &Read) -> Result<u64, Error> {
+    Ok(r.open_table(LIABILITIES_VERSION)?
+        .get(())?
+        .map_or(0, |v| v.value()))
+}
+
+/// Leaves in the tree of an asset.
+fn size(
+    outputs: &impl ReadableTable<(Hash, u64), (Hash, u64)>,
+    asset_id: Hash,
+) -> Result<u64, Error> {
+    Ok(outputs
+        .range((asset_id, 0)..=(asset_id, u64::MAX))?
+        .next_back()
+        .transpose()?
+        .map_or(0, |(k, _)| k.value().1 + 1))
+}
+
+fn node(
+    table: &impl ReadableTable<(Hash, u32, u64), merkle::SumNode>,
+    asset_id: Hash,
+    level: u32,
+    index: u64,
+) -> Result<merkle::SumNode, Error> {
+    match table.get((asset_id, level, index))? {
+        Some(node) => Ok(node.value()),
+        None => Err(Error::ServerError {
+            reason: format!("Outputs of {asset_id} are missing subtree {index} of level {level}"),
+        }),
+    }
+}
+
+fn root(r: &Read, asset_id: Hash, size: u64) -> Result<merkle::SumNode, Error> {
+    let nodes = r.open_table(OUTPUT_NODES)?;
+
+    match size {
+        0 => Ok(merkle::sum_root(&[])),
+        _ => merkle::stored_root(
+            0,
+            size,
+            &mut |level, index| node(&nodes, asset_id, level, index),
+            &merkle::sum_node,
+        ),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+pub fn report(
+    database: &Database,
+    keypair: &Keypair,
+    cache: &Cache,
+) -> Result<Signed<LiabilitiesReport>, Error> {
+    let r = database.read()?;
+    let version = version(&r)?;
+
+    if let Some(report) = cache.liabilities(version)? {
+        return Ok(report);
+    }
+
+    let issued = r.open_table(ISSUED)?;
+    let burned = r.open_table(BURNED)?;
+    let outputs = r.open_table(OUTPUTS)?;
+
+    let mut assets = Vec::new();
+
+    for entry in issued.iter()? {
+        let (asset_id, total) = entry?;
+        let asset_id = asset_id.value();
+        let leaves = size(&outputs, asset_id)?;
+        let (root, _) = root(&r, asset_id, leaves)?;
+
+        assets.push(AssetLiabilities {
+            asset_id,
+            issued: total.value(),
+            burned: burned.get(asset_id)?.map(|v| v.value()).unwrap_or(0),
+            leaves,
+            root,
+        });
+    }
+
+    let report = LiabilitiesReport {
+        delegate: keypair.public_key,
+        assets,
+    };
+    let report = Signed::new(&mut thread_rng(), &keypair.secret_key, &report)?;
+    cache.set_liabilities(version, report.clone())?;
+
+    Ok(report)
+}
+
+#[tracing::instrument(skip(database))]
+pub fn proof(database: &Database, commitment: Hash) -> Result<LiabilityProof, Error> {
+    let r = database.read()?;
+
+    let (asset_id, index) = match r.open_table(OUTPUT_INDEX)?.get(commitment)? {
+        Some(v) => v.value(),
+        None => {
+            return Err(Error::NotFound {
+                reason: format!("Output {commitment} was not issued by this delegate"),
+            })
+        }
+    };
+
+    let outputs = r.open_table(OUTPUTS)?;
+    let nodes = r.open_table(OUTPUT_NODES)?;
+    let size = size(&outputs, asset_id)?;
+    let share = |index: u64| match outputs.get((asset_id, index))? {
+        Some(v) => Ok(LiabilityShare {
+            index,
+            amount: v.value().1,
+            path: merkle::stored_inclusion_proof(
+                index,
+                size,
+                &mut |level, index| node(&nodes, asset_id, level, index),
+                &merkle::sum_node,
+            )?,
+        }),
+        None => Err(Error::NotFound {
+            reason: format!("Output {commitment} is missing from the issuance tree"),
+        }),
+    };
+    let shares = [share(index)?, share(index + 1)?];
+
+    Ok(LiabilityProof {
+        asset_id,
+        commitment,
+        amount: shares[0].amount + shares[1].amount,
+        size,
+        shares,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Setup;
+
+    #[test]
+    fn test_liability_proofs() {
+        let mut s = Setup::new();
+        let asset_id = s.asset();
+        let notes = s.notes(asset_id, &[70, 30, 50]);
+
+        let report = report(&s.database, &s.keypair, &Cache::default())
+            .unwrap()
+            .verify(&s.keypair.public_key)
+            .unwrap();
+
+        let liabilities = report.asset(&asset_id).unwrap();
+        assert_eq!(liabilities.issued, 150);
+        assert_eq!(liabilities.leaves, 7);
+
+        let proofs = notes
+            .iter()
+            .map(|note| proof(&s.database, note.commitment()).unwrap())
+            .collect::<Vec<_>>();
+
+        for (note, proof) in notes.iter().zip(&proofs) {
+            assert_eq!(proof.amount, note.amount);
+            assert!(proof.verify(liabilities));
+            assert!(!LiabilityProof {
+                amount: note.amount + 1,
+                ..proof.clone()
+            }
+            .verify(liabilities));
+        }
+
+        // The first sibling of a share is a share of the note before, not
+        // the note itself.
+        assert_eq!(
+            proofs[1].shares[0].path[0],
+            merkle::sum_leaf(
+                share_id(&notes[0].commitment(), 1).as_ref(),
+                proofs[0].shares[1].amount
+            )
+        );
+    }
+
+    #[test]
+    fn test_report_cached() {
+        let mut s = Setup::new();
+        let asset_id = s.asset();
+        s.notes(asset_id, &[70, 30]);
+
+        let cache = Cache::default();
+        let first = report(&s.database, &s.keypair, &cache).unwrap();
+        assert_eq!(report(&s.database, &s.keypair, &cache).unwrap(), first);
+
+        // Liabilities change without the log growing, like on a refund.
+        let w = s.database.write().unwrap();
+        record_burned(&w, asset_id, 30).unwrap();
+        w.commit().unwrap();
+
+        let second = report(&s.database, &s.keypair, &cache).unwrap();
+        let second = second.verify(&s.keypair.public_key).unwrap();
+        assert_eq!(second.asset(&asset_id).unwrap().burned, 30);
+    }
+}
@@ -1,20 +1,93 @@
+use std::sync::Arc;
+
 use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
 use color_eyre::eyre::Result;
+use tokio::{net::TcpListener, task::JoinSet};
+use tracing::info;
+
+use crate::{chain::ChainBackend, database::Database};
 
+pub mod assets;
+pub mod cache;
+pub mod chain;
 pub mod config;
 pub mod database;
+pub mod fees;
+pub mod liabilities;
+pub mod log;
+pub mod peer;
 pub mod route;
+pub mod settlement;
+pub mod withdrawal;
+
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
 pub use route::v0;
 
 pub async fn start(config: &config::Config) -> Result<()> {
-    let listener = tokio::net::TcpListener::bind(config.addr).await?;
+    start_with_chain(config, config.chain()?).await
+}
+
+/// Starts the delegate, watching `chain` for deposits and payouts instead of
+/// the backend configured.
+pub async fn start_with_chain(config: &config::Config, chain: Arc<dyn ChainBackend>) -> Result<()> {
+    let database = Database::setup(&config.storage.database)?;
+
+    {
+        let w = database.write()?;
+
+        for asset in config.assets.iter() {
+            assets::register(&w, asset)?;
+        }
+
+        w.commit()?;
+    }
+
+    let context = v0::Context::new(config.keypair()?, chain, config.fees.clone(), database)
+        .peers(config.peers()?);
+
+    tokio::spawn(withdrawal::run(context.clone()));
+    tokio::spawn(settlement::run(context.clone()));
+    tokio::spawn(v0::run_recovery(context.clone()));
+
+    let app = route::limit(
+        Router::new().nest("/v0", v0::router(context)),
+        &config.limits,
+    );
+    let tls = match &config.server.tls {
+        Some(tls) => {
+            // Fails if a provider was installed already, which is as good.
+            let _ = rustls::crypto::ring::default_provider().install_default();
+            Some(RustlsConfig::from_pem_file(&tls.cert, &tls.key).await?)
+        }
+        None => None,
+    };
+
+    let mut servers = JoinSet::new();
+
+    for addr in config.server.listen.iter().copied() {
+        let app = app.clone();
+
+        match &tls {
+            Some(tls) => {
+                servers.spawn(
+                    axum_server::bind_rustls(addr, tls.clone()).serve(app.into_make_service()),
+                );
+            }
+            None => {
+                let listener = TcpListener::bind(addr).await?;
+                servers.spawn(async move { axum::serve(listener, app).await });
+            }
+        }
+
+        info!(%addr, tls = tls.is_some(), "Listening");
+    }
 
-    axum::serve(
-        listener,
-        Router::new().nest("/v0", route::v0::router(config.keypair()?)?),
-    )
-    .await?;
+    while let Some(result) = servers.join_next().await {
+        result??;
+    }
 
     Ok(())
 }
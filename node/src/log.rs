@@ -0,0 +1,305 @@
+//! Append-only log of every accepted transaction, stored as the leaves of a
+//! Merkle tree so auditors can detect a delegate rewriting its history.
+//!
+//! Every complete subtree is stored as it is finished, see
+//! [`merkle::append_nodes`], so heads and proofs only read a logarithmic
+//! number of them instead of every leaf.
+
+use mugraph_core::{error::Error, merkle, types::*};
+use rand::thread_rng;
+use redb::{ReadableTable, ReadableTableMetadata};
+
+use crate::{
+    cache::Cache,
+    database::{Database, Write, LOG, LOG_INDEX, LOG_NODES},
+};
+
+/// Appends a transaction id to the log, returning its position.
+#[tracing::instrument(skip_all)]
+pub fn append(w: &Write, id: Hash) -> Result<u64, Error> {
+    let mut log = w.open_table(LOG)?;
+    let mut index = w.open_table(LOG_INDEX)?;
+
+    if let Some(position) = index.get(id)? {
+        return Ok(position.value());
+    }
+
+    let position = log.len()?;
+
+    log.insert(position, id)?;
+    index.insert(id, position)?;
+    store_nodes(w, position, id)?;
+
+    Ok(position)
+}
+
+fn store_nodes(w: &Write, position: u64, id: Hash) -> Result<(), Error> {
+    let mut nodes = w.open_table(LOG_NODES)?;
+    let leaf = merkle::leaf_hash(id.as_ref());
+    let added = merkle::append_nodes(
+        position,
+        leaf,
+        &mut |level, index| node(&nodes, level, index),
+        &merkle::node_hash,
+    )?;
+
+    for (level, index, hash) in added {
+        nodes.insert((level, index), hash)?;
+    }
+
+    Ok(())
+}
+
+/// Stores the subtrees of a log written before they were, once.
+pub fn backfill(w: &Write) -> Result<(), Error> {
+    if !w.open_table(LOG_NODES)?.is_empty()? {
+        return Ok(());
+    }
+
+    let ids = w
+        .open_table(LOG)?
+        .iter()?
+        .map(|entry| Ok(entry?.1.value()))
+        .collect::<Result<Vec<Hash>, Error>>()?;
+
+    for (position, id) in ids.into_iter().enumerate() {
+        store_nodes(w, position as u64, id)?;
+    }
+
+    Ok(())
+}
+
+fn node(
+    table: &impl ReadableTable<(u32, u64), Hash>,
+    level: u32,
+    index: u64,
+) -> Result<Hash, Error> {
+    match table.get((level, index))? {
+        Some(hash) => Ok(hash.value()),
+        None => Err(Error::ServerError {
+            reason: format!("Log is missing subtree {index} of level {level}"),
+        }),
+    }
+}
+
+/// Checks that the log has at least `size` entries, defaulting to all of
+/// them.
+fn size(len: u64, size: Option<u64>) -> Result<u64, Error> {
+    match size.unwrap_or(len) {
+        size if size > len => Err(Error::NotFound {
+            reason: format!("Log has {len} entries, but {size} were requested"),
+        }),
+        size => Ok(size),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+pub fn tree_head(
+    database: &Database,
+    keypair: &Keypair,
+    cache: &Cache,
+) -> Result<SignedTreeHead, Error> {
+    let r = database.read()?;
+    let size = r.open_table(LOG)?.len()?;
+
+    if let Some(head) = cache.head(size)? {
+        return Ok(head);
+    }
+
+    let nodes = r.open_table(LOG_NODES)?;
+    let root = match size {
+        0 => merkle::root(&[]),
+        _ => merkle::stored_root(
+            0,
+            size,
+            &mut |level, index| node(&nodes, level, index),
+            &merkle::node_hash,
+        )?,
+    };
+
+    let head = TreeHead { size, root }.sign(&mut thread_rng(), &keypair.secret_key);
+    cache.set_head(head)?;
+
+    Ok(head)
+}
+
+#[tracing::instrument(skip(database))]
+pub fn inclusion_proof(
+    database: &Database,
+    id: Hash,
+    size: Option<u64>,
+) -> Result<InclusionProof, Error> {
+    let r = database.read()?;
+
+    let index = match r.open_table(LOG_INDEX)?.get(id)? {
+        Some(index) => index.value(),
+        None => {
+            return Err(Error::NotFound {
+                reason: format!("Transaction {id} is not in the log"),
+            })
+        }
+    };
+
+    let size = self::size(r.open_table(LOG)?.len()?, size)?;
+
+    if index >= size {
+        return Err(Error::NotFound {
+            reason: format!("Transaction {id} is not in a tree of size {size}"),
+        });
+    }
+
+    let nodes = r.open_table(LOG_NODES)?;
+
+    Ok(InclusionProof {
+        id,
+        index,
+        size,
+        path: merkle::stored_inclusion_proof(
+            index,
+            size,
+            &mut |level, index| node(&nodes, level, index),
+            &merkle::node_hash,
+        )?,
+    })
+}
+
+#[tracing::instrument(skip(database))]
+pub fn consistency_proof(
+    database: &Database,
+    first: u64,
+    second: u64,
+) -> Result<ConsistencyProof, Error> {
+    if first > second {
+        return Err(Error::NotFound {
+            reason: format!("Can not prove {first} is a prefix of {second}"),
+        });
+    }
+
+    let r = database.read()?;
+    let second = size(r.open_table(LOG)?.len()?, Some(second))?;
+    let nodes = r.open_table(LOG_NODES)?;
+
+    Ok(ConsistencyProof {
+        first,
+        second,
+        path: merkle::stored_consistency_proof(first, second, &mut |level, index| {
+            node(&nodes, level, index)
+        })?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Setup;
+
+    fn append_all(s: &Setup, ids: &[Hash]) -> Vec<u64> {
+        let w = s.database.write().unwrap();
+        let positions = ids.iter().map(|id| append(&w, *id).unwrap()).collect();
+        w.commit().unwrap();
+
+        positions
+    }
+
+    fn ids(n: u8) -> Vec<Hash> {
+        (0..n).map(|i| Hash::digest(&[i])).collect()
+    }
+
+    #[test]
+    fn test_append() {
+        let s = Setup::new();
+        let cache = Cache::default();
+        let ids = ids(13);
+        let leaves = ids
+            .iter()
+            .map(|id| merkle::leaf_hash(id.as_ref()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(append_all(&s, &ids[..5]), [0, 1, 2, 3, 4]);
+        // Appending again keeps the first position.
+        assert_eq!(append_all(&s, &ids[..1]), [0]);
+
+        let first = tree_head(&s.database, &s.keypair, &cache).unwrap();
+        assert!(first.verify(&s.keypair.public_key).is_ok());
+        assert_eq!(
+            first.head,
+            TreeHead {
+                size: 5,
+                root: merkle::root(&leaves[..5])
+            }
+        );
+
+        // Signed once until the log grows.
+        assert_eq!(tree_head(&s.database, &s.keypair, &cache).unwrap(), first);
+
+        append_all(&s, &ids[5..]);
+        let second = tree_head(&s.database, &s.keypair, &cache).unwrap();
+        assert_eq!(
+            second.head,
+            TreeHead {
+                size: 13,
+                root: merkle::root(&leaves)
+            }
+        );
+
+        for (i, id) in ids.iter().enumerate() {
+            let proof = inclusion_proof(&s.database, *id, None).unwrap();
+
+            assert_eq!((proof.index, proof.size), (i as u64, 13));
+            assert!(merkle::verify_inclusion(
+                leaves[i],
+                proof.index,
+                proof.size,
+                &proof.path,
+                second.head.root
+            ));
+        }
+
+        let proof = inclusion_proof(&s.database, ids[2], Some(5)).unwrap();
+        assert!(merkle::verify_inclusion(
+            leaves[2],
+            2,
+            5,
+            &proof.path,
+            first.head.root
+        ));
+
+        let proof = consistency_proof(&s.database, 5, 13).unwrap();
+        assert!(merkle::verify_consistency(
+            5,
+            13,
+            first.head.root,
+            second.head.root,
+            &proof.path
+        ));
+
+        for result in [
+            inclusion_proof(&s.database, ids[7], Some(5)).map(|_| ()),
+            inclusion_proof(&s.database, Hash::digest(b"missing"), None).map(|_| ()),
+            inclusion_proof(&s.database, ids[0], Some(14)).map(|_| ()),
+            consistency_proof(&s.database, 13, 5).map(|_| ()),
+            consistency_proof(&s.database, 5, 14).map(|_| ()),
+        ] {
+            assert!(matches!(result, Err(Error::NotFound { .. })));
+        }
+    }
+
+    #[test]
+    fn test_backfill() {
+        let s = Setup::new();
+        append_all(&s, &ids(6));
+        let head = tree_head(&s.database, &s.keypair, &Cache::default()).unwrap();
+
+        // Like a log written before subtrees were stored.
+        let w = s.database.write().unwrap();
+        w.open_table(LOG_NODES)
+            .unwrap()
+            .retain(|_, _| false)
+            .unwrap();
+        backfill(&w).unwrap();
+        w.commit().unwrap();
+
+        let again = tree_head(&s.database, &s.keypair, &Cache::default()).unwrap();
+        assert_eq!(again.head, head.head);
+    }
+}
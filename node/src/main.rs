@@ -1,11 +1,19 @@
+use clap::Parser;
 use color_eyre::eyre::Result;
-use mugraph_node::{config::Config, start};
+use mugraph_node::{
+    config::{Args, Config},
+    start,
+};
 
 #[tokio::main]
 async fn main() -> Result<()> {
     color_eyre::install()?;
 
-    start(&Config::new()).await?;
+    let config = Config::load(&Args::parse())?;
+    config.logging.init()?;
+    config.metrics.init()?;
+
+    start(&config).await?;
 
     Ok(())
 }
@@ -0,0 +1,106 @@
+//! Other delegates this one settles cross-delegate transactions with.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+use mugraph_core::{
+    error::Error,
+    types::{PublicKey, Request, Response, V0Request, V0Response},
+};
+
+/// How long to wait for a peer before giving up on a request.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Peers by the public key they sign notes with.
+pub type Peers = HashMap<PublicKey, Arc<dyn Peer>>;
+
+pub trait Peer: Send + Sync {
+    /// Sends a request to the peer, blocking until it answers.
+    fn call(&self, request: V0Request) -> Result<V0Response, Error>;
+}
+
+/// Peer reached through its RPC endpoint.
+pub struct HttpPeer {
+    public_key: PublicKey,
+    url: String,
+    // The blocking client panics when created inside the async runtime, so it
+    // is only created on the first request, from the blocking pool.
+    client: OnceLock<reqwest::blocking::Client>,
+}
+
+impl HttpPeer {
+    pub fn new(public_key: PublicKey, url: impl Into<String>) -> Self {
+        Self {
+            public_key,
+            url: url.into().trim_end_matches('/').to_string(),
+            client: OnceLock::new(),
+        }
+    }
+
+    fn error(&self, reason: impl ToString) -> Error {
+        Error::PeerError {
+            delegate: self.public_key,
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl Peer for HttpPeer {
+    fn call(&self, request: V0Request) -> Result<V0Response, Error> {
+        let client = self.client.get_or_init(|| {
+            reqwest::blocking::Client::builder()
+                .timeout(TIMEOUT)
+                .build()
+                .expect("HTTP client should be valid")
+        });
+
+        let response = client
+            .post(format!("{}/v0/rpc", self.url))
+            .json(&Request::from(request))
+            .send()
+            .and_then(|r| r.json::<Response>())
+            .map_err(|e| self.error(e))?;
+
+        match response {
+            Response::V0(response) => Ok(response),
+            Response::Error { error, .. } => Err(error),
+        }
+    }
+}
+
+/// Parses a peer given as `<public key>@<url>`, with the key in hex.
+pub fn parse(peer: &str) -> Result<(PublicKey, String), Error> {
+    let invalid = |reason: &str| Error::InvalidKey {
+        reason: format!("Invalid peer {peer}: {reason}"),
+    };
+
+    let (key, url) = peer
+        .split_once('@')
+        .ok_or_else(|| invalid("expected <public key>@<url>"))?;
+    let key: [u8; 32] = hex::decode(key)
+        .map_err(|e| invalid(&e.to_string()))?
+        .try_into()
+        .map_err(|_| invalid("public key must have 32 bytes"))?;
+
+    Ok((PublicKey(key), url.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let key = PublicKey([7; 32]);
+
+        assert_eq!(
+            parse(&format!("{key}@http://localhost:9999")).unwrap(),
+            (key, "http://localhost:9999".to_string())
+        );
+        assert!(parse("http://localhost:9999").is_err());
+        assert!(parse("0707@http://localhost:9999").is_err());
+    }
+}
@@ -0,0 +1,44 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use mugraph_core::{error::Error, types};
+
+pub fn status(error: &Error) -> StatusCode {
+    match error {
+        Error::InsufficientFunds { .. }
+        | Error::UnbalancedTransaction { .. }
+        | Error::UnsupportedAsset { .. }
+        | Error::InvalidFee { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+        Error::AlreadySpent { .. }
+        | Error::DepositNotConfirmed { .. }
+        | Error::InvalidStatement { .. } => StatusCode::CONFLICT,
+        Error::InvalidSignature { .. }
+        | Error::InvalidKey { .. }
+        | Error::InvalidHash { .. }
+        | Error::InvalidAtom { .. }
+        | Error::InvalidTransaction { .. }
+        | Error::InvalidUri { .. }
+        | Error::JsonError { .. } => StatusCode::BAD_REQUEST,
+        Error::NotFound { .. } => StatusCode::NOT_FOUND,
+        Error::PeerError { .. } => StatusCode::BAD_GATEWAY,
+        Error::StorageError { .. } | Error::SimulatedError { .. } | Error::Unavailable { .. } => {
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+        Error::Multiple { errors } if error.is_client_error() => errors
+            .first()
+            .map(status)
+            .unwrap_or(StatusCode::BAD_REQUEST),
+        Error::ServerError { .. }
+        | Error::RngError { .. }
+        | Error::SimulationError { .. }
+        | Error::Multiple { .. }
+        | Error::Other => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Turns an error into a [`types::Response::Error`] with the matching status.
+pub fn error_response(error: Error) -> Response {
+    (status(&error), Json(types::Response::from(error))).into_response()
+}
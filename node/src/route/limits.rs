@@ -0,0 +1,136 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    extract::{DefaultBodyLimit, Request, State},
+    middleware::{self, Next},
+    response::Response,
+    Router,
+};
+use mugraph_core::error::Error;
+use tokio::sync::Semaphore;
+
+use super::error_response;
+use crate::config::Limits;
+
+#[derive(Clone)]
+struct Permits {
+    semaphore: Arc<Semaphore>,
+    timeout: Duration,
+}
+
+/// Applies the request limits to every route of `router`.
+///
+/// Requests over the concurrency limit wait for a turn, and the ones that
+/// time out while waiting or being handled get a retryable
+/// [`Error::Unavailable`].
+pub fn limit(router: Router, limits: &Limits) -> Router {
+    let permits = Permits {
+        semaphore: Arc::new(Semaphore::new(limits.max_concurrent_requests)),
+        timeout: limits.request_timeout(),
+    };
+
+    router
+        .layer(middleware::from_fn_with_state(permits, permit))
+        .layer(DefaultBodyLimit::max(limits.max_body_size))
+}
+
+async fn permit(State(permits): State<Permits>, request: Request, next: Next) -> Response {
+    let handle = async {
+        let _permit = permits
+            .semaphore
+            .acquire()
+            .await
+            .expect("Semaphore is never closed");
+
+        next.run(request).await
+    };
+
+    match tokio::time::timeout(permits.timeout, handle).await {
+        Ok(response) => response,
+        Err(_) => error_response(Error::Unavailable {
+            reason: format!("request timed out after {:?}", permits.timeout),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::{to_bytes, Body},
+        http::StatusCode,
+        routing::post,
+    };
+    use mugraph_core::types;
+    use tokio::sync::Notify;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn request(body: &'static str) -> Request {
+        Request::post("/").body(Body::from(body)).unwrap()
+    }
+
+    async fn assert_unavailable(response: Response) {
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(matches!(
+            serde_json::from_slice(&body).unwrap(),
+            types::Response::Error {
+                retryable: true,
+                error: Error::Unavailable { .. },
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_limits() {
+        let holding = Arc::new(Notify::new());
+        let router = Router::new().route(
+            "/",
+            post({
+                let holding = holding.clone();
+
+                move |body: String| async move {
+                    match body.as_str() {
+                        "wait" => std::future::pending().await,
+                        // Blocks its worker, so the request keeps its permit
+                        // past the timeout of the ones after it.
+                        "hold" => {
+                            holding.notify_one();
+                            std::thread::sleep(Duration::from_millis(1500));
+                        }
+                        _ => {}
+                    }
+
+                    body
+                }
+            }),
+        );
+        let router = limit(
+            router,
+            &Limits {
+                max_body_size: 8,
+                max_concurrent_requests: 1,
+                request_timeout: 1,
+            },
+        );
+
+        let response = router.clone().oneshot(request("ok")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = router.clone().oneshot(request("too large")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // Times out while being handled.
+        assert_unavailable(router.clone().oneshot(request("wait")).await.unwrap()).await;
+
+        // Times out waiting for the only permit.
+        let first = tokio::spawn(router.clone().oneshot(request("hold")));
+        holding.notified().await;
+
+        assert_unavailable(router.clone().oneshot(request("ok")).await.unwrap()).await;
+        first.await.unwrap().unwrap();
+    }
+}
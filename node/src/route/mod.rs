@@ -1 +1,6 @@
+mod error;
+mod limits;
 pub mod v0;
+
+pub use error::*;
+pub use limits::*;
@@ -0,0 +1,33 @@
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+    Json,
+};
+use mugraph_core::types::Hash;
+
+use super::{blocking, error_response, Context};
+use crate::{assets, database::ASSETS};
+
+#[tracing::instrument(skip_all)]
+pub async fn assets_list(State(Context { database, .. }): State<Context>) -> impl IntoResponse {
+    match blocking(move || assets::list(&database)).await {
+        Ok(assets) => Json(assets).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn assets_get(
+    State(Context { database, .. }): State<Context>,
+    Path(asset_id): Path<Hash>,
+) -> impl IntoResponse {
+    let result = blocking(move || {
+        let table = database.read()?.open_table(ASSETS)?;
+        assets::get(&table, asset_id)
+    });
+
+    match result.await {
+        Ok(asset) => Json(asset).into_response(),
+        Err(e) => error_response(e),
+    }
+}
@@ -0,0 +1,181 @@
+use mugraph_core::{error::Error, types::*};
+
+use super::{apply, check_inputs_batch, sign_outputs, spent_input, validate};
+use crate::database::{Database, ASSETS};
+
+/// Processes many independent transactions, returning one result for each.
+///
+/// Batches are best-effort: a transaction being rejected does not affect the
+/// others, and every accepted transaction is committed in a single write
+/// transaction. The whole batch only fails if the database does.
+#[inline]
+pub fn batch_v0(
+    transactions: &[Transaction],
+    keypair: Keypair,
+    fees: &FeeSchedule,
+    database: &Database,
+) -> Result<V0Response, Error> {
+    if transactions.is_empty() || transactions.len() > MAX_BATCH {
+        return Err(Error::InvalidTransaction {
+            reason: format!("Batches must have between 1 and {MAX_BATCH} transactions"),
+        });
+    }
+
+    let checked = {
+        let assets = database.read()?.open_table(ASSETS)?;
+
+        transactions
+            .iter()
+            .map(|t| validate(t, &keypair, fees, &assets))
+            .collect::<Vec<_>>()
+    };
+
+    let valid = transactions
+        .iter()
+        .zip(checked.iter())
+        .filter(|(_, r)| r.is_ok())
+        .map(|(t, _)| t)
+        .collect::<Vec<_>>();
+    let mut inputs = check_inputs_batch(&valid, &keypair).into_iter();
+
+    let prepared = transactions
+        .iter()
+        .zip(checked)
+        .map(|(t, r)| {
+            r.and_then(|paid| {
+                let consumed_inputs = inputs.next().expect("Every valid transaction is checked")?;
+                Ok((consumed_inputs, paid, sign_outputs(t, &keypair)))
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let w = database.write()?;
+    let mut results = Vec::with_capacity(transactions.len());
+
+    for (transaction, prepared) in transactions.iter().zip(prepared) {
+        let result = match prepared {
+            Ok((consumed_inputs, paid, outputs)) => match spent_input(&w, &consumed_inputs)? {
+                Some(signature) => Err(Error::AlreadySpent { signature }),
+                // Inputs reserved by a cross-delegate transaction are only
+                // found by apply, before it writes anything.
+                None => match apply(&w, transaction, consumed_inputs, &paid) {
+                    Ok(()) => Ok(outputs),
+                    Err(e @ Error::AlreadySpent { .. }) => Err(e),
+                    Err(e) => return Err(e),
+                },
+            },
+            Err(e) => Err(e),
+        };
+
+        results.push(result.into());
+    }
+
+    w.commit()?;
+
+    Ok(V0Response::Batch { results })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testing::{spend, Setup},
+        v0::transaction_v0,
+    };
+
+    fn results(s: &Setup, transactions: &[Transaction]) -> Vec<BatchResult> {
+        match batch_v0(transactions, s.keypair, &s.fees, &s.database).unwrap() {
+            V0Response::Batch { results } => results,
+            r => panic!("Unexpected response: {r:?}"),
+        }
+    }
+
+    #[test]
+    fn test_best_effort() {
+        let mut s = Setup::new();
+        let asset_id = s.asset();
+        let notes = s.notes(asset_id, &[10, 20, 30, 40]);
+
+        let valid = spend(&notes[..1], &[(asset_id, 10)]);
+        let mut forged = spend(&notes[1..2], &[(asset_id, 20)]);
+        forged.atoms[0].amount = 25;
+        forged.atoms[1].amount = 25;
+        let unbalanced = spend(&notes[2..3], &[(asset_id, 31)]);
+        let spent_twice = spend(&notes[3..], &[(asset_id, 40)]);
+
+        let results = results(
+            &s,
+            &[
+                valid.clone(),
+                forged,
+                unbalanced,
+                spent_twice.clone(),
+                spent_twice,
+            ],
+        );
+
+        assert!(results[0].is_ok());
+        assert!(matches!(
+            results[1],
+            BatchResult::Error {
+                error: Error::InvalidSignature { .. },
+                ..
+            }
+        ));
+        assert!(matches!(
+            results[2],
+            BatchResult::Error {
+                error: Error::UnbalancedTransaction { .. },
+                ..
+            }
+        ));
+        assert!(results[3].is_ok());
+        assert!(matches!(results[4], BatchResult::Error { code: 1002, .. }));
+
+        // Only the accepted transactions were committed.
+        assert!(matches!(
+            transaction_v0(&valid, s.keypair, &s.fees, &s.database),
+            Err(Error::AlreadySpent { .. })
+        ));
+        assert!(transaction_v0(
+            &spend(&notes[1..3], &[(asset_id, 50)]),
+            s.keypair,
+            &s.fees,
+            &s.database
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_input_spent_twice_in_transaction() {
+        let mut s = Setup::new();
+        let asset_id = s.asset();
+        let notes = s.notes(asset_id, &[10]);
+
+        let transaction = spend(&[notes[0].clone(), notes[0].clone()], &[(asset_id, 20)]);
+        let results = results(&s, &[transaction]);
+
+        assert!(matches!(results[0], BatchResult::Error { code: 1002, .. }));
+        assert!(transaction_v0(
+            &spend(&notes, &[(asset_id, 10)]),
+            s.keypair,
+            &s.fees,
+            &s.database
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_batch_size() {
+        let s = Setup::new();
+
+        assert!(batch_v0(&[], s.keypair, &s.fees, &s.database).is_err());
+        assert!(batch_v0(
+            &vec![Transaction::default(); MAX_BATCH + 1],
+            s.keypair,
+            &s.fees,
+            &s.database
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,124 @@
+use mugraph_core::{error::Error, types::*};
+
+use super::{apply, check_inputs_batch, sign_outputs, spent_input, validate};
+use crate::database::{Database, ASSETS};
+
+/// Processes many transactions atomically: either all of them are committed,
+/// or none are and the error of the first one rejected is returned.
+///
+/// The transactions can not spend the same input twice, and are committed in
+/// a single write transaction.
+#[inline]
+pub fn bundle_v0(
+    transactions: &[Transaction],
+    keypair: Keypair,
+    fees: &FeeSchedule,
+    database: &Database,
+) -> Result<V0Response, Error> {
+    if transactions.is_empty() || transactions.len() > MAX_BATCH {
+        return Err(Error::InvalidTransaction {
+            reason: format!("Bundles must have between 1 and {MAX_BATCH} transactions"),
+        });
+    }
+
+    let paid = {
+        let assets = database.read()?.open_table(ASSETS)?;
+
+        transactions
+            .iter()
+            .map(|t| validate(t, &keypair, fees, &assets))
+            .collect::<Result<Vec<_>, Error>>()?
+    };
+
+    let consumed_inputs = check_inputs_batch(&transactions.iter().collect::<Vec<_>>(), &keypair)
+        .into_iter()
+        .collect::<Result<Vec<_>, Error>>()?;
+    let outputs = transactions
+        .iter()
+        .map(|t| sign_outputs(t, &keypair))
+        .collect();
+
+    let w = database.write()?;
+
+    if let Some(signature) = spent_input(&w, &consumed_inputs.concat())? {
+        return Err(Error::AlreadySpent { signature });
+    }
+
+    for ((transaction, consumed_inputs), paid) in
+        transactions.iter().zip(consumed_inputs).zip(paid.iter())
+    {
+        apply(&w, transaction, consumed_inputs, paid)?;
+    }
+
+    w.commit()?;
+
+    Ok(V0Response::Bundle { outputs })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testing::{spend, Setup},
+        v0::transaction_v0,
+    };
+
+    #[test]
+    fn test_bundle() {
+        let mut s = Setup::new();
+        let asset_id = s.asset();
+        let notes = s.notes(asset_id, &[10, 20]);
+
+        let first = spend(&notes[..1], &[(asset_id, 4), (asset_id, 6)]);
+        let second = spend(&notes[1..], &[(asset_id, 20)]);
+
+        match bundle_v0(&[first.clone(), second], s.keypair, &s.fees, &s.database) {
+            Ok(V0Response::Bundle { outputs }) => {
+                assert_eq!(outputs.iter().map(Vec::len).collect::<Vec<_>>(), [2, 1]);
+            }
+            r => panic!("Unexpected response: {r:?}"),
+        }
+
+        assert!(matches!(
+            transaction_v0(&first, s.keypair, &s.fees, &s.database),
+            Err(Error::AlreadySpent { .. })
+        ));
+    }
+
+    #[test]
+    fn test_all_or_nothing() {
+        let mut s = Setup::new();
+        let asset_id = s.asset();
+        let notes = s.notes(asset_id, &[10, 20]);
+
+        let valid = spend(&notes[..1], &[(asset_id, 10)]);
+        let unbalanced = spend(&notes[1..], &[(asset_id, 21)]);
+
+        assert!(matches!(
+            bundle_v0(
+                &[valid.clone(), unbalanced],
+                s.keypair,
+                &s.fees,
+                &s.database
+            ),
+            Err(Error::UnbalancedTransaction { .. })
+        ));
+        assert!(transaction_v0(&valid, s.keypair, &s.fees, &s.database).is_ok());
+    }
+
+    #[test]
+    fn test_conflicting_inputs() {
+        let mut s = Setup::new();
+        let asset_id = s.asset();
+        let notes = s.notes(asset_id, &[10, 20]);
+
+        let first = spend(&notes, &[(asset_id, 30)]);
+        let second = spend(&notes[1..], &[(asset_id, 20)]);
+
+        assert!(matches!(
+            bundle_v0(&[first.clone(), second], s.keypair, &s.fees, &s.database),
+            Err(Error::AlreadySpent { signature }) if signature == notes[1].signature
+        ));
+        assert!(transaction_v0(&first, s.keypair, &s.fees, &s.database).is_ok());
+    }
+}
@@ -0,0 +1,166 @@
+use mugraph_core::{error::Error, types::*};
+
+use crate::database::{Database, NOTES, PENDING, SPENT};
+
+#[inline]
+pub fn check_v0(
+    notes: &[NoteRef],
+    keypair: Keypair,
+    database: &Database,
+) -> Result<V0Response, Error> {
+    if notes.len() > MAX_CHECK {
+        return Err(Error::InvalidTransaction {
+            reason: format!("Can not check more than {MAX_CHECK} notes at once"),
+        });
+    }
+
+    let read = database.read()?;
+    let spent = read.open_table(SPENT)?;
+    let pending = read.open_table(PENDING)?;
+    let signatures = read.open_table(NOTES)?;
+
+    let states = notes
+        .iter()
+        .map(|note| {
+            let y = note.y();
+
+            match (spent.get(y)?, pending.get(y)?) {
+                (Some(_), _) => Ok(NoteState::Spent),
+                (None, Some(_)) => Ok(NoteState::Pending),
+                // Notes spent before the Y points were recorded are only
+                // found by their signature.
+                (None, None) => match signature(keypair, y) {
+                    Some(signature) if signatures.get(signature)?.is_some() => Ok(NoteState::Spent),
+                    Some(_) => Ok(NoteState::Unspent),
+                    None => Ok(NoteState::Invalid),
+                },
+            }
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    Ok(V0Response::Check { states })
+}
+
+/// Signature of the delegate on the note with Y point `y`, as inputs are
+/// recorded in [`NOTES`], or `None` if `y` is not a point.
+fn signature(keypair: Keypair, y: Hash) -> Option<Signature> {
+    let point = Signature(y.0).to_point().ok()?;
+
+    Some((point * keypair.secret_key.to_scalar()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use mugraph_core::crypto;
+
+    use super::*;
+    use crate::{
+        testing::{spend, Setup},
+        v0::transaction_v0,
+    };
+
+    fn states(s: &mut Setup, notes: &[NoteRef]) -> Vec<NoteState> {
+        match check_v0(notes, s.keypair, &s.database).unwrap() {
+            V0Response::Check { states } => states,
+            r => panic!("Unexpected response: {r:?}"),
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let mut s = Setup::new();
+        let asset_id = s.asset();
+        let notes = s.notes(asset_id, &[10, 20]);
+
+        let refs = [
+            NoteRef::Commitment(notes[0].commitment()),
+            NoteRef::Y(crypto::y_point(&notes[0].commitment())),
+            NoteRef::Commitment(notes[1].commitment()),
+        ];
+
+        assert_eq!(states(&mut s, &refs), vec![NoteState::Unspent; 3]);
+
+        transaction_v0(
+            &spend(&notes[..1], &[(asset_id, 10)]),
+            s.keypair,
+            &s.fees,
+            &s.database,
+        )
+        .unwrap();
+
+        assert_eq!(
+            states(&mut s, &refs),
+            vec![NoteState::Spent, NoteState::Spent, NoteState::Unspent]
+        );
+    }
+
+    #[test]
+    fn test_check_pending() {
+        let mut s = Setup::new();
+        let asset_id = s.asset();
+        let note = s.notes(asset_id, &[10]).remove(0);
+        let y = crypto::y_point(&note.commitment());
+
+        let w = s.database.write().unwrap();
+        w.open_table(PENDING)
+            .unwrap()
+            .insert(y, Hash::zero())
+            .unwrap();
+        w.commit().unwrap();
+
+        assert_eq!(states(&mut s, &[NoteRef::Y(y)]), vec![NoteState::Pending]);
+    }
+
+    #[test]
+    fn test_check_spent_before_y_points() {
+        let mut s = Setup::new();
+        let asset_id = s.asset();
+        let notes = s.notes(asset_id, &[10]);
+        let y = crypto::y_point(&notes[0].commitment());
+
+        transaction_v0(
+            &spend(&notes, &[(asset_id, 10)]),
+            s.keypair,
+            &s.fees,
+            &s.database,
+        )
+        .unwrap();
+
+        // Like a spend from before the SPENT table existed.
+        let w = s.database.write().unwrap();
+        w.open_table(SPENT).unwrap().remove(y).unwrap();
+        w.commit().unwrap();
+
+        assert_eq!(states(&mut s, &[NoteRef::Y(y)]), vec![NoteState::Spent]);
+    }
+
+    #[test]
+    fn test_check_invalid_y() {
+        let mut s = Setup::new();
+        let asset_id = s.asset();
+        let note = s.notes(asset_id, &[10]).remove(0);
+
+        // Not the compressed encoding of any point.
+        let malformed = Hash([0xff; 32]);
+        assert!(Signature(malformed.0).to_point().is_err());
+
+        let refs = [
+            NoteRef::Commitment(note.commitment()),
+            NoteRef::Y(malformed),
+            NoteRef::Y(crypto::y_point(&note.commitment())),
+        ];
+
+        assert_eq!(
+            states(&mut s, &refs),
+            vec![NoteState::Unspent, NoteState::Invalid, NoteState::Unspent]
+        );
+    }
+
+    #[test]
+    fn test_check_too_many() {
+        let s = Setup::new();
+        let notes = vec![NoteRef::Y(Hash::zero()); MAX_CHECK + 1];
+
+        assert!(check_v0(&notes, s.keypair, &s.database).is_err());
+    }
+}
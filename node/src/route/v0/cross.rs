@@ -0,0 +1,811 @@
+//! Transactions with atoms of more than one delegate, settled with a
+//! two-phase commit between them.
+//!
+//! The delegate the transaction is submitted to coordinates it. It prepares
+//! the transaction itself and on every other delegate in it, which check
+//! their own atoms and reserve their inputs in [`PENDING`]. If all of them
+//! succeed, the coordinator commits and sends its signed decision to the
+//! others, which spend their inputs and sign their outputs. Otherwise it
+//! aborts and they release their inputs.
+//!
+//! Delegates only take part in transactions coordinated by their peers, as
+//! they trust the coordinator to burn the inputs backing their outputs.
+//! Every step can be repeated: submitting a committed transaction again
+//! delivers the decision to the delegates that missed it and returns the
+//! same signatures. An aborted transaction stays aborted, and has to be
+//! submitted again with other outputs.
+//!
+//! Proposals expire after [`PREPARE_TIMEOUT`], after which the coordinator
+//! no longer commits them. Delegates left with an expired transaction they
+//! prepared ask the coordinator for its outcome, see [`recover`], which
+//! aborts it if it was not decided in time.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use mugraph_core::{crypto, error::Error, types::*};
+use rand::thread_rng;
+use redb::ReadableTable;
+use tracing::warn;
+
+use super::{check_own_inputs, mark_spent, record_atoms, sign_outputs_where};
+use crate::{
+    assets,
+    database::{Database, ASSETS, CROSS, NOTES, PENDING},
+    fees, log,
+    peer::{Peer, Peers},
+    settlement,
+    v0::Context,
+};
+
+/// Time the other delegates have to prepare a transaction before its
+/// coordinator gives up on it.
+pub const PREPARE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How often delegates look for expired transactions they prepared.
+pub const RECOVERY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Processes a transaction with atoms of other delegates, coordinating it
+/// with them. Returns the signatures for all of its outputs, each made by the
+/// delegate the output belongs to.
+pub fn cross_v0(
+    transaction: &Transaction,
+    keypair: Keypair,
+    fees: &FeeSchedule,
+    peers: &Peers,
+    database: &Database,
+) -> Result<V0Response, Error> {
+    let id = transaction.id();
+    let participants = transaction
+        .delegates()
+        .into_iter()
+        .filter(|delegate| *delegate != keypair.public_key)
+        .map(|delegate| match peers.get(&delegate) {
+            Some(peer) => Ok((delegate, peer.as_ref())),
+            None => Err(Error::InvalidTransaction {
+                reason: format!("Delegate {delegate} is not a peer of this delegate"),
+            }),
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    let paid = transaction.verify_with_fees(fees)?;
+
+    let committed = match database.read()?.open_table(CROSS)?.get(id)? {
+        Some(entry) => entry.value()?.decision == Some(Decision::Commit),
+        None => false,
+    };
+
+    if !committed {
+        let proposal = Proposal {
+            transaction: transaction.clone(),
+            coordinator: keypair.public_key,
+            expires: now() + PREPARE_TIMEOUT.as_secs(),
+        };
+        let signed = Signed::new(&mut thread_rng(), &keypair.secret_key, &proposal)?;
+
+        prepare_all(
+            &proposal,
+            &signed,
+            &paid,
+            &participants,
+            keypair,
+            peers,
+            database,
+        )?;
+    }
+
+    let outcome = sign(&keypair, id, Decision::Commit)?;
+    let mut outputs = HashMap::from([(
+        keypair.public_key,
+        decide(&outcome, keypair, database)?.into_iter(),
+    )]);
+
+    for (delegate, peer) in participants {
+        match peer.call(V0Request::Decide {
+            outcome: outcome.clone(),
+        })? {
+            V0Response::Decided { outputs: signed } => {
+                outputs.insert(delegate, signed.into_iter());
+            }
+            response => return Err(unexpected(delegate, response)),
+        }
+    }
+
+    let outputs = transaction
+        .atoms
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| transaction.is_output(*i))
+        .map(|(_, atom)| {
+            outputs
+                .get_mut(&atom.delegate)
+                .and_then(Iterator::next)
+                .ok_or_else(|| Error::PeerError {
+                    delegate: atom.delegate,
+                    reason: format!("Missing signatures for transaction {id}"),
+                })
+        })
+        .collect::<Result<_, Error>>()?;
+
+    Ok(V0Response::Transaction { outputs })
+}
+
+/// Prepares the transaction here and on every participant, aborting it
+/// everywhere it was prepared if any of them fails. `signed` is the proposal
+/// as sent to them.
+fn prepare_all(
+    proposal: &Proposal,
+    signed: &Signed<Proposal>,
+    paid: &[(Hash, u128)],
+    participants: &[(PublicKey, &dyn Peer)],
+    keypair: Keypair,
+    peers: &Peers,
+    database: &Database,
+) -> Result<(), Error> {
+    let transaction = &proposal.transaction;
+    prepare(proposal, paid, keypair, peers, database)?;
+
+    for (i, (delegate, peer)) in participants.iter().enumerate() {
+        let result = match peer.call(V0Request::Prepare {
+            proposal: signed.clone(),
+        }) {
+            Ok(V0Response::Prepared { .. }) => Ok(()),
+            Ok(response) => Err(unexpected(*delegate, response)),
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = result {
+            let outcome = sign(&keypair, transaction.id(), Decision::Abort)?;
+            decide(&outcome, keypair, database)?;
+
+            // The failed participant may have prepared it before failing, so
+            // it is told as well.
+            for (delegate, peer) in participants[..=i].iter() {
+                if let Err(e) = peer.call(V0Request::Decide {
+                    outcome: outcome.clone(),
+                }) {
+                    warn!(%delegate, error = %e, "Failed to abort cross-delegate transaction");
+                }
+            }
+
+            return Err(e);
+        }
+    }
+
+    Ok(())
+}
+
+/// First phase of a cross-delegate transaction: checks the atoms of this
+/// delegate and reserves its inputs until the coordinator decides.
+pub fn prepare_v0(
+    proposal: &Signed<Proposal>,
+    keypair: Keypair,
+    peers: &Peers,
+    database: &Database,
+) -> Result<V0Response, Error> {
+    let Proposal {
+        transaction,
+        coordinator,
+        ..
+    } = proposal.unverified()?;
+
+    let proposal = proposal
+        .verify(&coordinator)
+        .map_err(|_| Error::InvalidTransaction {
+            reason: format!(
+                "Proposal of {} is not signed by its coordinator",
+                transaction.id()
+            ),
+        })?;
+
+    prepare(&proposal, &[], keypair, peers, database)?;
+
+    Ok(V0Response::Prepared {
+        id: proposal.transaction.id(),
+    })
+}
+
+fn prepare(
+    proposal: &Proposal,
+    paid: &[(Hash, u128)],
+    keypair: Keypair,
+    peers: &Peers,
+    database: &Database,
+) -> Result<(), Error> {
+    let Proposal {
+        transaction,
+        coordinator,
+        expires,
+    } = proposal;
+    let coordinator = *coordinator;
+    let delegates = transaction.delegates();
+
+    if coordinator != keypair.public_key && !peers.contains_key(&coordinator) {
+        return Err(Error::InvalidTransaction {
+            reason: format!("Coordinator {coordinator} is not a peer of this delegate"),
+        });
+    }
+
+    if *expires <= now() {
+        return Err(Error::InvalidTransaction {
+            reason: format!("Proposal of {} has expired", transaction.id()),
+        });
+    }
+
+    if !delegates.contains(&keypair.public_key) || !delegates.contains(&coordinator) {
+        return Err(Error::InvalidTransaction {
+            reason: "Transaction has no atoms of this delegate or its coordinator".to_string(),
+        });
+    }
+
+    // Only the coordinator knows the fees, so the others only check that no
+    // value is created.
+    transaction.check_data()?;
+    let (pre, post) = transaction.balances()?;
+
+    if pre.iter().zip(post.iter()).any(|(pre, post)| pre < post) {
+        return Err(Error::UnbalancedTransaction { pre, post });
+    }
+
+    let owned = |atom: &Atom| atom.delegate == keypair.public_key;
+    assets::check_where(
+        transaction,
+        &database.read()?.open_table(ASSETS)?,
+        false,
+        owned,
+    )?;
+    let consumed_inputs = check_own_inputs(transaction, &keypair)?;
+
+    let id = transaction.id();
+    let w = database.write()?;
+
+    {
+        let mut cross = w.open_table(CROSS)?;
+        let notes = w.open_table(NOTES)?;
+        let mut pending = w.open_table(PENDING)?;
+
+        match cross.get(id)?.map(|entry| entry.value()).transpose()? {
+            Some(entry) if entry.coordinator != coordinator => {
+                return Err(Error::InvalidTransaction {
+                    reason: format!("Transaction {id} is coordinated by {}", entry.coordinator),
+                });
+            }
+            Some(entry) if entry.decision == Some(Decision::Abort) => {
+                return Err(Error::InvalidTransaction {
+                    reason: format!(
+                        "Transaction {id} was aborted, submit it again with other outputs"
+                    ),
+                });
+            }
+            // Prepared or committed before, nothing left to do.
+            Some(_) => return Ok(()),
+            None => {}
+        }
+
+        for (input, y) in consumed_inputs.iter().zip(input_ys(transaction, owned)) {
+            if notes.get(input)?.is_some() || pending.get(y)?.is_some() {
+                return Err(Error::AlreadySpent { signature: *input });
+            }
+
+            pending.insert(y, id)?;
+        }
+
+        cross.insert(
+            id,
+            Ok(CrossTransaction {
+                transaction: transaction.clone(),
+                coordinator,
+                fees: paid.to_vec(),
+                decision: None,
+                expires: *expires,
+            }),
+        )?;
+    }
+
+    w.commit()?;
+
+    Ok(())
+}
+
+/// Second phase of a cross-delegate transaction: applies the decision of the
+/// coordinator, returning the signatures for the outputs of this delegate.
+pub fn decide_v0(
+    outcome: &Signed<Outcome>,
+    keypair: Keypair,
+    database: &Database,
+) -> Result<V0Response, Error> {
+    Ok(V0Response::Decided {
+        outputs: decide(outcome, keypair, database)?,
+    })
+}
+
+fn decide(
+    outcome: &Signed<Outcome>,
+    keypair: Keypair,
+    database: &Database,
+) -> Result<Vec<Blinded<OutputSignature>>, Error> {
+    let Outcome { id, .. } = outcome.unverified()?;
+    let w = database.write()?;
+
+    let mut entry = w
+        .open_table(CROSS)?
+        .get(id)?
+        .map(|entry| entry.value())
+        .transpose()?
+        .ok_or_else(|| Error::NotFound {
+            reason: format!("Cross-delegate transaction {id} was not prepared"),
+        })?;
+
+    let Outcome { decision, .. } =
+        outcome
+            .verify(&entry.coordinator)
+            .map_err(|_| Error::InvalidTransaction {
+                reason: format!("Outcome of {id} is not signed by its coordinator"),
+            })?;
+
+    let transaction = &entry.transaction;
+    let owned = |atom: &Atom| atom.delegate == keypair.public_key;
+
+    // Others may have asked for the outcome and aborted already.
+    if entry.coordinator == keypair.public_key
+        && entry.decision.is_none()
+        && decision == Decision::Commit
+        && entry.expires <= now()
+    {
+        return Err(Error::InvalidTransaction {
+            reason: format!("Transaction {id} expired before it was committed"),
+        });
+    }
+
+    match (entry.decision, decision) {
+        (Some(previous), _) if previous != decision => {
+            return Err(Error::InvalidTransaction {
+                reason: format!("Transaction {id} was already decided: {previous:?}"),
+            });
+        }
+        (Some(_), _) => {}
+        (None, _) => {
+            let mut pending = w.open_table(PENDING)?;
+
+            for y in input_ys(transaction, owned) {
+                pending.remove(y)?;
+            }
+        }
+    }
+
+    if entry.decision.is_none() && decision == Decision::Commit {
+        let consumed_inputs = transaction
+            .atoms
+            .iter()
+            .enumerate()
+            .filter(|(i, atom)| transaction.is_input(*i) && owned(atom))
+            .filter_map(|(_, atom)| transaction.signatures.get(atom.signature? as usize))
+            .copied()
+            .collect();
+
+        mark_spent(&w, consumed_inputs)?;
+        log::append(&w, id)?;
+        fees::record(&w, &entry.fees)?;
+        record_atoms(&w, transaction, owned)?;
+        settlement::record(&w, transaction, keypair.public_key)?;
+    }
+
+    let outputs = match decision {
+        Decision::Commit => sign_outputs_where(transaction, &keypair, owned),
+        Decision::Abort => vec![],
+    };
+
+    if entry.decision.is_none() {
+        entry.decision = Some(decision);
+        w.open_table(CROSS)?.insert(id, Ok(entry))?;
+        w.commit()?;
+    }
+
+    Ok(outputs)
+}
+
+/// Decision taken on a transaction this delegate coordinates, aborting it if
+/// it expired before it was decided.
+pub fn outcome_v0(id: Hash, keypair: Keypair, database: &Database) -> Result<V0Response, Error> {
+    let entry = database
+        .read()?
+        .open_table(CROSS)?
+        .get(id)?
+        .map(|entry| entry.value())
+        .transpose()?
+        .filter(|entry| entry.coordinator == keypair.public_key)
+        .ok_or_else(|| Error::NotFound {
+            reason: format!("Transaction {id} is not coordinated by this delegate"),
+        })?;
+
+    let decision = match entry.decision {
+        Some(decision) => decision,
+        None if entry.expires <= now() => Decision::Abort,
+        None => {
+            return Err(Error::InvalidTransaction {
+                reason: format!("Transaction {id} is not decided yet"),
+            })
+        }
+    };
+
+    let outcome = sign(&keypair, id, decision)?;
+
+    if entry.decision.is_none() {
+        decide(&outcome, keypair, database)?;
+    }
+
+    Ok(V0Response::Outcome { outcome })
+}
+
+/// Asks the coordinators of expired transactions this delegate prepared for
+/// their outcome, applying it to release or spend their inputs.
+pub fn recover(context: &Context) -> Result<(), Error> {
+    let Context {
+        keypair,
+        peers,
+        database,
+        ..
+    } = context;
+
+    let mut expired = vec![];
+
+    for entry in database.read()?.open_table(CROSS)?.iter()? {
+        let (id, entry) = entry?;
+
+        match entry.value() {
+            Ok(entry)
+                if entry.decision.is_none()
+                    && entry.coordinator != keypair.public_key
+                    && entry.expires <= now() =>
+            {
+                expired.push((id.value(), entry.coordinator));
+            }
+            Ok(_) => {}
+            Err(e) => warn!(id = %id.value(), reason = %e, "Skipped unreadable transaction"),
+        }
+    }
+
+    for (id, coordinator) in expired {
+        let result = match peers.get(&coordinator) {
+            Some(peer) => match peer.call(V0Request::Outcome { id }) {
+                Ok(V0Response::Outcome { outcome })
+                    if outcome.unverified().is_ok_and(|o| o.id == id) =>
+                {
+                    decide(&outcome, *keypair, database).map(|_| ())
+                }
+                Ok(response) => Err(unexpected(coordinator, response)),
+                Err(e) => Err(e),
+            },
+            None => Err(Error::InvalidTransaction {
+                reason: format!("Coordinator {coordinator} is not a peer of this delegate"),
+            }),
+        };
+
+        if let Err(e) = result {
+            warn!(%id, %coordinator, error = %e, "Failed to recover cross-delegate transaction");
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn run_recovery(context: Context) {
+    let mut interval = tokio::time::interval(RECOVERY_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let context = context.clone();
+        let result = tokio::task::spawn_blocking(move || recover(&context))
+            .await
+            .unwrap_or_else(|e| {
+                Err(Error::ServerError {
+                    reason: e.to_string(),
+                })
+            });
+
+        if let Err(e) = result {
+            warn!(reason = %e, "Failed to recover cross-delegate transactions");
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+fn sign(keypair: &Keypair, id: Hash, decision: Decision) -> Result<Signed<Outcome>, Error> {
+    Signed::new(
+        &mut thread_rng(),
+        &keypair.secret_key,
+        &Outcome { id, decision },
+    )
+}
+
+/// Y points of the inputs selected by `filter`, see [`crypto::y_point`].
+fn input_ys(transaction: &Transaction, filter: impl Fn(&Atom) -> bool) -> Vec<Hash> {
+    transaction
+        .atoms
+        .iter()
+        .enumerate()
+        .filter(|(i, atom)| transaction.is_input(*i) && filter(atom))
+        .map(|(_, atom)| crypto::y_point(&atom.commitment(&transaction.asset_ids)))
+        .collect()
+}
+
+fn unexpected(delegate: PublicKey, response: V0Response) -> Error {
+    Error::PeerError {
+        delegate,
+        reason: format!("Unexpected response: {response:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::Router;
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::{
+        peer::HttpPeer,
+        testing::{spend, transfer, Pair},
+        v0::{check_v0, handle, router, transaction_v0, Context},
+    };
+
+    fn propose(signer: &Keypair, transaction: &Transaction, expires: u64) -> Signed<Proposal> {
+        let proposal = Proposal {
+            transaction: transaction.clone(),
+            coordinator: signer.public_key,
+            expires,
+        };
+
+        Signed::new(&mut thread_rng(), &signer.secret_key, &proposal).unwrap()
+    }
+
+    fn decision(context: &Context, id: Hash) -> Option<Decision> {
+        let table = context.database.read().unwrap().open_table(CROSS).unwrap();
+        table.get(id).unwrap().unwrap().value().unwrap().decision
+    }
+
+    fn state(context: &Context, note: &Note) -> NoteState {
+        match check_v0(
+            &[NoteRef::Commitment(note.commitment())],
+            context.keypair,
+            &context.database,
+        ) {
+            Ok(V0Response::Check { states }) => states[0],
+            r => panic!("Unexpected response: {r:?}"),
+        }
+    }
+
+    #[test]
+    fn test_cross_delegate() {
+        let d = Pair::new(&[100]).peered();
+        let (a, b) = (d.a.keypair.public_key, d.b.keypair.public_key);
+        let transaction = transfer(&d.notes, &[(b, d.asset_id, 60), (a, d.asset_id, 40)]);
+
+        let outputs = match handle(V0Request::Transaction(transaction.clone()), &d.a) {
+            Ok(V0Response::Transaction { outputs }) => outputs,
+            r => panic!("Unexpected response: {r:?}"),
+        };
+        let received = transaction.output_notes(&outputs).unwrap();
+
+        assert_eq!(received[0].delegate, b);
+        assert_eq!(state(&d.a, &d.notes[0]), NoteState::Spent);
+
+        // Submitting it again returns the same signatures.
+        assert!(matches!(
+            handle(V0Request::Transaction(transaction), &d.a),
+            Ok(V0Response::Transaction { outputs: again })
+                if again.iter().map(|s| s.0).eq(outputs.iter().map(|s| s.0))
+        ));
+
+        // The note received at the other delegate can be spent there.
+        let transaction = spend(&received[..1], &[(d.asset_id, 60)]);
+        assert!(transaction_v0(&transaction, d.b.keypair, &d.b.fees, &d.b.database).is_ok());
+    }
+
+    #[test]
+    fn test_abort_releases_inputs() {
+        let d = Pair::new(&[100]).peered();
+        let (a, b) = (d.a.keypair.public_key, d.b.keypair.public_key);
+
+        // The other delegate rejects outputs below the minimum amount.
+        let transaction = transfer(&d.notes, &[(b, d.asset_id, 0), (a, d.asset_id, 100)]);
+
+        assert!(matches!(
+            handle(V0Request::Transaction(transaction), &d.a),
+            Err(Error::InvalidAtom { .. })
+        ));
+        assert_eq!(state(&d.a, &d.notes[0]), NoteState::Unspent);
+
+        let transaction = spend(&d.notes, &[(d.asset_id, 100)]);
+        assert!(transaction_v0(&transaction, d.a.keypair, &d.a.fees, &d.a.database).is_ok());
+    }
+
+    #[test]
+    fn test_prepared_inputs_are_reserved() {
+        let d = Pair::new(&[100]).peered();
+        let b = d.b.keypair.public_key;
+        let transaction = transfer(&d.notes, &[(b, d.asset_id, 100)]);
+        let local = spend(&d.notes, &[(d.asset_id, 100)]);
+        let proposal = propose(&d.a.keypair, &transaction, now() + 60);
+
+        prepare_v0(&proposal, d.a.keypair, &d.a.peers, &d.a.database).unwrap();
+
+        assert_eq!(state(&d.a, &d.notes[0]), NoteState::Pending);
+        assert!(matches!(
+            transaction_v0(&local, d.a.keypair, &d.a.fees, &d.a.database),
+            Err(Error::AlreadySpent { .. })
+        ));
+
+        let abort = sign(&d.a.keypair, transaction.id(), Decision::Abort).unwrap();
+        decide_v0(&abort, d.a.keypair, &d.a.database).unwrap();
+
+        // Aborting is final, the same transaction is not prepared again.
+        assert!(matches!(
+            prepare_v0(&proposal, d.a.keypair, &d.a.peers, &d.a.database),
+            Err(Error::InvalidTransaction { .. })
+        ));
+        assert_eq!(state(&d.a, &d.notes[0]), NoteState::Unspent);
+        assert!(transaction_v0(&local, d.a.keypair, &d.a.fees, &d.a.database).is_ok());
+    }
+
+    #[test]
+    fn test_only_peers_coordinate() {
+        let d = Pair::new(&[100]);
+        let b = d.b.keypair.public_key;
+        let transaction = transfer(&d.notes, &[(b, d.asset_id, 100)]);
+        let proposal = propose(&d.a.keypair, &transaction, now() + 60);
+
+        // Neither knows the other yet.
+        assert!(matches!(
+            handle(V0Request::Transaction(transaction.clone()), &d.a),
+            Err(Error::InvalidTransaction { .. })
+        ));
+        assert!(matches!(
+            prepare_v0(&proposal, d.b.keypair, &d.b.peers, &d.b.database),
+            Err(Error::InvalidTransaction { .. })
+        ));
+
+        // Decisions must be signed by the coordinator.
+        prepare_v0(&proposal, d.a.keypair, &d.a.peers, &d.a.database).unwrap();
+        let forged = sign(&d.b.keypair, transaction.id(), Decision::Commit).unwrap();
+
+        assert!(matches!(
+            decide_v0(&forged, d.a.keypair, &d.a.database),
+            Err(Error::InvalidTransaction { .. })
+        ));
+        assert_eq!(state(&d.a, &d.notes[0]), NoteState::Pending);
+    }
+
+    #[test]
+    fn test_proposals_signed_and_expiring() {
+        let d = Pair::new(&[100]).peered();
+        let b = d.b.keypair.public_key;
+        let transaction = transfer(&d.notes, &[(b, d.asset_id, 100)]);
+
+        // Signed by someone else than the coordinator it names.
+        let mut forged = propose(&d.b.keypair, &transaction, now() + 60);
+        let proposal = Proposal {
+            coordinator: d.a.keypair.public_key,
+            ..forged.unverified().unwrap()
+        };
+        forged.payload = serde_json::to_vec(&proposal).unwrap();
+
+        assert!(matches!(
+            prepare_v0(&forged, d.b.keypair, &d.b.peers, &d.b.database),
+            Err(Error::InvalidTransaction { .. })
+        ));
+
+        let expired = propose(&d.a.keypair, &transaction, now() - 1);
+
+        assert!(matches!(
+            prepare_v0(&expired, d.b.keypair, &d.b.peers, &d.b.database),
+            Err(Error::InvalidTransaction { .. })
+        ));
+    }
+
+    #[test]
+    fn test_recover_expired() {
+        let d = Pair::new(&[100, 100]).peered();
+        let b = d.b.keypair.public_key;
+        let stalled = transfer(&d.notes[..1], &[(b, d.asset_id, 100)]);
+        let late = transfer(&d.notes[1..], &[(b, d.asset_id, 100)]);
+        let expires = now() + 3;
+        let proposals = [&stalled, &late].map(|t| propose(&d.a.keypair, t, expires));
+
+        // The coordinator stops after preparing them everywhere.
+        for proposal in &proposals {
+            prepare(
+                &proposal.unverified().unwrap(),
+                &[],
+                d.a.keypair,
+                &d.a.peers,
+                &d.a.database,
+            )
+            .unwrap();
+            prepare_v0(proposal, d.b.keypair, &d.b.peers, &d.b.database).unwrap();
+        }
+
+        // Nothing is decided before they expire.
+        recover(&d.b).unwrap();
+        assert_eq!(decision(&d.b, stalled.id()), None);
+
+        while now() < expires {
+            std::thread::sleep(Duration::from_millis(100));
+        }
+
+        // Committing too late fails, leaving it for the others to abort.
+        let commit = sign(&d.a.keypair, late.id(), Decision::Commit).unwrap();
+        assert!(matches!(
+            decide(&commit, d.a.keypair, &d.a.database),
+            Err(Error::InvalidTransaction { .. })
+        ));
+
+        recover(&d.b).unwrap();
+
+        for (transaction, note) in [&stalled, &late].into_iter().zip(&d.notes) {
+            assert_eq!(decision(&d.a, transaction.id()), Some(Decision::Abort));
+            assert_eq!(decision(&d.b, transaction.id()), Some(Decision::Abort));
+            assert_eq!(state(&d.a, note), NoteState::Unspent);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_cross_delegate_http() {
+        let mut d = Pair::new(&[100]);
+        let (a, b) = (d.a.keypair.public_key, d.b.keypair.public_key);
+        let listeners = [
+            TcpListener::bind("127.0.0.1:0").await.unwrap(),
+            TcpListener::bind("127.0.0.1:0").await.unwrap(),
+        ];
+        let urls = listeners
+            .each_ref()
+            .map(|l| format!("http://{}", l.local_addr().unwrap()));
+        let http = |delegate: PublicKey, url: &str| -> Peers {
+            Peers::from([(
+                delegate,
+                Arc::new(HttpPeer::new(delegate, url)) as Arc<dyn Peer>,
+            )])
+        };
+
+        d.a = d.a.clone().peers(http(b, &urls[1]));
+        d.b = d.b.clone().peers(http(a, &urls[0]));
+
+        for (listener, context) in listeners.into_iter().zip([d.a.clone(), d.b.clone()]) {
+            let app = Router::new().nest("/v0", router(context));
+            tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        }
+
+        let transaction = transfer(&d.notes, &[(b, d.asset_id, 70), (a, d.asset_id, 30)]);
+        let response = reqwest::Client::new()
+            .post(format!("{}/v0/rpc", urls[0]))
+            .json(&Request::from(V0Request::Transaction(transaction.clone())))
+            .send()
+            .await
+            .unwrap()
+            .json::<Response>()
+            .await
+            .unwrap();
+
+        let outputs = match response {
+            Response::V0(V0Response::Transaction { outputs }) => outputs,
+            r => panic!("Unexpected response: {r:?}"),
+        };
+        let received = transaction.output_notes(&outputs).unwrap();
+
+        assert_eq!(
+            received
+                .iter()
+                .map(|note| (note.delegate, note.amount))
+                .collect::<Vec<_>>(),
+            [(b, 70), (a, 30)]
+        );
+    }
+}
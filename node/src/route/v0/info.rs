@@ -0,0 +1,192 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use mugraph_core::{error::Error, types::*};
+use rand::thread_rng;
+use serde::Serialize;
+
+use super::{blocking, error_response, Context};
+use crate::{assets, chain::ChainBackend, database::Database};
+
+/// Seconds clients may cache the delegate metadata for.
+pub const MAX_AGE: u64 = 60;
+
+/// Seconds the signed metadata stays valid for.
+pub const VALIDITY: u64 = 10 * MAX_AGE;
+
+/// When metadata signed at `now` is issued and expires. Issuing it at the
+/// start of a [`MAX_AGE`] window keeps its ETag the same for the whole window.
+fn validity(now: u64) -> (u64, u64) {
+    let issued_at = now - now % MAX_AGE;
+
+    (issued_at, issued_at + VALIDITY)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+pub fn info_v0(
+    keypair: &Keypair,
+    chain: &dyn ChainBackend,
+    fees: &FeeSchedule,
+    database: &Database,
+    now: u64,
+) -> Result<Info, Error> {
+    let (issued_at, expires) = validity(now);
+
+    Ok(Info {
+        public_key: keypair.public_key,
+        versions: PROTOCOL_VERSIONS.iter().map(|v| v.to_string()).collect(),
+        limits: Limits::default(),
+        assets: assets::list(database)?,
+        min_confirmations: chain.min_confirmations(),
+        fees: fees.clone(),
+        issued_at,
+        expires,
+    })
+}
+
+pub fn keys_v0(keypair: &Keypair, now: u64) -> Keys {
+    let (issued_at, expires) = validity(now);
+
+    Keys {
+        keys: vec![DelegateKey {
+            public_key: keypair.public_key,
+            active: true,
+        }],
+        issued_at,
+        expires,
+    }
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn delegate_info(
+    State(Context {
+        keypair,
+        chain,
+        fees,
+        database,
+        ..
+    }): State<Context>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let info = blocking(move || info_v0(&keypair, chain.as_ref(), &fees, &database, now())).await;
+
+    cached(&headers, &keypair, info)
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn delegate_keys(
+    State(Context { keypair, .. }): State<Context>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    cached(&headers, &keypair, Ok(keys_v0(&keypair, now())))
+}
+
+/// Signs the payload, tagging it with an ETag over its contents so clients
+/// can revalidate their cached copy without downloading it again.
+fn cached<T: Serialize>(
+    headers: &HeaderMap,
+    keypair: &Keypair,
+    payload: Result<T, Error>,
+) -> Response {
+    let result = payload.and_then(|payload| {
+        let etag = format!("\"{}\"", Hash::digest(&serde_json::to_vec(&payload)?));
+        let cache = [
+            (header::CACHE_CONTROL, format!("public, max-age={MAX_AGE}")),
+            (header::ETAG, etag.clone()),
+        ];
+
+        if headers
+            .get(header::IF_NONE_MATCH)
+            .is_some_and(|v| v.as_bytes() == etag.as_bytes())
+        {
+            return Ok((StatusCode::NOT_MODIFIED, cache).into_response());
+        }
+
+        let signed = Signed::new(&mut thread_rng(), &keypair.secret_key, &payload)?;
+
+        Ok((cache, Json(signed)).into_response())
+    });
+
+    match result {
+        Ok(response) => response,
+        Err(e) => error_response(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::body::to_bytes;
+
+    use super::*;
+    use crate::testing::Setup;
+
+    async fn get(context: &Context, headers: HeaderMap) -> Response {
+        delegate_info(State(context.clone()), headers)
+            .await
+            .into_response()
+    }
+
+    #[tokio::test]
+    async fn test_info() {
+        let mut s = Setup::new();
+        let asset_id = s.asset();
+        s.fees.default.flat = 1;
+        let context = Context::new(
+            s.keypair,
+            Arc::new(s.chain.clone()),
+            s.fees.clone(),
+            s.database,
+        );
+
+        let response = get(&context, HeaderMap::new()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let etag = response.headers()[header::ETAG].clone();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let info: Signed<Info> = serde_json::from_slice(&body).unwrap();
+        let info = info.verify(&s.keypair.public_key).unwrap();
+
+        assert_eq!(info.public_key, s.keypair.public_key);
+        assert_eq!(info.limits.max_inputs, MAX_INPUTS as u64);
+        assert_eq!(info.assets[0].id, asset_id);
+        assert_eq!(info.fees, s.fees);
+        assert!(info.is_valid_at(now()));
+        assert!(!info.is_valid_at(now() + VALIDITY + CLOCK_SKEW));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, etag);
+
+        assert_eq!(
+            get(&context, headers).await.status(),
+            StatusCode::NOT_MODIFIED
+        );
+    }
+
+    #[tokio::test]
+    async fn test_keys() {
+        let s = Setup::new();
+        let context = Context::new(s.keypair, Arc::new(s.chain.clone()), s.fees, s.database);
+
+        let response = delegate_keys(State(context), HeaderMap::new())
+            .await
+            .into_response();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let keys: Signed<Keys> = serde_json::from_slice(&body).unwrap();
+        let keys = keys.verify(&s.keypair.public_key).unwrap();
+
+        assert_eq!(keys.active(), Some(s.keypair.public_key));
+        assert!(keys.is_valid_at(now()));
+    }
+}
@@ -0,0 +1,35 @@
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+    Json,
+};
+use mugraph_core::types::Hash;
+
+use super::{blocking, error_response, Context};
+use crate::liabilities;
+
+#[tracing::instrument(skip_all)]
+pub async fn liabilities_report(
+    State(Context {
+        keypair,
+        database,
+        cache,
+        ..
+    }): State<Context>,
+) -> impl IntoResponse {
+    match blocking(move || liabilities::report(&database, &keypair, &cache)).await {
+        Ok(report) => Json(report).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn liabilities_proof(
+    State(Context { database, .. }): State<Context>,
+    Path(commitment): Path<Hash>,
+) -> impl IntoResponse {
+    match blocking(move || liabilities::proof(&database, commitment)).await {
+        Ok(proof) => Json(proof).into_response(),
+        Err(e) => error_response(e),
+    }
+}
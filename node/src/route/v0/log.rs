@@ -0,0 +1,145 @@
+use axum::{
+    extract::{Path, Query, State},
+    response::IntoResponse,
+    Json,
+};
+use mugraph_core::types::Hash;
+use serde::Deserialize;
+
+use super::{blocking, error_response, Context};
+use crate::log;
+
+#[derive(Debug, Deserialize)]
+pub struct InclusionQuery {
+    size: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConsistencyQuery {
+    first: u64,
+    second: u64,
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn log_head(
+    State(Context {
+        keypair,
+        database,
+        cache,
+        ..
+    }): State<Context>,
+) -> impl IntoResponse {
+    match blocking(move || log::tree_head(&database, &keypair, &cache)).await {
+        Ok(head) => Json(head).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn log_inclusion(
+    State(Context { database, .. }): State<Context>,
+    Path(id): Path<Hash>,
+    Query(InclusionQuery { size }): Query<InclusionQuery>,
+) -> impl IntoResponse {
+    match blocking(move || log::inclusion_proof(&database, id, size)).await {
+        Ok(proof) => Json(proof).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn log_consistency(
+    State(Context { database, .. }): State<Context>,
+    Query(ConsistencyQuery { first, second }): Query<ConsistencyQuery>,
+) -> impl IntoResponse {
+    match blocking(move || log::consistency_proof(&database, first, second)).await {
+        Ok(proof) => Json(proof).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::{to_bytes, Body},
+        http::{Request, StatusCode},
+        Router,
+    };
+    use mugraph_core::{merkle, types::*};
+    use serde::de::DeserializeOwned;
+    use tower::ServiceExt;
+
+    use crate::{testing::Setup, v0::router};
+
+    async fn get<T: DeserializeOwned>(app: &Router, uri: &str) -> Result<T, StatusCode> {
+        let request = Request::get(uri).body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+
+        match response.status() {
+            StatusCode::OK => {
+                let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                Ok(serde_json::from_slice(&body).unwrap())
+            }
+            status => Err(status),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_log_endpoints() {
+        let s = Setup::new();
+        let ids = (0..3u8).map(|i| Hash::digest(&[i])).collect::<Vec<_>>();
+        let leaves = ids
+            .iter()
+            .map(|id| merkle::leaf_hash(id.as_ref()))
+            .collect::<Vec<_>>();
+
+        let w = s.database.write().unwrap();
+        for id in &ids {
+            crate::log::append(&w, *id).unwrap();
+        }
+        w.commit().unwrap();
+
+        let public_key = s.keypair.public_key;
+        let (context, _dir) = s.into_context();
+        let app = Router::new().nest("/v0", router(context));
+
+        let head: SignedTreeHead = get(&app, "/v0/log/head").await.unwrap();
+        assert!(head.verify(&public_key).is_ok());
+        assert_eq!(head.head.size, 3);
+        assert_eq!(head.head.root, merkle::root(&leaves));
+
+        let proof: InclusionProof = get(&app, &format!("/v0/log/inclusion/{}?size=2", ids[1]))
+            .await
+            .unwrap();
+        assert!(merkle::verify_inclusion(
+            leaves[1],
+            proof.index,
+            proof.size,
+            &proof.path,
+            merkle::root(&leaves[..2])
+        ));
+
+        let proof: ConsistencyProof = get(&app, "/v0/log/consistency?first=1&second=3")
+            .await
+            .unwrap();
+        assert!(merkle::verify_consistency(
+            1,
+            3,
+            merkle::root(&leaves[..1]),
+            head.head.root,
+            &proof.path
+        ));
+
+        let missing = format!("/v0/log/inclusion/{}", Hash::digest(b"missing"));
+        assert_eq!(
+            get::<InclusionProof>(&app, &missing).await.unwrap_err(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            get::<ConsistencyProof>(&app, "/v0/log/consistency?first=3&second=4")
+                .await
+                .unwrap_err(),
+            StatusCode::NOT_FOUND
+        );
+    }
+}
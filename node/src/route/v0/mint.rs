@@ -0,0 +1,202 @@
+use mugraph_core::{
+    error::Error,
+    types::{Keypair, Mint, V0Response},
+};
+use redb::ReadableTable;
+
+use super::sign_outputs;
+use crate::{
+    assets,
+    chain::ChainBackend,
+    database::{Database, ASSETS, DEPOSITS, LOG_INDEX},
+    liabilities, log,
+};
+
+#[inline]
+pub fn mint_v0(
+    mint: &Mint,
+    keypair: Keypair,
+    chain: &dyn ChainBackend,
+    database: &Database,
+) -> Result<V0Response, Error> {
+    let deposit = match chain.deposit(mint.deposit)? {
+        Some(deposit) => deposit,
+        None => {
+            return Err(Error::NotFound {
+                reason: format!("Deposit {} does not exist", mint.deposit),
+            })
+        }
+    };
+
+    let required = chain.min_confirmations();
+
+    if deposit.confirmations < required {
+        return Err(Error::DepositNotConfirmed {
+            deposit: deposit.id,
+            confirmations: deposit.confirmations,
+            required,
+        });
+    }
+
+    mint.verify(&deposit.owner)
+        .map_err(|_| Error::InvalidTransaction {
+            reason: "Mint is not signed by the owner of the deposit".to_string(),
+        })?;
+
+    let outputs = &mint.outputs;
+    outputs.check_data()?;
+
+    let mut total = 0u128;
+
+    if outputs.atoms.is_empty() {
+        return Err(Error::InvalidTransaction {
+            reason: "Mint has no outputs".to_string(),
+        });
+    }
+
+    for (i, atom) in outputs.atoms.iter().enumerate() {
+        if outputs.is_input(i) {
+            return Err(Error::InvalidTransaction {
+                reason: "Mints can not spend inputs".to_string(),
+            });
+        }
+
+        if outputs.asset_ids.get(atom.asset_id as usize) != Some(&deposit.asset_id) {
+            return Err(Error::InvalidAtom {
+                reason: format!("Atom {i} does not match the asset of the deposit"),
+            });
+        }
+
+        if atom.delegate != keypair.public_key {
+            return Err(Error::InvalidAtom {
+                reason: format!("Atom {i} belongs to another delegate"),
+            });
+        }
+
+        total += atom.amount as u128;
+    }
+
+    assets::check(outputs, &database.read()?.open_table(ASSETS)?, false)?;
+
+    let signatures = sign_outputs(outputs, &keypair);
+    let w = database.write()?;
+
+    {
+        let mut deposits = w.open_table(DEPOSITS)?;
+        let id = outputs.id();
+
+        if w.open_table(LOG_INDEX)?.get(id)?.is_some() {
+            return Err(Error::InvalidTransaction {
+                reason: "Mint has already been processed".to_string(),
+            });
+        }
+
+        let claimed = deposits.get(deposit.id)?.map(|v| v.value()).unwrap_or(0);
+        let available = deposit.amount.saturating_sub(claimed);
+
+        if total > available as u128 {
+            return Err(Error::InsufficientFunds {
+                asset_id: deposit.asset_id,
+                expected: total.min(u64::MAX as u128) as u64,
+                got: available,
+            });
+        }
+
+        deposits.insert(deposit.id, claimed + total as u64)?;
+
+        for atom in outputs.atoms.iter() {
+            let commitment = atom.commitment(&outputs.asset_ids);
+            liabilities::record_issued(&w, deposit.asset_id, commitment, atom.amount)?;
+        }
+
+        log::append(&w, id)?;
+    }
+
+    w.commit()?;
+
+    Ok(V0Response::Mint {
+        outputs: signatures,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Setup;
+
+    #[test]
+    fn test_mint_flow() {
+        let mut s = Setup::new();
+        let asset_id = s.asset();
+
+        let deposit = s.chain.lock(asset_id, 100, s.owner.public_key).unwrap();
+        let outputs = s.outputs(asset_id, &[60, 40]);
+        let mint = Mint::new(&mut s.rng, &s.owner.secret_key, deposit, outputs.clone());
+
+        assert!(matches!(
+            mint_v0(&mint, s.keypair, &s.chain, &s.database),
+            Err(Error::DepositNotConfirmed { .. })
+        ));
+
+        s.chain.advance(2).unwrap();
+
+        let signatures = match mint_v0(&mint, s.keypair, &s.chain, &s.database).unwrap() {
+            V0Response::Mint { outputs } => outputs,
+            r => panic!("Unexpected response: {r:?}"),
+        };
+
+        for (atom, signature) in outputs.atoms.iter().zip(signatures) {
+            let commitment = atom.commitment(&outputs.asset_ids);
+
+            assert!(signature
+                .0
+                .verify(&s.keypair.public_key, commitment.as_ref())
+                .unwrap());
+        }
+
+        assert!(mint_v0(&mint, s.keypair, &s.chain, &s.database).is_err());
+    }
+
+    #[test]
+    fn test_mint_over_deposit() {
+        let mut s = Setup::new();
+        let asset_id = s.asset();
+
+        let deposit = s.chain.lock(asset_id, 100, s.owner.public_key).unwrap();
+        s.chain.advance(2).unwrap();
+
+        let first = s.outputs(asset_id, &[70]);
+        let mint = Mint::new(&mut s.rng, &s.owner.secret_key, deposit, first);
+        mint_v0(&mint, s.keypair, &s.chain, &s.database).unwrap();
+
+        let second = s.outputs(asset_id, &[31]);
+        let mint = Mint::new(&mut s.rng, &s.owner.secret_key, deposit, second);
+
+        assert!(matches!(
+            mint_v0(&mint, s.keypair, &s.chain, &s.database),
+            Err(Error::InsufficientFunds {
+                expected: 31,
+                got: 30,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_mint_wrong_owner() {
+        let mut s = Setup::new();
+        let asset_id = s.asset();
+        let thief = Keypair::random(&mut s.rng);
+
+        let deposit = s.chain.lock(asset_id, 100, s.owner.public_key).unwrap();
+        s.chain.advance(2).unwrap();
+
+        let outputs = s.outputs(asset_id, &[100]);
+        let mint = Mint::new(&mut s.rng, &thief.secret_key, deposit, outputs);
+
+        assert!(matches!(
+            mint_v0(&mint, s.keypair, &s.chain, &s.database),
+            Err(Error::InvalidTransaction { .. })
+        ));
+    }
+}
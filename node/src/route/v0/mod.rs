@@ -1,4 +1,4 @@
-use std::sync::{Arc, Mutex};
+use std::{sync::Arc, time::Instant};
 
 use axum::{
     extract::State,
@@ -6,35 +6,92 @@ use axum::{
     routing::{get, post},
     Json, Router,
 };
-use color_eyre::eyre::Result;
+use metrics::{counter, histogram};
 use mugraph_core::{
     error::Error,
-    types::{Keypair, Request, Response, V0Request},
+    types::{FeeSchedule, Keypair, Request, Response, V0Request, V0Response},
 };
 
+mod assets;
+mod batch;
+mod bundle;
+mod check;
+mod cross;
+mod info;
+mod liabilities;
+mod log;
+mod mint;
+mod netting;
+mod restore;
 mod transaction;
+mod withdraw;
 
-use serde_json::json;
+pub use assets::*;
+pub use batch::*;
+pub use bundle::*;
+pub use check::*;
+pub use cross::*;
+pub use info::*;
+pub use liabilities::*;
+pub use log::*;
+pub use mint::*;
+pub use netting::*;
+pub use restore::*;
 pub use transaction::*;
+pub use withdraw::*;
 
-use crate::database::Database;
+use crate::{
+    cache::Cache, chain::ChainBackend, database::Database, peer::Peers, route::error_response,
+};
 
 #[derive(Clone)]
 pub struct Context {
-    keypair: Keypair,
-    database: Arc<Mutex<Database>>,
+    pub(crate) keypair: Keypair,
+    pub(crate) chain: Arc<dyn ChainBackend>,
+    pub(crate) fees: Arc<FeeSchedule>,
+    pub(crate) peers: Arc<Peers>,
+    pub(crate) database: Arc<Database>,
+    pub(crate) cache: Arc<Cache>,
 }
 
-pub fn router(keypair: Keypair) -> Result<Router, Error> {
-    let router = Router::new()
-        .route("/health", get(health))
-        .route("/rpc", post(rpc))
-        .with_state(Context {
-            database: Arc::new(Mutex::new(Database::setup("./db")?)),
+impl Context {
+    pub fn new(
+        keypair: Keypair,
+        chain: Arc<dyn ChainBackend>,
+        fees: FeeSchedule,
+        database: Database,
+    ) -> Self {
+        Self {
             keypair,
-        });
+            chain,
+            fees: Arc::new(fees),
+            peers: Arc::new(Peers::new()),
+            database: Arc::new(database),
+            cache: Arc::new(Cache::default()),
+        }
+    }
 
-    Ok(router)
+    /// Sets the delegates this one settles cross-delegate transactions with.
+    pub fn peers(mut self, peers: Peers) -> Self {
+        self.peers = Arc::new(peers);
+        self
+    }
+}
+
+pub fn router(context: Context) -> Router {
+    Router::new()
+        .route("/health", get(health))
+        .route("/rpc", post(rpc))
+        .route("/info", get(delegate_info))
+        .route("/keys", get(delegate_keys))
+        .route("/log/head", get(log_head))
+        .route("/log/inclusion/:id", get(log_inclusion))
+        .route("/log/consistency", get(log_consistency))
+        .route("/assets", get(assets_list))
+        .route("/assets/:id", get(assets_get))
+        .route("/liabilities", get(liabilities_report))
+        .route("/liabilities/:commitment", get(liabilities_proof))
+        .with_state(context)
 }
 
 pub async fn health() -> &'static str {
@@ -43,17 +100,130 @@ pub async fn health() -> &'static str {
 
 #[tracing::instrument(skip_all)]
 pub async fn rpc(
-    State(Context { keypair, database }): State<Context>,
+    State(context): State<Context>,
     Json(request): Json<Request>,
 ) -> impl IntoResponse {
+    let method = match &request {
+        Request::V0(request) => request.method(),
+    };
+    let start = Instant::now();
+
+    let result = blocking(move || match request {
+        Request::V0(request) => handle(request, &context),
+    })
+    .await;
+
+    let status = match &result {
+        Ok(_) => "ok",
+        Err(e) if e.is_client_error() => "rejected",
+        Err(_) => "failed",
+    };
+    counter!("mugraph.node.rpc.requests", "method" => method, "status" => status).increment(1);
+    histogram!("mugraph.node.rpc.duration_seconds", "method" => method)
+        .record(start.elapsed().as_secs_f64());
+
+    match result {
+        Ok(response) => Json(Response::V0(response)).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Runs `f` on the blocking pool, for handlers that are CPU bound or block
+/// on the database, so they do not stall the executor.
+pub async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T, Error> + Send + 'static,
+) -> Result<T, Error> {
+    tokio::task::spawn_blocking(f).await.unwrap_or_else(|e| {
+        Err(Error::ServerError {
+            reason: e.to_string(),
+        })
+    })
+}
+
+/// Processes a request on the current thread, which blocks on the database
+/// and on peers, so it must not run inside the async runtime.
+pub fn handle(request: V0Request, context: &Context) -> Result<V0Response, Error> {
+    let Context {
+        keypair,
+        chain,
+        fees,
+        peers,
+        database,
+        ..
+    } = context;
+    let keypair = *keypair;
+
     match request {
-        Request::V0(V0Request::Transaction(t)) => {
-            let mut db = database.lock().unwrap();
+        V0Request::Transaction(t) if t.atoms.iter().any(|a| a.delegate != keypair.public_key) => {
+            cross_v0(&t, keypair, fees, peers, database)
+        }
+        V0Request::Transaction(t) => transaction_v0(&t, keypair, fees, database),
+        V0Request::Mint(m) => mint_v0(&m, keypair, chain.as_ref(), database),
+        V0Request::Withdraw(w) => withdraw_v0(&w, keypair, fees, chain.as_ref(), database),
+        V0Request::Withdrawal { id } => withdrawal_v0(id, database),
+        V0Request::Refund(refund) => refund_v0(&refund, keypair, database),
+        V0Request::Check { notes } => check_v0(&notes, keypair, database),
+        V0Request::Batch { transactions } => batch_v0(&transactions, keypair, fees, database),
+        V0Request::Bundle { transactions } => bundle_v0(&transactions, keypair, fees, database),
+        V0Request::Prepare { proposal } => prepare_v0(&proposal, keypair, peers, database),
+        V0Request::Decide { outcome } => decide_v0(&outcome, keypair, database),
+        V0Request::Outcome { id } => outcome_v0(id, keypair, database),
+        V0Request::Netting { query } => {
+            netting_v0(&query, keypair, chain.as_ref(), peers, database)
+        }
+        V0Request::Net { statement } => {
+            net_v0(&statement, keypair, chain.as_ref(), peers, database)
+        }
+        V0Request::Restore {
+            transaction,
+            secret,
+        } => restore_v0(&transaction, &secret, keypair, database),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::to_bytes, http::StatusCode};
 
-            match transaction_v0(&t, keypair, &mut db) {
-                Ok(response) => Json(Response::V0(response)).into_response(),
-                Err(e) => Json(json!({ "error": e.to_string() })).into_response(),
+    use super::*;
+    use crate::testing::{spend, Setup};
+
+    async fn call(context: &Context, request: V0Request) -> (StatusCode, Response) {
+        let response = rpc(State(context.clone()), Json(request.into()))
+            .await
+            .into_response();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_rpc_errors() {
+        let mut s = Setup::new();
+        let asset_id = s.asset();
+        let notes = s.notes(asset_id, &[10]);
+        let context = Context::new(s.keypair, Arc::new(s.chain.clone()), s.fees, s.database);
+        let transaction = spend(&notes, &[(asset_id, 10)]);
+
+        let (status, response) = call(&context, V0Request::Transaction(transaction.clone())).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(matches!(response, Response::V0(_)));
+
+        let (status, response) = call(&context, V0Request::Transaction(transaction)).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(matches!(
+            response,
+            Response::Error {
+                code: 1002,
+                retryable: false,
+                error: mugraph_core::error::Error::AlreadySpent { .. },
+                ..
             }
-        }
+        ));
+
+        let (status, response) = call(&context, V0Request::Withdrawal { id: asset_id }).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(matches!(response, Response::Error { code: 1011, .. }));
     }
 }
@@ -0,0 +1,150 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use mugraph_core::{error::Error, types::*};
+use rand::thread_rng;
+
+use crate::{
+    chain::ChainBackend,
+    database::{Database, CROSS, NETTING},
+    peer::Peers,
+    settlement::{self, Netting},
+};
+
+/// Seconds a netting query is answered for after it was made, either way,
+/// to allow for clock drift between delegates.
+pub const QUERY_MAX_AGE: u64 = 300;
+
+/// Netting with the peer signing `query` as recorded by this delegate, for
+/// the peer to check against its own before proposing the next statement.
+#[inline]
+pub fn netting_v0(
+    query: &Signed<NettingQuery>,
+    keypair: Keypair,
+    chain: &dyn ChainBackend,
+    peers: &Peers,
+    database: &Database,
+) -> Result<V0Response, Error> {
+    let NettingQuery { peer, .. } = query.unverified()?;
+    let invalid = |reason: String| Error::InvalidStatement { reason };
+
+    if !peers.contains_key(&peer) {
+        return Err(invalid(format!(
+            "Delegate {peer} is not a peer of this delegate"
+        )));
+    }
+
+    let NettingQuery {
+        delegate,
+        timestamp,
+        ..
+    } = query
+        .verify(&peer)
+        .map_err(|_| invalid(format!("Netting query is not signed by {peer}")))?;
+
+    if delegate != keypair.public_key {
+        return Err(invalid(format!(
+            "Netting query from {peer} is for delegate {delegate}"
+        )));
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+
+    if now.abs_diff(timestamp) > QUERY_MAX_AGE {
+        return Err(invalid(format!(
+            "Netting query from {peer} was made at {timestamp}, too far from now"
+        )));
+    }
+
+    let Netting {
+        statement,
+        settlements,
+        ..
+    } = settlement::load(&database.read()?.open_table(NETTING)?, peer)?;
+
+    Ok(V0Response::Netting {
+        vault: chain.vault_address(),
+        statement: statement.map(Box::new),
+        settlements,
+    })
+}
+
+/// Countersigns a statement proposed by a peer, if it matches the records of
+/// this delegate. A statement with other balances is recorded as a dispute.
+pub fn net_v0(
+    proposal: &Signed<Statement>,
+    keypair: Keypair,
+    chain: &dyn ChainBackend,
+    peers: &Peers,
+    database: &Database,
+) -> Result<V0Response, Error> {
+    let peer = proposal.unverified()?.delegates[0];
+    let invalid = |reason: String| Error::InvalidStatement { reason };
+
+    if peer == keypair.public_key {
+        return Err(invalid(
+            "Statement must be proposed by the other delegate in it".to_string(),
+        ));
+    }
+
+    if !peers.contains_key(&peer) {
+        return Err(invalid(format!(
+            "Delegate {peer} is not a peer of this delegate"
+        )));
+    }
+
+    let statement = &proposal
+        .verify(&peer)
+        .map_err(|_| invalid(format!("Statement is not signed by {peer}")))?;
+
+    if statement.delegates != settlement::delegates(peer, keypair.public_key) {
+        return Err(invalid(
+            "Statement must be proposed by the other delegate in it".to_string(),
+        ));
+    }
+
+    if statement.vaults[1] != chain.vault_address() {
+        return Err(invalid(
+            "Statement does not pay settlements to the vault of this delegate".to_string(),
+        ));
+    }
+
+    let w = database.write()?;
+    let netting = settlement::load(&w.open_table(NETTING)?, peer)?;
+    let balances = settlement::check(statement, &netting, chain, &w.open_table(CROSS)?)?;
+
+    if balances != statement.balances {
+        let ours = Signed::new(
+            &mut thread_rng(),
+            &keypair.secret_key,
+            &Statement {
+                balances,
+                ..statement.clone()
+            },
+        )?;
+
+        settlement::record_dispute(
+            &w,
+            &Dispute::Mismatch {
+                ours,
+                theirs: proposal.clone(),
+            },
+        )?;
+        w.commit()?;
+
+        return Err(invalid(format!(
+            "Balances of statement {} do not match the ones of this delegate",
+            statement.sequence
+        )));
+    }
+
+    let signature = proposal
+        .countersign(&mut thread_rng(), &keypair.secret_key)
+        .signature;
+
+    settlement::store(&w, peer, NettingStatement::new(proposal, signature)?)?;
+    w.commit()?;
+
+    Ok(V0Response::Netted { signature })
+}
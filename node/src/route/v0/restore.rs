@@ -0,0 +1,138 @@
+use mugraph_core::{
+    error::Error,
+    types::{Hash, Keypair, Transaction, V0Response},
+};
+
+use super::sign_outputs_where;
+use crate::database::{Database, LOG_INDEX};
+
+/// Signs again the outputs of this delegate in a transaction it already
+/// processed, so a client that lost the response can recover its notes
+/// without spending the inputs twice.
+///
+/// Signatures are deterministic, so these are the same ones returned when the
+/// transaction was processed. Anyone can rebuild a transaction they saw, so
+/// the caller must also prove it owns the outputs with the secret they are
+/// derived from, see [`Transaction::output_nonce`], which never appears in
+/// the transaction.
+#[inline]
+pub fn restore_v0(
+    transaction: &Transaction,
+    secret: &Hash,
+    keypair: Keypair,
+    database: &Database,
+) -> Result<V0Response, Error> {
+    let id = transaction.id();
+    let derived = transaction
+        .atoms
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| transaction.is_output(*i))
+        .enumerate()
+        .all(|(index, (_, atom))| {
+            atom.delegate != keypair.public_key
+                || atom.nonce == Transaction::output_nonce(secret, index)
+        });
+
+    if !derived {
+        return Err(Error::InvalidTransaction {
+            reason: format!("Outputs of transaction {id} are not derived from the secret"),
+        });
+    }
+
+    if database.read()?.open_table(LOG_INDEX)?.get(id)?.is_none() {
+        return Err(Error::NotFound {
+            reason: format!("Transaction {id} has not been processed by this delegate"),
+        });
+    }
+
+    Ok(V0Response::Restored {
+        outputs: sign_outputs_where(transaction, &keypair, |atom| {
+            atom.delegate == keypair.public_key
+        }),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use mugraph_core::types::Mint;
+
+    use super::*;
+    use crate::{
+        chain::ChainBackend,
+        testing::{spend, Setup, SECRET},
+        v0::{mint_v0, transaction_v0},
+    };
+
+    #[test]
+    fn test_restore() {
+        let mut s = Setup::new();
+        let asset_id = s.asset();
+        let notes = s.notes(asset_id, &[10, 20]);
+        let transaction = spend(&notes, &[(asset_id, 30)]);
+
+        assert!(matches!(
+            restore_v0(&transaction, &SECRET, s.keypair, &s.database),
+            Err(Error::NotFound { .. })
+        ));
+
+        let outputs = match transaction_v0(&transaction, s.keypair, &s.fees, &s.database) {
+            Ok(V0Response::Transaction { outputs }) => outputs,
+            r => panic!("Unexpected response: {r:?}"),
+        };
+
+        assert!(matches!(
+            transaction_v0(&transaction, s.keypair, &s.fees, &s.database),
+            Err(Error::AlreadySpent { .. })
+        ));
+        assert!(matches!(
+            restore_v0(&transaction, &SECRET, s.keypair, &s.database),
+            Ok(V0Response::Restored { outputs: restored }) if restored == outputs
+        ));
+    }
+
+    #[test]
+    fn test_restore_wrong_secret() {
+        let mut s = Setup::new();
+        let asset_id = s.asset();
+        let notes = s.notes(asset_id, &[10, 20]);
+        let transaction = spend(&notes, &[(asset_id, 30)]);
+
+        assert!(matches!(
+            transaction_v0(&transaction, s.keypair, &s.fees, &s.database),
+            Ok(V0Response::Transaction { .. })
+        ));
+
+        // Knowing the transaction is not enough to get its outputs signed.
+        assert!(matches!(
+            restore_v0(&transaction, &Hash([8; 32]), s.keypair, &s.database),
+            Err(Error::InvalidTransaction { .. })
+        ));
+    }
+
+    #[test]
+    fn test_restore_mint() {
+        let mut s = Setup::new();
+        let asset_id = s.asset();
+
+        let deposit = s.chain.lock(asset_id, 100, s.owner.public_key).unwrap();
+        s.chain.advance(s.chain.min_confirmations()).unwrap();
+        let secret = Hash::random(&mut s.rng);
+        let outputs = s.outputs_from(&secret, asset_id, &[60, 40]);
+        let mint = Mint::new(&mut s.rng, &s.owner.secret_key, deposit, outputs.clone());
+
+        let signatures = match mint_v0(&mint, s.keypair, &s.chain, &s.database) {
+            Ok(V0Response::Mint { outputs }) => outputs,
+            r => panic!("Unexpected response: {r:?}"),
+        };
+
+        assert!(matches!(
+            restore_v0(&outputs, &secret, s.keypair, &s.database),
+            Ok(V0Response::Restored { outputs: restored }) if restored == signatures
+        ));
+        assert!(matches!(
+            restore_v0(&outputs, &SECRET, s.keypair, &s.database),
+            Err(Error::InvalidTransaction { .. })
+        ));
+    }
+}
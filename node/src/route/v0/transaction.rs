@@ -1,77 +1,409 @@
+use std::collections::HashSet;
+
 use color_eyre::eyre::Result;
 use mugraph_core::{
     crypto,
     error::Error,
-    types::{Keypair, Signature, Transaction, V0Response},
+    record::Record,
+    types::{
+        Asset, Atom, Blinded, FeeSchedule, Hash, Keypair, OutputSignature, Signature, Transaction,
+        V0Response,
+    },
 };
+use rand::thread_rng;
+use redb::ReadableTable;
 
-use crate::database::{Database, NOTES};
+use crate::{
+    assets,
+    database::{Database, Write, ASSETS, NOTES, PENDING, SPENT},
+    fees, liabilities, log,
+};
 
+/// Processes a transaction.
+///
+/// Everything that does not depend on the spent set, including all curve
+/// operations, runs before the write transaction is opened, so concurrent
+/// requests only wait on each other to check and insert their inputs.
 #[inline]
 pub fn transaction_v0(
     transaction: &Transaction,
     keypair: Keypair,
-    database: &mut Database,
+    fees: &FeeSchedule,
+    database: &Database,
 ) -> Result<V0Response, Error> {
-    let mut outputs = Vec::with_capacity(transaction.input_mask.count_zeros() as usize);
-    let mut consumed_inputs = Vec::with_capacity(transaction.input_mask.count_ones() as usize);
+    let paid = validate(
+        transaction,
+        &keypair,
+        fees,
+        &database.read()?.open_table(ASSETS)?,
+    )?;
+
+    let consumed_inputs = check_inputs(transaction, &keypair)?;
+    let outputs = sign_outputs(transaction, &keypair);
 
     let w = database.write()?;
-    let read = database.read()?.open_table(NOTES)?;
+    apply(&w, transaction, consumed_inputs, &paid)?;
+    w.commit()?;
+
+    Ok(V0Response::Transaction { outputs })
+}
+
+/// Checks everything about a transaction that does not depend on the spent
+/// set or the signatures of its inputs, returning the fees it pays.
+pub fn validate(
+    transaction: &Transaction,
+    keypair: &Keypair,
+    fees: &FeeSchedule,
+    assets: &impl ReadableTable<Hash, Record<Asset>>,
+) -> Result<Vec<(Hash, u128)>, Error> {
+    check_delegate(transaction, keypair)?;
+    let paid = transaction.verify_with_fees(fees)?;
+    assets::check(transaction, assets, false)?;
+
+    Ok(paid)
+}
 
+/// Checks that every atom of the transaction belongs to this delegate. Those
+/// that do not are settled with their delegates, see [`super::cross_v0`].
+pub fn check_delegate(transaction: &Transaction, keypair: &Keypair) -> Result<(), Error> {
+    match transaction
+        .atoms
+        .iter()
+        .position(|atom| atom.delegate != keypair.public_key)
     {
-        for (i, atom) in transaction.atoms.iter().enumerate() {
-            if transaction.is_output(i) {
-                let sig = crypto::sign_blinded(
-                    &keypair.secret_key,
-                    &crypto::hash_to_curve(atom.commitment(&transaction.asset_ids).as_ref()),
-                );
+        Some(i) => Err(Error::InvalidAtom {
+            reason: format!(
+                "Atom {i} belongs to delegate {}",
+                transaction.atoms[i].delegate
+            ),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Checks that every input of the transaction is signed by this delegate,
+/// returning the signatures that will be consumed by it.
+pub fn check_inputs(transaction: &Transaction, keypair: &Keypair) -> Result<Vec<Signature>, Error> {
+    check_inputs_batch(&[transaction], keypair).remove(0)
+}
+
+/// Checks the inputs of many transactions, returning one result for each.
+///
+/// All signatures are verified together, and only checked one by one when
+/// some of them are invalid.
+pub fn check_inputs_batch(
+    transactions: &[&Transaction],
+    keypair: &Keypair,
+) -> Vec<Result<Vec<Signature>, Error>> {
+    let mut results = transactions
+        .iter()
+        .map(|transaction| inputs(transaction, |_| true))
+        .collect::<Vec<_>>();
+
+    let owners = results
+        .iter()
+        .enumerate()
+        .filter_map(|(t, inputs)| Some((t, inputs.as_ref().ok()?)))
+        .flat_map(|(t, inputs)| inputs.iter().map(move |input| (t, input)))
+        .collect::<Vec<_>>();
+    let items = owners
+        .iter()
+        .map(|(_, (_, commitment, signature))| (&commitment.as_ref()[..], *signature))
+        .collect::<Vec<_>>();
 
-                outputs.push(sig);
+    let invalid = crypto::verify_batch(&mut thread_rng(), &keypair.secret_key, &items)
+        .into_iter()
+        .map(|i| (owners[i].0, owners[i].1 .0, owners[i].1 .2))
+        .collect::<Vec<_>>();
+
+    for (t, atom, signature) in invalid {
+        if results[t].is_ok() {
+            results[t] = Err(Error::InvalidSignature {
+                reason: format!("Atom {atom} is not signed by this delegate"),
+                signature,
+            });
+        }
+    }
+
+    results
+        .into_iter()
+        .map(|inputs| Ok(inputs?.into_iter().map(|(_, _, s)| s).collect()))
+        .collect()
+}
 
-                continue;
+/// Checks the inputs of the transaction that belong to this delegate,
+/// skipping the ones of other delegates in a cross-delegate transaction.
+pub fn check_own_inputs(
+    transaction: &Transaction,
+    keypair: &Keypair,
+) -> Result<Vec<Signature>, Error> {
+    let inputs = inputs(transaction, |atom| atom.delegate == keypair.public_key)?;
+    let items = inputs
+        .iter()
+        .map(|(_, commitment, signature)| (&commitment.as_ref()[..], *signature))
+        .collect::<Vec<_>>();
+
+    match crypto::verify_batch(&mut thread_rng(), &keypair.secret_key, &items).first() {
+        Some(&i) => Err(Error::InvalidSignature {
+            reason: format!("Atom {} is not signed by this delegate", inputs[i].0),
+            signature: inputs[i].2,
+        }),
+        None => Ok(inputs.into_iter().map(|(_, _, s)| s).collect()),
+    }
+}
+
+/// Lists the inputs of the transaction selected by `filter`, with the index
+/// of their atom and their commitment, once the locked ones are checked to be
+/// signed by their lock.
+fn inputs(
+    transaction: &Transaction,
+    filter: impl Fn(&Atom) -> bool,
+) -> Result<Vec<(usize, Hash, Signature)>, Error> {
+    transaction.verify_witnesses(&filter)?;
+
+    let mut inputs = Vec::with_capacity(transaction.input_mask.count_ones() as usize);
+
+    for (i, atom) in transaction.atoms.iter().enumerate() {
+        if transaction.is_output(i) || !filter(atom) {
+            continue;
+        }
+
+        let signature = match atom
+            .signature
+            .map(|s| transaction.signatures.get(s as usize))
+        {
+            Some(Some(s)) if *s == Signature::zero() => {
+                return Err(Error::InvalidSignature {
+                    reason: "Signature can not be empty".to_string(),
+                    signature: Signature::zero(),
+                });
+            }
+            Some(Some(s)) => *s,
+            Some(None) => {
+                return Err(Error::InvalidAtom {
+                    reason: format!("Atom {i} points to a missing signature"),
+                });
             }
+            None => {
+                return Err(Error::InvalidAtom {
+                    reason: format!("Atom {i} is an input but it is not signed."),
+                });
+            }
+        };
+
+        inputs.push((i, atom.commitment(&transaction.asset_ids), signature));
+    }
+
+    Ok(inputs)
+}
+
+/// Blind signs every output of the transaction.
+pub fn sign_outputs(transaction: &Transaction, keypair: &Keypair) -> Vec<Blinded<OutputSignature>> {
+    sign_outputs_where(transaction, keypair, |_| true)
+}
+
+/// Signs the outputs of the transaction selected by `filter`, in order, with
+/// a proof for each that the wallet can check.
+pub fn sign_outputs_where(
+    transaction: &Transaction,
+    keypair: &Keypair,
+    filter: impl Fn(&Atom) -> bool,
+) -> Vec<Blinded<OutputSignature>> {
+    transaction
+        .atoms
+        .iter()
+        .enumerate()
+        .filter(|(i, atom)| transaction.is_output(*i) && filter(atom))
+        .map(|(_, atom)| {
+            let commitment = atom.commitment(&transaction.asset_ids);
+
+            Blinded(OutputSignature::new(
+                &keypair.secret_key,
+                commitment.as_ref(),
+            ))
+        })
+        .collect()
+}
+
+/// Finds an input that is already spent, or that appears more than once in
+/// `consumed_inputs`, so applying transactions can not fail halfway through.
+pub fn spent_input(w: &Write, consumed_inputs: &[Signature]) -> Result<Option<Signature>, Error> {
+    let table = w.open_table(NOTES)?;
+    let mut seen = HashSet::with_capacity(consumed_inputs.len());
+
+    for input in consumed_inputs.iter() {
+        if !seen.insert(input) || table.get(input)?.is_some() {
+            return Ok(Some(*input));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Marks the inputs of an already validated transaction as spent, failing if
+/// any of them already was, and records it in the log, the liabilities
+/// counters and the fee ledger.
+///
+/// Inputs reserved by a cross-delegate transaction count as spent, and are
+/// checked before anything is written.
+pub fn apply(
+    w: &Write,
+    transaction: &Transaction,
+    consumed_inputs: Vec<Signature>,
+    fees: &[(Hash, u128)],
+) -> Result<(), Error> {
+    {
+        let pending = w.open_table(PENDING)?;
+        let inputs = transaction
+            .atoms
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| transaction.is_input(*i));
+
+        for ((_, atom), input) in inputs.zip(consumed_inputs.iter()) {
+            let y = crypto::y_point(&atom.commitment(&transaction.asset_ids));
 
-            let signature = match atom.signature {
-                Some(s) if transaction.signatures[s as usize] == Signature::zero() => {
-                    return Err(Error::InvalidSignature {
-                        reason: "Signature can not be empty".to_string(),
-                        signature: Signature::zero(),
-                    });
-                }
-                Some(s) => transaction.signatures[s as usize],
-                None => {
-                    return Err(Error::InvalidAtom {
-                        reason: "Atom {} is an input but it is not signed.".into(),
-                    });
-                }
-            };
-
-            crypto::verify(&keypair.public_key, atom.nonce.as_ref(), signature)?;
-
-            match read.get(signature) {
-                Ok(Some(_)) => {
-                    return Err(Error::AlreadySpent { signature });
-                }
-                Ok(None) => {
-                    consumed_inputs.push(signature);
-                }
-                Err(e) => {
-                    return Err(Error::ServerError {
-                        reason: e.to_string(),
-                    });
-                }
+            if pending.get(y)?.is_some() {
+                return Err(Error::AlreadySpent { signature: *input });
             }
         }
+    }
+
+    mark_spent(w, consumed_inputs)?;
+    log::append(w, transaction.id())?;
+    fees::record(w, fees)?;
+    record_atoms(w, transaction, |_| true)
+}
 
-        let mut table = w.open_table(NOTES)?;
+/// Inserts the signatures of consumed inputs into the spent set, failing if
+/// any of them already was there.
+pub fn mark_spent(w: &Write, consumed_inputs: Vec<Signature>) -> Result<(), Error> {
+    let mut table = w.open_table(NOTES)?;
 
-        for input in consumed_inputs.into_iter() {
-            table.insert(input, true)?;
+    for input in consumed_inputs.into_iter() {
+        if table.insert(input, true)?.is_some() {
+            return Err(Error::AlreadySpent { signature: input });
         }
     }
 
-    w.commit()?;
+    Ok(())
+}
 
-    Ok(V0Response::Transaction { outputs })
+/// Records the atoms selected by `filter` in the set of spent Y points and
+/// the liabilities counters.
+pub fn record_atoms(
+    w: &Write,
+    transaction: &Transaction,
+    filter: impl Fn(&Atom) -> bool,
+) -> Result<(), Error> {
+    let mut spent = w.open_table(SPENT)?;
+
+    for (i, atom) in transaction.atoms.iter().enumerate() {
+        if !filter(atom) {
+            continue;
+        }
+
+        let asset_id = transaction.asset_ids[atom.asset_id as usize];
+        let commitment = atom.commitment(&transaction.asset_ids);
+
+        if transaction.is_input(i) {
+            spent.insert(crypto::y_point(&commitment), true)?;
+            liabilities::record_burned(w, asset_id, atom.amount)?;
+            continue;
+        }
+
+        liabilities::record_issued(w, asset_id, commitment, atom.amount)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::testing::{spend, Setup};
+
+    #[test]
+    fn test_forged_input() {
+        let mut s = Setup::new();
+        let asset_id = s.asset();
+        let notes = s.notes(asset_id, &[10, 20]);
+
+        let mut transaction = spend(&notes, &[(asset_id, 30)]);
+        transaction.atoms[1].amount = 25;
+        transaction.atoms[2].amount = 35;
+
+        assert!(matches!(
+            transaction_v0(&transaction, s.keypair, &s.fees, &s.database),
+            Err(Error::InvalidSignature { reason, signature })
+                if reason.contains("Atom 1") && signature == notes[1].signature
+        ));
+    }
+
+    #[test]
+    fn test_concurrent_double_spend() {
+        let mut s = Setup::new();
+        let asset_id = s.asset();
+        let notes = s.notes(asset_id, &[10]);
+        let transaction = spend(&notes, &[(asset_id, 10)]);
+
+        let results = thread::scope(|scope| {
+            (0..8)
+                .map(|_| {
+                    scope.spawn(|| transaction_v0(&transaction, s.keypair, &s.fees, &s.database))
+                })
+                .collect::<Vec<_>>()
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+        assert!(results
+            .iter()
+            .filter_map(|r| r.as_ref().err())
+            .all(|e| matches!(e, Error::AlreadySpent { .. })));
+    }
+
+    #[test]
+    fn test_locked_input() {
+        let mut s = Setup::new();
+        let asset_id = s.asset();
+        let notes = s.notes(asset_id, &[10]);
+        let owner = Keypair::random(&mut s.rng);
+
+        let mut transaction = spend(&notes, &[(asset_id, 10)]);
+        transaction.atoms[1].lock = Some(owner.public_key);
+        let locked = match transaction_v0(&transaction, s.keypair, &s.fees, &s.database) {
+            Ok(V0Response::Transaction { outputs }) => transaction.output_notes(&outputs).unwrap(),
+            r => panic!("Unexpected response: {r:?}"),
+        };
+        assert_eq!(locked[0].lock, Some(owner.public_key));
+
+        let mut transaction = spend(&locked, &[(asset_id, 10)]);
+        assert!(matches!(
+            transaction_v0(&transaction, s.keypair, &s.fees, &s.database),
+            Err(Error::InvalidTransaction { .. })
+        ));
+
+        transaction
+            .sign_witnesses(&mut s.rng, &s.owner.secret_key)
+            .unwrap_err();
+        transaction.witnesses = vec![crypto::schnorr::sign(
+            &mut s.rng,
+            &s.owner.secret_key,
+            &transaction.witness_message(),
+        )];
+        assert!(matches!(
+            transaction_v0(&transaction, s.keypair, &s.fees, &s.database),
+            Err(Error::InvalidAtom { reason }) if reason.contains("Atom 0")
+        ));
+
+        transaction
+            .sign_witnesses(&mut s.rng, &owner.secret_key)
+            .unwrap();
+        assert!(transaction_v0(&transaction, s.keypair, &s.fees, &s.database).is_ok());
+    }
 }
@@ -0,0 +1,170 @@
+use mugraph_core::{
+    error::Error,
+    types::{
+        FeeSchedule, Hash, Keypair, Refund, Transaction, V0Response, Withdraw, Withdrawal,
+        WithdrawalStatus,
+    },
+};
+use redb::ReadableTable;
+
+use super::{apply, check_delegate, check_inputs, sign_outputs};
+use crate::{
+    assets,
+    chain::ChainBackend,
+    database::{Database, ASSETS, WITHDRAWALS},
+    withdrawal::Entry,
+};
+
+#[inline]
+pub fn withdraw_v0(
+    withdraw: &Withdraw,
+    keypair: Keypair,
+    fees: &FeeSchedule,
+    chain: &dyn ChainBackend,
+    database: &Database,
+) -> Result<V0Response, Error> {
+    withdraw.transaction.check_data()?;
+    withdraw.refund.check_data()?;
+    chain.check_address(&withdraw.address)?;
+    check_delegate(&withdraw.transaction, &keypair)?;
+
+    let payouts = withdraw.payouts(fees)?;
+    let paid = withdraw.fees(fees)?;
+    let id = withdraw.id();
+
+    check_refund(&withdraw.refund, &payouts, &keypair)?;
+
+    {
+        let table = database.read()?.open_table(ASSETS)?;
+        assets::check(&withdraw.transaction, &table, true)?;
+        assets::check(&withdraw.refund, &table, true)?;
+    }
+
+    let consumed_inputs = check_inputs(&withdraw.transaction, &keypair)?;
+    let outputs = sign_outputs(&withdraw.transaction, &keypair);
+
+    let w = database.write()?;
+    apply(&w, &withdraw.transaction, consumed_inputs, &paid)?;
+
+    w.open_table(WITHDRAWALS)?.insert(
+        id,
+        Ok(Entry {
+            withdrawal: Withdrawal {
+                id,
+                address: withdraw.address.clone(),
+                payouts,
+                status: WithdrawalStatus::Pending,
+            },
+            owner: withdraw.owner,
+            refund: withdraw.refund.clone(),
+        }),
+    )?;
+
+    w.commit()?;
+
+    Ok(V0Response::Withdraw { id, outputs })
+}
+
+#[inline]
+pub fn withdrawal_v0(id: Hash, database: &Database) -> Result<V0Response, Error> {
+    match database.read()?.open_table(WITHDRAWALS)?.get(id)? {
+        Some(entry) => Ok(V0Response::Withdrawal(entry.value()?.withdrawal)),
+        None => Err(Error::NotFound {
+            reason: format!("Withdrawal {id} does not exist"),
+        }),
+    }
+}
+
+#[inline]
+pub fn refund_v0(
+    refund: &Refund,
+    keypair: Keypair,
+    database: &Database,
+) -> Result<V0Response, Error> {
+    let id = refund.id;
+    let w = database.write()?;
+
+    let outputs = {
+        let mut table = w.open_table(WITHDRAWALS)?;
+
+        let mut entry = match table.get(id)? {
+            Some(entry) => entry.value()?,
+            None => {
+                return Err(Error::NotFound {
+                    reason: format!("Withdrawal {id} does not exist"),
+                })
+            }
+        };
+
+        if refund.verify(&entry.owner).is_err() {
+            return Err(Error::InvalidTransaction {
+                reason: format!("Refund of {id} is not signed by the owner of the withdrawal"),
+            });
+        }
+
+        if !matches!(entry.withdrawal.status, WithdrawalStatus::Failed { .. }) {
+            return Err(Error::InvalidTransaction {
+                reason: format!(
+                    "Only failed withdrawals can be refunded, but {id} is {:?}",
+                    entry.withdrawal.status
+                ),
+            });
+        }
+
+        apply(&w, &entry.refund, vec![], &[])?;
+
+        let outputs = sign_outputs(&entry.refund, &keypair);
+        entry.withdrawal.status = WithdrawalStatus::Refunded;
+        table.insert(id, Ok(entry))?;
+
+        outputs
+    };
+
+    w.commit()?;
+
+    Ok(V0Response::Refund { outputs })
+}
+
+/// Checks that the refund only has outputs, adding up to the payouts.
+fn check_refund(
+    refund: &Transaction,
+    payouts: &[(Hash, u64)],
+    keypair: &Keypair,
+) -> Result<(), Error> {
+    if !refund.input_mask.is_empty() {
+        return Err(Error::InvalidTransaction {
+            reason: "Refunds can not spend inputs".to_string(),
+        });
+    }
+
+    if refund
+        .atoms
+        .iter()
+        .any(|atom| atom.delegate != keypair.public_key)
+    {
+        return Err(Error::InvalidAtom {
+            reason: "Refund outputs must belong to this delegate".to_string(),
+        });
+    }
+
+    let (_, post) = refund.balances()?;
+    let total = post.iter().sum::<u128>();
+    let expected = payouts.iter().map(|(_, a)| *a as u128).sum::<u128>();
+
+    let matches = payouts.iter().all(|(asset_id, amount)| {
+        refund
+            .asset_ids
+            .iter()
+            .position(|a| a == asset_id)
+            .map(|i| post[i])
+            == Some(*amount as u128)
+    });
+
+    if !matches || total != expected {
+        return Err(Error::InvalidTransaction {
+            reason: "Refund outputs do not match the payouts".to_string(),
+        });
+    }
+
+    Ok(())
+}
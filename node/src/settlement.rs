@@ -0,0 +1,940 @@
+//! Netting and settlement of the value cross-delegate transactions move
+//! between the vaults of delegates.
+//!
+//! Every committed cross-delegate transaction leaves some delegates holding
+//! value on the L1 that backs notes issued by others, see [`Obligation`].
+//! Each delegate records the transactions it has with every peer, and
+//! periodically the one with the lowest key proposes a [`Statement`] netting
+//! them, which the other checks against its own records and countersigns.
+//! The delegate left owing value then pays it off with a [`Settlement`] to
+//! the vault named in that statement.
+//!
+//! Statements form a chain and both delegates keep the last one they signed,
+//! so signing two different statements for the same point of the chain, or
+//! computing different balances over the same transactions, is caught and
+//! recorded as a [`Dispute`].
+
+use std::{
+    collections::BTreeSet,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use mugraph_core::{error::Error, types::*};
+use rand::thread_rng;
+use redb::ReadableTable;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{
+    chain::{ChainBackend, Payout, PayoutStatus},
+    database::{Database, Record, Write, CROSS, DISPUTES, NETTING, SETTLEMENTS},
+    peer::Peer,
+    v0::Context,
+};
+
+pub const NETTING_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Netting with a peer, as recorded by this delegate.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Netting {
+    /// Committed cross-delegate transactions with the peer, not netted yet.
+    pub transactions: Vec<Hash>,
+    /// Settlements this delegate paid the peer, confirmed but not netted yet.
+    pub settlements: Vec<Settlement>,
+    /// L1 transactions of every settlement netted so far, so the same payment
+    /// is never netted twice, whatever settlement it is claimed for.
+    pub settled: Vec<Hash>,
+    /// Last statement signed by both delegates.
+    pub statement: Option<NettingStatement>,
+}
+
+/// Loads the netting with `peer`, which is empty until they have a
+/// cross-delegate transaction.
+pub fn load(
+    table: &impl ReadableTable<PublicKey, Record<Netting>>,
+    peer: PublicKey,
+) -> Result<Netting, Error> {
+    Ok(table
+        .get(peer)?
+        .map(|v| v.value())
+        .transpose()?
+        .unwrap_or_default())
+}
+
+/// Records the peers a committed cross-delegate transaction left obligations
+/// with, so it is netted with them.
+#[tracing::instrument(skip_all)]
+pub fn record(w: &Write, transaction: &Transaction, delegate: PublicKey) -> Result<(), Error> {
+    let id = transaction.id();
+    let peers = Obligation::of(transaction)
+        .into_iter()
+        .filter_map(|o| match o {
+            Obligation {
+                debtor, creditor, ..
+            } if debtor == delegate => Some(creditor),
+            Obligation {
+                debtor, creditor, ..
+            } if creditor == delegate => Some(debtor),
+            _ => None,
+        })
+        .collect::<BTreeSet<_>>();
+
+    let mut table = w.open_table(NETTING)?;
+
+    for peer in peers {
+        let mut netting = load(&table, peer)?;
+
+        if !netting.transactions.contains(&id) {
+            netting.transactions.push(id);
+            table.insert(peer, Ok(netting))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The two delegates of a statement between `a` and `b`, in order.
+pub fn delegates(a: PublicKey, b: PublicKey) -> [PublicKey; 2] {
+    match a < b {
+        true => [a, b],
+        false => [b, a],
+    }
+}
+
+/// Checks that `statement` follows the last one in `netting` and that
+/// everything it nets is known to this delegate, returning the balances it
+/// should have.
+pub fn check(
+    statement: &Statement,
+    netting: &Netting,
+    chain: &dyn ChainBackend,
+    cross: &impl ReadableTable<Hash, Record<CrossTransaction>>,
+) -> Result<Vec<(Hash, i128)>, Error> {
+    let invalid = |reason: String| Error::InvalidStatement { reason };
+    let (sequence, previous, balances) = match &netting.statement {
+        Some(last) => (
+            last.statement.sequence + 1,
+            last.statement.id(),
+            last.statement.balances.as_slice(),
+        ),
+        None => (0, Hash::default(), [].as_slice()),
+    };
+
+    if statement.sequence != sequence || statement.previous != previous {
+        return Err(invalid(format!(
+            "Statement {} does not follow {previous}",
+            statement.sequence
+        )));
+    }
+
+    let mut obligations = vec![];
+
+    for id in &statement.transactions {
+        let entry = cross
+            .get(id)?
+            .map(|v| v.value())
+            .transpose()?
+            .filter(|_| netting.transactions.contains(id))
+            .ok_or_else(|| invalid(format!("Transaction {id} is not committed here yet")))?;
+
+        obligations.extend(Obligation::of(&entry.transaction));
+    }
+
+    let mut paid = BTreeSet::new();
+
+    for settlement in &statement.settlements {
+        let tx = check_settlement(settlement, statement, netting, chain)?;
+
+        if !paid.insert(tx) {
+            return Err(invalid(format!(
+                "Settlement {} pays with {tx}, like another one in the statement",
+                settlement.id
+            )));
+        }
+    }
+
+    Ok(Statement::apply(
+        &statement.delegates,
+        balances,
+        &obligations,
+        &statement.settlements,
+    ))
+}
+
+/// Checks that a settlement between the delegates of `statement` was paid to
+/// the vault of the creditor and confirmed on the L1, with an L1 transaction
+/// sending at least its assets there that was not netted before, returning
+/// that transaction.
+fn check_settlement(
+    settlement: &Settlement,
+    statement: &Statement,
+    netting: &Netting,
+    chain: &dyn ChainBackend,
+) -> Result<Hash, Error> {
+    let invalid = |reason: &str| Error::InvalidStatement {
+        reason: format!("Settlement {}: {reason}", settlement.id),
+    };
+
+    let creditor = statement
+        .position(&settlement.creditor)
+        .filter(|_| statement.position(&settlement.debtor).is_some())
+        .filter(|_| settlement.debtor != settlement.creditor)
+        .ok_or_else(|| invalid("not between the delegates of the statement"))?;
+
+    if settlement.address != statement.vaults[creditor] {
+        return Err(invalid("not paid to the vault of the creditor"));
+    }
+
+    let tx = match settlement.status {
+        SettlementStatus::Confirmed { tx }
+            if chain.payout_status(tx)? == PayoutStatus::Confirmed =>
+        {
+            tx
+        }
+        _ => return Err(invalid("not confirmed on the L1")),
+    };
+
+    if netting.settled.contains(&tx) {
+        return Err(invalid("already netted"));
+    }
+
+    let outputs = chain.outputs(tx, &settlement.address)?;
+    let covered = settlement.assets.iter().all(|(asset_id, amount)| {
+        outputs
+            .iter()
+            .filter(|(a, _)| a == asset_id)
+            .map(|(_, paid)| *paid as u128)
+            .sum::<u128>()
+            >= *amount as u128
+    });
+
+    if !covered {
+        return Err(invalid("claims more than the L1 transaction paid"));
+    }
+
+    Ok(tx)
+}
+
+/// Stores a statement signed by both delegates as the last one with `peer`,
+/// dropping what it netted from what is left to net.
+pub fn store(w: &Write, peer: PublicKey, statement: NettingStatement) -> Result<(), Error> {
+    let mut table = w.open_table(NETTING)?;
+    let mut netting = load(&table, peer)?;
+    let netted = &statement.statement;
+
+    netting
+        .transactions
+        .retain(|id| !netted.transactions.contains(id));
+    netting
+        .settlements
+        .retain(|s| !netted.settlements.iter().any(|n| n.id == s.id));
+    netting
+        .settled
+        .extend(netted.settlements.iter().filter_map(|s| match s.status {
+            SettlementStatus::Confirmed { tx } => Some(tx),
+            _ => None,
+        }));
+    netting.statement = Some(statement);
+
+    table.insert(peer, Ok(netting))?;
+
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+pub fn record_dispute(w: &Write, dispute: &Dispute) -> Result<(), Error> {
+    w.open_table(DISPUTES)?
+        .insert(dispute.id(), Ok(dispute.clone()))?;
+
+    Ok(())
+}
+
+/// Every dispute recorded with peers.
+pub fn disputes(database: &Database) -> Result<Vec<Dispute>, Error> {
+    database
+        .read()?
+        .open_table(DISPUTES)?
+        .iter()?
+        .map(|entry| entry?.1.value())
+        .collect()
+}
+
+/// Proposes a statement to `peer` netting everything since the last one,
+/// returning it once countersigned.
+///
+/// Only the delegate with the lowest key proposes, so two statements are
+/// never proposed for the same point of the chain. Returns `None` for the
+/// other one, or when there is nothing to net.
+#[tracing::instrument(skip_all, fields(%peer))]
+pub fn net(
+    keypair: Keypair,
+    chain: &dyn ChainBackend,
+    peer: PublicKey,
+    client: &dyn Peer,
+    database: &Database,
+) -> Result<Option<NettingStatement>, Error> {
+    let delegates = delegates(keypair.public_key, peer);
+
+    if delegates[0] != keypair.public_key {
+        return Ok(None);
+    }
+
+    let query = Signed::new(
+        &mut thread_rng(),
+        &keypair.secret_key,
+        &NettingQuery {
+            peer: keypair.public_key,
+            delegate: peer,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+        },
+    )?;
+
+    let (vault, theirs, settlements) = match client.call(V0Request::Netting { query })? {
+        V0Response::Netting {
+            vault,
+            statement,
+            settlements,
+        } => (vault, statement.map(|s| *s), settlements),
+        response => {
+            return Err(Error::PeerError {
+                delegate: peer,
+                reason: format!("Unexpected response: {response:?}"),
+            })
+        }
+    };
+
+    let mut netting = load(&database.read()?.open_table(NETTING)?, peer)?;
+    let id = |s: &Option<NettingStatement>| s.as_ref().map(|s| s.statement.id());
+
+    match (&netting.statement, theirs) {
+        (ours, theirs) if id(ours) == id(&theirs) => {}
+        (Some(ours), Some(theirs)) if ours.conflicts(&theirs) => {
+            let w = database.write()?;
+            record_dispute(
+                &w,
+                &Dispute::Conflict {
+                    ours: Box::new(ours.clone()),
+                    theirs: Box::new(theirs),
+                },
+            )?;
+            w.commit()?;
+
+            return Err(Error::InvalidStatement {
+                reason: format!("Delegate {peer} signed a conflicting statement"),
+            });
+        }
+        // The last statement was countersigned, but its signature never made
+        // it back here.
+        (ours, Some(theirs))
+            if theirs.statement.delegates == delegates
+                && theirs.statement.previous == id(ours).unwrap_or_default()
+                && theirs.verify().is_ok() =>
+        {
+            let w = database.write()?;
+            store(&w, peer, theirs)?;
+            w.commit()?;
+
+            netting = load(&database.read()?.open_table(NETTING)?, peer)?;
+        }
+        _ => {
+            return Err(Error::InvalidStatement {
+                reason: format!("Last statement with delegate {peer} is out of sync"),
+            });
+        }
+    }
+
+    let settlements = netting
+        .settlements
+        .iter()
+        .cloned()
+        .chain(settlements)
+        .collect::<Vec<_>>();
+
+    if netting.transactions.is_empty() && settlements.is_empty() {
+        return Ok(None);
+    }
+
+    let mut statement = Statement {
+        delegates,
+        vaults: [chain.vault_address(), vault],
+        sequence: 0,
+        previous: Hash::default(),
+        transactions: netting.transactions.clone(),
+        settlements,
+        balances: vec![],
+    };
+
+    if let Some(last) = &netting.statement {
+        statement.sequence = last.statement.sequence + 1;
+        statement.previous = last.statement.id();
+    }
+
+    statement.balances = check(
+        &statement,
+        &netting,
+        chain,
+        &database.read()?.open_table(CROSS)?,
+    )?;
+
+    let proposal = Signed::new(&mut thread_rng(), &keypair.secret_key, &statement)?;
+
+    let signature = match client.call(V0Request::Net {
+        statement: proposal.clone(),
+    })? {
+        V0Response::Netted { signature } => signature,
+        response => {
+            return Err(Error::PeerError {
+                delegate: peer,
+                reason: format!("Unexpected response: {response:?}"),
+            })
+        }
+    };
+
+    let netted = NettingStatement::new(&proposal, signature)?;
+    netted.verify()?;
+
+    let w = database.write()?;
+    store(&w, peer, netted.clone())?;
+    w.commit()?;
+
+    info!(sequence = netted.statement.sequence, "Netted with peer");
+
+    Ok(Some(netted))
+}
+
+/// Starts paying off what this delegate owes `peer` as of their last
+/// statement, unless it was paid already or a payment is still in progress.
+#[tracing::instrument(skip_all, fields(%peer))]
+pub fn settle(
+    keypair: Keypair,
+    peer: PublicKey,
+    database: &Database,
+) -> Result<Option<Settlement>, Error> {
+    let w = database.write()?;
+
+    let settlement = {
+        let netting = load(&w.open_table(NETTING)?, peer)?;
+        let mut settlements = w.open_table(SETTLEMENTS)?;
+
+        let Some(NettingStatement { statement, .. }) = netting.statement else {
+            return Ok(None);
+        };
+
+        let id = statement.id();
+        // A settlement that can not be read might still be paying the peer,
+        // so none is started next to it.
+        let in_progress = settlements.iter()?.any(|entry| {
+            entry.is_ok_and(|(_, v)| {
+                v.value().map_or(true, |s| {
+                    s.creditor == peer
+                        && matches!(
+                            s.status,
+                            SettlementStatus::Pending | SettlementStatus::Submitted { .. }
+                        )
+                })
+            })
+        });
+
+        if in_progress || !netting.settlements.is_empty() || settlements.get(id)?.is_some() {
+            return Ok(None);
+        }
+
+        let assets = statement
+            .owed(&keypair.public_key)
+            .into_iter()
+            .filter(|(_, owed)| *owed > 0)
+            .map(|(asset_id, owed)| (asset_id, owed.min(u64::MAX as i128) as u64))
+            .collect::<Vec<_>>();
+
+        if assets.is_empty() {
+            return Ok(None);
+        }
+
+        let creditor = statement.position(&peer).unwrap_or(1);
+        let settlement = Settlement {
+            id,
+            debtor: keypair.public_key,
+            creditor: peer,
+            address: statement.vaults[creditor].clone(),
+            assets,
+            status: SettlementStatus::Pending,
+        };
+
+        settlements.insert(id, Ok(settlement.clone()))?;
+
+        settlement
+    };
+
+    w.commit()?;
+
+    info!(id = %settlement.id, "Started settlement");
+
+    Ok(Some(settlement))
+}
+
+/// Advances every unfinished settlement by at most one step, like
+/// [`crate::withdrawal::process`] does for withdrawals.
+#[tracing::instrument(skip_all)]
+pub fn advance(database: &Database, chain: &dyn ChainBackend) -> Result<(), Error> {
+    let mut unfinished = vec![];
+
+    for entry in database.read()?.open_table(SETTLEMENTS)?.iter()? {
+        let (id, entry) = entry?;
+
+        match entry.value() {
+            Ok(
+                s @ Settlement {
+                    status: SettlementStatus::Pending | SettlementStatus::Submitted { .. },
+                    ..
+                },
+            ) => unfinished.push(s),
+            Ok(_) => {}
+            Err(e) => warn!(id = %id.value(), reason = %e, "Skipped unreadable settlement"),
+        }
+    }
+
+    for mut settlement in unfinished {
+        let status = match settlement.status {
+            SettlementStatus::Pending => {
+                let payout = Payout {
+                    id: settlement.id,
+                    address: settlement.address.clone(),
+                    assets: settlement.assets.clone(),
+                };
+
+                match chain.submit_payout(&payout) {
+                    Ok(tx) => SettlementStatus::Submitted { tx },
+                    Err(e) => {
+                        warn!(id = %settlement.id, reason = %e, "Failed to submit settlement");
+                        continue;
+                    }
+                }
+            }
+            SettlementStatus::Submitted { tx } => match chain.payout_status(tx) {
+                Ok(PayoutStatus::Pending) => continue,
+                Ok(PayoutStatus::Confirmed) => SettlementStatus::Confirmed { tx },
+                Ok(PayoutStatus::Failed { reason }) => SettlementStatus::Failed { reason },
+                Err(e) => {
+                    warn!(id = %settlement.id, %tx, reason = %e, "Failed to check settlement");
+                    continue;
+                }
+            },
+            _ => continue,
+        };
+
+        info!(id = %settlement.id, status = ?status, "Settlement changed status");
+
+        settlement.status = status;
+
+        let w = database.write()?;
+        w.open_table(SETTLEMENTS)?
+            .insert(settlement.id, Ok(settlement.clone()))?;
+
+        if let SettlementStatus::Confirmed { .. } = settlement.status {
+            let mut table = w.open_table(NETTING)?;
+            let mut netting = load(&table, settlement.creditor)?;
+            let creditor = settlement.creditor;
+            netting.settlements.push(settlement);
+            table.insert(creditor, Ok(netting))?;
+        }
+
+        w.commit()?;
+    }
+
+    Ok(())
+}
+
+/// Runs a round of netting and settlement with every peer.
+pub fn process(context: &Context) -> Result<(), Error> {
+    let Context {
+        keypair,
+        chain,
+        peers,
+        database,
+        ..
+    } = context;
+
+    for (public_key, peer) in peers.iter() {
+        if let Err(e) = net(
+            *keypair,
+            chain.as_ref(),
+            *public_key,
+            peer.as_ref(),
+            database,
+        ) {
+            warn!(peer = %public_key, reason = %e, "Failed to net with peer");
+        }
+
+        if let Err(e) = settle(*keypair, *public_key, database) {
+            warn!(peer = %public_key, reason = %e, "Failed to settle with peer");
+        }
+    }
+
+    advance(database, chain.as_ref())
+}
+
+pub async fn run(context: Context) {
+    let mut interval = tokio::time::interval(NETTING_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let context = context.clone();
+        let result = tokio::task::spawn_blocking(move || process(&context))
+            .await
+            .unwrap_or_else(|e| {
+                Err(Error::ServerError {
+                    reason: e.to_string(),
+                })
+            });
+
+        if let Err(e) = result {
+            warn!(reason = %e, "Failed to settle with peers");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::thread_rng;
+
+    use super::*;
+    use crate::{
+        testing::{transfer, Pair},
+        v0::{handle, QUERY_MAX_AGE},
+    };
+
+    /// Commits a cross-delegate transaction from `a`, spending `notes`.
+    fn commit(d: &Pair, notes: &[Note], outputs: &[(PublicKey, u64)]) {
+        let outputs = outputs
+            .iter()
+            .map(|(delegate, amount)| (*delegate, d.asset_id, *amount))
+            .collect::<Vec<_>>();
+        let transaction = transfer(notes, &outputs);
+
+        assert!(matches!(
+            handle(V0Request::Transaction(transaction), &d.a),
+            Ok(V0Response::Transaction { .. })
+        ));
+    }
+
+    fn round(d: &Pair) {
+        process(&d.a).unwrap();
+        process(&d.b).unwrap();
+    }
+
+    fn last(context: &Context, peer: &Context) -> Option<NettingStatement> {
+        let table = context
+            .database
+            .read()
+            .unwrap()
+            .open_table(NETTING)
+            .unwrap();
+        load(&table, peer.keypair.public_key).unwrap().statement
+    }
+
+    /// The delegate proposing statements, and the one countersigning them.
+    fn sides(d: &Pair) -> (&Context, &Context) {
+        match d.a.keypair.public_key < d.b.keypair.public_key {
+            true => (&d.a, &d.b),
+            false => (&d.b, &d.a),
+        }
+    }
+
+    #[test]
+    fn test_net_and_settle() {
+        let d = Pair::new(&[100]).peered();
+        let (a, b) = (d.a.keypair.public_key, d.b.keypair.public_key);
+
+        commit(&d, &d.notes, &[(b, 60), (a, 40)]);
+        round(&d);
+
+        let netted = last(&d.a, &d.b).unwrap();
+        let statement = &netted.statement;
+
+        assert_eq!(last(&d.b, &d.a).as_ref(), Some(&netted));
+        assert!(netted.verify().is_ok());
+        assert_eq!(statement.owed(&a), [(d.asset_id, 60)]);
+        assert_eq!(statement.vaults[statement.position(&b).unwrap()], "vault_b");
+
+        round(&d);
+
+        assert_eq!(
+            d.chain.payouts().unwrap(),
+            [Payout {
+                id: statement.id(),
+                address: "vault_b".to_string(),
+                assets: vec![(d.asset_id, 60)],
+            }]
+        );
+
+        // Once confirmed, the settlement is netted in the next statement.
+        d.chain.advance(2).unwrap();
+        round(&d);
+        round(&d);
+
+        let settled = last(&d.a, &d.b).unwrap();
+
+        assert_eq!(last(&d.b, &d.a).as_ref(), Some(&settled));
+        assert_eq!(settled.statement.sequence, 1);
+        assert_eq!(settled.statement.previous, statement.id());
+        assert_eq!(settled.statement.settlements.len(), 1);
+        assert!(settled.statement.balances.is_empty());
+
+        assert!(settle(d.a.keypair, b, &d.a.database).unwrap().is_none());
+        assert_eq!(d.chain.payouts().unwrap().len(), 1);
+        assert!(disputes(&d.a.database).unwrap().is_empty());
+        assert!(disputes(&d.b.database).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_mismatch_dispute() {
+        let d = Pair::new(&[100]).peered();
+        let (a, b) = (d.a.keypair.public_key, d.b.keypair.public_key);
+        let (proposer, other) = sides(&d);
+
+        commit(&d, &d.notes, &[(b, 60), (a, 40)]);
+
+        let cross = d.a.database.read().unwrap().open_table(CROSS).unwrap();
+        let id = cross.iter().unwrap().next().unwrap().unwrap().0.value();
+        let mut statement = Statement {
+            delegates: delegates(a, b),
+            vaults: [proposer, other].map(|c| c.chain.vault_address()),
+            sequence: 0,
+            previous: Hash::default(),
+            transactions: vec![Hash::digest(b"unknown")],
+            settlements: vec![],
+            balances: vec![(d.asset_id, 1)],
+        };
+        let net = |statement: &Statement| {
+            let statement =
+                Signed::new(&mut thread_rng(), &proposer.keypair.secret_key, statement).unwrap();
+            handle(V0Request::Net { statement }, other)
+        };
+
+        // Transactions the other delegate does not know are not disputes, as
+        // it may not have committed them yet.
+        assert!(matches!(
+            net(&statement),
+            Err(Error::InvalidStatement { .. })
+        ));
+        assert!(disputes(&other.database).unwrap().is_empty());
+
+        statement.transactions = vec![id];
+
+        assert!(matches!(
+            net(&statement),
+            Err(Error::InvalidStatement { .. })
+        ));
+        assert!(matches!(
+            &disputes(&other.database).unwrap()[..],
+            [Dispute::Mismatch { ours, theirs }]
+                if theirs.unverified().is_ok_and(|t| t == statement)
+                    && ours.verify(&other.keypair.public_key).is_ok()
+        ));
+
+        // Netting goes on with the right balances.
+        round(&d);
+        assert!(last(&d.a, &d.b).is_some());
+    }
+
+    #[test]
+    fn test_conflict_dispute() {
+        let d = Pair::new(&[100, 100]).peered();
+        let (a, b) = (d.a.keypair.public_key, d.b.keypair.public_key);
+        let (proposer, other) = sides(&d);
+
+        commit(&d, &d.notes[..1], &[(b, 60), (a, 40)]);
+        round(&d);
+
+        // The other delegate ends up with a different statement signed by
+        // both, for the same sequence.
+        let mut statement = last(other, proposer).unwrap().statement;
+        statement.balances = vec![];
+        let signed =
+            Signed::new(&mut thread_rng(), &proposer.keypair.secret_key, &statement).unwrap();
+        let signature = signed
+            .countersign(&mut thread_rng(), &other.keypair.secret_key)
+            .signature;
+        let forged = NettingStatement::new(&signed, signature).unwrap();
+
+        let w = other.database.write().unwrap();
+        store(&w, proposer.keypair.public_key, forged.clone()).unwrap();
+        w.commit().unwrap();
+
+        commit(&d, &d.notes[1..], &[(b, 10), (a, 90)]);
+        round(&d);
+
+        assert!(matches!(
+            &disputes(&proposer.database).unwrap()[..],
+            [Dispute::Conflict { ours, theirs }]
+                if **theirs == forged && ours.conflicts(theirs)
+        ));
+        assert_eq!(last(proposer, other).unwrap().statement.sequence, 0);
+    }
+
+    #[test]
+    fn test_settlement_paid_on_l1() {
+        let d = Pair::new(&[100]);
+        let (a, b) = (d.a.keypair.public_key, d.b.keypair.public_key);
+        let delegates = delegates(a, b);
+        let vaults = delegates.map(|k| match k == a {
+            true => "vault_a".to_string(),
+            false => "vault_b".to_string(),
+        });
+
+        let tx = d
+            .chain
+            .submit_payout(&Payout {
+                id: Hash::digest(b"payment"),
+                address: "vault_b".to_string(),
+                assets: vec![(d.asset_id, 10)],
+            })
+            .unwrap();
+        d.chain.advance(2).unwrap();
+
+        let settlement = |id: &[u8], amount: u64| Settlement {
+            id: Hash::digest(id),
+            debtor: a,
+            creditor: b,
+            address: "vault_b".to_string(),
+            assets: vec![(d.asset_id, amount)],
+            status: SettlementStatus::Confirmed { tx },
+        };
+        let checked = |netting: &Netting, settlements: Vec<Settlement>| {
+            let statement = Statement {
+                delegates,
+                vaults: vaults.clone(),
+                sequence: 0,
+                previous: Hash::default(),
+                transactions: vec![],
+                settlements,
+                balances: vec![],
+            };
+            let cross = d.b.database.read().unwrap().open_table(CROSS).unwrap();
+
+            check(&statement, netting, &d.chain, &cross)
+        };
+        let invalid = |r: Result<_, Error>| matches!(r, Err(Error::InvalidStatement { .. }));
+        let fresh = Netting::default();
+
+        assert!(checked(&fresh, vec![settlement(b"a", 10)]).is_ok());
+
+        // More than the L1 transaction paid to the vault.
+        assert!(invalid(checked(&fresh, vec![settlement(b"a", 11)])));
+
+        // The same payment claimed twice, in one statement or across them.
+        assert!(invalid(checked(
+            &fresh,
+            vec![settlement(b"a", 5), settlement(b"b", 5)]
+        )));
+        assert!(invalid(checked(
+            &Netting {
+                settled: vec![tx],
+                ..Default::default()
+            },
+            vec![settlement(b"b", 10)]
+        )));
+    }
+
+    #[test]
+    fn test_settlement_status_unknown() {
+        let d = Pair::new(&[100]);
+        let (a, b) = (d.a.keypair.public_key, d.b.keypair.public_key);
+        let payout = Payout {
+            id: Hash::digest(b"known"),
+            address: "vault_b".to_string(),
+            assets: vec![(d.asset_id, 10)],
+        };
+        let known = d.chain.submit_payout(&payout).unwrap();
+        d.chain.advance(2).unwrap();
+
+        // The first settlement waits on a payout the chain does not know about.
+        let w = d.a.database.write().unwrap();
+        {
+            let mut table = w.open_table(SETTLEMENTS).unwrap();
+
+            for (id, tx) in [(b"lost", Hash::digest(b"unknown")), (b"kept", known)] {
+                let id = Hash::digest(id);
+                let settlement = Settlement {
+                    id,
+                    debtor: a,
+                    creditor: b,
+                    address: payout.address.clone(),
+                    assets: payout.assets.clone(),
+                    status: SettlementStatus::Submitted { tx },
+                };
+                table.insert(id, Ok(settlement)).unwrap();
+            }
+        }
+        w.commit().unwrap();
+
+        advance(&d.a.database, &d.chain).unwrap();
+
+        let status = |id: &[u8]| {
+            let r = d.a.database.read().unwrap();
+            let table = r.open_table(SETTLEMENTS).unwrap();
+            let settlement = table.get(Hash::digest(id)).unwrap().unwrap().value();
+            settlement.unwrap().status
+        };
+
+        assert!(matches!(
+            status(b"lost"),
+            SettlementStatus::Submitted { .. }
+        ));
+        assert_eq!(status(b"kept"), SettlementStatus::Confirmed { tx: known });
+    }
+
+    #[test]
+    fn test_netting_query_signed() {
+        let d = Pair::new(&[100]).peered();
+        let (a, b) = (&d.a.keypair, &d.b.keypair);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let query = |signer: &Keypair, delegate: PublicKey, timestamp: u64| V0Request::Netting {
+            query: Signed::new(
+                &mut thread_rng(),
+                &signer.secret_key,
+                &NettingQuery {
+                    peer: a.public_key,
+                    delegate,
+                    timestamp,
+                },
+            )
+            .unwrap(),
+        };
+        let invalid = |r: Result<_, Error>| matches!(r, Err(Error::InvalidStatement { .. }));
+
+        assert!(matches!(
+            handle(query(a, b.public_key, now), &d.b),
+            Ok(V0Response::Netting { .. })
+        ));
+
+        // Signed by someone else than the peer, meant for another delegate,
+        // or replayed long after.
+        assert!(invalid(handle(query(b, b.public_key, now), &d.b)));
+        assert!(invalid(handle(query(a, a.public_key, now), &d.b)));
+        assert!(invalid(handle(
+            query(a, b.public_key, now - 2 * QUERY_MAX_AGE),
+            &d.b
+        )));
+
+        // Only peers can ask.
+        let unpeered = Pair::new(&[100]);
+        let query = Signed::new(
+            &mut thread_rng(),
+            &unpeered.a.keypair.secret_key,
+            &NettingQuery {
+                peer: unpeered.a.keypair.public_key,
+                delegate: unpeered.b.keypair.public_key,
+                timestamp: now,
+            },
+        )
+        .unwrap();
+        assert!(invalid(handle(V0Request::Netting { query }, &unpeered.b)));
+    }
+}
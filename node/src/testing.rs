@@ -0,0 +1,370 @@
+use std::{
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, OnceLock},
+    thread,
+    time::Duration,
+};
+
+use mugraph_core::{builder::TransactionBuilder, error::Error, types::*, utils::BitSet32};
+use rand::prelude::*;
+use rand_chacha::ChaCha20Rng;
+use tempfile::TempDir;
+
+use crate::{
+    assets,
+    chain::{ChainBackend, MockChain},
+    config::{Config, Server, Storage},
+    database::Database,
+    peer::{Peer, Peers},
+    start_with_chain,
+    v0::{handle, mint_v0, Context},
+};
+
+pub struct Setup {
+    pub rng: ChaCha20Rng,
+    pub keypair: Keypair,
+    pub owner: Keypair,
+    pub chain: MockChain,
+    pub fees: FeeSchedule,
+    pub database: Database,
+    _dir: TempDir,
+}
+
+impl Default for Setup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Setup {
+    pub fn new() -> Self {
+        Self::with_seed(0)
+    }
+
+    /// Sets up a delegate with keys derived from `seed`, so tests can run
+    /// several distinct delegates side by side.
+    pub fn with_seed(seed: u64) -> Self {
+        let mut rng = ChaCha20Rng::seed_from_u64(seed);
+        let dir = tempfile::tempdir().unwrap();
+
+        Self {
+            keypair: Keypair::random(&mut rng),
+            owner: Keypair::random(&mut rng),
+            chain: MockChain::new(&mut rng, 2),
+            fees: FeeSchedule::default(),
+            database: Database::setup(dir.path().join("db")).unwrap(),
+            rng,
+            _dir: dir,
+        }
+    }
+
+    /// Registers a new enabled asset with no practical amount limits.
+    pub fn asset(&mut self) -> Hash {
+        let asset_id = Hash::random(&mut self.rng);
+        self.register(asset_id);
+
+        asset_id
+    }
+
+    /// Registers `asset_id` as an enabled asset with no practical amount
+    /// limits, named after its id so every asset has its own Cardano unit.
+    pub fn register(&mut self, asset_id: Hash) {
+        let asset = Asset {
+            id: asset_id,
+            policy_id: vec![0; 28],
+            asset_name: asset_id.as_ref().to_vec(),
+            decimals: 0,
+            ticker: "TEST".to_string(),
+            min_amount: 1,
+            max_amount: u64::MAX,
+            enabled: true,
+        };
+
+        let w = self.database.write().unwrap();
+        assets::register(&w, &asset).unwrap();
+        w.commit().unwrap();
+    }
+
+    /// Turns the setup into a context serving requests as its delegate, along
+    /// with the directory of its database, which is removed once dropped.
+    pub fn into_context(self) -> (Context, TempDir) {
+        let context = Context::new(self.keypair, Arc::new(self.chain), self.fees, self.database);
+
+        (context, self._dir)
+    }
+
+    /// Builds a transaction with only outputs, all for the same asset.
+    pub fn outputs(&mut self, asset_id: Hash, amounts: &[u64]) -> Transaction {
+        let secret = Hash::random(&mut self.rng);
+
+        self.outputs_from(&secret, asset_id, amounts)
+    }
+
+    /// Builds a transaction with only outputs, all for the same asset, derived
+    /// from `secret`.
+    pub fn outputs_from(&self, secret: &Hash, asset_id: Hash, amounts: &[u64]) -> Transaction {
+        Transaction {
+            input_mask: BitSet32::new(),
+            atoms: amounts
+                .iter()
+                .enumerate()
+                .map(|(i, &amount)| Atom {
+                    delegate: self.keypair.public_key,
+                    asset_id: 0,
+                    amount,
+                    nonce: Transaction::output_nonce(secret, i),
+                    signature: None,
+                    lock: None,
+                })
+                .collect(),
+            asset_ids: vec![asset_id],
+            signatures: vec![],
+            data: vec![],
+            witnesses: vec![],
+        }
+    }
+
+    /// Deposits and mints notes with the given amounts.
+    pub fn notes(&mut self, asset_id: Hash, amounts: &[u64]) -> Vec<Note> {
+        let deposit = self
+            .chain
+            .lock(asset_id, amounts.iter().sum(), self.owner.public_key)
+            .unwrap();
+        self.chain.advance(self.chain.min_confirmations()).unwrap();
+
+        let outputs = self.outputs(asset_id, amounts);
+        let mint = Mint::new(
+            &mut self.rng,
+            &self.owner.secret_key,
+            deposit,
+            outputs.clone(),
+        );
+
+        let signatures = match mint_v0(&mint, self.keypair, &self.chain, &self.database) {
+            Ok(V0Response::Mint { outputs }) => outputs,
+            r => panic!("Unexpected response: {r:?}"),
+        };
+
+        outputs.output_notes(&signatures).unwrap()
+    }
+}
+
+/// Secret the outputs of [`spend`] are derived from.
+pub const SECRET: Hash = Hash([7; 32]);
+
+/// Builds a transaction spending `inputs` into outputs with `amounts`.
+pub fn spend(inputs: &[Note], outputs: &[(Hash, u64)]) -> Transaction {
+    let mut asset_ids: Vec<Hash> = vec![];
+    let mut index = |asset_id: Hash| match asset_ids.iter().position(|a| *a == asset_id) {
+        Some(i) => i as u32,
+        None => {
+            asset_ids.push(asset_id);
+            (asset_ids.len() - 1) as u32
+        }
+    };
+
+    let mut input_mask = BitSet32::new();
+    let mut atoms = vec![];
+    let mut signatures = vec![];
+
+    for (i, note) in inputs.iter().enumerate() {
+        input_mask.insert(i as u32);
+        atoms.push(Atom {
+            delegate: note.delegate,
+            asset_id: index(note.asset_id),
+            amount: note.amount,
+            nonce: note.nonce,
+            signature: Some(signatures.len() as u32),
+            lock: note.lock,
+        });
+        signatures.push(note.signature);
+    }
+
+    let delegate = inputs[0].delegate;
+
+    for (i, (asset_id, amount)) in outputs.iter().enumerate() {
+        atoms.push(Atom {
+            delegate,
+            asset_id: index(*asset_id),
+            amount: *amount,
+            nonce: Transaction::output_nonce(&SECRET, i),
+            signature: None,
+            lock: None,
+        });
+    }
+
+    Transaction {
+        input_mask,
+        atoms,
+        asset_ids,
+        signatures,
+        data: vec![],
+        witnesses: vec![],
+    }
+}
+
+/// Spends `notes` into outputs at the delegates given for each amount,
+/// derived from [`SECRET`].
+pub fn transfer(notes: &[Note], outputs: &[(PublicKey, Hash, u64)]) -> Transaction {
+    let builder = notes
+        .iter()
+        .fold(TransactionBuilder::new(), |b, n| b.input(n.clone()));
+
+    outputs
+        .iter()
+        .fold(builder, |b, &(delegate, asset_id, amount)| {
+            b.output_to(delegate, asset_id, amount)
+        })
+        .build(&SECRET)
+        .unwrap()
+}
+
+/// Two delegates sharing an asset and the L1, each with its own vault.
+pub struct Pair {
+    pub a: Context,
+    pub b: Context,
+    pub chain: MockChain,
+    pub asset_id: Hash,
+    /// Notes held at `a`.
+    pub notes: Vec<Note>,
+    _dirs: [TempDir; 2],
+}
+
+impl Pair {
+    /// Sets up the delegates with notes of `amounts` held at the first one.
+    /// They are not peers yet.
+    pub fn new(amounts: &[u64]) -> Self {
+        let mut a = Setup::with_seed(1);
+        let mut b = Setup::with_seed(2);
+        let chain = a.chain.clone();
+        a.chain = chain.with_vault("vault_a");
+        b.chain = chain.with_vault("vault_b");
+
+        let asset_id = a.asset();
+        b.register(asset_id);
+        let notes = a.notes(asset_id, amounts);
+
+        let (a, a_dir) = a.into_context();
+        let (b, b_dir) = b.into_context();
+
+        Self {
+            a,
+            b,
+            chain,
+            asset_id,
+            notes,
+            _dirs: [a_dir, b_dir],
+        }
+    }
+
+    /// Makes the delegates peers, talking to each other in-process.
+    pub fn peered(mut self) -> Self {
+        let local = |context: &Context| -> Peers {
+            Peers::from([(
+                context.keypair.public_key,
+                Arc::new(LocalPeer(context.clone())) as Arc<dyn Peer>,
+            )])
+        };
+        let (a, b) = (self.a.clone(), self.b.clone());
+
+        // Each side reaches a context of the other that knows it as a peer in
+        // turn, which is as deep as requests between peers go.
+        self.a = a.clone().peers(local(&b.clone().peers(local(&a))));
+        self.b = b.clone().peers(local(&a.peers(local(&b))));
+
+        self
+    }
+}
+
+/// Peer running in the same process, answering through [`handle`].
+pub struct LocalPeer(pub Context);
+
+impl Peer for LocalPeer {
+    fn call(&self, request: V0Request) -> Result<V0Response, Error> {
+        handle(request, &self.0)
+    }
+}
+
+/// Delegate started with [`start_with_chain`] on a free local port, for tests of
+/// clients talking to it over HTTP.
+pub struct Local {
+    pub url: String,
+    pub keypair: Keypair,
+    pub chain: MockChain,
+    /// Asset registered on startup, with no practical amount limits.
+    pub asset_id: Hash,
+}
+
+impl Local {
+    /// Starts the delegate on the first call, sharing it with every test in
+    /// the process.
+    pub fn shared() -> &'static Self {
+        static LOCAL: OnceLock<Local> = OnceLock::new();
+
+        LOCAL.get_or_init(|| {
+            let mut rng = thread_rng();
+            let dir = tempfile::tempdir().unwrap().into_path();
+
+            let asset_id = Hash::random(&mut rng);
+            let addr: SocketAddr = TcpListener::bind("127.0.0.1:0")
+                .unwrap()
+                .local_addr()
+                .unwrap();
+            let keypair = Keypair::random(&mut rng);
+            std::fs::write(dir.join("key"), hex::encode(keypair.secret_key.0)).unwrap();
+
+            let config = Config {
+                server: Server {
+                    listen: vec![addr],
+                    tls: None,
+                },
+                storage: Storage {
+                    database: dir.join("db"),
+                    keystore: Some(dir.join("key")),
+                },
+                assets: vec![Asset {
+                    id: asset_id,
+                    policy_id: vec![0; 28],
+                    asset_name: b"TEST".to_vec(),
+                    decimals: 0,
+                    ticker: "TEST".to_string(),
+                    min_amount: 1,
+                    max_amount: u64::MAX,
+                    enabled: true,
+                }],
+                dev: true,
+                ..Default::default()
+            };
+            config.validate().unwrap();
+
+            let chain = MockChain::new(&mut rng, 1);
+            let backend = Arc::new(chain.clone());
+
+            thread::spawn(move || {
+                tokio::runtime::Runtime::new()
+                    .unwrap()
+                    .block_on(start_with_chain(&config, backend))
+                    .unwrap();
+            });
+
+            while TcpStream::connect(addr).is_err() {
+                thread::sleep(Duration::from_millis(10));
+            }
+
+            Local {
+                url: format!("http://{addr}"),
+                keypair,
+                chain,
+                asset_id,
+            }
+        })
+    }
+
+    /// Locks a confirmed deposit of the asset into the vault of the delegate.
+    pub fn deposit(&self, amount: u64, owner: PublicKey) -> Hash {
+        let deposit = self.chain.lock(self.asset_id, amount, owner).unwrap();
+        self.chain.advance(1).unwrap();
+
+        deposit
+    }
+}
@@ -0,0 +1,382 @@
+//! Moves withdrawals through their lifecycle, submitting payouts to the L1
+//! and tracking them until they are confirmed or fail.
+
+use std::time::Duration;
+
+use mugraph_core::{error::Error, types::*};
+use redb::ReadableTable;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{
+    chain::{ChainBackend, Payout, PayoutStatus},
+    database::{Database, WITHDRAWALS},
+    v0::Context,
+};
+
+pub const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Withdrawal as recorded by the delegate, with the refund only its owner
+/// can claim.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    #[serde(flatten)]
+    pub withdrawal: Withdrawal,
+    pub owner: PublicKey,
+    pub refund: Transaction,
+}
+
+/// Advances every unfinished withdrawal by at most one step.
+#[tracing::instrument(skip_all)]
+pub fn process(database: &Database, chain: &dyn ChainBackend) -> Result<(), Error> {
+    let mut unfinished = vec![];
+
+    for entry in database.read()?.open_table(WITHDRAWALS)?.iter()? {
+        let (id, entry) = entry?;
+
+        match entry.value() {
+            Ok(
+                e @ Entry {
+                    withdrawal:
+                        Withdrawal {
+                            status: WithdrawalStatus::Pending | WithdrawalStatus::Submitted { .. },
+                            ..
+                        },
+                    ..
+                },
+            ) => unfinished.push(e),
+            Ok(_) => {}
+            Err(e) => warn!(id = %id.value(), reason = %e, "Skipped unreadable withdrawal"),
+        }
+    }
+
+    for mut entry in unfinished {
+        let withdrawal = &mut entry.withdrawal;
+        let status = match withdrawal.status {
+            WithdrawalStatus::Pending => {
+                let payout = Payout {
+                    id: withdrawal.id,
+                    address: withdrawal.address.clone(),
+                    assets: withdrawal.payouts.clone(),
+                };
+
+                match chain.submit_payout(&payout) {
+                    Ok(tx) => WithdrawalStatus::Submitted { tx },
+                    Err(e) => {
+                        warn!(id = %withdrawal.id, reason = %e, "Failed to submit payout");
+                        continue;
+                    }
+                }
+            }
+            WithdrawalStatus::Submitted { tx } => match chain.payout_status(tx) {
+                Ok(PayoutStatus::Pending) => continue,
+                Ok(PayoutStatus::Confirmed) => WithdrawalStatus::Confirmed { tx },
+                Ok(PayoutStatus::Failed { reason }) => WithdrawalStatus::Failed { reason },
+                Err(e) => {
+                    warn!(id = %withdrawal.id, %tx, reason = %e, "Failed to check payout");
+                    continue;
+                }
+            },
+            _ => continue,
+        };
+
+        info!(id = %withdrawal.id, status = ?status, "Withdrawal changed status");
+
+        withdrawal.status = status;
+
+        let w = database.write()?;
+        w.open_table(WITHDRAWALS)?
+            .insert(withdrawal.id, Ok(entry))?;
+        w.commit()?;
+    }
+
+    Ok(())
+}
+
+pub async fn run(context: Context) {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let Context {
+            database, chain, ..
+        } = context.clone();
+
+        let result = tokio::task::spawn_blocking(move || process(&database, chain.as_ref()))
+            .await
+            .unwrap_or_else(|e| {
+                Err(Error::ServerError {
+                    reason: e.to_string(),
+                })
+            });
+
+        if let Err(e) = result {
+            warn!(reason = %e, "Failed to process withdrawals");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chain::MockChain,
+        testing::{spend, Setup},
+        v0::{refund_v0, withdraw_v0, withdrawal_v0},
+    };
+
+    fn status(s: &mut Setup, id: Hash) -> WithdrawalStatus {
+        match withdrawal_v0(id, &s.database).unwrap() {
+            V0Response::Withdrawal(w) => w.status,
+            r => panic!("Unexpected response: {r:?}"),
+        }
+    }
+
+    fn withdraw(s: &mut Setup, asset_id: Hash) -> Hash {
+        let notes = s.notes(asset_id, &[70, 30]);
+        let withdraw = Withdraw {
+            transaction: spend(&notes, &[(asset_id, 25)]),
+            address: "addr_test1vault".to_string(),
+            refund: s.outputs(asset_id, &[75]),
+            owner: s.owner.public_key,
+        };
+
+        match withdraw_v0(&withdraw, s.keypair, &s.fees, &s.chain, &s.database).unwrap() {
+            V0Response::Withdraw { id, outputs } => {
+                assert_eq!(outputs.len(), 1);
+                id
+            }
+            r => panic!("Unexpected response: {r:?}"),
+        }
+    }
+
+    fn refund(s: &mut Setup, id: Hash) -> Result<V0Response, Error> {
+        let refund = Refund::new(&mut s.rng, &s.owner.secret_key, id);
+        refund_v0(&refund, s.keypair, &s.database)
+    }
+
+    fn process(s: &mut Setup, chain: &MockChain) {
+        super::process(&s.database, chain).unwrap();
+    }
+
+    #[test]
+    fn test_withdrawal_confirmed() {
+        let mut s = Setup::new();
+        let chain = s.chain.clone();
+        let asset_id = s.asset();
+        let id = withdraw(&mut s, asset_id);
+
+        assert_eq!(status(&mut s, id), WithdrawalStatus::Pending);
+
+        process(&mut s, &chain);
+        assert!(matches!(
+            status(&mut s, id),
+            WithdrawalStatus::Submitted { .. }
+        ));
+        assert_eq!(
+            chain.payouts().unwrap(),
+            vec![Payout {
+                id,
+                address: "addr_test1vault".to_string(),
+                assets: vec![(asset_id, 75)],
+            }]
+        );
+
+        chain.advance(2).unwrap();
+        process(&mut s, &chain);
+        assert!(matches!(
+            status(&mut s, id),
+            WithdrawalStatus::Confirmed { .. }
+        ));
+
+        assert!(refund(&mut s, id).is_err());
+    }
+
+    #[test]
+    fn test_payout_status_unknown() {
+        let mut s = Setup::new();
+        let chain = s.chain.clone();
+        let asset_id = s.asset();
+        let lost = withdraw(&mut s, asset_id);
+        let id = withdraw(&mut s, asset_id);
+
+        process(&mut s, &chain);
+
+        // Like a payout the chain no longer knows about.
+        let w = s.database.write().unwrap();
+        {
+            let mut table = w.open_table(WITHDRAWALS).unwrap();
+            let mut entry = table.get(lost).unwrap().unwrap().value().unwrap();
+            entry.withdrawal.status = WithdrawalStatus::Submitted {
+                tx: Hash::digest(b"unknown"),
+            };
+            table.insert(lost, Ok(entry)).unwrap();
+        }
+        w.commit().unwrap();
+
+        chain.advance(2).unwrap();
+        process(&mut s, &chain);
+
+        assert!(matches!(
+            status(&mut s, lost),
+            WithdrawalStatus::Submitted { .. }
+        ));
+        assert!(matches!(
+            status(&mut s, id),
+            WithdrawalStatus::Confirmed { .. }
+        ));
+    }
+
+    #[test]
+    fn test_withdrawal_refund() {
+        let mut s = Setup::new();
+        let chain = s.chain.clone();
+        let asset_id = s.asset();
+        let id = withdraw(&mut s, asset_id);
+
+        assert!(refund(&mut s, id).is_err());
+
+        process(&mut s, &chain);
+
+        let tx = match status(&mut s, id) {
+            WithdrawalStatus::Submitted { tx } => tx,
+            status => panic!("Unexpected status: {status:?}"),
+        };
+
+        chain.fail_payout(tx, "vault script failed").unwrap();
+        process(&mut s, &chain);
+        assert!(matches!(
+            status(&mut s, id),
+            WithdrawalStatus::Failed { .. }
+        ));
+
+        let forged = Refund::new(&mut s.rng, &s.keypair.secret_key, id);
+        assert!(matches!(
+            refund_v0(&forged, s.keypair, &s.database),
+            Err(Error::InvalidTransaction { .. })
+        ));
+
+        match refund(&mut s, id).unwrap() {
+            V0Response::Refund { outputs } => assert_eq!(outputs.len(), 1),
+            r => panic!("Unexpected response: {r:?}"),
+        }
+
+        assert_eq!(status(&mut s, id), WithdrawalStatus::Refunded);
+        assert!(refund(&mut s, id).is_err());
+    }
+
+    #[test]
+    fn test_withdrawal_refund_mismatch() {
+        let mut s = Setup::new();
+        let asset_id = s.asset();
+        let notes = s.notes(asset_id, &[100]);
+
+        let withdraw = Withdraw {
+            transaction: spend(&notes, &[(asset_id, 25)]),
+            address: "addr_test1vault".to_string(),
+            refund: s.outputs(asset_id, &[100]),
+            owner: s.owner.public_key,
+        };
+
+        assert!(matches!(
+            withdraw_v0(&withdraw, s.keypair, &s.fees, &s.chain, &s.database),
+            Err(Error::InvalidTransaction { .. })
+        ));
+    }
+
+    #[test]
+    fn test_withdrawal_checked() {
+        let mut s = Setup::new();
+        let asset_id = s.asset();
+        let notes = s.notes(asset_id, &[100]);
+        let withdraw = Withdraw {
+            transaction: spend(&notes, &[(asset_id, 25)]),
+            address: String::new(),
+            refund: s.outputs(asset_id, &[75]),
+            owner: s.owner.public_key,
+        };
+
+        assert!(matches!(
+            withdraw_v0(&withdraw, s.keypair, &s.fees, &s.chain, &s.database),
+            Err(Error::InvalidTransaction { .. })
+        ));
+
+        // Change at another delegate would be signed by this one.
+        let mut withdraw = Withdraw {
+            address: "addr_test1vault".to_string(),
+            ..withdraw
+        };
+        withdraw.transaction.atoms[1].delegate = s.owner.public_key;
+
+        assert!(matches!(
+            withdraw_v0(&withdraw, s.keypair, &s.fees, &s.chain, &s.database),
+            Err(Error::InvalidAtom { .. })
+        ));
+
+        // Nothing was spent.
+        let withdraw = Withdraw {
+            transaction: spend(&notes, &[(asset_id, 25)]),
+            ..withdraw
+        };
+        assert!(withdraw_v0(&withdraw, s.keypair, &s.fees, &s.chain, &s.database).is_ok());
+    }
+
+    #[test]
+    fn test_withdrawal_fees() {
+        let mut s = Setup::new();
+        let chain = s.chain.clone();
+        let asset_id = s.asset();
+        let notes = s.notes(asset_id, &[70, 30, 2]);
+
+        s.fees.default = Fee {
+            flat: 1,
+            per_input: 2,
+            basis_points: 0,
+        };
+
+        // 1 + 2 * 2 taken from the 75 not sent to outputs.
+        let withdraw = Withdraw {
+            transaction: spend(&notes[..2], &[(asset_id, 25)]),
+            address: "addr_test1vault".to_string(),
+            refund: s.outputs(asset_id, &[70]),
+            owner: s.owner.public_key,
+        };
+
+        let id = match withdraw_v0(&withdraw, s.keypair, &s.fees, &s.chain, &s.database).unwrap() {
+            V0Response::Withdraw { id, .. } => id,
+            r => panic!("Unexpected response: {r:?}"),
+        };
+
+        process(&mut s, &chain);
+        assert_eq!(
+            chain.payouts().unwrap(),
+            vec![Payout {
+                id,
+                address: "addr_test1vault".to_string(),
+                assets: vec![(asset_id, 70)],
+            }]
+        );
+        assert_eq!(
+            crate::fees::collected(&s.database).unwrap(),
+            vec![(asset_id, 5)]
+        );
+
+        // The only input does not cover the fee of 3.
+        let unpaid = Withdraw {
+            transaction: spend(&notes[2..], &[]),
+            address: "addr_test1vault".to_string(),
+            refund: s.outputs(asset_id, &[2]),
+            owner: s.owner.public_key,
+        };
+
+        assert!(matches!(
+            withdraw_v0(&unpaid, s.keypair, &s.fees, &s.chain, &s.database),
+            Err(Error::InvalidFee {
+                expected: 3,
+                got: 2,
+                ..
+            })
+        ));
+    }
+}
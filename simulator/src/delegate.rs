@@ -1,6 +1,6 @@
 use color_eyre::eyre::Result;
 use mugraph_core::{crypto, error::Error, types::*};
-use mugraph_node::{database::Database, v0::transaction_v0};
+use mugraph_node::{assets, database::Database, v0::transaction_v0};
 use rand::prelude::*;
 use rand_chacha::ChaCha20Rng;
 use tracing::info;
@@ -22,6 +22,27 @@ impl Delegate {
         Ok(Self { db, rng, keypair })
     }
 
+    #[tracing::instrument(skip_all)]
+    pub fn register(&mut self, asset_id: Hash) -> Result<(), Error> {
+        let w = self.db.write()?;
+
+        assets::register(
+            &w,
+            &Asset {
+                id: asset_id,
+                policy_id: asset_id.as_ref()[..28].to_vec(),
+                asset_name: vec![],
+                decimals: 0,
+                ticker: asset_id.to_string()[..8].to_uppercase(),
+                min_amount: 1,
+                max_amount: u64::MAX,
+                enabled: true,
+            },
+        )?;
+
+        w.commit()
+    }
+
     #[tracing::instrument(skip_all)]
     pub fn emit(&mut self, asset_id: Hash, amount: u64) -> Result<Note, Error> {
         let mut note = Note {
@@ -30,6 +51,8 @@ impl Delegate {
             nonce: Hash::random(&mut self.rng),
             amount,
             signature: Signature::default(),
+            lock: None,
+            proof: None,
         };
 
         let blind = crypto::blind_note(&mut self.rng, &note);
@@ -43,6 +66,6 @@ impl Delegate {
     #[inline(always)]
     #[tracing::instrument(skip_all)]
     pub fn recv_transaction_v0(&mut self, tx: &Transaction) -> Result<V0Response, Error> {
-        transaction_v0(tx, self.keypair, &mut self.db)
+        transaction_v0(tx, self.keypair, &FeeSchedule::default(), &self.db)
     }
 }
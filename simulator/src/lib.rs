@@ -75,13 +75,19 @@ impl Simulation {
 
                             let asset_id = transaction.asset_ids[atom.asset_id as usize];
 
-                            self.state.recv(asset_id, atom.amount, outputs[index])?;
+                            self.state
+                                .recv(asset_id, atom.amount, atom.nonce, outputs[index])?;
 
                             index += 1;
                         }
 
                         counter!("mugraph.simulator.transactions").increment(1);
                     }
+                    response => {
+                        return Err(Error::SimulationError {
+                            reason: format!("Unexpected response: {response:?}"),
+                        })
+                    }
                 }
             }
             Action::DoubleSpend(transaction) => {
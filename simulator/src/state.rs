@@ -1,9 +1,11 @@
 use std::collections::VecDeque;
 
-use blake3::Hasher;
-use indexmap::{IndexMap, IndexSet};
 use metrics::gauge;
-use mugraph_core::{builder::TransactionBuilder, crypto, error::Error, types::*};
+use mugraph_core::{
+    builder::{LargestFirst, TransactionBuilder},
+    error::Error,
+    types::*,
+};
 use rand::prelude::*;
 use rand_chacha::ChaCha20Rng;
 
@@ -13,7 +15,6 @@ pub struct State {
     pub rng: ChaCha20Rng,
     pub keypair: Keypair,
     pub notes: VecDeque<Note>,
-    pub by_asset_id: IndexMap<Hash, IndexSet<u32>>,
 }
 
 impl State {
@@ -22,8 +23,12 @@ impl State {
         let assets = (0..config.assets)
             .map(|_| Hash::random(rng))
             .collect::<Vec<_>>();
+
+        for asset_id in assets.iter() {
+            delegate.register(*asset_id)?;
+        }
+
         let mut notes = VecDeque::with_capacity(config.notes);
-        let mut by_asset_id = IndexMap::new();
 
         for _ in 0..config.notes {
             let idx = rng.gen_range(0..config.assets);
@@ -31,23 +36,13 @@ impl State {
             let asset_id = assets[idx];
             let amount = rng.gen_range(1..u64::MAX / 2);
 
-            let note = delegate.emit(asset_id, amount)?;
-
-            by_asset_id
-                .entry(note.asset_id)
-                .and_modify(|x: &mut IndexSet<u32>| {
-                    x.insert(notes.len() as u32);
-                })
-                .or_insert(IndexSet::from_iter([notes.len() as u32]));
-
-            notes.push_back(note);
+            notes.push_back(delegate.emit(asset_id, amount)?);
         }
 
         Ok(Self {
             rng: ChaCha20Rng::seed_from_u64(rng.gen()),
             keypair: delegate.keypair,
             notes,
-            by_asset_id,
         })
     }
 
@@ -77,33 +72,29 @@ impl State {
             }
         }
 
-        Ok(Action::DoubleSpend(transaction.build()?))
+        Ok(Action::DoubleSpend(
+            transaction.build(&Hash::random(&mut self.rng))?,
+        ))
+    }
+
+    /// Takes the inputs picked by `transaction` out of the notes held.
+    fn take_inputs(&mut self, transaction: &TransactionBuilder) {
+        self.notes.retain(|note| !transaction.inputs.contains(note));
     }
 
     #[tracing::instrument(skip_all)]
     fn generate_split(&mut self) -> Result<Action, Error> {
         let mut transaction = TransactionBuilder::new();
 
-        while transaction.output_count() < MAX_OUTPUTS {
-            let input = match self.notes.pop_front() {
-                Some(input) => input,
-                None => {
-                    break;
-                }
-            };
-
-            if input.amount > 2 {
-                let rem = input.amount % 2;
-                let (a, b) = (input.amount / 2, input.amount / 2 + rem);
-
-                transaction = transaction
-                    .output(input.asset_id, a)
-                    .output(input.asset_id, b);
-            } else {
-                transaction = transaction.output(input.asset_id, input.amount);
+        // Each note is paid in half, with the other half as change.
+        for note in self.notes.iter() {
+            if transaction.output_count() + 2 > MAX_OUTPUTS {
+                break;
             }
 
-            transaction = transaction.input(input);
+            let half = (note.asset_id, (note.amount / 2).max(1));
+            transaction =
+                transaction.select(std::slice::from_ref(note), &[half], &mut LargestFirst)?;
         }
 
         if transaction.input_count() == 0 {
@@ -112,55 +103,48 @@ impl State {
             });
         }
 
-        Ok(Action::Transaction(transaction.build()?))
+        self.take_inputs(&transaction);
+
+        Ok(Action::Transaction(
+            transaction.build(&Hash::random(&mut self.rng))?,
+        ))
     }
 
     #[tracing::instrument(skip_all)]
     fn generate_join(&mut self) -> Result<Action, Error> {
-        let mut transaction = TransactionBuilder::new();
+        let Some(asset_id) = self.notes.front().map(|note| note.asset_id) else {
+            return self.generate_split();
+        };
 
-        'outer: while transaction.input_count() < MAX_INPUTS {
-            let mut found_pair = false;
-
-            for (_, notes) in self.by_asset_id.iter_mut() {
-                if self.notes.len() < 2 {
-                    break 'outer;
-                }
-
-                let a = match notes.pop().and_then(|x| self.notes.remove(x as usize)) {
-                    Some(a) => a,
-                    None => continue,
-                };
-
-                let b = match notes.pop().and_then(|x| self.notes.remove(x as usize)) {
-                    Some(b) => b,
-                    None => {
-                        // Put 'a' back if we couldn't find a pair
-                        notes.insert(self.notes.len() as u32);
-                        self.notes.push_back(a);
-                        continue;
-                    }
-                };
+        // Pays the total of as many notes of the asset as fit into a single
+        // output, so they are all picked and leave no change.
+        let mut pool = vec![];
+        let mut total = 0u64;
 
-                transaction = transaction
-                    .output(b.asset_id, b.amount + a.amount)
-                    .input(a)
-                    .input(b);
-
-                found_pair = true;
+        for note in self.notes.iter().filter(|note| note.asset_id == asset_id) {
+            if pool.len() == MAX_INPUTS {
                 break;
             }
 
-            if !found_pair {
-                break;
-            }
+            let Some(next) = total.checked_add(note.amount) else {
+                continue;
+            };
+
+            total = next;
+            pool.push(note.clone());
         }
 
-        if transaction.input_count() == 0 {
+        if pool.len() < 2 {
             return self.generate_split();
         }
 
-        Ok(Action::Transaction(transaction.build()?))
+        let transaction =
+            TransactionBuilder::new().select(&pool, &[(asset_id, total)], &mut LargestFirst)?;
+        self.take_inputs(&transaction);
+
+        Ok(Action::Transaction(
+            transaction.build(&Hash::random(&mut self.rng))?,
+        ))
     }
 
     #[tracing::instrument(skip_all)]
@@ -168,36 +152,23 @@ impl State {
         &mut self,
         asset_id: Hash,
         amount: u64,
-        signature: Blinded<Signature>,
+        nonce: Hash,
+        signature: Blinded<OutputSignature>,
     ) -> Result<(), Error> {
-        let mut nonce = Hasher::new();
-        nonce.update(asset_id.as_ref());
-        nonce.update(&amount.to_be_bytes());
-        nonce.update(signature.0.as_ref());
-
+        // Outputs are signed on their commitment without blinding, so the
+        // signature needs no unblinding.
         let note = Note {
             amount,
             delegate: self.keypair.public_key,
             asset_id,
-            nonce: nonce.finalize().into(),
-            signature: crypto::unblind_signature(
-                &signature,
-                &crypto::blind(&mut self.rng, &[]).factor,
-                &self.keypair.public_key,
-            )?,
+            nonce,
+            signature: signature.0.signature,
+            lock: None,
+            proof: Some(signature.0.proof),
         };
 
-        let note_index = self.notes.len() as u32;
         self.notes.push_back(note);
 
-        // Update by_asset_id
-        self.by_asset_id
-            .entry(asset_id)
-            .and_modify(|x: &mut IndexSet<u32>| {
-                x.insert(note_index);
-            })
-            .or_insert_with(|| IndexSet::from_iter([note_index]));
-
         Ok(())
     }
 }
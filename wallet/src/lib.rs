@@ -0,0 +1,904 @@
+//! Wallet keeping notes of one or more delegates in a local database.
+//!
+//! Every request that spends or creates notes is recorded as in flight,
+//! along with the secrets of its outputs, before it is sent. If the wallet
+//! stops before getting the answer, [`Wallet::recover`] sends it again, and
+//! the delegate answers with the signatures it already issued when it had
+//! processed it.
+
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use mugraph_client::{Client, Retry};
+use mugraph_core::{
+    builder::{RandomImprove, TransactionBuilder},
+    error::Error,
+    types::*,
+    utils::BitSet32,
+};
+use rand::prelude::*;
+use rand_chacha::ChaCha20Rng;
+use redb::{ReadableTable, WriteTransaction};
+use tracing::{info, warn};
+
+mod store;
+
+pub use self::store::*;
+
+pub const SECRET_SEP: &[u8] = b"mugraph_v0_wallet_secret";
+pub const KEY_SEP: &[u8] = b"mugraph_v0_wallet_key";
+
+pub struct Wallet {
+    store: Store,
+    seed: Hash,
+    retry: Retry,
+}
+
+impl Wallet {
+    /// Creates a wallet at `path`, deriving its secrets from `seed`.
+    pub fn create(path: impl AsRef<Path>, seed: Hash) -> Result<Self, Error> {
+        Ok(Self {
+            store: Store::create(path, seed)?,
+            seed,
+            retry: Retry::default(),
+        })
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let store = Store::open(path)?;
+
+        Ok(Self {
+            seed: store.meta()?.seed,
+            store,
+            retry: Retry::default(),
+        })
+    }
+
+    /// Sets how requests to delegates are retried.
+    pub fn retry(mut self, retry: Retry) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn store(&self) -> &Store {
+        &self.store
+    }
+
+    /// Key owning the L1 deposits the wallet mints notes for, and unlocking
+    /// the notes locked to the wallet.
+    pub fn keypair(&self) -> Keypair {
+        let seed = Hash::digest(&[KEY_SEP, self.seed.as_ref()].concat());
+
+        Keypair::random(&mut ChaCha20Rng::from_seed(seed.0))
+    }
+
+    /// Adds the delegate at `url`, or updates its metadata if it was already
+    /// added.
+    pub async fn add_delegate(&self, url: &str) -> Result<PublicKey, Error> {
+        let client = Client::connect_with(url, self.retry).await?;
+        self.save_delegate(&client)?;
+
+        Ok(client.public_key())
+    }
+
+    fn save_delegate(&self, client: &Client) -> Result<(), Error> {
+        let w = self.store.write()?;
+        w.open_table(DELEGATES)?.insert(
+            client.public_key(),
+            Ok(Delegate {
+                url: client.url().to_string(),
+                info: client.info().clone(),
+            }),
+        )?;
+        w.commit()?;
+
+        Ok(())
+    }
+
+    pub fn delegates(&self) -> Result<Vec<(PublicKey, Delegate)>, Error> {
+        self.store.delegates()
+    }
+
+    /// Adds the delegate at `url` unless it already was, checking it is the
+    /// one with `public_key`.
+    async fn check_delegate(&self, public_key: PublicKey, url: &str) -> Result<(), Error> {
+        if self.store.delegate(public_key).is_ok() {
+            return Ok(());
+        }
+
+        if self.add_delegate(url).await? != public_key {
+            return Err(Error::InvalidKey {
+                reason: format!("Delegate at {url} is not {public_key}"),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Connects to a delegate added to the wallet, checking it still has the
+    /// same key.
+    async fn client(&self, public_key: PublicKey) -> Result<Client, Error> {
+        let delegate = self.store.delegate(public_key)?;
+        let client = Client::connect_with(&delegate.url, self.retry).await?;
+
+        if client.public_key() != public_key {
+            return Err(Error::InvalidKey {
+                reason: format!(
+                    "Delegate at {} is now {}, expected {public_key}",
+                    delegate.url,
+                    client.public_key()
+                ),
+            });
+        }
+
+        if client.info() != &delegate.info {
+            self.save_delegate(&client)?;
+        }
+
+        Ok(client)
+    }
+
+    /// Unspent amount of each asset, by delegate.
+    pub fn balances(&self) -> Result<BTreeMap<(PublicKey, Hash), u128>, Error> {
+        let mut balances = BTreeMap::new();
+
+        for entry in self.store.notes(|entry| entry.status == Status::Unspent)? {
+            *balances
+                .entry((entry.note.delegate, entry.note.asset_id))
+                .or_default() += entry.note.amount as u128;
+        }
+
+        Ok(balances)
+    }
+
+    /// Unspent amount of `asset_id`, held with `delegate` or with any of them.
+    pub fn balance(&self, delegate: Option<PublicKey>, asset_id: Hash) -> Result<u128, Error> {
+        Ok(self
+            .balances()?
+            .into_iter()
+            .filter(|((d, a), _)| *a == asset_id && delegate.is_none_or(|delegate| *d == delegate))
+            .map(|(_, amount)| amount)
+            .sum())
+    }
+
+    /// Requests that went through, oldest first.
+    pub fn history(&self) -> Result<Vec<Event>, Error> {
+        self.store.history()
+    }
+
+    /// Mints notes with the given amounts for a deposit owned by
+    /// [`Wallet::keypair`].
+    pub async fn mint(
+        &self,
+        delegate: PublicKey,
+        deposit: Hash,
+        asset_id: Hash,
+        amounts: &[u64],
+    ) -> Result<Vec<Note>, Error> {
+        let client = self.client(delegate).await?;
+        let outputs = |secret: &Hash| {
+            Ok(Transaction {
+                input_mask: BitSet32::new(),
+                atoms: amounts
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &amount)| Atom {
+                        delegate,
+                        asset_id: 0,
+                        amount,
+                        nonce: Transaction::output_nonce(secret, i),
+                        signature: None,
+                        lock: None,
+                    })
+                    .collect(),
+                asset_ids: vec![asset_id],
+                signatures: vec![],
+                data: vec![],
+                witnesses: vec![],
+            })
+        };
+        let owner = self.keypair().secret_key;
+
+        let in_flight = self.prepare(
+            Kind::Mint,
+            delegate,
+            &[],
+            outputs,
+            vec![Status::Unspent; amounts.len()],
+            |outputs| Submission::Mint(Mint::new(&mut thread_rng(), &owner, deposit, outputs)),
+        )?;
+
+        Ok(notes(self.submit(&client, in_flight).await?))
+    }
+
+    /// Pays `amount` of `asset_id` out of the notes held with `delegate`,
+    /// returning a token with a note for it.
+    pub async fn send(
+        &self,
+        delegate: PublicKey,
+        asset_id: Hash,
+        amount: u64,
+    ) -> Result<Token, Error> {
+        self.send_with(Kind::Send, delegate, asset_id, amount, None, vec![])
+            .await
+    }
+
+    /// Pays like [`Wallet::send`], with the note in the token locked to
+    /// `lock`, so only its owner can spend it.
+    pub async fn send_locked(
+        &self,
+        delegate: PublicKey,
+        asset_id: Hash,
+        amount: u64,
+        lock: PublicKey,
+    ) -> Result<Token, Error> {
+        self.send_with(Kind::Send, delegate, asset_id, amount, Some(lock), vec![])
+            .await
+    }
+
+    /// Pays a payment request, returning the token to hand over to whoever
+    /// made it. Adds its delegate if needed.
+    pub async fn pay(&self, request: &PaymentRequest) -> Result<Token, Error> {
+        self.check_delegate(request.delegate, &request.url).await?;
+
+        self.send_with(
+            Kind::Pay {
+                memo: request.memo.clone(),
+            },
+            request.delegate,
+            request.asset_id,
+            request.amount,
+            request.lock,
+            request.memo.clone().unwrap_or_default().into_bytes(),
+        )
+        .await
+    }
+
+    async fn send_with(
+        &self,
+        kind: Kind,
+        delegate: PublicKey,
+        asset_id: Hash,
+        amount: u64,
+        lock: Option<PublicKey>,
+        data: Vec<u8>,
+    ) -> Result<Token, Error> {
+        let client = self.client(delegate).await?;
+        let pool = notes(self.store.notes(|entry| {
+            entry.status == Status::Unspent
+                && entry.note.delegate == delegate
+                && entry.note.asset_id == asset_id
+        })?);
+
+        let builder = TransactionBuilder::new()
+            .fees(client.info().fees.clone())
+            .data(data)
+            .select(
+                &pool,
+                &[(asset_id, amount)],
+                &mut RandomImprove::new(thread_rng()),
+            )?;
+
+        // The payment comes first, then the change.
+        let mut outputs = vec![Status::Unspent; builder.output_count()];
+        outputs[0] = Status::Sent;
+
+        let inputs = builder.inputs.clone();
+        let transaction = |secret: &Hash| {
+            let mut transaction = builder.build(secret)?;
+            transaction.atoms[inputs.len()].lock = lock;
+
+            Ok(transaction)
+        };
+
+        let in_flight = self.prepare(
+            kind,
+            delegate,
+            &inputs,
+            transaction,
+            outputs,
+            Submission::Transaction,
+        )?;
+
+        let sent = self
+            .submit(&client, in_flight)
+            .await?
+            .into_iter()
+            .filter(|entry| entry.status == Status::Sent)
+            .map(|entry| entry.note)
+            .collect();
+
+        Ok(Token {
+            url: client.url().to_string(),
+            notes: sent,
+        })
+    }
+
+    /// Swaps the notes of a token for new ones only this wallet knows about,
+    /// paying the fees out of them. Adds their delegate if needed.
+    pub async fn receive(&self, token: &Token) -> Result<Vec<Note>, Error> {
+        let delegate = token.delegate()?;
+        self.check_delegate(delegate, &token.url).await?;
+
+        let client = self.client(delegate).await?;
+        let in_flight = self.swap(token, delegate, client.info().fees.clone())?;
+
+        Ok(notes(self.submit(&client, in_flight).await?))
+    }
+
+    /// Accepts a token without reaching its delegate, which must have been
+    /// added before. Its notes must be signed by the delegate and locked to
+    /// [`Wallet::keypair`], so the sender can not spend them again.
+    ///
+    /// The swap is left in flight, and goes through on the next
+    /// [`Wallet::recover`], with the fees the delegate had when last reached.
+    pub fn receive_offline(&self, token: &Token) -> Result<(), Error> {
+        let delegate = token.delegate()?;
+        let fees = self.store.delegate(delegate)?.info.fees;
+
+        token.verify(self.keypair().public_key)?;
+        self.swap(token, delegate, fees)?;
+
+        Ok(())
+    }
+
+    /// Records a transaction swapping the notes of a token as in flight.
+    fn swap(
+        &self,
+        token: &Token,
+        delegate: PublicKey,
+        fees: FeeSchedule,
+    ) -> Result<InFlight, Error> {
+        let mut builder = token
+            .notes
+            .iter()
+            .fold(TransactionBuilder::new().fees(fees), |builder, note| {
+                builder.input(note.clone())
+            });
+        let mut assets = token.notes.iter().map(|n| n.asset_id).collect::<Vec<_>>();
+        assets.sort();
+        assets.dedup();
+
+        for asset_id in assets {
+            let amount = builder.remaining(asset_id);
+
+            if amount == 0 {
+                return Err(Error::InsufficientFunds {
+                    asset_id,
+                    expected: builder.fee(asset_id).min(u64::MAX as u128) as u64 + 1,
+                    got: 0,
+                });
+            }
+
+            builder = builder.output(
+                asset_id,
+                u64::try_from(amount).map_err(|_| Error::InvalidTransaction {
+                    reason: format!("Token amount of {asset_id} does not fit in a note"),
+                })?,
+            );
+        }
+
+        let outputs = vec![Status::Unspent; builder.output_count()];
+
+        self.prepare(
+            Kind::Receive,
+            delegate,
+            &token.notes,
+            |secret| builder.build(secret),
+            outputs,
+            Submission::Transaction,
+        )
+    }
+
+    /// Sends again every request left in flight, returning the notes they
+    /// created. Requests the delegate rejects are dropped.
+    pub async fn recover(&self) -> Result<Vec<Note>, Error> {
+        let mut recovered = vec![];
+
+        for in_flight in self.store.in_flight()? {
+            let client = self.client(in_flight.delegate).await?;
+            let id = in_flight.submission.transaction().id();
+
+            match self.submit(&client, in_flight).await {
+                Ok(entries) => {
+                    info!(%id, "Recovered request in flight");
+                    recovered.extend(notes(entries));
+                }
+                Err(e) if !e.is_retryable() => {
+                    warn!(%id, reason = %e, "Dropped request in flight");
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(recovered)
+    }
+
+    /// Asks the delegates which of the notes held or sent were spent, and
+    /// marks them as such. Returns how many were.
+    pub async fn sync(&self) -> Result<usize, Error> {
+        let mut spent = 0;
+
+        for (public_key, _) in self.store.delegates()? {
+            let entries = self.store.notes(|entry| {
+                entry.note.delegate == public_key
+                    && matches!(entry.status, Status::Unspent | Status::Sent)
+            })?;
+
+            if entries.is_empty() {
+                continue;
+            }
+
+            let refs = entries
+                .iter()
+                .map(|entry| NoteRef::Commitment(entry.note.commitment()))
+                .collect::<Vec<_>>();
+            let states = self.client(public_key).await?.check(&refs).await?;
+            let commitments = entries
+                .iter()
+                .zip(states)
+                .filter(|(_, state)| *state == NoteState::Spent)
+                .map(|(entry, _)| entry.note.commitment())
+                .collect::<Vec<_>>();
+
+            let w = self.store.write()?;
+            set_status(&w, &commitments, Status::Spent)?;
+            w.commit()?;
+
+            spent += commitments.len();
+        }
+
+        Ok(spent)
+    }
+
+    /// Records a request as in flight, building its transaction with the
+    /// outputs derived from the next secret of the seed and reserving its
+    /// inputs, which are added to the wallet if they came from a token.
+    fn prepare(
+        &self,
+        kind: Kind,
+        delegate: PublicKey,
+        inputs: &[Note],
+        build: impl FnOnce(&Hash) -> Result<Transaction, Error>,
+        outputs: Vec<Status>,
+        submission: impl FnOnce(Transaction) -> Submission,
+    ) -> Result<InFlight, Error> {
+        let w = self.store.write()?;
+        let secret = self.secret(&w)?;
+        let mut transaction = build(&secret)?;
+
+        transaction.sign_witnesses(&mut thread_rng(), &self.keypair().secret_key)?;
+
+        let in_flight = InFlight {
+            kind,
+            delegate,
+            submission: submission(transaction),
+            secret,
+            outputs,
+        };
+        let id = in_flight.submission.transaction().id();
+
+        {
+            let mut table = w.open_table(NOTES)?;
+
+            for note in inputs {
+                let commitment = note.commitment();
+                let entry = table
+                    .get(commitment)?
+                    .map(|entry| entry.value())
+                    .transpose()?;
+
+                match entry {
+                    Some(Entry {
+                        status: Status::Unspent | Status::Sent,
+                        ..
+                    })
+                    | None => {
+                        table.insert(
+                            commitment,
+                            Ok(Entry {
+                                note: note.clone(),
+                                status: Status::Pending { transaction: id },
+                            }),
+                        )?;
+                    }
+                    Some(entry) => {
+                        return Err(Error::InvalidTransaction {
+                            reason: format!(
+                                "Note {commitment} can not be spent, it is {:?}",
+                                entry.status
+                            ),
+                        });
+                    }
+                }
+            }
+        }
+
+        w.open_table(IN_FLIGHT)?.insert(id, Ok(in_flight.clone()))?;
+        w.commit()?;
+
+        Ok(in_flight)
+    }
+
+    /// Derives the next secret from the seed.
+    fn secret(&self, w: &WriteTransaction) -> Result<Hash, Error> {
+        let mut table = w.open_table(META)?;
+        let mut meta = match table.get(META_KEY)? {
+            Some(meta) => meta.value()?,
+            None => {
+                return Err(Error::NotFound {
+                    reason: "Wallet has no seed".to_string(),
+                })
+            }
+        };
+
+        let secret =
+            Hash::digest(&[SECRET_SEP, self.seed.as_ref(), &meta.secrets.to_le_bytes()].concat());
+
+        meta.secrets += 1;
+        table.insert(META_KEY, Ok(meta))?;
+
+        Ok(secret)
+    }
+
+    /// Sends a request in flight, storing its outputs once signed.
+    ///
+    /// It stays in flight when the delegate can not be reached, and is
+    /// dropped when the delegate rejects it, releasing its inputs.
+    async fn submit(&self, client: &Client, in_flight: InFlight) -> Result<Vec<Entry>, Error> {
+        let result = match &in_flight.submission {
+            Submission::Transaction(transaction) => {
+                client.transaction(transaction, &in_flight.secret).await
+            }
+            Submission::Mint(mint) => client.mint(mint, &in_flight.secret).await,
+        };
+
+        let transaction = in_flight.submission.transaction();
+        let inputs = inputs(transaction);
+        let w = self.store.write()?;
+
+        let entries = match result {
+            Ok(signatures) => {
+                let entries = in_flight.notes(&signatures)?;
+                let mut table = w.open_table(NOTES)?;
+
+                for entry in entries.iter() {
+                    table.insert(entry.note.commitment(), Ok(entry.clone()))?;
+                }
+
+                drop(table);
+                set_status(&w, &inputs, Status::Spent)?;
+
+                let timestamp = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |d| d.as_secs());
+                let mut history = w.open_table(HISTORY)?;
+                let next = history.last()?.map_or(0, |(i, _)| i.value() + 1);
+                history.insert(next, Ok(in_flight.event(&entries, timestamp)))?;
+
+                entries
+            }
+            Err(e) if e.is_retryable() => return Err(e),
+            Err(e) => {
+                set_status(&w, &inputs, Status::Unspent)?;
+
+                // The input spent by something else is gone for good.
+                if let Error::AlreadySpent { signature } = e {
+                    let spent = transaction
+                        .atoms
+                        .iter()
+                        .enumerate()
+                        .filter(|(i, atom)| {
+                            transaction.is_input(*i)
+                                && atom
+                                    .signature
+                                    .and_then(|s| transaction.signatures.get(s as usize))
+                                    == Some(&signature)
+                        })
+                        .map(|(_, atom)| atom.commitment(&transaction.asset_ids))
+                        .collect::<Vec<_>>();
+
+                    set_status(&w, &spent, Status::Spent)?;
+                }
+
+                w.open_table(IN_FLIGHT)?.remove(transaction.id())?;
+                w.commit()?;
+
+                return Err(e);
+            }
+        };
+
+        w.open_table(IN_FLIGHT)?.remove(transaction.id())?;
+        w.commit()?;
+
+        Ok(entries)
+    }
+}
+
+/// Commitments of the inputs of a transaction.
+fn inputs(transaction: &Transaction) -> Vec<Hash> {
+    transaction
+        .atoms
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| transaction.is_input(*i))
+        .map(|(_, atom)| atom.commitment(&transaction.asset_ids))
+        .collect()
+}
+
+fn notes(entries: Vec<Entry>) -> Vec<Note> {
+    entries.into_iter().map(|entry| entry.note).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use mugraph_core::crypto::{Scalar, HTC_SEP};
+    use mugraph_node::testing::Local;
+
+    use super::*;
+
+    fn wallet() -> (Wallet, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let wallet = Wallet::create(dir.path().join("wallet"), Hash::random(&mut thread_rng()))
+            .unwrap()
+            .retry(Retry::none());
+
+        (wallet, dir)
+    }
+
+    async fn funded(node: &Local, amounts: &[u64]) -> (Wallet, tempfile::TempDir) {
+        let (wallet, dir) = wallet();
+        let delegate = wallet.add_delegate(&node.url).await.unwrap();
+        let deposit = node.deposit(amounts.iter().sum(), wallet.keypair().public_key);
+
+        wallet
+            .mint(delegate, deposit, node.asset_id, amounts)
+            .await
+            .unwrap();
+
+        (wallet, dir)
+    }
+
+    #[tokio::test]
+    async fn test_send_and_receive() {
+        let node = Local::shared();
+        let (alice, _a) = funded(node, &[60, 40]).await;
+        let (bob, _b) = wallet();
+        let delegate = node.keypair.public_key;
+
+        assert_eq!(alice.balance(Some(delegate), node.asset_id).unwrap(), 100);
+
+        let token = alice.send(delegate, node.asset_id, 30).await.unwrap();
+        assert_eq!(token.notes.iter().map(|n| n.amount).sum::<u64>(), 30);
+        assert_eq!(alice.balance(None, node.asset_id).unwrap(), 70);
+
+        let received = bob.receive(&token).await.unwrap();
+        assert_eq!(received.iter().map(|n| n.amount).sum::<u64>(), 30);
+        assert!(received.iter().all(|n| !token.notes.contains(n)));
+        assert_eq!(
+            bob.balances().unwrap(),
+            BTreeMap::from([((delegate, node.asset_id), 30)])
+        );
+        assert_eq!(bob.delegates().unwrap()[0].1.url, node.url);
+
+        // The token was claimed, so it can not be received twice.
+        assert!(matches!(
+            bob.receive(&token).await,
+            Err(Error::InvalidTransaction { .. })
+        ));
+        let (carol, _c) = wallet();
+        assert!(matches!(
+            carol.receive(&token).await,
+            Err(Error::AlreadySpent { .. })
+        ));
+        assert_eq!(carol.balance(None, node.asset_id).unwrap(), 0);
+
+        assert_eq!(
+            alice
+                .history()
+                .unwrap()
+                .iter()
+                .map(|e| (e.kind.clone(), e.amounts.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (Kind::Mint, vec![(node.asset_id, 100)]),
+                (Kind::Send, vec![(node.asset_id, 30)])
+            ]
+        );
+        assert_eq!(bob.history().unwrap()[0].kind, Kind::Receive);
+
+        assert_eq!(alice.sync().await.unwrap(), 1);
+        assert!(alice
+            .store()
+            .notes(|entry| entry.status == Status::Sent)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pay() {
+        let node = Local::shared();
+        let (alice, _a) = funded(node, &[25]).await;
+        let (bob, _b) = wallet();
+        let request = PaymentRequest {
+            url: node.url.clone(),
+            delegate: node.keypair.public_key,
+            asset_id: node.asset_id,
+            amount: 20,
+            memo: Some("invoice 42".to_string()),
+            lock: None,
+        };
+
+        let token = alice.pay(&request).await.unwrap();
+        bob.receive(&token).await.unwrap();
+
+        assert_eq!(alice.balance(None, node.asset_id).unwrap(), 5);
+        assert_eq!(bob.balance(None, node.asset_id).unwrap(), 20);
+
+        let event = alice.history().unwrap().pop().unwrap();
+        assert_eq!(
+            event.kind,
+            Kind::Pay {
+                memo: Some("invoice 42".to_string())
+            }
+        );
+        assert_eq!(event.amounts, vec![(node.asset_id, 20)]);
+    }
+
+    #[tokio::test]
+    async fn test_receive_offline() {
+        let node = Local::shared();
+        let (alice, _a) = funded(node, &[40]).await;
+        let (bob, _b) = wallet();
+        let (carol, _c) = wallet();
+        let delegate = bob.add_delegate(&node.url).await.unwrap();
+
+        let token = alice
+            .send_locked(delegate, node.asset_id, 30, bob.keypair().public_key)
+            .await
+            .unwrap();
+        assert_eq!(token.notes[0].lock, Some(bob.keypair().public_key));
+
+        // Nobody else can accept or spend it.
+        assert!(matches!(
+            token.verify(carol.keypair().public_key),
+            Err(Error::InvalidAtom { .. })
+        ));
+        assert!(matches!(
+            carol.receive(&token).await,
+            Err(Error::InvalidAtom { .. })
+        ));
+
+        let mut forged = token.clone();
+        forged.notes[0].amount = 35;
+        assert!(matches!(
+            bob.receive_offline(&forged),
+            Err(Error::InvalidSignature { .. })
+        ));
+
+        // Nor a note with the signature anyone could compute from the key of
+        // the delegate if `H(m)` were `h(m) * G`.
+        let note = &mut forged.notes[0];
+        note.amount = 1_000_000;
+        note.proof = None;
+        let h: Scalar = Hash::digest(&[HTC_SEP, note.commitment().as_ref()].concat()).into();
+        note.signature = (delegate.to_point().unwrap() * h).into();
+        assert!(matches!(
+            bob.receive_offline(&forged),
+            Err(Error::InvalidSignature { .. })
+        ));
+        assert!(bob.store().in_flight().unwrap().is_empty());
+
+        bob.receive_offline(&token).unwrap();
+        assert!(matches!(
+            bob.receive_offline(&token),
+            Err(Error::InvalidTransaction { .. })
+        ));
+        assert_eq!(bob.store().in_flight().unwrap().len(), 1);
+        assert_eq!(bob.balance(None, node.asset_id).unwrap(), 0);
+
+        // The swap goes through once the delegate can be reached.
+        let recovered = bob.recover().await.unwrap();
+        assert_eq!(recovered.iter().map(|n| n.amount).sum::<u64>(), 30);
+        assert!(recovered.iter().all(|n| n.lock.is_none()));
+        assert_eq!(bob.balance(None, node.asset_id).unwrap(), 30);
+        assert_eq!(bob.history().unwrap()[0].kind, Kind::Receive);
+        assert_eq!(alice.sync().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_insufficient_funds() {
+        let node = Local::shared();
+        let (wallet, _dir) = funded(node, &[10]).await;
+
+        assert!(matches!(
+            wallet
+                .send(node.keypair.public_key, node.asset_id, 11)
+                .await,
+            Err(Error::InsufficientFunds { .. })
+        ));
+        assert_eq!(wallet.balance(None, node.asset_id).unwrap(), 10);
+        assert!(wallet.store().in_flight().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_recover() {
+        let node = Local::shared();
+        let (wallet, dir) = funded(node, &[50]).await;
+        let delegate = node.keypair.public_key;
+        let pool = notes(wallet.store().notes(|_| true).unwrap());
+
+        // The wallet stops right after the delegate processed the
+        // transaction, before storing its outputs.
+        let builder = TransactionBuilder::new()
+            .input(pool[0].clone())
+            .output(node.asset_id, 20)
+            .output(node.asset_id, 30);
+        let in_flight = wallet
+            .prepare(
+                Kind::Send,
+                delegate,
+                &pool,
+                |secret| builder.build(secret),
+                vec![Status::Unspent; 2],
+                Submission::Transaction,
+            )
+            .unwrap();
+        let client = Client::connect(&node.url).await.unwrap();
+        client
+            .transaction(in_flight.submission.transaction(), &in_flight.secret)
+            .await
+            .unwrap();
+        assert_eq!(wallet.balance(None, node.asset_id).unwrap(), 0);
+        drop(wallet);
+
+        // It stops again before sending a mint.
+        let wallet = Wallet::open(dir.path().join("wallet")).unwrap();
+        let deposit = node.deposit(5, wallet.keypair().public_key);
+        let outputs = |secret: &Hash| {
+            Ok(Transaction {
+                input_mask: BitSet32::new(),
+                atoms: vec![Atom {
+                    delegate,
+                    asset_id: 0,
+                    amount: 5,
+                    nonce: Transaction::output_nonce(secret, 0),
+                    signature: None,
+                    lock: None,
+                }],
+                asset_ids: vec![node.asset_id],
+                signatures: vec![],
+                data: vec![],
+                witnesses: vec![],
+            })
+        };
+        let owner = wallet.keypair().secret_key;
+        wallet
+            .prepare(
+                Kind::Mint,
+                delegate,
+                &[],
+                outputs,
+                vec![Status::Unspent],
+                |outputs| Submission::Mint(Mint::new(&mut thread_rng(), &owner, deposit, outputs)),
+            )
+            .unwrap();
+
+        let mut recovered = wallet
+            .recover()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|n| n.amount)
+            .collect::<Vec<_>>();
+        recovered.sort();
+
+        assert_eq!(recovered, vec![5, 20, 30]);
+        assert_eq!(wallet.balance(None, node.asset_id).unwrap(), 55);
+        assert!(wallet.store().in_flight().unwrap().is_empty());
+        assert_eq!(wallet.sync().await.unwrap(), 0);
+    }
+}
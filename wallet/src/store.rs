@@ -0,0 +1,326 @@
+//! Notes and everything else the wallet keeps on disk.
+
+use std::{collections::BTreeMap, path::Path};
+
+use mugraph_core::{error::Error, record::Record, types::*};
+use redb::{
+    Database, ReadTransaction, ReadableTable, ReadableTableMetadata, TableDefinition,
+    WriteTransaction,
+};
+use serde::{Deserialize, Serialize};
+
+/// Seed of the wallet and the secrets derived from it, under [`META_KEY`].
+pub const META: TableDefinition<&str, Record<Meta>> = TableDefinition::new("meta");
+pub const DELEGATES: TableDefinition<PublicKey, Record<Delegate>> =
+    TableDefinition::new("delegates");
+/// Every note the wallet has held, by commitment.
+pub const NOTES: TableDefinition<Hash, Record<Entry>> = TableDefinition::new("notes");
+/// Requests sent to a delegate that got no definitive answer yet, by the id
+/// of their transaction.
+pub const IN_FLIGHT: TableDefinition<Hash, Record<InFlight>> = TableDefinition::new("in_flight");
+/// Requests that went through, in the order they did.
+pub const HISTORY: TableDefinition<u64, Record<Event>> = TableDefinition::new("history");
+
+pub const META_KEY: &str = "meta";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Meta {
+    pub seed: Hash,
+    /// Secrets of requests derived so far, see [`crate::SECRET_SEP`].
+    pub secrets: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Delegate {
+    pub url: String,
+    /// Metadata of the delegate when it was last reached.
+    pub info: Info,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Unspent,
+    /// Spent by an in-flight transaction.
+    Pending {
+        transaction: Hash,
+    },
+    /// Handed over to someone else in a token, and not claimed yet.
+    Sent,
+    Spent,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub note: Note,
+    pub status: Status,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Submission {
+    Transaction(Transaction),
+    Mint(Mint),
+}
+
+impl Submission {
+    /// Transaction with the outputs to be signed.
+    pub fn transaction(&self) -> &Transaction {
+        match self {
+            Self::Transaction(transaction) => transaction,
+            Self::Mint(mint) => &mint.outputs,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    Mint,
+    Send,
+    Receive,
+    /// Payment of a [`PaymentRequest`], with its memo.
+    Pay {
+        memo: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Event {
+    pub kind: Kind,
+    pub delegate: PublicKey,
+    pub transaction: Hash,
+    /// Amount of each asset minted, sent or received, without fees.
+    pub amounts: Vec<(Hash, u128)>,
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+/// Request recorded before it is sent, so its outputs can be recovered if
+/// the wallet stops before getting the answer.
+///
+/// Outputs are signed on their commitment without blinding, so the nonce in
+/// each output atom is all the wallet needs to turn it into a note.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InFlight {
+    pub kind: Kind,
+    pub delegate: PublicKey,
+    pub submission: Submission,
+    /// Secret the outputs are derived from, which proves the wallet owns
+    /// them when restoring the request.
+    pub secret: Hash,
+    /// Status each output gets once signed, in order.
+    pub outputs: Vec<Status>,
+}
+
+impl InFlight {
+    /// Notes for the outputs of the transaction, given their signatures.
+    pub fn notes(&self, signatures: &[Blinded<OutputSignature>]) -> Result<Vec<Entry>, Error> {
+        let transaction = self.submission.transaction();
+        let outputs = transaction
+            .atoms
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| transaction.is_output(*i))
+            .map(|(_, atom)| atom)
+            .collect::<Vec<_>>();
+
+        if outputs.len() != signatures.len() || outputs.len() != self.outputs.len() {
+            return Err(Error::ServerError {
+                reason: format!(
+                    "Expected {} signatures for transaction {}, got {}",
+                    outputs.len(),
+                    transaction.id(),
+                    signatures.len()
+                ),
+            });
+        }
+
+        outputs
+            .into_iter()
+            .zip(signatures)
+            .zip(self.outputs.iter())
+            .map(|((atom, signature), status)| {
+                let note = Note {
+                    amount: atom.amount,
+                    delegate: atom.delegate,
+                    asset_id: transaction.asset_ids[atom.asset_id as usize],
+                    nonce: atom.nonce,
+                    signature: signature.0.signature,
+                    lock: atom.lock,
+                    proof: Some(signature.0.proof),
+                };
+
+                if !note.verify()? {
+                    return Err(Error::InvalidSignature {
+                        reason: format!("Output {} is not signed by its delegate", atom.nonce),
+                        signature: note.signature,
+                    });
+                }
+
+                Ok(Entry {
+                    note,
+                    status: *status,
+                })
+            })
+            .collect()
+    }
+
+    /// Event recording the request once its outputs are signed.
+    pub fn event(&self, notes: &[Entry], timestamp: u64) -> Event {
+        let sent = matches!(self.kind, Kind::Send | Kind::Pay { .. });
+        let mut amounts = BTreeMap::<Hash, u128>::new();
+
+        for entry in notes.iter().filter(|e| !sent || e.status == Status::Sent) {
+            *amounts.entry(entry.note.asset_id).or_default() += entry.note.amount as u128;
+        }
+
+        Event {
+            kind: self.kind.clone(),
+            delegate: self.delegate,
+            transaction: self.submission.transaction().id(),
+            amounts: amounts.into_iter().collect(),
+            timestamp,
+        }
+    }
+}
+
+/// Handle to the database of a wallet.
+#[derive(Debug)]
+pub struct Store {
+    db: Database,
+}
+
+impl Store {
+    /// Creates the database of a new wallet, failing if there already is
+    /// one at `path`.
+    pub fn create(path: impl AsRef<Path>, seed: Hash) -> Result<Self, Error> {
+        let path = path.as_ref();
+
+        if path.exists() {
+            return Err(Error::StorageError {
+                kind: "already exists".to_string(),
+                reason: format!("There is already a wallet at {}", path.display()),
+            });
+        }
+
+        let store = Self {
+            db: Database::create(path)?,
+        };
+
+        let w = store.write()?;
+        w.open_table(META)?
+            .insert(META_KEY, Ok(Meta { seed, secrets: 0 }))?;
+        w.open_table(DELEGATES)?;
+        w.open_table(NOTES)?;
+        w.open_table(IN_FLIGHT)?;
+        w.open_table(HISTORY)?;
+        w.commit()?;
+
+        Ok(store)
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+
+        if !path.exists() {
+            return Err(Error::NotFound {
+                reason: format!("There is no wallet at {}", path.display()),
+            });
+        }
+
+        Ok(Self {
+            db: Database::open(path)?,
+        })
+    }
+
+    pub fn read(&self) -> Result<ReadTransaction, Error> {
+        Ok(self.db.begin_read()?)
+    }
+
+    pub fn write(&self) -> Result<WriteTransaction, Error> {
+        Ok(self.db.begin_write()?)
+    }
+
+    pub fn meta(&self) -> Result<Meta, Error> {
+        match self.read()?.open_table(META)?.get(META_KEY)? {
+            Some(meta) => meta.value(),
+            None => Err(Error::NotFound {
+                reason: "Wallet has no seed".to_string(),
+            }),
+        }
+    }
+
+    pub fn delegates(&self) -> Result<Vec<(PublicKey, Delegate)>, Error> {
+        self.read()?
+            .open_table(DELEGATES)?
+            .iter()?
+            .map(|entry| {
+                let (key, delegate) = entry?;
+                Ok((key.value(), delegate.value()?))
+            })
+            .collect()
+    }
+
+    pub fn delegate(&self, public_key: PublicKey) -> Result<Delegate, Error> {
+        match self.read()?.open_table(DELEGATES)?.get(public_key)? {
+            Some(delegate) => delegate.value(),
+            None => Err(Error::NotFound {
+                reason: format!("Delegate {public_key} was not added to the wallet"),
+            }),
+        }
+    }
+
+    /// Notes matching `filter`.
+    pub fn notes(&self, filter: impl Fn(&Entry) -> bool) -> Result<Vec<Entry>, Error> {
+        let read = self.read()?;
+        let table = read.open_table(NOTES)?;
+        let mut notes = Vec::with_capacity(table.len()? as usize);
+
+        for entry in table.iter()? {
+            let entry = entry?.1.value()?;
+
+            if filter(&entry) {
+                notes.push(entry);
+            }
+        }
+
+        Ok(notes)
+    }
+
+    pub fn history(&self) -> Result<Vec<Event>, Error> {
+        self.read()?
+            .open_table(HISTORY)?
+            .iter()?
+            .map(|entry| entry?.1.value())
+            .collect()
+    }
+
+    pub fn in_flight(&self) -> Result<Vec<InFlight>, Error> {
+        self.read()?
+            .open_table(IN_FLIGHT)?
+            .iter()?
+            .map(|entry| entry?.1.value())
+            .collect()
+    }
+}
+
+/// Sets the status of the notes with the given commitments.
+pub fn set_status(w: &WriteTransaction, notes: &[Hash], status: Status) -> Result<(), Error> {
+    let mut table = w.open_table(NOTES)?;
+
+    for commitment in notes {
+        let entry = match table.get(commitment)? {
+            Some(entry) => entry.value()?,
+            None => {
+                return Err(Error::NotFound {
+                    reason: format!("Note {commitment} is not in the wallet"),
+                })
+            }
+        };
+
+        table.insert(commitment, Ok(Entry { status, ..entry }))?;
+    }
+
+    Ok(())
+}